@@ -0,0 +1,105 @@
+//! Measures end-to-end throughput for a growing number of concurrent producers writing to a single
+//! topic, until every live subscriber has received every message. Producers only contend for
+//! the topic log while appending, so throughput is expected not to drop as producers are added;
+//! the bench fails if it falls well below the single-producer throughput.
+//! Run with `cargo bench --bench publish_throughput`.
+
+#[path = "../tests/helpers/mod.rs"]
+pub mod helpers;
+
+use bytes::Bytes;
+use helpers::test_broker::TestBroker;
+use helpers::test_client::TestClient;
+use kafkalite::compression::Compression;
+use kafkalite::protocol::request::{IsolationLevel, Request};
+use kafkalite::protocol::response::Response;
+use std::time::{Duration, Instant};
+
+const MESSAGES_PER_PRODUCER: usize = 2_000;
+const SUBSCRIBERS: usize = 4;
+const PAYLOAD_SIZE: usize = 1024;
+/// Share of the single-producer throughput more producers have to reach, leaving room for noise.
+const MIN_SCALING: f64 = 0.8;
+
+#[tokio::main]
+async fn main() {
+    let mut baseline = None;
+    for producers in [1, 2, 4, 8] {
+        let throughput = measure(producers).await;
+        println!("{producers} producer(s): {throughput:.0} messages/s");
+        let baseline = *baseline.get_or_insert(throughput);
+        assert!(
+            throughput >= baseline * MIN_SCALING,
+            "Throughput dropped from {baseline:.0} to {throughput:.0} messages/s with {producers} producers"
+        );
+    }
+}
+
+async fn measure(producers: usize) -> f64 {
+    let config = kafkalite::config::BrokerConfig::new(0, Duration::from_secs(30));
+    let test_broker = TestBroker::start_with_config(config).await;
+
+    let mut admin = TestClient::connect(test_broker.socket_addr).await;
+    let add_topic = Request::AddTopic {
+        topic: "bench-topic".to_string(),
+        retention: Some(1024),
+        compression: None,
+    };
+    assert_eq!(admin.send_and_receive(add_topic).await, Response::Ack);
+
+    let mut subscribers = Vec::with_capacity(SUBSCRIBERS);
+    for _ in 0..SUBSCRIBERS {
+        let mut subscriber = TestClient::connect(test_broker.socket_addr).await;
+        let subscribe = Request::Subscribe {
+            topic: "bench-topic".to_string(),
+            client_id: subscriber.client_id,
+            from_offset: None,
+            isolation_level: IsolationLevel::ReadUncommitted,
+        };
+        assert_eq!(subscriber.send_and_receive(subscribe).await, Response::Ack);
+        subscribers.push(subscriber);
+    }
+
+    let payload = Bytes::from(vec![0u8; PAYLOAD_SIZE]);
+    let total_messages = producers * MESSAGES_PER_PRODUCER;
+    let started_at = Instant::now();
+
+    let subscribers: Vec<_> = subscribers
+        .into_iter()
+        .map(|mut subscriber| {
+            tokio::spawn(async move {
+                for _ in 0..total_messages {
+                    subscriber.receive(1).await;
+                }
+            })
+        })
+        .collect();
+
+    let mut handles = Vec::with_capacity(producers);
+    for _ in 0..producers {
+        let mut producer = TestClient::connect(test_broker.socket_addr).await;
+        let payload = payload.clone();
+        handles.push(tokio::spawn(async move {
+            for _ in 0..MESSAGES_PER_PRODUCER {
+                let publish = Request::Publish {
+                    topic: "bench-topic".to_string(),
+                    payload: payload.clone(),
+                    compression: Compression::None,
+                    traceparent: None,
+                };
+                assert_eq!(producer.send_and_receive(publish).await, Response::Ack);
+            }
+        }));
+    }
+    for handle in handles {
+        handle.await.expect("Producer failed");
+    }
+    for subscriber in subscribers {
+        subscriber.await.expect("Subscriber failed");
+    }
+
+    let elapsed = started_at.elapsed();
+    test_broker.stop().await;
+
+    total_messages as f64 / elapsed.as_secs_f64()
+}
@@ -0,0 +1,223 @@
+use super::Principal;
+use crate::config::ConfigError;
+use crate::topic::TopicName;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Principal of connections that did not authenticate, so that rules can still be written for
+/// brokers without a credentials file.
+pub const ANONYMOUS: &str = "ANONYMOUS";
+/// Principal of rules that apply to every connection.
+const ANY_PRINCIPAL: &str = "*";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AclOperation {
+    Publish,
+    Subscribe,
+    AddTopic,
+    DeleteTopic,
+    ListTopics,
+}
+
+impl std::fmt::Display for AclOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Decides which operations a principal may perform on which topics. Everything not allowed by a
+/// rule is denied, except for super users who may do anything.
+pub struct Authorizer {
+    super_users: HashSet<Principal>,
+    rules: Vec<AclRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AclFile {
+    #[serde(default)]
+    super_users: Vec<Principal>,
+    #[serde(default)]
+    acls: Vec<AclEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AclEntry {
+    principal: Principal,
+    operations: Vec<AclOperation>,
+    topic: Option<TopicName>,
+    topic_prefix: Option<String>,
+}
+
+struct AclRule {
+    principal: Principal,
+    operations: Vec<AclOperation>,
+    pattern: TopicPattern,
+}
+
+enum TopicPattern {
+    Literal(TopicName),
+    Prefix(String),
+}
+
+impl TopicPattern {
+    fn matches(&self, topic: &str) -> bool {
+        match self {
+            TopicPattern::Literal(name) => name == topic,
+            TopicPattern::Prefix(prefix) => topic.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+impl Authorizer {
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError(format!("Failed to read {}: {}", path.display(), e)))?;
+        Self::from_toml(&content)
+            .map_err(|e| ConfigError(format!("Failed to parse {}: {}", path.display(), e.0)))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        let file: AclFile =
+            toml::from_str(content).map_err(|e| ConfigError(e.message().to_string()))?;
+        let mut rules = Vec::with_capacity(file.acls.len());
+        for entry in file.acls {
+            let pattern = match (entry.topic, entry.topic_prefix) {
+                (Some(topic), None) => TopicPattern::Literal(topic),
+                (None, Some(prefix)) => TopicPattern::Prefix(prefix),
+                _ => {
+                    return Err(ConfigError(format!(
+                        "ACL for {} needs exactly one of topic or topic_prefix",
+                        entry.principal
+                    )));
+                }
+            };
+            if entry.operations.is_empty() {
+                return Err(ConfigError(format!(
+                    "ACL for {} allows no operations",
+                    entry.principal
+                )));
+            }
+            rules.push(AclRule {
+                principal: entry.principal,
+                operations: entry.operations,
+                pattern,
+            });
+        }
+        Ok(Authorizer {
+            super_users: file.super_users.into_iter().collect(),
+            rules,
+        })
+    }
+
+    pub fn is_super_user(&self, principal: &str) -> bool {
+        self.super_users.contains(principal)
+    }
+
+    /// Whether the principal may perform the operation on the topic. `ListTopics`, which lets a
+    /// topic show up in listings and be described, is implied by every other operation, as any of
+    /// them reveals that the topic exists.
+    pub fn is_allowed(&self, principal: &str, operation: AclOperation, topic: &str) -> bool {
+        self.super_users.contains(principal)
+            || self.rules_for(principal, topic).any(|rule| {
+                operation == AclOperation::ListTopics || rule.operations.contains(&operation)
+            })
+    }
+
+    fn rules_for<'a>(
+        &'a self,
+        principal: &'a str,
+        topic: &'a str,
+    ) -> impl Iterator<Item = &'a AclRule> {
+        self.rules.iter().filter(move |rule| {
+            (rule.principal == principal || rule.principal == ANY_PRINCIPAL)
+                && rule.pattern.matches(topic)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authorizer() -> Authorizer {
+        Authorizer::from_toml(
+            r#"
+            super_users = ["admin"]
+
+            [[acls]]
+            principal = "alice"
+            operations = ["Publish", "Subscribe"]
+            topic = "orders"
+
+            [[acls]]
+            principal = "bob"
+            operations = ["AddTopic", "DeleteTopic"]
+            topic_prefix = "bob."
+
+            [[acls]]
+            principal = "*"
+            operations = ["ListTopics"]
+            topic_prefix = "public."
+            "#,
+        )
+        .expect("Failed to parse ACLs")
+    }
+
+    #[test]
+    fn allows_only_operations_granted_by_matching_rules() {
+        let authorizer = authorizer();
+        assert!(authorizer.is_allowed("alice", AclOperation::Publish, "orders"));
+        assert!(authorizer.is_allowed("alice", AclOperation::Subscribe, "orders"));
+        assert!(!authorizer.is_allowed("alice", AclOperation::DeleteTopic, "orders"));
+        assert!(!authorizer.is_allowed("alice", AclOperation::Publish, "orders-archive"));
+        assert!(authorizer.is_allowed("bob", AclOperation::AddTopic, "bob.events"));
+        assert!(!authorizer.is_allowed("bob", AclOperation::AddTopic, "events"));
+        assert!(!authorizer.is_allowed("bob", AclOperation::Publish, "orders"));
+        assert!(authorizer.is_allowed("admin", AclOperation::DeleteTopic, "orders"));
+        assert!(!authorizer.is_allowed(ANONYMOUS, AclOperation::Publish, "public.news"));
+    }
+
+    #[test]
+    fn any_rule_on_a_topic_allows_listing_it() {
+        let authorizer = authorizer();
+        let can_list =
+            |principal, topic| authorizer.is_allowed(principal, AclOperation::ListTopics, topic);
+        assert!(can_list("alice", "orders"));
+        assert!(can_list("alice", "public.news"));
+        assert!(can_list(ANONYMOUS, "public.news"));
+        assert!(!can_list("alice", "bob.events"));
+        assert!(can_list("admin", "bob.events"));
+        assert!(!authorizer.is_allowed(ANONYMOUS, AclOperation::Subscribe, "public.news"));
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let both_patterns = r#"
+            [[acls]]
+            principal = "alice"
+            operations = ["Publish"]
+            topic = "orders"
+            topic_prefix = "orders"
+        "#;
+        assert!(Authorizer::from_toml(both_patterns).is_err());
+
+        let no_operations = r#"
+            [[acls]]
+            principal = "alice"
+            operations = []
+            topic = "orders"
+        "#;
+        assert!(Authorizer::from_toml(no_operations).is_err());
+
+        let unknown_operation = r#"
+            [[acls]]
+            principal = "alice"
+            operations = ["Drop"]
+            topic = "orders"
+        "#;
+        assert!(Authorizer::from_toml(unknown_operation).is_err());
+    }
+}
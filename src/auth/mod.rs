@@ -0,0 +1,207 @@
+mod acl;
+mod scram;
+
+use crate::config::ConfigError;
+pub use acl::{ANONYMOUS, AclOperation, Authorizer};
+pub use scram::ScramExchange;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Iteration count used when deriving SCRAM credentials from the passwords in the credentials
+/// file; 4096 is the minimum RFC 7677 recommends.
+const SCRAM_ITERATIONS: u32 = 4096;
+
+pub type Principal = String;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaslMechanism {
+    Plain,
+    ScramSha256,
+}
+
+impl SaslMechanism {
+    pub const SUPPORTED: &'static [&'static str] = &["PLAIN", "SCRAM-SHA-256"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "PLAIN" => Some(SaslMechanism::Plain),
+            "SCRAM-SHA-256" => Some(SaslMechanism::ScramSha256),
+            _ => None,
+        }
+    }
+}
+
+/// Where a connection is in the SASL exchange.
+#[derive(Default)]
+pub enum SaslState {
+    #[default]
+    Idle,
+    MechanismSelected(SaslMechanism),
+    ScramClientFinal(Box<ScramExchange>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SaslError {
+    MalformedMessage(&'static str),
+    AuthenticationFailed,
+}
+
+impl std::fmt::Display for SaslError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaslError::MalformedMessage(reason) => write!(f, "Malformed SASL message: {reason}"),
+            SaslError::AuthenticationFailed => write!(f, "Authentication failed"),
+        }
+    }
+}
+
+/// Verifies SASL credentials against the users of the credentials file. Passwords are only kept
+/// as derived SCRAM keys, which both mechanisms are checked against.
+pub struct Authenticator {
+    users: HashMap<Principal, scram::Credential>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CredentialsFile {
+    #[serde(default)]
+    users: Vec<UserEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserEntry {
+    username: String,
+    password: String,
+}
+
+impl Authenticator {
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError(format!("Failed to read {}: {}", path.display(), e)))?;
+        Self::from_toml(&content)
+            .map_err(|e| ConfigError(format!("Failed to parse {}: {}", path.display(), e.0)))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        let file: CredentialsFile =
+            toml::from_str(content).map_err(|e| ConfigError(e.message().to_string()))?;
+        let mut users = HashMap::new();
+        for user in file.users {
+            if user.username.is_empty() {
+                return Err(ConfigError("username must not be empty".to_string()));
+            }
+            let credential = scram::Credential::generate(&user.password, SCRAM_ITERATIONS);
+            if users.insert(user.username.clone(), credential).is_some() {
+                return Err(ConfigError(format!(
+                    "user {} is defined more than once",
+                    user.username
+                )));
+            }
+        }
+        Ok(Authenticator { users })
+    }
+
+    /// Checks a PLAIN message, `[authzid] NUL authcid NUL passwd`, and returns the authenticated
+    /// user. Acting on behalf of another user is not supported.
+    pub fn authenticate_plain(&self, message: &[u8]) -> Result<Principal, SaslError> {
+        let message = std::str::from_utf8(message)
+            .map_err(|_| SaslError::MalformedMessage("message is not valid UTF-8"))?;
+        let mut parts = message.split('\0');
+        let (Some(authzid), Some(username), Some(password), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(SaslError::MalformedMessage(
+                "expected authzid, username and password",
+            ));
+        };
+        if !authzid.is_empty() && authzid != username {
+            return Err(SaslError::AuthenticationFailed);
+        }
+        let verified = match self.users.get(username) {
+            Some(credential) => credential.verify_password(password),
+            // Unknown users still get a password hashed, against a credential nobody can
+            // authenticate with, so that the time taken doesn't reveal which users exist.
+            None => {
+                let credential = scram::Credential::unknown(username, SCRAM_ITERATIONS);
+                let _ = credential.verify_password(password);
+                false
+            }
+        };
+        if !verified {
+            return Err(SaslError::AuthenticationFailed);
+        }
+        Ok(username.to_string())
+    }
+
+    /// Handles the SCRAM client-first message, returning the exchange to finish with the
+    /// client-final message and the server-first message to send back.
+    pub fn start_scram(&self, client_first: &[u8]) -> Result<(ScramExchange, Vec<u8>), SaslError> {
+        ScramExchange::start(client_first, |username| self.users.get(username).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticator() -> Authenticator {
+        Authenticator::from_toml(
+            r#"
+            [[users]]
+            username = "alice"
+            password = "alice-secret"
+            "#,
+        )
+        .expect("Failed to parse credentials")
+    }
+
+    #[test]
+    fn authenticates_plain_credentials() {
+        let authenticator = authenticator();
+        assert_eq!(
+            authenticator.authenticate_plain(b"\0alice\0alice-secret"),
+            Ok("alice".to_string())
+        );
+        assert_eq!(
+            authenticator.authenticate_plain(b"alice\0alice\0alice-secret"),
+            Ok("alice".to_string())
+        );
+        assert_eq!(
+            authenticator.authenticate_plain(b"\0alice\0wrong"),
+            Err(SaslError::AuthenticationFailed)
+        );
+        assert_eq!(
+            authenticator.authenticate_plain(b"\0bob\0alice-secret"),
+            Err(SaslError::AuthenticationFailed)
+        );
+        assert_eq!(
+            authenticator.authenticate_plain(b"bob\0alice\0alice-secret"),
+            Err(SaslError::AuthenticationFailed)
+        );
+        assert!(matches!(
+            authenticator.authenticate_plain(b"alice-secret"),
+            Err(SaslError::MalformedMessage(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_credentials_files() {
+        assert!(Authenticator::from_toml("[[users]]\nusername = \"alice\"").is_err());
+        assert!(
+            Authenticator::from_toml(
+                r#"
+                [[users]]
+                username = "alice"
+                password = "a"
+
+                [[users]]
+                username = "alice"
+                password = "b"
+                "#
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Server side of SCRAM-SHA-256 (RFC 5802, RFC 7677) without channel binding.
+
+use super::{Principal, SCRAM_ITERATIONS, SaslError};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, hmac, pbkdf2};
+use std::num::NonZeroU32;
+use std::sync::LazyLock;
+use subtle::ConstantTimeEq;
+
+const KEY_LEN: usize = digest::SHA256_OUTPUT_LEN;
+const SALT_LEN: usize = 16;
+const SERVER_NONCE_LEN: usize = 18;
+
+/// Secret the salts of unknown users are derived from, so that they stay the same across
+/// attempts like those of real users do, yet can't be told apart from random ones.
+static UNKNOWN_SALT_SECRET: LazyLock<[u8; KEY_LEN]> = LazyLock::new(|| {
+    let mut secret = [0; KEY_LEN];
+    secret.copy_from_slice(&random_bytes(KEY_LEN));
+    secret
+});
+
+/// The keys a user is verified with, derived once from their password.
+#[derive(Clone)]
+pub struct Credential {
+    salt: Vec<u8>,
+    iterations: u32,
+    stored_key: [u8; KEY_LEN],
+    server_key: [u8; KEY_LEN],
+}
+
+impl Credential {
+    pub fn generate(password: &str, iterations: u32) -> Self {
+        Self::derive(password, random_bytes(SALT_LEN), iterations)
+    }
+
+    fn derive(password: &str, salt: Vec<u8>, iterations: u32) -> Self {
+        let salted_password = salt_password(password, &salt, iterations);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        let server_key = hmac_sha256(&salted_password, b"Server Key");
+        Credential {
+            stored_key: sha256(&client_key),
+            server_key,
+            salt,
+            iterations,
+        }
+    }
+
+    /// A credential nobody can authenticate with, used for unknown users so that the exchange
+    /// does not reveal which users exist.
+    pub(super) fn unknown(username: &str, iterations: u32) -> Self {
+        let mut stored_key = [0; KEY_LEN];
+        let mut server_key = [0; KEY_LEN];
+        stored_key.copy_from_slice(&random_bytes(KEY_LEN));
+        server_key.copy_from_slice(&random_bytes(KEY_LEN));
+        Credential {
+            salt: hmac_sha256(&*UNKNOWN_SALT_SECRET, username.as_bytes())[..SALT_LEN].to_vec(),
+            iterations,
+            stored_key,
+            server_key,
+        }
+    }
+
+    pub fn verify_password(&self, password: &str) -> bool {
+        let salted_password = salt_password(password, &self.salt, self.iterations);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        bool::from(sha256(&client_key).ct_eq(&self.stored_key))
+    }
+}
+
+/// An exchange that has answered the client-first message and waits for the client-final one.
+pub struct ScramExchange {
+    username: Principal,
+    credential: Credential,
+    gs2_header: String,
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+}
+
+impl ScramExchange {
+    pub fn start<F>(client_first: &[u8], lookup: F) -> Result<(Self, Vec<u8>), SaslError>
+    where
+        F: FnOnce(&str) -> Option<Credential>,
+    {
+        let server_nonce = BASE64.encode(random_bytes(SERVER_NONCE_LEN));
+        Self::start_with_nonce(client_first, lookup, &server_nonce)
+    }
+
+    fn start_with_nonce<F>(
+        client_first: &[u8],
+        lookup: F,
+        server_nonce: &str,
+    ) -> Result<(Self, Vec<u8>), SaslError>
+    where
+        F: FnOnce(&str) -> Option<Credential>,
+    {
+        let client_first = std::str::from_utf8(client_first)
+            .map_err(|_| SaslError::MalformedMessage("message is not valid UTF-8"))?;
+        let (gs2_header, client_first_bare) = split_gs2_header(client_first)?;
+
+        let mut attributes = client_first_bare.split(',');
+        let username = attributes
+            .next()
+            .and_then(|attribute| attribute.strip_prefix("n="))
+            .ok_or(SaslError::MalformedMessage("expected username"))
+            .and_then(decode_username)?;
+        let client_nonce = attributes
+            .next()
+            .and_then(|attribute| attribute.strip_prefix("r="))
+            .filter(|nonce| !nonce.is_empty())
+            .ok_or(SaslError::MalformedMessage("expected client nonce"))?;
+
+        let authzid = gs2_header
+            .trim_end_matches(',')
+            .split_once(',')
+            .map(|(_, authzid)| authzid)
+            .unwrap_or_default();
+        if let Some(authzid) = authzid.strip_prefix("a=")
+            && decode_username(authzid)? != username
+        {
+            return Err(SaslError::AuthenticationFailed);
+        }
+
+        let credential =
+            lookup(&username).unwrap_or_else(|| Credential::unknown(&username, SCRAM_ITERATIONS));
+        let nonce = format!("{client_nonce}{server_nonce}");
+        let server_first = format!(
+            "r={},s={},i={}",
+            nonce,
+            BASE64.encode(&credential.salt),
+            credential.iterations
+        );
+        let exchange = ScramExchange {
+            username,
+            credential,
+            gs2_header: gs2_header.to_string(),
+            client_first_bare: client_first_bare.to_string(),
+            server_first: server_first.clone(),
+            nonce,
+        };
+        Ok((exchange, server_first.into_bytes()))
+    }
+
+    /// Verifies the client proof of the client-final message, returning the authenticated user
+    /// and the server-final message that proves the server knows the credential too.
+    pub fn finish(self, client_final: &[u8]) -> Result<(Principal, Vec<u8>), SaslError> {
+        let client_final = std::str::from_utf8(client_final)
+            .map_err(|_| SaslError::MalformedMessage("message is not valid UTF-8"))?;
+        let (without_proof, proof) = client_final
+            .rsplit_once(",p=")
+            .ok_or(SaslError::MalformedMessage("expected client proof"))?;
+
+        let mut attributes = without_proof.split(',');
+        let channel_binding = attributes
+            .next()
+            .and_then(|attribute| attribute.strip_prefix("c="))
+            .and_then(|value| BASE64.decode(value).ok())
+            .ok_or(SaslError::MalformedMessage("expected channel binding"))?;
+        if channel_binding != self.gs2_header.as_bytes() {
+            return Err(SaslError::MalformedMessage("channel binding mismatch"));
+        }
+        let nonce = attributes
+            .next()
+            .and_then(|attribute| attribute.strip_prefix("r="))
+            .ok_or(SaslError::MalformedMessage("expected nonce"))?;
+        if nonce != self.nonce {
+            return Err(SaslError::AuthenticationFailed);
+        }
+        let proof = BASE64
+            .decode(proof)
+            .ok()
+            .filter(|proof| proof.len() == KEY_LEN)
+            .ok_or(SaslError::MalformedMessage("invalid client proof"))?;
+
+        let auth_message = format!(
+            "{},{},{}",
+            self.client_first_bare, self.server_first, without_proof
+        );
+        let client_signature = hmac_sha256(&self.credential.stored_key, auth_message.as_bytes());
+        let mut client_key = [0; KEY_LEN];
+        for (i, byte) in client_key.iter_mut().enumerate() {
+            *byte = proof[i] ^ client_signature[i];
+        }
+        if !bool::from(sha256(&client_key).ct_eq(&self.credential.stored_key)) {
+            return Err(SaslError::AuthenticationFailed);
+        }
+
+        let server_signature = hmac_sha256(&self.credential.server_key, auth_message.as_bytes());
+        let server_final = format!("v={}", BASE64.encode(server_signature));
+        Ok((self.username, server_final.into_bytes()))
+    }
+}
+
+/// Splits `n,[a=authzid],client-first-bare` into the GS2 header (including its trailing comma)
+/// and the bare message.
+fn split_gs2_header(message: &str) -> Result<(&str, &str), SaslError> {
+    match message.as_bytes().first() {
+        Some(b'n') | Some(b'y') => {}
+        Some(b'p') => return Err(SaslError::MalformedMessage("channel binding not supported")),
+        _ => return Err(SaslError::MalformedMessage("expected GS2 header")),
+    }
+    let header_len = message
+        .match_indices(',')
+        .nth(1)
+        .map(|(index, _)| index + 1)
+        .ok_or(SaslError::MalformedMessage("expected GS2 header"))?;
+    Ok(message.split_at(header_len))
+}
+
+/// Reverses the `=2C`/`=3D` escaping of `,` and `=` in SCRAM usernames.
+fn decode_username(value: &str) -> Result<Principal, SaslError> {
+    let mut username = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('=') {
+        username.push_str(&rest[..index]);
+        match rest.get(index..index + 3) {
+            Some("=2C") => username.push(','),
+            Some("=3D") => username.push('='),
+            _ => return Err(SaslError::MalformedMessage("invalid username escape")),
+        }
+        rest = &rest[index + 3..];
+    }
+    username.push_str(rest);
+    Ok(username)
+}
+
+fn salt_password(password: &str, salt: &[u8], iterations: u32) -> [u8; KEY_LEN] {
+    let iterations = NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN);
+    let mut salted_password = [0; KEY_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        password.as_bytes(),
+        &mut salted_password,
+    );
+    salted_password
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; KEY_LEN] {
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), message);
+    let mut output = [0; KEY_LEN];
+    output.copy_from_slice(tag.as_ref());
+    output
+}
+
+fn sha256(data: &[u8]) -> [u8; KEY_LEN] {
+    let mut output = [0; KEY_LEN];
+    output.copy_from_slice(digest::digest(&digest::SHA256, data).as_ref());
+    output
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("System random number generator failed");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Example exchange from RFC 7677, section 3.
+    const SALT: &str = "W22ZaJ0SNY7soEsUEjb6gQ==";
+    const CLIENT_FIRST: &[u8] = b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO";
+    const SERVER_NONCE: &str = "%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0";
+    const SERVER_FIRST: &[u8] =
+        b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+    const CLIENT_FINAL: &[u8] = b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+    const SERVER_FINAL: &[u8] = b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+
+    fn rfc_credential(username: &str) -> Option<Credential> {
+        (username == "user")
+            .then(|| Credential::derive("pencil", BASE64.decode(SALT).unwrap(), 4096))
+    }
+
+    #[test]
+    fn completes_rfc_7677_example_exchange() {
+        let (exchange, server_first) =
+            ScramExchange::start_with_nonce(CLIENT_FIRST, rfc_credential, SERVER_NONCE).unwrap();
+        assert_eq!(server_first, SERVER_FIRST);
+
+        let (username, server_final) = exchange.finish(CLIENT_FINAL).unwrap();
+        assert_eq!(username, "user");
+        assert_eq!(server_final, SERVER_FINAL);
+    }
+
+    #[test]
+    fn rejects_wrong_proof_and_unknown_users() {
+        let (exchange, _) =
+            ScramExchange::start_with_nonce(CLIENT_FIRST, rfc_credential, SERVER_NONCE).unwrap();
+        let tampered = String::from_utf8(CLIENT_FINAL.to_vec())
+            .unwrap()
+            .replace("p=dHzb", "p=eHzb");
+        assert_eq!(
+            exchange.finish(tampered.as_bytes()).err(),
+            Some(SaslError::AuthenticationFailed)
+        );
+
+        let client_first = b"n,,n=mallory,r=rOprNGfwEbeRWgbNEkqO";
+        let (exchange, _) =
+            ScramExchange::start_with_nonce(client_first, rfc_credential, SERVER_NONCE).unwrap();
+        assert_eq!(
+            exchange.finish(CLIENT_FINAL).err(),
+            Some(SaslError::AuthenticationFailed)
+        );
+    }
+
+    #[test]
+    fn gives_unknown_users_the_same_salt_on_every_attempt() {
+        let salt = |client_first: &[u8]| {
+            let (_, server_first) = ScramExchange::start(client_first, rfc_credential).unwrap();
+            let server_first = String::from_utf8(server_first).unwrap();
+            server_first.split(',').nth(1).unwrap().to_string()
+        };
+
+        let mallory = salt(b"n,,n=mallory,r=abc");
+        assert_eq!(mallory, salt(b"n,,n=mallory,r=def"));
+        assert_ne!(mallory, salt(b"n,,n=trudy,r=abc"));
+    }
+
+    #[test]
+    fn rejects_malformed_client_first_messages() {
+        for message in [
+            &b"p=tls-unique,,n=user,r=abc"[..],
+            b"n,,r=abc",
+            b"n,,n=user",
+            b"n,,n=us=er,r=abc",
+            b"n=user,r=abc",
+        ] {
+            assert!(
+                ScramExchange::start(message, rfc_credential).is_err(),
+                "Expected {:?} to be rejected",
+                String::from_utf8_lossy(message)
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_escaped_usernames() {
+        assert_eq!(decode_username("a=2Cb=3Dc").unwrap(), "a,b=c");
+    }
+}
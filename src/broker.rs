@@ -1,53 +1,196 @@
+use crate::compression::Compression;
+use crate::config::BrokerConfig;
+use crate::metrics::BrokerMetrics;
+use crate::session::ClientRegistry;
 use crate::topic::{
-    ClientId, Subscription, Topic, TopicManager, TopicName, TopicPublishError, TopicPublisher,
-    TopicSubscribeError, TopicSubscriber,
+    ClientId, IsolationLevel, MessageRecord, Subscription, Topic, TopicAddError, TopicDescription,
+    TopicManager, TopicName, TopicPublishError, TopicPublisher, TopicSubscribeError,
+    TopicSubscriber, TopicTransactionCoordinator, TopicTransactionError, TransactionId,
 };
-use std::collections::HashMap;
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock, watch};
+use tokio::time::Instant;
 
-#[derive(Default)]
 pub struct Broker {
-    topics: RwLock<HashMap<TopicName, Arc<RwLock<Topic>>>>,
+    topics: RwLock<HashMap<TopicName, Arc<Topic>>>,
+    topics_added: watch::Sender<()>,
+    transactions: Mutex<HashMap<TransactionId, Arc<Mutex<OpenTransaction>>>>,
+    clients: Mutex<HashSet<ClientId>>,
+    default_retention: u64,
+    max_retention: u64,
+    max_payload_size: usize,
+    transaction_timeout: Duration,
+    metrics: Arc<BrokerMetrics>,
+}
+
+/// Topics written by a transaction, ordered by name so they are always locked in the same order.
+type TransactionTopics = BTreeMap<TopicName, Arc<Topic>>;
+
+/// Each transaction is locked on its own, so appends of different transactions don't wait for
+/// each other; the map of transactions is only locked to look them up.
+struct OpenTransaction {
+    topics: TransactionTopics,
+    /// The transaction is aborted if it is still open by then, so that a client that went away
+    /// without finishing it doesn't hold back read-committed subscribers forever.
+    expires_at: Instant,
+    /// Set once the transaction is committed or aborted, for appends that looked it up before
+    /// it was removed from the map.
+    finished: bool,
+}
+
+impl Broker {
+    pub fn new(config: &BrokerConfig) -> Self {
+        Self {
+            topics: RwLock::new(HashMap::new()),
+            topics_added: watch::channel(()).0,
+            transactions: Mutex::new(HashMap::new()),
+            clients: Mutex::new(HashSet::new()),
+            default_retention: config.default_retention,
+            max_retention: config.max_retention,
+            max_payload_size: config.max_payload_size,
+            transaction_timeout: config.transaction_timeout,
+            metrics: Arc::new(BrokerMetrics::new()),
+        }
+    }
+
+    pub fn metrics(&self) -> &Arc<BrokerMetrics> {
+        &self.metrics
+    }
+
+    /// Largest payload, in bytes, a single publish may carry.
+    pub fn max_payload_size(&self) -> usize {
+        self.max_payload_size
+    }
+
+    fn check_payload_size(&self, message_payload: &Bytes) -> Result<(), TopicPublishError> {
+        if message_payload.len() > self.max_payload_size {
+            return Err(TopicPublishError::PayloadTooLarge {
+                size: message_payload.len(),
+                limit: self.max_payload_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl ClientRegistry for Broker {
+    async fn register_client(&self, client_id: ClientId) -> bool {
+        self.clients.lock().await.insert(client_id)
+    }
+
+    async fn deregister_client(&self, client_id: ClientId) {
+        self.clients.lock().await.remove(&client_id);
+    }
 }
 
 impl TopicManager for Broker {
-    async fn add_topic(&self, topic_name: &TopicName, retention: u64) -> bool {
+    async fn add_topic(
+        &self,
+        topic_name: &TopicName,
+        retention: Option<u64>,
+        compression: Option<Compression>,
+    ) -> Result<(), TopicAddError> {
+        if topic_name.is_empty() {
+            return Err(TopicAddError::EmptyName);
+        }
+        let retention = retention.unwrap_or(self.default_retention);
+        if retention == 0 || retention > self.max_retention {
+            return Err(TopicAddError::InvalidRetention {
+                retention,
+                limit: self.max_retention,
+            });
+        }
         {
             let mut topics = self.topics.write().await;
             if topics.contains_key(topic_name) {
-                return false;
+                return Err(TopicAddError::AlreadyExists(topic_name.clone()));
             }
-            let topic = Arc::new(RwLock::new(Topic::new(topic_name, retention)));
+            let topic = Topic::new(topic_name, retention, compression)
+                .with_metrics(self.metrics.topic(topic_name));
+            let topic = Arc::new(topic);
             topics.insert(topic_name.clone(), topic);
         }
-        true
+        self.topics_added.send_replace(());
+        Ok(())
     }
 
     async fn delete_topic(&self, topic_name: &TopicName) -> bool {
         let mut topics = self.topics.write().await;
-        topics.remove(topic_name).is_some()
+        match topics.remove(topic_name) {
+            Some(topic) => {
+                topic.close();
+                self.metrics.remove_topic(topic_name);
+                true
+            }
+            None => false,
+        }
     }
 
     async fn list_topics(&self) -> Vec<TopicName> {
         self.topics.read().await.keys().cloned().collect()
     }
+
+    async fn describe_topic(&self, topic_name: &TopicName) -> Option<TopicDescription> {
+        let topic = self.topics.read().await.get(topic_name).cloned()?;
+        Some(topic.describe())
+    }
+
+    async fn topic_exists(&self, topic_name: &TopicName) -> bool {
+        self.topics.read().await.contains_key(topic_name)
+    }
+
+    fn topics_added(&self) -> watch::Receiver<()> {
+        self.topics_added.subscribe()
+    }
 }
 
 impl TopicPublisher for Broker {
     async fn publish(
         &self,
         topic_name: &TopicName,
-        message_payload: Vec<u8>,
-    ) -> Result<(), TopicPublishError> {
+        message_payload: Bytes,
+        compression: Compression,
+        traceparent: Option<String>,
+    ) -> Result<u64, TopicPublishError> {
+        self.check_payload_size(&message_payload)?;
         let topic = {
             let topics = self.topics.read().await;
             topics.get(topic_name).cloned()
         };
         let topic = topic.ok_or(TopicPublishError::TopicNotFound(topic_name.to_string()))?;
-        let mut topic_guard = topic.write().await;
-        topic_guard.publish(message_payload);
-        Ok(())
+        let (message_payload, compression) = topic
+            .encode_payload(message_payload, compression, self.max_payload_size)
+            .map_err(|e| TopicPublishError::InvalidPayload(topic_name.to_string(), e))?;
+        Ok(topic.publish(message_payload, compression, traceparent))
+    }
+
+    async fn publish_batch(
+        &self,
+        topic_name: &TopicName,
+        message_payloads: Vec<Bytes>,
+        compression: Compression,
+        traceparent: Option<String>,
+    ) -> Result<u64, TopicPublishError> {
+        for message_payload in &message_payloads {
+            self.check_payload_size(message_payload)?;
+        }
+        let topic = {
+            let topics = self.topics.read().await;
+            topics.get(topic_name).cloned()
+        };
+        let topic = topic.ok_or(TopicPublishError::TopicNotFound(topic_name.to_string()))?;
+        let message_payloads = message_payloads
+            .into_iter()
+            .map(|message_payload| {
+                topic
+                    .encode_payload(message_payload, compression, self.max_payload_size)
+                    .map_err(|e| TopicPublishError::InvalidPayload(topic_name.to_string(), e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(topic.publish_batch(message_payloads, traceparent))
     }
 }
 
@@ -56,6 +199,7 @@ impl TopicSubscriber for Broker {
         &self,
         topic_name: &TopicName,
         from_offset: Option<u64>,
+        isolation_level: IsolationLevel,
         client_id: ClientId,
     ) -> Result<Subscription, TopicSubscribeError> {
         let topic = {
@@ -63,8 +207,7 @@ impl TopicSubscriber for Broker {
             topics.get(topic_name).cloned()
         };
         let topic = topic.ok_or(TopicSubscribeError::TopicNotFound(topic_name.to_string()))?;
-        let mut topic_guard = topic.write().await;
-        let subscription = topic_guard.subscribe(client_id, from_offset);
+        let subscription = topic.subscribe(client_id, from_offset, isolation_level);
         Ok(subscription)
     }
 
@@ -78,8 +221,185 @@ impl TopicSubscriber for Broker {
             topics.get(topic_name).cloned()
         };
         let topic = topic.ok_or(TopicSubscribeError::TopicNotFound(topic_name.to_string()))?;
-        let mut topic_guard = topic.write().await;
-        topic_guard.unsubscribe(client_id);
+        topic.unsubscribe(client_id);
         Ok(())
     }
+
+    async fn fetch(
+        &self,
+        topic_name: &TopicName,
+        from_offset: u64,
+        isolation_level: IsolationLevel,
+        max: usize,
+    ) -> Result<Vec<MessageRecord>, TopicSubscribeError> {
+        let topic = {
+            let topics = self.topics.read().await;
+            topics.get(topic_name).cloned()
+        };
+        let topic = topic.ok_or(TopicSubscribeError::TopicNotFound(topic_name.to_string()))?;
+        Ok(topic.fetch(from_offset, isolation_level, max))
+    }
+
+    async fn appended(
+        &self,
+        topic_name: &TopicName,
+    ) -> Result<watch::Receiver<()>, TopicSubscribeError> {
+        let topic = {
+            let topics = self.topics.read().await;
+            topics.get(topic_name).cloned()
+        };
+        let topic = topic.ok_or(TopicSubscribeError::TopicNotFound(topic_name.to_string()))?;
+        Ok(topic.appended())
+    }
+}
+
+impl TopicTransactionCoordinator for Broker {
+    async fn begin_transaction(&self) -> TransactionId {
+        let transaction_id = TransactionId::new_v4();
+        let mut transactions = self.transactions.lock().await;
+        let transaction = OpenTransaction {
+            topics: BTreeMap::new(),
+            expires_at: Instant::now() + self.transaction_timeout,
+            finished: false,
+        };
+        transactions.insert(transaction_id, Arc::new(Mutex::new(transaction)));
+        transaction_id
+    }
+
+    async fn publish_in_transaction(
+        &self,
+        transaction_id: TransactionId,
+        topic_name: &TopicName,
+        message_payload: Bytes,
+        compression: Compression,
+        traceparent: Option<String>,
+    ) -> Result<(), TopicPublishError> {
+        self.check_payload_size(&message_payload)?;
+        let topic = {
+            let topics = self.topics.read().await;
+            topics.get(topic_name).cloned()
+        };
+        let topic = topic.ok_or(TopicPublishError::TopicNotFound(topic_name.to_string()))?;
+        let (message_payload, compression) = topic
+            .encode_payload(message_payload, compression, self.max_payload_size)
+            .map_err(|e| TopicPublishError::InvalidPayload(topic_name.to_string(), e))?;
+
+        let transaction = {
+            let transactions = self.transactions.lock().await;
+            transactions.get(&transaction_id).cloned()
+        };
+        let transaction =
+            transaction.ok_or(TopicPublishError::TransactionNotFound(transaction_id))?;
+        // Held across the append, so the transaction can't be committed or aborted in between.
+        let mut transaction = transaction.lock().await;
+        if transaction.finished {
+            return Err(TopicPublishError::TransactionNotFound(transaction_id));
+        }
+        if transaction.expires_at <= Instant::now() {
+            drop(transaction);
+            if let Some(transaction) = self.remove_transaction(transaction_id).await {
+                self.expire_transaction(transaction_id, transaction).await;
+            }
+            return Err(TopicPublishError::TransactionNotFound(transaction_id));
+        }
+        // A topic deleted and created again under the same name since the transaction first
+        // wrote to it is a different topic, which the transaction would only partly commit to.
+        let transaction_topic = transaction
+            .topics
+            .entry(topic_name.clone())
+            .or_insert_with(|| Arc::clone(&topic));
+        if !Arc::ptr_eq(transaction_topic, &topic) {
+            return Err(TopicPublishError::TopicRecreated(topic_name.clone()));
+        }
+        topic.publish_in_transaction(transaction_id, message_payload, compression, traceparent);
+        Ok(())
+    }
+
+    async fn commit_transaction(
+        &self,
+        transaction_id: TransactionId,
+    ) -> Result<(), TopicTransactionError> {
+        let transaction_topics = self.take_transaction(transaction_id).await?;
+        Topic::commit_transaction(
+            transaction_topics.values().map(|topic| topic.as_ref()),
+            transaction_id,
+        );
+        Ok(())
+    }
+
+    async fn abort_transaction(
+        &self,
+        transaction_id: TransactionId,
+    ) -> Result<(), TopicTransactionError> {
+        let transaction_topics = self.take_transaction(transaction_id).await?;
+        for topic in transaction_topics.values() {
+            topic.abort_transaction(transaction_id);
+        }
+        Ok(())
+    }
+}
+
+impl Broker {
+    /// Aborts every transaction that has been open for longer than the transaction timeout.
+    pub async fn abort_expired_transactions(&self) {
+        let now = Instant::now();
+        let open: Vec<_> = {
+            let transactions = self.transactions.lock().await;
+            transactions
+                .iter()
+                .map(|(transaction_id, transaction)| (*transaction_id, Arc::clone(transaction)))
+                .collect()
+        };
+        for (transaction_id, transaction) in open {
+            if transaction.lock().await.expires_at > now {
+                continue;
+            }
+            if let Some(transaction) = self.remove_transaction(transaction_id).await {
+                self.expire_transaction(transaction_id, transaction).await;
+            }
+        }
+    }
+
+    /// Removes a transaction to commit or abort it; expired ones are aborted instead and
+    /// reported as not found.
+    async fn take_transaction(
+        &self,
+        transaction_id: TransactionId,
+    ) -> Result<TransactionTopics, TopicTransactionError> {
+        let transaction = self
+            .remove_transaction(transaction_id)
+            .await
+            .ok_or(TopicTransactionError::TransactionNotFound(transaction_id))?;
+        let mut open_transaction = transaction.lock().await;
+        if open_transaction.expires_at <= Instant::now() {
+            drop(open_transaction);
+            self.expire_transaction(transaction_id, transaction).await;
+            return Err(TopicTransactionError::TransactionNotFound(transaction_id));
+        }
+        open_transaction.finished = true;
+        Ok(std::mem::take(&mut open_transaction.topics))
+    }
+
+    async fn remove_transaction(
+        &self,
+        transaction_id: TransactionId,
+    ) -> Option<Arc<Mutex<OpenTransaction>>> {
+        self.transactions.lock().await.remove(&transaction_id)
+    }
+
+    async fn expire_transaction(
+        &self,
+        transaction_id: TransactionId,
+        transaction: Arc<Mutex<OpenTransaction>>,
+    ) {
+        tracing::warn!(
+            "Aborting transaction {transaction_id} left open for longer than {:?}",
+            self.transaction_timeout
+        );
+        let mut transaction = transaction.lock().await;
+        transaction.finished = true;
+        for topic in transaction.topics.values() {
+            topic.abort_transaction(transaction_id);
+        }
+    }
 }
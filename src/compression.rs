@@ -0,0 +1,169 @@
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Codec a message payload is compressed with, numbered the same way as Kafka's batch attribute.
+#[derive(PartialEq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    pub fn compress(self, payload: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(payload.to_vec()),
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(payload)?;
+                encoder.finish()
+            }
+            Compression::Snappy => snap::raw::Encoder::new()
+                .compress_vec(payload)
+                .map_err(invalid_data),
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(payload)),
+            Compression::Zstd => zstd::encode_all(payload, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+
+    /// Decompresses a payload, failing with `InvalidData` rather than producing more than `limit`
+    /// bytes, so that a small payload can't expand to exhaust the broker's memory.
+    pub fn decompress(self, payload: &[u8], limit: usize) -> std::io::Result<Vec<u8>> {
+        let decompressed = match self {
+            Compression::None => payload.to_vec(),
+            Compression::Gzip => read_bounded(flate2::read::GzDecoder::new(payload), limit)?,
+            Compression::Snappy => {
+                let len = snap::raw::decompress_len(payload).map_err(invalid_data)?;
+                check_limit(len, limit)?;
+                snap::raw::Decoder::new()
+                    .decompress_vec(payload)
+                    .map_err(invalid_data)?
+            }
+            Compression::Lz4 => {
+                // The size is prepended as a little-endian u32 and allocated up front.
+                let len = payload
+                    .first_chunk::<4>()
+                    .map(|len| u32::from_le_bytes(*len) as usize)
+                    .ok_or_else(|| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "Payload too short for its size prefix",
+                        )
+                    })?;
+                check_limit(len, limit)?;
+                lz4_flex::decompress_size_prepended(payload).map_err(invalid_data)?
+            }
+            Compression::Zstd => read_bounded(zstd::stream::read::Decoder::new(payload)?, limit)?,
+        };
+        check_limit(decompressed.len(), limit)?;
+        Ok(decompressed)
+    }
+
+    /// Converts a payload compressed with `self` into one compressed with `target`, decompressing
+    /// it to at most `limit` bytes on the way.
+    pub fn recompress(
+        self,
+        payload: Bytes,
+        target: Compression,
+        limit: usize,
+    ) -> std::io::Result<Bytes> {
+        if self == target {
+            return Ok(payload);
+        }
+        let decompressed = self.decompress(&payload, limit)?;
+        Ok(Bytes::from(target.compress(&decompressed)?))
+    }
+}
+
+/// Reads at most one byte more than `limit`, enough to tell that the limit was exceeded.
+pub(crate) fn read_bounded<R: Read>(reader: R, limit: usize) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+fn check_limit(len: usize, limit: usize) -> std::io::Result<()> {
+    if len > limit {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Decompressed payload exceeds the limit of {limit} bytes"),
+        ));
+    }
+    Ok(())
+}
+
+fn invalid_data<E: std::error::Error + Send + Sync + 'static>(e: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODECS: [Compression; 5] = [
+        Compression::None,
+        Compression::Gzip,
+        Compression::Snappy,
+        Compression::Lz4,
+        Compression::Zstd,
+    ];
+
+    #[test]
+    fn decompresses_what_was_compressed_with_every_codec() {
+        let payload = br#"{"order_id": 1, "items": ["a", "b", "c"]}"#.repeat(16);
+        for codec in CODECS {
+            let compressed = codec.compress(&payload).expect("Failed to compress");
+            let decompressed = codec
+                .decompress(&compressed, payload.len())
+                .expect("Failed to decompress");
+            assert_eq!(payload, decompressed, "round trip failed for {:?}", codec);
+        }
+    }
+
+    #[test]
+    fn recompresses_payload_between_codecs() {
+        let payload = b"test-payload".repeat(16);
+        let gzipped = Bytes::from(Compression::Gzip.compress(&payload).unwrap());
+
+        let zstd = Compression::Gzip
+            .recompress(gzipped, Compression::Zstd, payload.len())
+            .expect("Failed to recompress");
+
+        assert_eq!(
+            Compression::Zstd.decompress(&zstd, payload.len()).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn fails_to_decompress_corrupted_payload() {
+        assert!(Compression::Gzip.decompress(b"not gzip", 1024).is_err());
+    }
+
+    #[test]
+    fn refuses_to_decompress_beyond_limit_with_every_codec() {
+        let payload = vec![0; 64 * 1024];
+        for codec in CODECS {
+            let compressed = codec.compress(&payload).expect("Failed to compress");
+            let error = codec
+                .decompress(&compressed, payload.len() - 1)
+                .unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{:?}", codec);
+        }
+    }
+
+    #[test]
+    fn refuses_lz4_payload_announcing_more_than_limit() {
+        let mut payload = u32::MAX.to_le_bytes().to_vec();
+        payload.extend_from_slice(b"tiny");
+        assert!(Compression::Lz4.decompress(&payload, 1024).is_err());
+    }
+}
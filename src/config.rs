@@ -1,15 +1,1121 @@
+use clap::{Args, Parser, ValueEnum};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+
+pub const DEFAULT_LISTENER_NAME: &str = "default";
+pub const DEFAULT_PORT: u16 = 9000;
+pub const DEFAULT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_RETENTION: u64 = 1000;
+pub const DEFAULT_MAX_RETENTION: u64 = 1_000_000;
+pub const DEFAULT_MAX_PAYLOAD_SIZE: usize = 1024 * 1024;
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(60);
 
 pub struct BrokerConfig {
+    pub listeners: Vec<ListenerConfig>,
+    /// Retention used for topics created without an explicit one.
+    pub default_retention: u64,
+    /// Largest retention, in number of messages, a topic may be created with.
+    pub max_retention: u64,
+    /// Largest payload, in bytes, a single publish request may carry.
+    pub max_payload_size: usize,
+    /// How long a transaction may stay open before the broker aborts it.
+    pub transaction_timeout: Duration,
+    /// When set, clients must authenticate with SASL against the users listed in this file.
+    pub credentials_file: Option<PathBuf>,
+    /// When set, requests are only served if a rule in this file allows them.
+    pub acl_file: Option<PathBuf>,
+    pub quotas: QuotaConfig,
+    /// Most client connections open at once, over all listeners.
+    pub max_connections: Option<usize>,
+    /// Most client connections open at once from a single source IP.
+    pub max_connections_per_ip: Option<usize>,
+    /// How long open connections get to finish their work once the broker is shutting down.
+    pub shutdown_timeout: Duration,
+    /// When set, the broker serves its HTTP endpoints, such as `/metrics`, on this address.
+    pub http_address: Option<SocketAddr>,
+    /// Whether the admin API for managing topics and listing connections is served on
+    /// `http_address` as well.
+    pub admin_api: bool,
+    /// Whether records can be published and read over HTTP on `http_address` as well.
+    pub rest_proxy: bool,
+    /// Whether browser clients can subscribe to topics over WebSockets on `http_address` as well.
+    pub websocket_gateway: bool,
+    pub logging: LoggingConfig,
+}
+
+/// How the broker writes its logs and exports its traces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    /// Most verbose level logged by targets the filter doesn't mention.
+    pub level: Level,
+    /// `RUST_LOG`-style directives, e.g. `kafkalite::server=debug,tokio=warn`, refining the level.
+    pub filter: Option<String>,
+    /// When set, spans of appends and deliveries are exported to this OTLP/HTTP traces endpoint.
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(ValueEnum, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            format: LogFormat::default(),
+            level: Level::INFO,
+            filter: None,
+            otlp_endpoint: None,
+        }
+    }
+}
+
+/// A single named socket the broker accepts client connections on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenerConfig {
+    pub name: String,
+    pub bind_address: IpAddr,
     pub port: u16,
+    /// When set, the listener accepts connections on this Unix domain socket instead of TCP.
+    pub unix_socket_path: Option<PathBuf>,
+    /// Time after which a connection without any traffic in either direction is closed.
     pub connection_timeout: Duration,
+    /// When set, time after which a connection is closed if the client sent no request, even
+    /// while it is still receiving messages.
+    pub session_timeout: Option<Duration>,
+    /// When set, connections are only accepted over TLS.
+    pub tls: Option<TlsConfig>,
+    pub protocol: ListenerProtocol,
+}
+
+/// Wire protocol the clients of a listener speak.
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerProtocol {
+    /// The broker's own protocol, with every feature it offers.
+    #[default]
+    Kafkalite,
+    /// The subset of the Apache Kafka protocol needed by producers, consumers and admin tools.
+    Kafka,
+    /// MQTT 3.1.1, for devices publishing to and subscribing to topics.
+    Mqtt,
+}
+
+/// PEM files a TLS listener presents and, for mutual TLS, verifies clients against.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// CA bundle client certificates must chain to; clients without one are rejected.
+    pub client_ca_path: Option<PathBuf>,
+}
+
+/// Rates clients are throttled at once they exceed them; each scope is enforced separately.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+    /// Applies to every client identity on its own.
+    pub client: QuotaLimits,
+    /// Applies to every topic on its own, summed over all clients.
+    pub topic: QuotaLimits,
+    /// Applies to the broker as a whole.
+    pub global: QuotaLimits,
+}
+
+/// Per-second limits of a quota scope; unset limits are not enforced.
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaLimits {
+    /// Payload bytes published per second.
+    pub produce_byte_rate: Option<u64>,
+    /// Payload bytes delivered to subscribers per second.
+    pub consume_byte_rate: Option<u64>,
+    /// Requests per second.
+    pub request_rate: Option<u64>,
+}
+
+impl QuotaConfig {
+    pub fn is_enabled(&self) -> bool {
+        *self != QuotaConfig::default()
+    }
+}
+
+impl QuotaLimits {
+    fn rates(&self) -> [Option<u64>; 3] {
+        [
+            self.produce_byte_rate,
+            self.consume_byte_rate,
+            self.request_rate,
+        ]
+    }
+}
+
+impl ListenerConfig {
+    pub fn new(name: &str, bind_address: IpAddr, port: u16, connection_timeout: Duration) -> Self {
+        ListenerConfig {
+            name: name.to_string(),
+            bind_address,
+            port,
+            unix_socket_path: None,
+            connection_timeout,
+            session_timeout: None,
+            tls: None,
+            protocol: ListenerProtocol::default(),
+        }
+    }
+
+    pub fn unix(name: &str, path: impl Into<PathBuf>, connection_timeout: Duration) -> Self {
+        ListenerConfig {
+            name: name.to_string(),
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 0,
+            unix_socket_path: Some(path.into()),
+            connection_timeout,
+            session_timeout: None,
+            tls: None,
+            protocol: ListenerProtocol::default(),
+        }
+    }
+
+    pub fn with_tls(self, tls: TlsConfig) -> Self {
+        ListenerConfig {
+            tls: Some(tls),
+            ..self
+        }
+    }
 }
 
 impl BrokerConfig {
+    /// A configuration with a single listener on all interfaces.
     pub fn new(port: u16, connection_timeout: Duration) -> Self {
         BrokerConfig {
-            port,
-            connection_timeout,
+            listeners: vec![ListenerConfig::new(
+                DEFAULT_LISTENER_NAME,
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                port,
+                connection_timeout,
+            )],
+            ..BrokerConfig::default()
+        }
+    }
+
+    /// Builds the configuration from (in increasing order of precedence) built-in defaults, the
+    /// TOML file given with `--config`/`KAFKALITE_CONFIG`, `KAFKALITE_*` environment variables and
+    /// command-line flags.
+    pub fn load() -> Result<Self, ConfigError> {
+        let cli = Cli::parse();
+        let file_settings = match &cli.config {
+            Some(path) => BrokerSettings::from_file(path)?,
+            None => BrokerSettings::default(),
+        };
+        file_settings.merge(cli.settings).into_config()
+    }
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        BrokerConfig {
+            listeners: vec![ListenerConfig::new(
+                DEFAULT_LISTENER_NAME,
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                DEFAULT_PORT,
+                DEFAULT_CONNECTION_TIMEOUT,
+            )],
+            default_retention: DEFAULT_RETENTION,
+            max_retention: DEFAULT_MAX_RETENTION,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+            transaction_timeout: DEFAULT_TRANSACTION_TIMEOUT,
+            credentials_file: None,
+            acl_file: None,
+            quotas: QuotaConfig::default(),
+            max_connections: None,
+            max_connections_per_ip: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            http_address: None,
+            admin_api: false,
+            rest_proxy: false,
+            websocket_gateway: false,
+            logging: LoggingConfig::default(),
         }
     }
 }
+
+#[derive(Parser)]
+#[command(name = "kafkalite", about = "A minimal Kafka-style message broker")]
+struct Cli {
+    /// Path to a TOML configuration file
+    #[arg(long, env = "KAFKALITE_CONFIG")]
+    config: Option<PathBuf>,
+
+    #[command(flatten)]
+    settings: BrokerSettings,
+}
+
+/// A partial configuration as read from a single source; unset values fall through to the next
+/// source with lower precedence.
+#[derive(Args, Deserialize, Default, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BrokerSettings {
+    /// Interface the broker listens on, unless listeners are configured explicitly
+    #[arg(long, env = "KAFKALITE_BIND_ADDRESS")]
+    pub bind_address: Option<IpAddr>,
+
+    /// Port the broker listens on, unless listeners are configured explicitly
+    #[arg(long, env = "KAFKALITE_PORT")]
+    pub port: Option<u16>,
+
+    /// Time after which a connection without any traffic is closed, in milliseconds
+    #[arg(long, env = "KAFKALITE_CONNECTION_TIMEOUT_MS")]
+    pub connection_timeout_ms: Option<u64>,
+
+    /// Time after which a connection is closed if the client sent no request, in milliseconds
+    #[arg(long, env = "KAFKALITE_SESSION_TIMEOUT_MS")]
+    pub session_timeout_ms: Option<u64>,
+
+    /// Named listener given as NAME=ADDRESS:PORT or NAME=unix:PATH, e.g. internal=127.0.0.1:9000,
+    /// external=[::]:9093 or local=unix:/run/kafkalite.sock; may be repeated
+    #[arg(
+        long = "listener",
+        env = "KAFKALITE_LISTENERS",
+        value_delimiter = ',',
+        value_parser = parse_listener
+    )]
+    pub listeners: Option<Vec<ListenerSettings>>,
+
+    /// Certificate chain PEM served over TLS, unless listeners are configured explicitly
+    #[arg(long, env = "KAFKALITE_TLS_CERT_PATH")]
+    pub tls_cert_path: Option<PathBuf>,
+
+    /// Private key PEM matching the TLS certificate
+    #[arg(long, env = "KAFKALITE_TLS_KEY_PATH")]
+    pub tls_key_path: Option<PathBuf>,
+
+    /// CA bundle PEM used to require and verify client certificates
+    #[arg(long, env = "KAFKALITE_TLS_CLIENT_CA_PATH")]
+    pub tls_client_ca_path: Option<PathBuf>,
+
+    /// Retention for topics created without an explicit one, in number of messages
+    #[arg(long, env = "KAFKALITE_DEFAULT_RETENTION")]
+    pub default_retention: Option<u64>,
+
+    /// Largest retention a topic may be created with, in number of messages
+    #[arg(long, env = "KAFKALITE_MAX_RETENTION")]
+    pub max_retention: Option<u64>,
+
+    /// Largest payload a single publish request may carry, in bytes
+    #[arg(long, env = "KAFKALITE_MAX_PAYLOAD_SIZE")]
+    pub max_payload_size: Option<usize>,
+
+    /// How long a transaction may stay open before it is aborted, in milliseconds
+    #[arg(long, env = "KAFKALITE_TRANSACTION_TIMEOUT_MS")]
+    pub transaction_timeout_ms: Option<u64>,
+
+    /// TOML file with the users allowed to connect; enables SASL authentication
+    #[arg(long, env = "KAFKALITE_CREDENTIALS_FILE")]
+    pub credentials_file: Option<PathBuf>,
+
+    /// TOML file with the operations each principal may perform on which topics
+    #[arg(long, env = "KAFKALITE_ACL_FILE")]
+    pub acl_file: Option<PathBuf>,
+
+    /// Most client connections open at once, over all listeners
+    #[arg(long, env = "KAFKALITE_MAX_CONNECTIONS")]
+    pub max_connections: Option<usize>,
+
+    /// Most client connections open at once from a single source IP
+    #[arg(long, env = "KAFKALITE_MAX_CONNECTIONS_PER_IP")]
+    pub max_connections_per_ip: Option<usize>,
+
+    /// How long open connections get to finish their work on shutdown, in milliseconds
+    #[arg(long, env = "KAFKALITE_SHUTDOWN_TIMEOUT_MS")]
+    pub shutdown_timeout_ms: Option<u64>,
+
+    /// Address the HTTP endpoints, such as /metrics, are served on; disabled when unset
+    #[arg(long, env = "KAFKALITE_HTTP_ADDRESS")]
+    pub http_address: Option<SocketAddr>,
+
+    /// Serve the admin API for managing topics and listing connections on the HTTP address
+    #[arg(
+        long,
+        env = "KAFKALITE_ADMIN_API",
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    pub admin_api: Option<bool>,
+
+    /// Serve the REST proxy for publishing and reading records on the HTTP address
+    #[arg(
+        long,
+        env = "KAFKALITE_REST_PROXY",
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    pub rest_proxy: Option<bool>,
+
+    /// Serve the WebSocket gateway for subscribing to topics on the HTTP address
+    #[arg(
+        long,
+        env = "KAFKALITE_WEBSOCKET_GATEWAY",
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    pub websocket_gateway: Option<bool>,
+
+    /// Format logs are written in
+    #[arg(long, env = "KAFKALITE_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+
+    /// Most verbose level logged: error, warn, info, debug or trace
+    #[arg(long, env = "KAFKALITE_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Per-target log directives refining the level, e.g. kafkalite::server=debug,tokio=warn
+    #[arg(long, env = "KAFKALITE_LOG_FILTER")]
+    pub log_filter: Option<String>,
+
+    /// OTLP/HTTP endpoint spans are exported to, e.g. http://localhost:4318/v1/traces
+    #[arg(long, env = "KAFKALITE_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Produce, consume and request rate quotas; only configurable in the TOML file
+    #[arg(skip)]
+    pub quotas: Option<QuotaConfig>,
+}
+
+/// Settings of a single listener; unset values are taken from the top-level settings.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerSettings {
+    pub name: String,
+    pub bind_address: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub unix_socket_path: Option<PathBuf>,
+    pub connection_timeout_ms: Option<u64>,
+    pub session_timeout_ms: Option<u64>,
+    pub tls: Option<TlsConfig>,
+    pub protocol: Option<ListenerProtocol>,
+}
+
+fn parse_listener(value: &str) -> Result<ListenerSettings, String> {
+    let (name, address) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected NAME=ADDRESS:PORT or NAME=unix:PATH, got {value}"))?;
+    if let Some(path) = address.strip_prefix("unix:") {
+        return Ok(ListenerSettings {
+            name: name.to_string(),
+            bind_address: None,
+            port: None,
+            unix_socket_path: Some(PathBuf::from(path)),
+            connection_timeout_ms: None,
+            session_timeout_ms: None,
+            tls: None,
+            protocol: None,
+        });
+    }
+    let address: SocketAddr = address
+        .parse()
+        .map_err(|e| format!("Invalid address of listener {name}: {e}"))?;
+    Ok(ListenerSettings {
+        name: name.to_string(),
+        bind_address: Some(address.ip()),
+        port: Some(address.port()),
+        unix_socket_path: None,
+        connection_timeout_ms: None,
+        session_timeout_ms: None,
+        tls: None,
+        protocol: None,
+    })
+}
+
+impl BrokerSettings {
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError(format!("Failed to read {}: {}", path.display(), e)))?;
+        Self::from_toml(&content)
+            .map_err(|e| ConfigError(format!("Failed to parse {}: {}", path.display(), e.0)))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        toml::from_str(content).map_err(|e| ConfigError(e.message().to_string()))
+    }
+
+    /// Layers `other` on top of `self`, keeping values from `self` only where `other` has none.
+    pub fn merge(self, other: BrokerSettings) -> BrokerSettings {
+        BrokerSettings {
+            bind_address: other.bind_address.or(self.bind_address),
+            port: other.port.or(self.port),
+            connection_timeout_ms: other.connection_timeout_ms.or(self.connection_timeout_ms),
+            session_timeout_ms: other.session_timeout_ms.or(self.session_timeout_ms),
+            listeners: other.listeners.or(self.listeners),
+            tls_cert_path: other.tls_cert_path.or(self.tls_cert_path),
+            tls_key_path: other.tls_key_path.or(self.tls_key_path),
+            tls_client_ca_path: other.tls_client_ca_path.or(self.tls_client_ca_path),
+            default_retention: other.default_retention.or(self.default_retention),
+            max_retention: other.max_retention.or(self.max_retention),
+            max_payload_size: other.max_payload_size.or(self.max_payload_size),
+            transaction_timeout_ms: other.transaction_timeout_ms.or(self.transaction_timeout_ms),
+            credentials_file: other.credentials_file.or(self.credentials_file),
+            acl_file: other.acl_file.or(self.acl_file),
+            max_connections: other.max_connections.or(self.max_connections),
+            max_connections_per_ip: other.max_connections_per_ip.or(self.max_connections_per_ip),
+            shutdown_timeout_ms: other.shutdown_timeout_ms.or(self.shutdown_timeout_ms),
+            http_address: other.http_address.or(self.http_address),
+            admin_api: other.admin_api.or(self.admin_api),
+            rest_proxy: other.rest_proxy.or(self.rest_proxy),
+            websocket_gateway: other.websocket_gateway.or(self.websocket_gateway),
+            log_format: other.log_format.or(self.log_format),
+            log_level: other.log_level.or(self.log_level),
+            log_filter: other.log_filter.or(self.log_filter),
+            otlp_endpoint: other.otlp_endpoint.or(self.otlp_endpoint),
+            quotas: other.quotas.or(self.quotas),
+        }
+    }
+
+    pub fn into_config(self) -> Result<BrokerConfig, ConfigError> {
+        let defaults = BrokerConfig::default();
+        let log_level = match &self.log_level {
+            Some(level) => level
+                .parse()
+                .map_err(|_| ConfigError(format!("invalid log_level {level}")))?,
+            None => defaults.logging.level,
+        };
+        let bind_address = self
+            .bind_address
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let port = self.port.unwrap_or(DEFAULT_PORT);
+        let connection_timeout_ms = self
+            .connection_timeout_ms
+            .unwrap_or(DEFAULT_CONNECTION_TIMEOUT.as_millis() as u64);
+        let default_tls = match (self.tls_cert_path, self.tls_key_path) {
+            (Some(cert_path), Some(key_path)) => Some(TlsConfig {
+                cert_path,
+                key_path,
+                client_ca_path: self.tls_client_ca_path,
+            }),
+            (None, None) if self.tls_client_ca_path.is_none() => None,
+            _ => {
+                return Err(ConfigError(
+                    "tls_cert_path and tls_key_path must be set together".to_string(),
+                ));
+            }
+        };
+        if default_tls.is_some() && self.listeners.is_some() {
+            return Err(ConfigError(
+                "tls_* settings only apply without explicit listeners, use [listeners.tls] instead"
+                    .to_string(),
+            ));
+        }
+        let listeners = match self.listeners {
+            Some(listeners) => listeners
+                .into_iter()
+                .map(|listener| {
+                    let connection_timeout = Duration::from_millis(
+                        listener
+                            .connection_timeout_ms
+                            .unwrap_or(connection_timeout_ms),
+                    );
+                    let config = match listener.unix_socket_path {
+                        Some(path) => {
+                            ListenerConfig::unix(&listener.name, path, connection_timeout)
+                        }
+                        None => ListenerConfig::new(
+                            &listener.name,
+                            listener.bind_address.unwrap_or(bind_address),
+                            listener.port.unwrap_or(port),
+                            connection_timeout,
+                        ),
+                    };
+                    ListenerConfig {
+                        session_timeout: listener
+                            .session_timeout_ms
+                            .or(self.session_timeout_ms)
+                            .map(Duration::from_millis),
+                        tls: listener.tls,
+                        protocol: listener.protocol.unwrap_or_default(),
+                        ..config
+                    }
+                })
+                .collect(),
+            None => vec![ListenerConfig {
+                session_timeout: self.session_timeout_ms.map(Duration::from_millis),
+                tls: default_tls,
+                ..ListenerConfig::new(
+                    DEFAULT_LISTENER_NAME,
+                    bind_address,
+                    port,
+                    Duration::from_millis(connection_timeout_ms),
+                )
+            }],
+        };
+        let config = BrokerConfig {
+            listeners,
+            default_retention: self.default_retention.unwrap_or(defaults.default_retention),
+            max_retention: self.max_retention.unwrap_or(defaults.max_retention),
+            max_payload_size: self.max_payload_size.unwrap_or(defaults.max_payload_size),
+            transaction_timeout: self
+                .transaction_timeout_ms
+                .map_or(defaults.transaction_timeout, Duration::from_millis),
+            credentials_file: self.credentials_file,
+            acl_file: self.acl_file,
+            quotas: self.quotas.unwrap_or_default(),
+            max_connections: self.max_connections,
+            max_connections_per_ip: self.max_connections_per_ip,
+            shutdown_timeout: self
+                .shutdown_timeout_ms
+                .map_or(defaults.shutdown_timeout, Duration::from_millis),
+            http_address: self.http_address,
+            admin_api: self.admin_api.unwrap_or(defaults.admin_api),
+            rest_proxy: self.rest_proxy.unwrap_or(defaults.rest_proxy),
+            websocket_gateway: self.websocket_gateway.unwrap_or(defaults.websocket_gateway),
+            logging: LoggingConfig {
+                format: self.log_format.unwrap_or_default(),
+                level: log_level,
+                filter: self.log_filter,
+                otlp_endpoint: self.otlp_endpoint,
+            },
+        };
+        validate(&config)?;
+        Ok(config)
+    }
+}
+
+fn validate(config: &BrokerConfig) -> Result<(), ConfigError> {
+    if config.listeners.is_empty() {
+        return Err(ConfigError("at least one listener is required".to_string()));
+    }
+    let mut names = HashSet::new();
+    let mut addresses = HashSet::new();
+    let mut socket_paths = HashSet::new();
+    for listener in config.listeners.iter() {
+        if listener.name.is_empty() {
+            return Err(ConfigError("listener name must not be empty".to_string()));
+        }
+        if !names.insert(listener.name.as_str()) {
+            return Err(ConfigError(format!(
+                "listener {} is defined more than once",
+                listener.name
+            )));
+        }
+        let address_taken = match &listener.unix_socket_path {
+            Some(path) => !socket_paths.insert(path),
+            None => listener.port != 0 && !addresses.insert((listener.bind_address, listener.port)),
+        };
+        if address_taken {
+            return Err(ConfigError(format!(
+                "listener {} uses an address already taken by another listener",
+                listener.name
+            )));
+        }
+        if listener.connection_timeout.is_zero() {
+            return Err(ConfigError(format!(
+                "connection_timeout_ms of listener {} must be greater than 0",
+                listener.name
+            )));
+        }
+        if listener
+            .session_timeout
+            .is_some_and(|timeout| timeout.is_zero())
+        {
+            return Err(ConfigError(format!(
+                "session_timeout_ms of listener {} must be greater than 0",
+                listener.name
+            )));
+        }
+        if listener.protocol == ListenerProtocol::Kafka {
+            if listener.unix_socket_path.is_some() {
+                return Err(ConfigError(format!(
+                    "kafka listener {} must listen on TCP",
+                    listener.name
+                )));
+            }
+            if config.credentials_file.is_some() {
+                return Err(ConfigError(format!(
+                    "kafka listener {} does not support authentication, unset credentials_file",
+                    listener.name
+                )));
+            }
+        }
+    }
+    if config.default_retention == 0 || config.default_retention > config.max_retention {
+        return Err(ConfigError(
+            "default_retention must be between 1 and max_retention".to_string(),
+        ));
+    }
+    if config.max_payload_size == 0 || config.max_payload_size > u32::MAX as usize {
+        return Err(ConfigError(format!(
+            "max_payload_size must be between 1 and {}",
+            u32::MAX
+        )));
+    }
+    if config.transaction_timeout.is_zero() {
+        return Err(ConfigError(
+            "transaction_timeout_ms must be greater than 0".to_string(),
+        ));
+    }
+    if config.max_connections == Some(0) || config.max_connections_per_ip == Some(0) {
+        return Err(ConfigError(
+            "max_connections and max_connections_per_ip must be greater than 0".to_string(),
+        ));
+    }
+    let quota_scopes = [
+        &config.quotas.client,
+        &config.quotas.topic,
+        &config.quotas.global,
+    ];
+    if quota_scopes
+        .iter()
+        .flat_map(|limits| limits.rates())
+        .any(|rate| rate == Some(0))
+    {
+        return Err(ConfigError(
+            "quota rates must be greater than 0".to_string(),
+        ));
+    }
+    if let Some(filter) = &config.logging.filter
+        && let Err(e) = EnvFilter::builder().parse(filter)
+    {
+        return Err(ConfigError(format!("invalid log_filter {filter}: {e}")));
+    }
+    if let Some(endpoint) = &config.logging.otlp_endpoint
+        && !endpoint.starts_with("http://")
+    {
+        return Err(ConfigError(format!(
+            "otlp_endpoint {endpoint} must be an http:// URL"
+        )));
+    }
+    if (config.admin_api || config.rest_proxy || config.websocket_gateway)
+        && config.http_address.is_none()
+    {
+        return Err(ConfigError(
+            "admin_api, rest_proxy and websocket_gateway require http_address to be set"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct ConfigError(pub(crate) String);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration: {}", self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_defaults_when_nothing_is_set() {
+        let config = BrokerSettings::default()
+            .into_config()
+            .expect("Invalid config");
+
+        assert_eq!(
+            config.listeners,
+            vec![ListenerConfig::new(
+                DEFAULT_LISTENER_NAME,
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                DEFAULT_PORT,
+                DEFAULT_CONNECTION_TIMEOUT
+            )]
+        );
+        assert_eq!(config.default_retention, DEFAULT_RETENTION);
+    }
+
+    #[test]
+    fn reads_settings_from_toml() {
+        let settings = BrokerSettings::from_toml(
+            r#"
+            bind_address = "127.0.0.1"
+            port = 9092
+            connection_timeout_ms = 2500
+            default_retention = 50
+            max_retention = 500
+            max_payload_size = 4096
+            transaction_timeout_ms = 30000
+            shutdown_timeout_ms = 2000
+            http_address = "127.0.0.1:9100"
+            admin_api = true
+            rest_proxy = true
+            websocket_gateway = true
+            log_format = "json"
+            log_level = "debug"
+            log_filter = "kafkalite::server=trace"
+            otlp_endpoint = "http://localhost:4318/v1/traces"
+            "#,
+        )
+        .expect("Failed to parse config");
+
+        let config = settings.into_config().expect("Invalid config");
+        assert_eq!(
+            config.listeners,
+            vec![ListenerConfig::new(
+                DEFAULT_LISTENER_NAME,
+                IpAddr::V4(Ipv4Addr::LOCALHOST),
+                9092,
+                Duration::from_millis(2500)
+            )]
+        );
+        assert_eq!(config.default_retention, 50);
+        assert_eq!(config.max_retention, 500);
+        assert_eq!(config.max_payload_size, 4096);
+        assert_eq!(config.transaction_timeout, Duration::from_secs(30));
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(2));
+        assert_eq!(config.http_address, Some("127.0.0.1:9100".parse().unwrap()));
+        assert!(config.admin_api);
+        assert!(config.rest_proxy);
+        assert!(config.websocket_gateway);
+        assert_eq!(
+            config.logging,
+            LoggingConfig {
+                format: LogFormat::Json,
+                level: Level::DEBUG,
+                filter: Some("kafkalite::server=trace".to_string()),
+                otlp_endpoint: Some("http://localhost:4318/v1/traces".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_named_listeners_from_toml() {
+        let settings = BrokerSettings::from_toml(
+            r#"
+            connection_timeout_ms = 2500
+
+            [[listeners]]
+            name = "internal"
+            bind_address = "127.0.0.1"
+            port = 9000
+
+            [[listeners]]
+            name = "external"
+            bind_address = "::"
+            port = 9093
+            connection_timeout_ms = 30000
+
+            [[listeners]]
+            name = "local"
+            unix_socket_path = "/run/kafkalite.sock"
+            "#,
+        )
+        .expect("Failed to parse config");
+
+        let config = settings.into_config().expect("Invalid config");
+        assert_eq!(
+            config.listeners,
+            vec![
+                ListenerConfig::new(
+                    "internal",
+                    IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    9000,
+                    Duration::from_millis(2500)
+                ),
+                ListenerConfig::new(
+                    "external",
+                    "::".parse().unwrap(),
+                    9093,
+                    Duration::from_millis(30000)
+                ),
+                ListenerConfig::unix("local", "/run/kafkalite.sock", Duration::from_millis(2500)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_listener_tls_from_toml() {
+        let settings = BrokerSettings::from_toml(
+            r#"
+            [[listeners]]
+            name = "plain"
+            port = 9000
+
+            [[listeners]]
+            name = "secure"
+            port = 9093
+            tls = { cert_path = "server.pem", key_path = "server.key", client_ca_path = "ca.pem" }
+            "#,
+        )
+        .expect("Failed to parse config");
+
+        let config = settings.into_config().expect("Invalid config");
+        assert_eq!(config.listeners[0].tls, None);
+        assert_eq!(
+            config.listeners[1].tls,
+            Some(TlsConfig {
+                cert_path: PathBuf::from("server.pem"),
+                key_path: PathBuf::from("server.key"),
+                client_ca_path: Some(PathBuf::from("ca.pem")),
+            })
+        );
+    }
+
+    #[test]
+    fn reads_listener_protocol_from_toml() {
+        let settings = BrokerSettings::from_toml(
+            r#"
+            [[listeners]]
+            name = "native"
+            port = 9000
+
+            [[listeners]]
+            name = "kafka"
+            port = 9092
+            protocol = "kafka"
+
+            [[listeners]]
+            name = "mqtt"
+            unix_socket_path = "/run/kafkalite-mqtt.sock"
+            protocol = "mqtt"
+            "#,
+        )
+        .expect("Failed to parse config");
+
+        let config = settings.into_config().expect("Invalid config");
+        assert_eq!(config.listeners[0].protocol, ListenerProtocol::Kafkalite);
+        assert_eq!(config.listeners[1].protocol, ListenerProtocol::Kafka);
+        assert_eq!(config.listeners[2].protocol, ListenerProtocol::Mqtt);
+
+        let settings = BrokerSettings::from_toml(
+            r#"
+            [[listeners]]
+            name = "kafka"
+            unix_socket_path = "/run/kafkalite.sock"
+            protocol = "kafka"
+            "#,
+        )
+        .expect("Failed to parse config");
+        assert!(settings.into_config().is_err());
+    }
+
+    #[test]
+    fn top_level_tls_settings_apply_to_default_listener() {
+        let settings = BrokerSettings {
+            tls_cert_path: Some(PathBuf::from("server.pem")),
+            tls_key_path: Some(PathBuf::from("server.key")),
+            ..BrokerSettings::default()
+        };
+        let config = settings.into_config().expect("Invalid config");
+        assert!(config.listeners[0].tls.is_some());
+
+        let settings = BrokerSettings {
+            tls_cert_path: Some(PathBuf::from("server.pem")),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+    }
+
+    #[test]
+    fn rejects_listeners_with_duplicated_names_or_addresses() {
+        let settings = BrokerSettings::from_toml(
+            r#"
+            [[listeners]]
+            name = "internal"
+            port = 9000
+
+            [[listeners]]
+            name = "internal"
+            port = 9001
+            "#,
+        )
+        .unwrap();
+        assert!(settings.into_config().is_err());
+
+        let settings = BrokerSettings::from_toml(
+            r#"
+            [[listeners]]
+            name = "internal"
+            port = 9000
+
+            [[listeners]]
+            name = "external"
+            port = 9000
+            "#,
+        )
+        .unwrap();
+        assert!(settings.into_config().is_err());
+    }
+
+    #[test]
+    fn reads_quotas_from_toml() {
+        let settings = BrokerSettings::from_toml(
+            r#"
+            [quotas.client]
+            produce_byte_rate = 1048576
+            request_rate = 100
+
+            [quotas.global]
+            consume_byte_rate = 10485760
+            "#,
+        )
+        .expect("Failed to parse config");
+
+        let config = settings.into_config().expect("Invalid config");
+        assert_eq!(
+            config.quotas,
+            QuotaConfig {
+                client: QuotaLimits {
+                    produce_byte_rate: Some(1048576),
+                    consume_byte_rate: None,
+                    request_rate: Some(100),
+                },
+                topic: QuotaLimits::default(),
+                global: QuotaLimits {
+                    consume_byte_rate: Some(10485760),
+                    ..QuotaLimits::default()
+                },
+            }
+        );
+
+        let settings = BrokerSettings::from_toml("[quotas.topic]\nrequest_rate = 0").unwrap();
+        assert!(settings.into_config().is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_toml_keys() {
+        assert!(BrokerSettings::from_toml("prot = 9092").is_err());
+    }
+
+    #[test]
+    fn later_sources_take_precedence_over_earlier_ones() {
+        let file = BrokerSettings {
+            port: Some(9001),
+            default_retention: Some(10),
+            ..BrokerSettings::default()
+        };
+        let flags = BrokerSettings {
+            port: Some(9002),
+            ..BrokerSettings::default()
+        };
+
+        let merged = file.merge(flags);
+        assert_eq!(merged.port, Some(9002));
+        assert_eq!(merged.default_retention, Some(10));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let settings = BrokerSettings {
+            connection_timeout_ms: Some(0),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+
+        let settings = BrokerSettings {
+            default_retention: Some(0),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+
+        let settings = BrokerSettings {
+            default_retention: Some(100),
+            max_retention: Some(10),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+
+        let settings = BrokerSettings {
+            max_connections_per_ip: Some(0),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+
+        let settings = BrokerSettings {
+            log_level: Some("loud".to_string()),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+
+        let settings = BrokerSettings {
+            log_filter: Some("kafkalite=chatty".to_string()),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+
+        let settings = BrokerSettings {
+            otlp_endpoint: Some("localhost:4318".to_string()),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+
+        let settings = BrokerSettings {
+            admin_api: Some(true),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+
+        let settings = BrokerSettings {
+            rest_proxy: Some(true),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+
+        let settings = BrokerSettings {
+            websocket_gateway: Some(true),
+            ..BrokerSettings::default()
+        };
+        assert!(settings.into_config().is_err());
+    }
+
+    #[test]
+    fn cli_listener_flags_are_parsed_into_settings() {
+        let cli = Cli::try_parse_from([
+            "kafkalite",
+            "--listener",
+            "internal=127.0.0.1:9000",
+            "--listener",
+            "external=[::]:9093",
+            "--listener",
+            "local=unix:/run/kafkalite.sock",
+        ])
+        .expect("Failed to parse flags");
+
+        let listeners = cli.settings.listeners.expect("Expected listeners");
+        assert_eq!(listeners.len(), 3);
+        assert_eq!(
+            listeners[2].unix_socket_path,
+            Some(PathBuf::from("/run/kafkalite.sock"))
+        );
+        assert_eq!(listeners[0].name, "internal");
+        assert_eq!(listeners[1].bind_address, Some("::".parse().unwrap()));
+        assert_eq!(listeners[1].port, Some(9093));
+        assert!(Cli::try_parse_from(["kafkalite", "--listener", "internal"]).is_err());
+    }
+
+    #[test]
+    fn cli_flags_are_parsed_into_settings() {
+        let cli = Cli::try_parse_from([
+            "kafkalite",
+            "--port",
+            "9093",
+            "--bind-address",
+            "::1",
+            "--log-format",
+            "json",
+            "--admin-api",
+        ])
+        .expect("Failed to parse flags");
+
+        assert_eq!(cli.settings.port, Some(9093));
+        assert_eq!(cli.settings.bind_address, Some("::1".parse().unwrap()));
+        assert_eq!(cli.settings.log_format, Some(LogFormat::Json));
+        assert_eq!(cli.settings.admin_api, Some(true));
+    }
+}
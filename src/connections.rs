@@ -0,0 +1,240 @@
+use crate::auth::Principal;
+use crate::topic::ClientId;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+/// Keeps track of the open client connections across all listeners and refuses new ones once the
+/// configured limits are reached. Connections over Unix domain sockets have no source IP and only
+/// count towards the total.
+pub struct ConnectionTracker {
+    max_connections: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+    counts: Mutex<ConnectionCounts>,
+}
+
+#[derive(Default)]
+struct ConnectionCounts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+    next_id: u64,
+    open: BTreeMap<u64, ConnectionInfo>,
+}
+
+/// What is known about an open connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub listener: String,
+    pub client_addr: String,
+    pub opened_at: Instant,
+    /// The client id the connection is bound to, once it is.
+    pub client_id: Option<ClientId>,
+    /// The user the connection authenticated as, once it did.
+    pub principal: Option<Principal>,
+}
+
+/// Keeps a connection counted for as long as it is held.
+pub struct ConnectionPermit {
+    tracker: Arc<ConnectionTracker>,
+    id: u64,
+    peer_ip: Option<IpAddr>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConnectionLimitError {
+    TooManyConnections,
+    TooManyConnectionsFrom(IpAddr),
+}
+
+impl std::fmt::Display for ConnectionLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionLimitError::TooManyConnections => write!(f, "Too many connections"),
+            ConnectionLimitError::TooManyConnectionsFrom(ip) => {
+                write!(f, "Too many connections from {ip}")
+            }
+        }
+    }
+}
+
+impl ConnectionTracker {
+    pub fn new(max_connections: Option<usize>, max_connections_per_ip: Option<usize>) -> Self {
+        ConnectionTracker {
+            max_connections,
+            max_connections_per_ip,
+            counts: Mutex::new(ConnectionCounts::default()),
+        }
+    }
+
+    pub fn try_acquire(
+        self: &Arc<Self>,
+        peer_ip: Option<IpAddr>,
+        listener: &str,
+        client_addr: &str,
+    ) -> Result<ConnectionPermit, ConnectionLimitError> {
+        let mut counts = self.lock_counts();
+        if self.max_connections.is_some_and(|max| counts.total >= max) {
+            return Err(ConnectionLimitError::TooManyConnections);
+        }
+        if let Some(ip) = peer_ip {
+            let from_ip = counts.per_ip.entry(ip).or_default();
+            if self
+                .max_connections_per_ip
+                .is_some_and(|max| *from_ip >= max)
+            {
+                return Err(ConnectionLimitError::TooManyConnectionsFrom(ip));
+            }
+            *from_ip += 1;
+        }
+        counts.total += 1;
+        let id = counts.next_id;
+        counts.next_id += 1;
+        counts.open.insert(
+            id,
+            ConnectionInfo {
+                id,
+                listener: listener.to_string(),
+                client_addr: client_addr.to_string(),
+                opened_at: Instant::now(),
+                client_id: None,
+                principal: None,
+            },
+        );
+        Ok(ConnectionPermit {
+            tracker: Arc::clone(self),
+            id,
+            peer_ip,
+        })
+    }
+
+    /// The open connections, oldest first.
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        self.lock_counts().open.values().cloned().collect()
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.lock_counts().total
+    }
+
+    pub fn active_connections_from(&self, ip: IpAddr) -> usize {
+        self.lock_counts().per_ip.get(&ip).copied().unwrap_or(0)
+    }
+
+    fn release(&self, id: u64, peer_ip: Option<IpAddr>) {
+        let mut counts = self.lock_counts();
+        counts.total -= 1;
+        counts.open.remove(&id);
+        if let Some(ip) = peer_ip
+            && let Some(from_ip) = counts.per_ip.get_mut(&ip)
+        {
+            *from_ip -= 1;
+            if *from_ip == 0 {
+                counts.per_ip.remove(&ip);
+            }
+        }
+    }
+
+    fn lock_counts(&self) -> MutexGuard<'_, ConnectionCounts> {
+        self.counts.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl ConnectionPermit {
+    /// Records who the connection turned out to belong to.
+    pub fn identify(&self, client_id: Option<ClientId>, principal: Option<&Principal>) {
+        let mut counts = self.tracker.lock_counts();
+        if let Some(info) = counts.open.get_mut(&self.id) {
+            info.client_id = client_id;
+            info.principal = principal.cloned();
+        }
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.tracker.release(self.id, self.peer_ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_total_and_per_ip_connections() {
+        let tracker = Arc::new(ConnectionTracker::new(Some(3), Some(2)));
+        let first_ip: IpAddr = [10, 0, 0, 1].into();
+        let second_ip: IpAddr = [10, 0, 0, 2].into();
+
+        let first = tracker
+            .try_acquire(Some(first_ip), "default", "client")
+            .unwrap();
+        let _second = tracker
+            .try_acquire(Some(first_ip), "default", "client")
+            .unwrap();
+        assert_eq!(
+            tracker
+                .try_acquire(Some(first_ip), "default", "client")
+                .err(),
+            Some(ConnectionLimitError::TooManyConnectionsFrom(first_ip))
+        );
+        let _third = tracker
+            .try_acquire(Some(second_ip), "default", "client")
+            .unwrap();
+        assert_eq!(
+            tracker.try_acquire(None, "default", "client").err(),
+            Some(ConnectionLimitError::TooManyConnections)
+        );
+        assert_eq!(tracker.active_connections(), 3);
+        assert_eq!(tracker.active_connections_from(first_ip), 2);
+
+        drop(first);
+        assert_eq!(tracker.active_connections(), 2);
+        assert_eq!(tracker.active_connections_from(first_ip), 1);
+        assert!(
+            tracker
+                .try_acquire(Some(first_ip), "default", "client")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn accepts_any_number_of_connections_without_limits() {
+        let tracker = Arc::new(ConnectionTracker::new(None, None));
+        let permits: Vec<_> = (0..100)
+            .map(|_| {
+                tracker
+                    .try_acquire(Some([10, 0, 0, 1].into()), "default", "client")
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(tracker.active_connections(), permits.len());
+    }
+
+    #[test]
+    fn lists_open_connections_with_their_identity() {
+        let tracker = Arc::new(ConnectionTracker::new(None, None));
+        let first = tracker
+            .try_acquire(None, "internal", "10.0.0.1:5000")
+            .unwrap();
+        let second = tracker
+            .try_acquire(None, "external", "10.0.0.2:5000")
+            .unwrap();
+        let client_id = ClientId::new_v4();
+        second.identify(Some(client_id), Some(&"alice".to_string()));
+
+        let connections = tracker.connections();
+        assert_eq!(connections.len(), 2);
+        assert_eq!(connections[0].listener, "internal");
+        assert_eq!(connections[0].client_id, None);
+        assert_eq!(connections[1].client_addr, "10.0.0.2:5000");
+        assert_eq!(connections[1].client_id, Some(client_id));
+        assert_eq!(connections[1].principal.as_deref(), Some("alice"));
+
+        drop(first);
+        drop(second);
+        assert!(tracker.connections().is_empty());
+    }
+}
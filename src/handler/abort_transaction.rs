@@ -0,0 +1,38 @@
+use crate::protocol::response::Response;
+use crate::router::IntoResponse;
+use crate::server::BrokerResponse;
+use crate::session::Session;
+use crate::topic::{TopicTransactionCoordinator, TopicTransactionError};
+
+pub async fn handle_request<C>(
+    session: &mut Session,
+    coordinator: &C,
+) -> Result<BrokerResponse, AbortTransactionError>
+where
+    C: TopicTransactionCoordinator,
+{
+    let transaction_id = session.transaction_id.take().ok_or(AbortTransactionError(
+        "No transaction in progress".to_string(),
+    ))?;
+    tracing::debug!("Aborting transaction {}", transaction_id);
+    coordinator.abort_transaction(transaction_id).await?;
+    Ok(BrokerResponse::BasicResponse(Response::Ack))
+}
+
+pub struct AbortTransactionError(String);
+
+impl From<TopicTransactionError> for AbortTransactionError {
+    fn from(e: TopicTransactionError) -> Self {
+        match e {
+            TopicTransactionError::TransactionNotFound(transaction_id) => {
+                AbortTransactionError(format!("Transaction {} not found", transaction_id))
+            }
+        }
+    }
+}
+
+impl IntoResponse for AbortTransactionError {
+    fn into_response(self) -> Response {
+        Response::Error { message: self.0 }
+    }
+}
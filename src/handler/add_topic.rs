@@ -1,25 +1,35 @@
+use crate::compression::Compression;
 use crate::protocol::response::Response;
 use crate::router::IntoResponse;
 use crate::server::BrokerResponse;
-use crate::topic::{TopicManager, TopicName};
+use crate::topic::{TopicAddError, TopicManager, TopicName};
 
 pub async fn handle_request<T>(
     topic_name: TopicName,
-    retention: u64,
+    retention: Option<u64>,
+    compression: Option<Compression>,
     topic_manager: &T,
 ) -> Result<BrokerResponse, AddTopicError>
 where
     T: TopicManager,
 {
     tracing::debug!("Adding new topic: {}", topic_name);
-    let is_topic_added = topic_manager.add_topic(&topic_name, retention).await;
-    if is_topic_added {
-        Ok(BrokerResponse::BasicResponse(Response::Ack))
-    } else {
-        Err(AddTopicError(format!(
+    match topic_manager
+        .add_topic(&topic_name, retention, compression)
+        .await
+    {
+        Ok(()) => Ok(BrokerResponse::BasicResponse(Response::Ack)),
+        Err(TopicAddError::EmptyName) => {
+            Err(AddTopicError("Topic name must not be empty".to_string()))
+        }
+        Err(TopicAddError::AlreadyExists(topic_name)) => Err(AddTopicError(format!(
             "Topic {} already exists",
             topic_name
-        )))
+        ))),
+        Err(TopicAddError::InvalidRetention { retention, limit }) => Err(AddTopicError(format!(
+            "Retention {} is not between 1 and {}",
+            retention, limit
+        ))),
     }
 }
 
@@ -0,0 +1,32 @@
+use crate::protocol::response::Response;
+use crate::router::IntoResponse;
+use crate::server::BrokerResponse;
+use crate::session::Session;
+use crate::topic::TopicTransactionCoordinator;
+
+pub async fn handle_request<C>(
+    session: &mut Session,
+    coordinator: &C,
+) -> Result<BrokerResponse, BeginTransactionError>
+where
+    C: TopicTransactionCoordinator,
+{
+    if let Some(transaction_id) = session.transaction_id {
+        return Err(BeginTransactionError(format!(
+            "Transaction {} already in progress",
+            transaction_id
+        )));
+    }
+    let transaction_id = coordinator.begin_transaction().await;
+    tracing::debug!("Started transaction {}", transaction_id);
+    session.transaction_id = Some(transaction_id);
+    Ok(BrokerResponse::BasicResponse(Response::Ack))
+}
+
+pub struct BeginTransactionError(String);
+
+impl IntoResponse for BeginTransactionError {
+    fn into_response(self) -> Response {
+        Response::Error { message: self.0 }
+    }
+}
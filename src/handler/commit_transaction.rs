@@ -0,0 +1,38 @@
+use crate::protocol::response::Response;
+use crate::router::IntoResponse;
+use crate::server::BrokerResponse;
+use crate::session::Session;
+use crate::topic::{TopicTransactionCoordinator, TopicTransactionError};
+
+pub async fn handle_request<C>(
+    session: &mut Session,
+    coordinator: &C,
+) -> Result<BrokerResponse, CommitTransactionError>
+where
+    C: TopicTransactionCoordinator,
+{
+    let transaction_id = session.transaction_id.take().ok_or(CommitTransactionError(
+        "No transaction in progress".to_string(),
+    ))?;
+    tracing::debug!("Committing transaction {}", transaction_id);
+    coordinator.commit_transaction(transaction_id).await?;
+    Ok(BrokerResponse::BasicResponse(Response::Ack))
+}
+
+pub struct CommitTransactionError(String);
+
+impl From<TopicTransactionError> for CommitTransactionError {
+    fn from(e: TopicTransactionError) -> Self {
+        match e {
+            TopicTransactionError::TransactionNotFound(transaction_id) => {
+                CommitTransactionError(format!("Transaction {} not found", transaction_id))
+            }
+        }
+    }
+}
+
+impl IntoResponse for CommitTransactionError {
+    fn into_response(self) -> Response {
+        Response::Error { message: self.0 }
+    }
+}
@@ -0,0 +1,6 @@
+use crate::server::BrokerResponse;
+
+pub async fn handle_request() -> BrokerResponse {
+    tracing::trace!("Handling HEARTBEAT request");
+    BrokerResponse::Empty
+}
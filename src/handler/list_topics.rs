@@ -1,12 +1,15 @@
 use crate::protocol::response::Response;
 use crate::server::BrokerResponse;
-use crate::topic::TopicManager;
+use crate::topic::{TopicManager, TopicName};
 
-pub async fn handle_request<T>(topic_manager: &T) -> BrokerResponse
+/// Lists the topics the caller is allowed to see, as decided by `is_visible`.
+pub async fn handle_request<T, F>(topic_manager: &T, is_visible: F) -> BrokerResponse
 where
     T: TopicManager,
+    F: Fn(&TopicName) -> bool,
 {
     tracing::debug!("Listing all topics");
-    let topics = topic_manager.list_topics().await;
+    let mut topics = topic_manager.list_topics().await;
+    topics.retain(|topic| is_visible(topic));
     BrokerResponse::BasicResponse(Response::TopicsList { topics })
 }
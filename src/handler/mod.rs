@@ -1,15 +1,29 @@
+mod abort_transaction;
 mod add_topic;
+mod begin_transaction;
+mod commit_transaction;
 mod delete_topic;
+mod heartbeat;
 mod list_topics;
 mod ping;
 mod publish;
+mod register_client;
+mod sasl_authenticate;
+mod sasl_handshake;
 mod subscribe;
 mod unsubscribe;
 
+pub use abort_transaction::handle_request as abort_transaction;
 pub use add_topic::handle_request as add_topic;
+pub use begin_transaction::handle_request as begin_transaction;
+pub use commit_transaction::handle_request as commit_transaction;
 pub use delete_topic::handle_request as delete_topic;
+pub use heartbeat::handle_request as heartbeat;
 pub use list_topics::handle_request as list_topics;
 pub use ping::handle_request as ping;
 pub use publish::handle_request as publish;
+pub use register_client::handle_request as register_client;
+pub use sasl_authenticate::handle_request as sasl_authenticate;
+pub use sasl_handshake::handle_request as sasl_handshake;
 pub use subscribe::handle_request as subscribe;
 pub use unsubscribe::handle_request as unsubscribe;
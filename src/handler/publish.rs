@@ -1,18 +1,43 @@
+use crate::compression::Compression;
 use crate::protocol::response::Response;
 use crate::router::IntoResponse;
 use crate::server::BrokerResponse;
-use crate::topic::{TopicName, TopicPublishError, TopicPublisher};
+use crate::telemetry;
+use crate::topic::{
+    TopicName, TopicPublishError, TopicPublisher, TopicTransactionCoordinator, TransactionId,
+};
+use bytes::Bytes;
+use tracing::Instrument;
 
 pub async fn handle_request<P>(
     topic: TopicName,
-    payload: Vec<u8>,
+    payload: Bytes,
+    compression: Compression,
+    traceparent: Option<String>,
+    transaction_id: Option<TransactionId>,
     publisher: &P,
 ) -> Result<BrokerResponse, PublishError>
 where
-    P: TopicPublisher,
+    P: TopicPublisher + TopicTransactionCoordinator,
 {
-    tracing::debug!("Publishing to {}", topic);
-    publisher.publish(&topic, payload).await?;
+    let span = tracing::info_span!(target: telemetry::TARGET, "append", topic = %topic);
+    let traceparent = telemetry::continue_trace(&span, traceparent.as_deref());
+    match transaction_id {
+        Some(transaction_id) => {
+            tracing::debug!("Publishing to {} in transaction {}", topic, transaction_id);
+            publisher
+                .publish_in_transaction(transaction_id, &topic, payload, compression, traceparent)
+                .instrument(span)
+                .await?;
+        }
+        None => {
+            tracing::debug!("Publishing to {}", topic);
+            publisher
+                .publish(&topic, payload, compression, traceparent)
+                .instrument(span)
+                .await?;
+        }
+    }
     Ok(BrokerResponse::BasicResponse(Response::Ack))
 }
 
@@ -24,6 +49,21 @@ impl From<TopicPublishError> for PublishError {
             TopicPublishError::TopicNotFound(topic_name) => {
                 PublishError(format!("Topic {} not found", topic_name))
             }
+            TopicPublishError::TransactionNotFound(transaction_id) => {
+                PublishError(format!("Transaction {} not found", transaction_id))
+            }
+            TopicPublishError::InvalidPayload(topic_name, e) => PublishError(format!(
+                "Failed to recompress payload for topic {}: {}",
+                topic_name, e
+            )),
+            TopicPublishError::PayloadTooLarge { size, limit } => PublishError(format!(
+                "Payload of {} bytes exceeds the limit of {} bytes",
+                size, limit
+            )),
+            TopicPublishError::TopicRecreated(topic_name) => PublishError(format!(
+                "Topic {} was re-created during the transaction",
+                topic_name
+            )),
         }
     }
 }
@@ -0,0 +1,55 @@
+use crate::protocol::response::Response;
+use crate::router::IntoResponse;
+use crate::server::BrokerResponse;
+use crate::session::{ClientRegistry, Session};
+use crate::topic::ClientId;
+
+pub async fn handle_request<R>(
+    client_id: ClientId,
+    session: &mut Session,
+    registry: &R,
+) -> Result<BrokerResponse, RegisterClientError>
+where
+    R: ClientRegistry,
+{
+    bind_client_id(client_id, session, registry).await?;
+    Ok(BrokerResponse::BasicResponse(Response::Ack))
+}
+
+/// Binds `client_id` to the connection unless it already is. A connection keeps the identity it
+/// registered first, and an identity held by another connection can't be taken over.
+pub async fn bind_client_id<R>(
+    client_id: ClientId,
+    session: &mut Session,
+    registry: &R,
+) -> Result<(), RegisterClientError>
+where
+    R: ClientRegistry,
+{
+    match session.client_id {
+        Some(registered) if registered == client_id => Ok(()),
+        Some(registered) => Err(RegisterClientError(format!(
+            "Connection is already registered as client {}",
+            registered
+        ))),
+        None => {
+            if !registry.register_client(client_id).await {
+                return Err(RegisterClientError(format!(
+                    "Client {} is already registered on another connection",
+                    client_id
+                )));
+            }
+            tracing::debug!("Registered client {}", client_id);
+            session.client_id = Some(client_id);
+            Ok(())
+        }
+    }
+}
+
+pub struct RegisterClientError(pub String);
+
+impl IntoResponse for RegisterClientError {
+    fn into_response(self) -> Response {
+        Response::Error { message: self.0 }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::auth::{SaslError, SaslMechanism, SaslState};
+use crate::protocol::response::Response;
+use crate::router::IntoResponse;
+use crate::server::BrokerResponse;
+use crate::session::Session;
+use bytes::Bytes;
+
+/// Advances the SASL exchange selected by the handshake. Any failure resets the exchange, so the
+/// client has to start over with a new handshake.
+pub async fn handle_request(
+    auth_bytes: Bytes,
+    session: &mut Session,
+) -> Result<BrokerResponse, SaslAuthenticateError> {
+    let Some(authenticator) = session.authenticator.clone() else {
+        return Err(SaslAuthenticateError(
+            "SASL authentication is not enabled".to_string(),
+        ));
+    };
+    let challenge = match std::mem::take(&mut session.sasl_state) {
+        SaslState::Idle => {
+            return Err(SaslAuthenticateError(
+                "SASL handshake required before authentication".to_string(),
+            ));
+        }
+        SaslState::MechanismSelected(SaslMechanism::Plain) => {
+            let principal = authenticator
+                .authenticate_plain(&auth_bytes)
+                .inspect_err(|e| tracing::warn!("PLAIN authentication failed: {e}"))?;
+            tracing::info!("Authenticated {principal} with PLAIN");
+            session.principal = Some(principal);
+            Vec::new()
+        }
+        SaslState::MechanismSelected(SaslMechanism::ScramSha256) => {
+            let (exchange, server_first) = authenticator.start_scram(&auth_bytes)?;
+            session.sasl_state = SaslState::ScramClientFinal(Box::new(exchange));
+            server_first
+        }
+        SaslState::ScramClientFinal(exchange) => {
+            let (principal, server_final) = exchange
+                .finish(&auth_bytes)
+                .inspect_err(|e| tracing::warn!("SCRAM-SHA-256 authentication failed: {e}"))?;
+            tracing::info!("Authenticated {principal} with SCRAM-SHA-256");
+            session.principal = Some(principal);
+            server_final
+        }
+    };
+    Ok(BrokerResponse::BasicResponse(Response::SaslAuthenticate {
+        auth_bytes: Bytes::from(challenge),
+    }))
+}
+
+pub struct SaslAuthenticateError(String);
+
+impl From<SaslError> for SaslAuthenticateError {
+    fn from(error: SaslError) -> Self {
+        SaslAuthenticateError(error.to_string())
+    }
+}
+
+impl IntoResponse for SaslAuthenticateError {
+    fn into_response(self) -> Response {
+        Response::Error { message: self.0 }
+    }
+}
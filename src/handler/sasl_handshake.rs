@@ -0,0 +1,39 @@
+use crate::auth::{SaslMechanism, SaslState};
+use crate::protocol::response::Response;
+use crate::router::IntoResponse;
+use crate::server::BrokerResponse;
+use crate::session::Session;
+
+pub async fn handle_request(
+    mechanism: String,
+    session: &mut Session,
+) -> Result<BrokerResponse, SaslHandshakeError> {
+    if session.authenticator.is_none() {
+        return Err(SaslHandshakeError(
+            "SASL authentication is not enabled".to_string(),
+        ));
+    }
+    if let Some(principal) = &session.principal {
+        return Err(SaslHandshakeError(format!(
+            "Already authenticated as {}",
+            principal
+        )));
+    }
+    let Some(mechanism) = SaslMechanism::parse(&mechanism) else {
+        return Err(SaslHandshakeError(format!(
+            "Unsupported SASL mechanism {}, expected one of {}",
+            mechanism,
+            SaslMechanism::SUPPORTED.join(", ")
+        )));
+    };
+    session.sasl_state = SaslState::MechanismSelected(mechanism);
+    Ok(BrokerResponse::BasicResponse(Response::Ack))
+}
+
+pub struct SaslHandshakeError(String);
+
+impl IntoResponse for SaslHandshakeError {
+    fn into_response(self) -> Response {
+        Response::Error { message: self.0 }
+    }
+}
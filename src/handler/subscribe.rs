@@ -1,30 +1,43 @@
+use crate::handler::register_client::{RegisterClientError, bind_client_id};
 use crate::protocol::response::Response;
 use crate::router::IntoResponse;
 use crate::server::BrokerResponse;
-use crate::topic::{ClientId, TopicName, TopicSubscribeError, TopicSubscriber};
+use crate::session::{ClientRegistry, Session};
+use crate::topic::{ClientId, IsolationLevel, TopicName, TopicSubscribeError, TopicSubscriber};
 
 pub async fn handle_request<S>(
     topic_name: TopicName,
     client_id: ClientId,
     from_offset: Option<u64>,
+    isolation_level: IsolationLevel,
+    session: &mut Session,
     subscriber: &S,
 ) -> Result<BrokerResponse, SubscribeError>
 where
-    S: TopicSubscriber,
+    S: TopicSubscriber + ClientRegistry,
 {
+    bind_client_id(client_id, session, subscriber).await?;
     tracing::debug!(
-        "Subscribing new client {} to topic {}",
+        "Subscribing new client {} to topic {} with {:?}",
         client_id,
-        topic_name
+        topic_name,
+        isolation_level
     );
     let subscription = subscriber
-        .subscribe(&topic_name, from_offset, client_id)
+        .subscribe(&topic_name, from_offset, isolation_level, client_id)
         .await?;
+    session.subscribed_topics.insert(topic_name);
     Ok(BrokerResponse::StreamedResponse(subscription))
 }
 
 pub struct SubscribeError(String);
 
+impl From<RegisterClientError> for SubscribeError {
+    fn from(e: RegisterClientError) -> Self {
+        SubscribeError(e.0)
+    }
+}
+
 impl From<TopicSubscribeError> for SubscribeError {
     fn from(e: TopicSubscribeError) -> Self {
         match e {
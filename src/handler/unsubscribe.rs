@@ -1,22 +1,31 @@
 use crate::protocol::response::Response;
 use crate::router::IntoResponse;
 use crate::server::BrokerResponse;
+use crate::session::Session;
 use crate::topic::{ClientId, TopicName, TopicSubscribeError, TopicSubscriber};
 
 pub async fn handle_request<S>(
     topic_name: TopicName,
     client_id: ClientId,
+    session: &mut Session,
     subscriber: &S,
 ) -> Result<BrokerResponse, UnsubscribeError>
 where
     S: TopicSubscriber,
 {
+    if session.client_id != Some(client_id) {
+        return Err(UnsubscribeError(format!(
+            "Client {} is not registered on this connection",
+            client_id
+        )));
+    }
     tracing::debug!(
         "Unsubscribing client {} from topic {}",
         client_id,
         topic_name
     );
     subscriber.unsubscribe(&topic_name, client_id).await?;
+    session.subscribed_topics.remove(&topic_name);
     Ok(BrokerResponse::BasicResponse(Response::Ack))
 }
 
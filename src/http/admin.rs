@@ -0,0 +1,190 @@
+use crate::auth::AclOperation;
+use crate::compression::Compression;
+use crate::connections::ConnectionInfo;
+use crate::http::{ApiError, Caller, HttpState, admit, authorize, topic_not_found};
+use crate::session::Session;
+use crate::topic::{TopicAddError, TopicDescription, TopicManager, TopicName};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+/// Routes of the admin API, managing topics through the same [`TopicManager`] the binary
+/// protocol uses, under the same ACL operations and request quotas. Connections, and the client
+/// ids subscribed to a topic, are only listed to super users.
+pub fn router() -> Router<HttpState> {
+    Router::new()
+        .route("/topics", get(list_topics).post(add_topic))
+        .route("/topics/{name}", get(describe_topic).delete(delete_topic))
+        .route("/connections", get(list_connections))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AddTopic {
+    name: TopicName,
+    retention: Option<u64>,
+    compression: Option<Compression>,
+}
+
+#[derive(Serialize)]
+struct TopicBody {
+    name: TopicName,
+    retention: u64,
+    compression: Option<Compression>,
+    start_offset: u64,
+    end_offset: u64,
+    last_stable_offset: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subscribers: Option<Vec<String>>,
+    retained_records: usize,
+    retained_bytes: u64,
+}
+
+impl TopicBody {
+    /// The description as shown to `session`, leaving out the subscribers unless it is a super
+    /// user.
+    fn new(description: TopicDescription, session: &Session) -> Self {
+        let subscribers = session.is_super_user().then(|| {
+            description
+                .subscribers
+                .iter()
+                .map(|client_id| client_id.to_string())
+                .collect()
+        });
+        TopicBody {
+            name: description.name,
+            retention: description.retention,
+            compression: description.compression,
+            start_offset: description.start_offset,
+            end_offset: description.end_offset,
+            last_stable_offset: description.last_stable_offset,
+            subscribers,
+            retained_records: description.retained_records,
+            retained_bytes: description.retained_bytes,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ConnectionBody {
+    id: u64,
+    listener: String,
+    client_addr: String,
+    client_id: Option<String>,
+    principal: Option<String>,
+    connected_for_ms: u128,
+}
+
+impl From<ConnectionInfo> for ConnectionBody {
+    fn from(connection: ConnectionInfo) -> Self {
+        ConnectionBody {
+            id: connection.id,
+            listener: connection.listener,
+            client_addr: connection.client_addr,
+            client_id: connection.client_id.map(|client_id| client_id.to_string()),
+            principal: connection.principal,
+            connected_for_ms: connection.opened_at.elapsed().as_millis(),
+        }
+    }
+}
+
+async fn list_topics(
+    State(state): State<HttpState>,
+    Caller(session): Caller,
+) -> Result<Json<Vec<TopicName>>, ApiError> {
+    admit(&state, &session, None, 0).await?;
+    let mut topics = state.broker.list_topics().await;
+    topics.retain(|topic| session.is_authorized(AclOperation::ListTopics, topic));
+    topics.sort();
+    Ok(Json(topics))
+}
+
+async fn add_topic(
+    State(state): State<HttpState>,
+    Caller(session): Caller,
+    Json(request): Json<AddTopic>,
+) -> Result<(StatusCode, Json<TopicBody>), ApiError> {
+    authorize(&session, AclOperation::AddTopic, &request.name)?;
+    admit(&state, &session, Some(&request.name), 0).await?;
+    tracing::debug!("Adding new topic over HTTP: {}", request.name);
+    state
+        .broker
+        .add_topic(&request.name, request.retention, request.compression)
+        .await
+        .map_err(|e| match e {
+            TopicAddError::EmptyName => ApiError(
+                StatusCode::BAD_REQUEST,
+                "Topic name must not be empty".to_string(),
+            ),
+            TopicAddError::AlreadyExists(topic_name) => ApiError(
+                StatusCode::CONFLICT,
+                format!("Topic {} already exists", topic_name),
+            ),
+            TopicAddError::InvalidRetention { retention, limit } => ApiError(
+                StatusCode::BAD_REQUEST,
+                format!("Retention {} is not between 1 and {}", retention, limit),
+            ),
+        })?;
+    let description = state
+        .broker
+        .describe_topic(&request.name)
+        .await
+        .ok_or_else(|| topic_not_found(&request.name))?;
+    Ok((
+        StatusCode::CREATED,
+        Json(TopicBody::new(description, &session)),
+    ))
+}
+
+async fn describe_topic(
+    State(state): State<HttpState>,
+    Path(topic_name): Path<TopicName>,
+    Caller(session): Caller,
+) -> Result<Json<TopicBody>, ApiError> {
+    authorize(&session, AclOperation::ListTopics, &topic_name)?;
+    admit(&state, &session, Some(&topic_name), 0).await?;
+    let description = state
+        .broker
+        .describe_topic(&topic_name)
+        .await
+        .ok_or_else(|| topic_not_found(&topic_name))?;
+    Ok(Json(TopicBody::new(description, &session)))
+}
+
+async fn delete_topic(
+    State(state): State<HttpState>,
+    Path(topic_name): Path<TopicName>,
+    Caller(session): Caller,
+) -> Result<StatusCode, ApiError> {
+    authorize(&session, AclOperation::DeleteTopic, &topic_name)?;
+    admit(&state, &session, Some(&topic_name), 0).await?;
+    tracing::debug!("Deleting topic over HTTP: {}", topic_name);
+    if state.broker.delete_topic(&topic_name).await {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(topic_not_found(&topic_name))
+    }
+}
+
+async fn list_connections(
+    State(state): State<HttpState>,
+    Caller(session): Caller,
+) -> Result<Json<Vec<ConnectionBody>>, ApiError> {
+    if !session.is_super_user() {
+        tracing::warn!(
+            "Denied listing connections to {} over HTTP",
+            session.principal_name()
+        );
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            "Only super users may list connections".to_string(),
+        ));
+    }
+    admit(&state, &session, None, 0).await?;
+    let connections = state.connections.connections();
+    Ok(Json(
+        connections.into_iter().map(ConnectionBody::from).collect(),
+    ))
+}
@@ -0,0 +1,230 @@
+mod admin;
+mod records;
+mod websocket;
+
+use crate::auth::{AclOperation, Authenticator, Authorizer};
+use crate::broker::Broker;
+use crate::connections::ConnectionTracker;
+use crate::quota::QuotaManager;
+use crate::readiness::Readiness;
+use crate::session::Session;
+use crate::topic::{TopicManager, TopicName};
+use axum::extract::{ConnectInfo, FromRequestParts, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Serialize;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+/// Content type of the Prometheus text exposition format.
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// What the HTTP endpoints are served from.
+#[derive(Clone)]
+pub struct HttpState {
+    pub broker: Arc<Broker>,
+    pub connections: Arc<ConnectionTracker>,
+    pub readiness: Arc<Readiness>,
+    /// Present when requests to the admin API, REST proxy and WebSocket gateway have to carry
+    /// the HTTP Basic credentials of a known user.
+    pub authenticator: Option<Arc<Authenticator>>,
+    /// Present when those requests are checked against ACLs.
+    pub authorizer: Option<Arc<Authorizer>>,
+    /// Present when quotas are configured, to be enforced on records published and read over
+    /// HTTP.
+    pub quotas: Option<Arc<QuotaManager>>,
+    /// Cancelled once the broker starts shutting down, ending the streams still open.
+    pub shutdown: CancellationToken,
+}
+
+/// Which of the optional groups of endpoints are served.
+#[derive(Clone, Copy, Default)]
+pub struct HttpEndpoints {
+    pub admin_api: bool,
+    pub rest_proxy: bool,
+    pub websocket_gateway: bool,
+}
+
+/// The endpoints served, with the optional ones only included if enabled.
+pub fn router(state: HttpState, endpoints: HttpEndpoints) -> Router {
+    let mut router = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics));
+    if endpoints.admin_api {
+        router = router.merge(admin::router());
+    }
+    if endpoints.rest_proxy {
+        router = router.merge(records::router());
+    }
+    if endpoints.websocket_gateway {
+        router = router.merge(websocket::router());
+    }
+    router.with_state(state)
+}
+
+/// Serves `router` on `listener` until `shutdown` is cancelled.
+pub async fn serve(
+    listener: TcpListener,
+    router: Router,
+    shutdown: CancellationToken,
+) -> io::Result<()> {
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown.cancelled_owned())
+    .await
+}
+
+/// Answers as long as the broker process is alive and serving HTTP.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Answers with 503 until the broker is ready to serve clients, and again once it is shutting
+/// down.
+async fn readyz(State(state): State<HttpState>) -> (StatusCode, &'static str) {
+    if state.readiness.is_ready() {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not ready")
+    }
+}
+
+async fn metrics(State(state): State<HttpState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)],
+        state.broker.metrics().render(),
+    )
+}
+
+/// The caller of an endpoint, authenticated from the HTTP Basic credentials of the request when
+/// the broker has users, so that it is held to the same ACLs as connections of the binary
+/// protocol.
+struct Caller(Session);
+
+impl FromRequestParts<HttpState> for Caller {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &HttpState) -> Result<Self, ApiError> {
+        let peer_ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let mut session = Session {
+            peer_ip,
+            ..Session::new(
+                state.authenticator.clone(),
+                state.authorizer.clone(),
+                state.quotas.clone(),
+            )
+        };
+        if let Some(authenticator) = &state.authenticator {
+            let principal = basic_credentials(&parts.headers)
+                .and_then(|(username, password)| {
+                    let message = format!("\0{username}\0{password}");
+                    authenticator.authenticate_plain(message.as_bytes()).ok()
+                })
+                .ok_or_else(|| {
+                    ApiError(
+                        StatusCode::UNAUTHORIZED,
+                        "Authentication required".to_string(),
+                    )
+                })?;
+            session.principal = Some(principal);
+        }
+        Ok(Caller(session))
+    }
+}
+
+/// The username and password of an `Authorization: Basic` header.
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let encoded = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(BASE64.decode(encoded).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Fails with `403 Forbidden` unless `session` may perform `operation` on `topic`.
+fn authorize(session: &Session, operation: AclOperation, topic: &str) -> Result<(), ApiError> {
+    if session.is_authorized(operation, topic) {
+        return Ok(());
+    }
+    tracing::warn!(
+        "Denied {operation} on topic {topic} to {} over HTTP",
+        session.principal_name()
+    );
+    Err(ApiError(
+        StatusCode::FORBIDDEN,
+        format!("Not authorized to perform {operation} on topic {topic}"),
+    ))
+}
+
+/// Fails with `429 Too Many Requests` if a request publishing `produce_bytes`, to `topic` if it
+/// concerns one, would exceed one of the quotas of `session`. Like for requests of the binary
+/// protocol, only topics that exist are accounted to, so that requests naming made-up topics don't
+/// leave buckets behind for them.
+async fn admit(
+    state: &HttpState,
+    session: &Session,
+    topic: Option<&TopicName>,
+    produce_bytes: usize,
+) -> Result<(), ApiError> {
+    let Some(quotas) = &session.quotas else {
+        return Ok(());
+    };
+    let topic = match topic {
+        Some(topic) if state.broker.topic_exists(topic).await => Some(topic),
+        _ => None,
+    };
+    let client = session.client_identity();
+    quotas
+        .admit_request(&client, topic, produce_bytes)
+        .map_err(|throttle_time| {
+            tracing::debug!("Throttling {} for {:?}", client, throttle_time);
+            ApiError(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("Throttled for {} ms", throttle_time.as_millis().max(1)),
+            )
+        })
+}
+
+/// An error answered with its status and a `{"error": "..."}` body.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct ErrorBody {
+            error: String,
+        }
+        let mut response = (self.0, Json(ErrorBody { error: self.1 })).into_response();
+        if self.0 == StatusCode::UNAUTHORIZED {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Basic realm=\"kafkalite\""),
+            );
+        }
+        response
+    }
+}
+
+fn topic_not_found(topic_name: &str) -> ApiError {
+    ApiError(
+        StatusCode::NOT_FOUND,
+        format!("Topic {} not found", topic_name),
+    )
+}
@@ -0,0 +1,397 @@
+use crate::auth::AclOperation;
+use crate::broker::Broker;
+use crate::compression::Compression;
+use crate::http::{ApiError, Caller, HttpState, admit, authorize, topic_not_found};
+use crate::quota::QuotaManager;
+use crate::session::ClientRegistry;
+use crate::telemetry;
+use crate::topic::{
+    ClientId, IsolationLevel, MessageRecord, TopicName, TopicPublishError, TopicPublisher,
+    TopicSubscribeError, TopicSubscriber,
+};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::Instrument;
+
+/// Records returned by a fetch unless `max` asks for fewer.
+const DEFAULT_FETCH_RECORDS: usize = 100;
+/// Most records a single fetch returns, whatever `max` asks for.
+const MAX_FETCH_RECORDS: usize = 1000;
+
+/// Routes of the REST proxy, publishing and reading records through the same
+/// [`TopicPublisher`] and [`TopicSubscriber`] the binary protocol uses, under the
+/// same ACL operations and quotas.
+pub fn router() -> Router<HttpState> {
+    Router::new()
+        .route(
+            "/topics/{name}/records",
+            post(publish_records).get(fetch_records),
+        )
+        .route("/topics/{name}/stream", get(stream_records))
+}
+
+/// How record values are represented in responses.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum RecordFormat {
+    /// The payload as a base64 string.
+    #[default]
+    Binary,
+    /// The payload embedded as the JSON value it holds.
+    Json,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublishRecords {
+    records: Vec<PublishRecord>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublishRecord {
+    value: Value,
+}
+
+#[derive(Serialize)]
+struct Published {
+    published: usize,
+}
+
+#[derive(Deserialize)]
+struct FetchQuery {
+    offset: Option<u64>,
+    max: Option<usize>,
+    #[serde(default)]
+    format: RecordFormat,
+}
+
+#[derive(Serialize)]
+struct FetchedRecords {
+    records: Vec<RecordBody>,
+    /// Offset to fetch from to continue after the returned records.
+    next_offset: u64,
+}
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    offset: Option<u64>,
+    #[serde(default)]
+    format: RecordFormat,
+}
+
+#[derive(Serialize)]
+pub(super) struct RecordBody {
+    offset: u64,
+    value: Value,
+}
+
+impl RecordBody {
+    pub(super) fn new(
+        message: &MessageRecord,
+        format: RecordFormat,
+        max_payload_size: usize,
+    ) -> Result<Self, ApiError> {
+        let payload = decompressed_payload(message, max_payload_size)?;
+        let value = match format {
+            RecordFormat::Binary => Value::String(BASE64.encode(payload)),
+            RecordFormat::Json => serde_json::from_slice(&payload).map_err(|_| {
+                ApiError(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("Record at offset {} is not valid JSON", message.offset),
+                )
+            })?,
+        };
+        Ok(RecordBody {
+            offset: message.offset,
+            value,
+        })
+    }
+}
+
+/// The payload of `message` as it was published, before the topic compressed it.
+pub(super) fn decompressed_payload(
+    message: &MessageRecord,
+    max_payload_size: usize,
+) -> Result<Vec<u8>, ApiError> {
+    message
+        .compression
+        .decompress(&message.payload, max_payload_size)
+        .map_err(|e| {
+            ApiError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!(
+                    "Failed to decompress record at offset {}: {}",
+                    message.offset, e
+                ),
+            )
+        })
+}
+
+impl From<TopicPublishError> for ApiError {
+    fn from(e: TopicPublishError) -> Self {
+        match e {
+            TopicPublishError::TopicNotFound(topic_name) => topic_not_found(&topic_name),
+            TopicPublishError::TransactionNotFound(transaction_id) => ApiError(
+                StatusCode::NOT_FOUND,
+                format!("Transaction {} not found", transaction_id),
+            ),
+            TopicPublishError::InvalidPayload(topic_name, e) => ApiError(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Failed to recompress payload for topic {}: {}",
+                    topic_name, e
+                ),
+            ),
+            TopicPublishError::PayloadTooLarge { size, limit } => ApiError(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!(
+                    "Payload of {} bytes exceeds the limit of {} bytes",
+                    size, limit
+                ),
+            ),
+            TopicPublishError::TopicRecreated(topic_name) => ApiError(
+                StatusCode::CONFLICT,
+                format!("Topic {} was re-created during the transaction", topic_name),
+            ),
+        }
+    }
+}
+
+impl From<TopicSubscribeError> for ApiError {
+    fn from(e: TopicSubscribeError) -> Self {
+        match e {
+            TopicSubscribeError::TopicNotFound(topic_name) => topic_not_found(&topic_name),
+        }
+    }
+}
+
+/// Publishes the records of a `{"records": [{"value": ...}]}` body, each value as its JSON text,
+/// or any other body as a single raw record. A `traceparent` header is continued by the appends.
+async fn publish_records(
+    State(state): State<HttpState>,
+    Path(topic_name): Path<TopicName>,
+    Caller(session): Caller,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Published>, ApiError> {
+    authorize(&session, AclOperation::Publish, &topic_name)?;
+    let payloads = if is_json(&headers) {
+        let request: PublishRecords = serde_json::from_slice(&body)
+            .map_err(|e| ApiError(StatusCode::BAD_REQUEST, format!("Invalid records: {}", e)))?;
+        request
+            .records
+            .iter()
+            .map(|record| Bytes::from(record.value.to_string()))
+            .collect()
+    } else {
+        vec![body]
+    };
+    let traceparent = headers
+        .get("traceparent")
+        .and_then(|value| value.to_str().ok());
+    let produce_bytes = payloads.iter().map(Bytes::len).sum();
+    admit(&state, &session, Some(&topic_name), produce_bytes).await?;
+
+    tracing::debug!(
+        "Publishing {} records to {} over HTTP",
+        payloads.len(),
+        topic_name
+    );
+    let published = payloads.len();
+    let started = Instant::now();
+    // Every record is checked before any is appended, so a batch failing on one record, e.g. one
+    // too large for the topic, leaves none of its records behind.
+    let span = tracing::info_span!(target: telemetry::TARGET, "append", topic = %topic_name);
+    let traceparent = telemetry::continue_trace(&span, traceparent);
+    state
+        .broker
+        .publish_batch(&topic_name, payloads, Compression::None, traceparent)
+        .instrument(span)
+        .await?;
+    state
+        .broker
+        .metrics()
+        .observe_publish_latency(&topic_name, started.elapsed());
+    Ok(Json(Published { published }))
+}
+
+/// Returns up to `max` committed records starting at `offset`, or at the oldest retained one.
+async fn fetch_records(
+    State(state): State<HttpState>,
+    Path(topic_name): Path<TopicName>,
+    Caller(session): Caller,
+    Query(query): Query<FetchQuery>,
+) -> Result<Json<FetchedRecords>, ApiError> {
+    authorize(&session, AclOperation::Subscribe, &topic_name)?;
+    admit(&state, &session, Some(&topic_name), 0).await?;
+    let offset = query.offset.unwrap_or(0);
+    let max = query
+        .max
+        .unwrap_or(DEFAULT_FETCH_RECORDS)
+        .min(MAX_FETCH_RECORDS);
+    let messages = state
+        .broker
+        .fetch(&topic_name, offset, IsolationLevel::ReadCommitted, max)
+        .await?;
+
+    let next_offset = messages.last().map_or(offset, |message| message.offset + 1);
+    let mut records = Vec::with_capacity(messages.len());
+    let mut consumed_bytes = 0;
+    for message in messages {
+        consumed_bytes += message.payload.len();
+        records.push(RecordBody::new(
+            &message,
+            query.format,
+            state.broker.max_payload_size(),
+        )?);
+    }
+    // Like deliveries to subscribers, fetches over the consume quota are held back rather than
+    // refused.
+    let consume_quota = session
+        .quotas
+        .clone()
+        .map(|quotas| (quotas, session.client_identity()));
+    pace_delivery(&consume_quota, &topic_name, consumed_bytes).await;
+    Ok(Json(FetchedRecords {
+        records,
+        next_offset,
+    }))
+}
+
+/// Streams committed records as Server-Sent Events, starting at `offset` or, if not given, with
+/// the ones published from now on. Reconnecting clients resume after their `Last-Event-ID`.
+async fn stream_records(
+    State(state): State<HttpState>,
+    Path(topic_name): Path<TopicName>,
+    Caller(session): Caller,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    authorize(&session, AclOperation::Subscribe, &topic_name)?;
+    admit(&state, &session, Some(&topic_name), 0).await?;
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let from_offset = last_event_id.map(|offset| offset + 1).or(query.offset);
+    let client_id = state.broker.register_new_client().await;
+    let unsubscribe = Unsubscribe::new(Arc::clone(&state.broker), topic_name, client_id);
+    let subscription = state
+        .broker
+        .subscribe(
+            &unsubscribe.topic_name,
+            from_offset,
+            IsolationLevel::ReadCommitted,
+            client_id,
+        )
+        .await?;
+    tracing::debug!(
+        "Streaming topic {} over HTTP to {}",
+        unsubscribe.topic_name,
+        client_id
+    );
+
+    let format = query.format;
+    let max_payload_size = state.broker.max_payload_size();
+    let consume_quota = session
+        .quotas
+        .clone()
+        .map(|quotas| (quotas, session.client_identity()));
+    let messages = futures::stream::unfold(
+        (subscription, unsubscribe, consume_quota),
+        |(mut subscription, unsubscribe, consume_quota)| async move {
+            let message = subscription.recv().await?;
+            subscription.record_delivered(message.payload.len());
+            pace_delivery(
+                &consume_quota,
+                &unsubscribe.topic_name,
+                message.payload.len(),
+            )
+            .await;
+            Some((message, (subscription, unsubscribe, consume_quota)))
+        },
+    );
+    let events = messages
+        .map(move |message| Ok(record_event(&message, format, max_payload_size)))
+        .take_until(state.shutdown.clone().cancelled_owned());
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// An event carrying the record as its data and its offset as its id; records that can't be
+/// represented in the requested format are sent as `error` events instead.
+fn record_event(message: &MessageRecord, format: RecordFormat, max_payload_size: usize) -> Event {
+    let event = Event::default().id(message.offset.to_string());
+    match RecordBody::new(message, format, max_payload_size) {
+        Ok(record) => event.json_data(record).unwrap_or_else(|e| {
+            Event::default()
+                .event("error")
+                .data(format!("Failed to encode record: {}", e))
+        }),
+        Err(ApiError(_, message)) => event.event("error").data(message),
+    }
+}
+
+/// Waits for as long as `consume_quota` asks after delivering `bytes` from `topic_name`, since
+/// consumers can't be told to back off.
+pub(super) async fn pace_delivery(
+    consume_quota: &Option<(Arc<QuotaManager>, String)>,
+    topic_name: &TopicName,
+    bytes: usize,
+) {
+    if let Some((quotas, client)) = consume_quota {
+        let delay = quotas.record_consumed(client, topic_name, bytes);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"))
+}
+
+/// Removes a streaming subscription from its topic and releases its client id once the stream is
+/// dropped, e.g. because the client went away.
+pub(super) struct Unsubscribe {
+    broker: Arc<Broker>,
+    topic_name: TopicName,
+    client_id: ClientId,
+}
+
+impl Unsubscribe {
+    pub(super) fn new(broker: Arc<Broker>, topic_name: TopicName, client_id: ClientId) -> Self {
+        Unsubscribe {
+            broker,
+            topic_name,
+            client_id,
+        }
+    }
+}
+
+impl Drop for Unsubscribe {
+    fn drop(&mut self) {
+        let broker = Arc::clone(&self.broker);
+        let topic_name = std::mem::take(&mut self.topic_name);
+        let client_id = self.client_id;
+        tokio::spawn(async move {
+            let _ = broker.unsubscribe(&topic_name, client_id).await;
+            broker.deregister_client(client_id).await;
+        });
+    }
+}
@@ -0,0 +1,157 @@
+use crate::auth::AclOperation;
+use crate::broker::Broker;
+use crate::http::records::{
+    RecordBody, RecordFormat, Unsubscribe, decompressed_payload, pace_delivery,
+};
+use crate::http::{ApiError, Caller, HttpState, admit, authorize};
+use crate::quota::QuotaManager;
+use crate::session::ClientRegistry;
+use crate::topic::{IsolationLevel, MessageRecord, Subscription, TopicName, TopicSubscriber};
+use axum::Router;
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code};
+use axum::extract::{Path, Query, State};
+use axum::response::Response;
+use axum::routing::get;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+/// Routes of the WebSocket gateway, feeding topics to browser clients through subscriptions
+/// like the ones of the binary protocol, under the same ACL operation and quotas.
+pub fn router() -> Router<HttpState> {
+    Router::new().route("/topics/{name}/ws", get(subscribe))
+}
+
+/// How records are sent over the socket.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum FrameFormat {
+    /// A text frame with `{"offset": ..., "value": ...}`, the value embedded as the JSON it holds.
+    #[default]
+    Json,
+    /// A binary frame with the payload as it was published.
+    Binary,
+}
+
+#[derive(Deserialize)]
+struct SubscribeQuery {
+    offset: Option<u64>,
+    #[serde(default)]
+    format: FrameFormat,
+}
+
+#[derive(Serialize)]
+struct ErrorFrame {
+    offset: u64,
+    error: String,
+}
+
+/// Subscribes to a topic, starting at `offset` or, if not given, with the records published from
+/// now on, and upgrades the connection to a WebSocket the records are sent over. The caller is
+/// authorized before the upgrade, so that refused subscriptions get a plain HTTP error.
+async fn subscribe(
+    ws: WebSocketUpgrade,
+    State(state): State<HttpState>,
+    Path(topic_name): Path<TopicName>,
+    Caller(session): Caller,
+    Query(query): Query<SubscribeQuery>,
+) -> Result<Response, ApiError> {
+    authorize(&session, AclOperation::Subscribe, &topic_name)?;
+    admit(&state, &session, Some(&topic_name), 0).await?;
+    let client_id = state.broker.register_new_client().await;
+    let unsubscribe = Unsubscribe::new(Arc::clone(&state.broker), topic_name.clone(), client_id);
+    let subscription = state
+        .broker
+        .subscribe(
+            &topic_name,
+            query.offset,
+            IsolationLevel::ReadCommitted,
+            client_id,
+        )
+        .await?;
+    tracing::debug!(
+        "Streaming topic {} over WebSocket to {}",
+        topic_name,
+        client_id
+    );
+    let consume_quota = session
+        .quotas
+        .clone()
+        .map(|quotas| (quotas, session.client_identity()));
+    Ok(ws.on_upgrade(move |socket| async move {
+        forward_records(
+            socket,
+            subscription,
+            query.format,
+            consume_quota,
+            &state.broker,
+            &state.shutdown,
+        )
+        .await;
+        drop(unsubscribe);
+    }))
+}
+
+/// Sends every record of `subscription` until the client closes the socket, the topic is deleted
+/// or the broker shuts down. Anything the client sends besides a close is ignored.
+async fn forward_records(
+    mut socket: WebSocket,
+    mut subscription: Subscription,
+    format: FrameFormat,
+    consume_quota: Option<(Arc<QuotaManager>, String)>,
+    broker: &Broker,
+    shutdown: &CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            message = subscription.recv() => {
+                let Some(message) = message else {
+                    let _ = socket.send(close(close_code::NORMAL, "Topic deleted")).await;
+                    break;
+                };
+                let payload_size = message.payload.len();
+                let frame = record_frame(&message, format, broker.max_payload_size());
+                if socket.send(frame).await.is_err() {
+                    break;
+                }
+                subscription.record_delivered(payload_size);
+                pace_delivery(&consume_quota, &subscription.topic_name, payload_size).await;
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+            _ = shutdown.cancelled() => {
+                let _ = socket.send(close(close_code::AWAY, "Broker shutting down")).await;
+                break;
+            }
+        }
+    }
+}
+
+/// The frame carrying `message`; a record that can't be represented as requested is replaced by
+/// a text frame with `{"offset": ..., "error": ...}`.
+fn record_frame(message: &MessageRecord, format: FrameFormat, max_payload_size: usize) -> Message {
+    let frame = match format {
+        FrameFormat::Json => RecordBody::new(message, RecordFormat::Json, max_payload_size)
+            .map(|record| Message::Text(serde_json::to_string(&record).unwrap_or_default().into())),
+        FrameFormat::Binary => decompressed_payload(message, max_payload_size)
+            .map(|payload| Message::Binary(payload.into())),
+    };
+    frame.unwrap_or_else(|ApiError(_, error)| {
+        let error = ErrorFrame {
+            offset: message.offset,
+            error,
+        };
+        Message::Text(serde_json::to_string(&error).unwrap_or_default().into())
+    })
+}
+
+fn close(code: u16, reason: &str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
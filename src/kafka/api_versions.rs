@@ -0,0 +1,28 @@
+use crate::kafka::{API_VERSIONS, APIS, error_code};
+use bytes::{BufMut, BytesMut};
+
+/// Lists the supported APIs and versions. A client asking with a version newer than the listener
+/// knows gets the list in the oldest format, so that it can retry with one that is supported.
+pub fn handle_request(version: i16) -> BytesMut {
+    let supported = APIS
+        .iter()
+        .find(|api| api.key == API_VERSIONS)
+        .is_some_and(|api| version <= api.max_version);
+    let (version, error_code) = if supported {
+        (version, error_code::NONE)
+    } else {
+        (0, error_code::UNSUPPORTED_VERSION)
+    };
+    let mut body = BytesMut::new();
+    body.put_i16(error_code);
+    body.put_i32(APIS.len() as i32);
+    for api in APIS {
+        body.put_i16(api.key);
+        body.put_i16(api.min_version);
+        body.put_i16(api.max_version);
+    }
+    if version >= 1 {
+        body.put_i32(0); // throttle time
+    }
+    body
+}
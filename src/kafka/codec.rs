@@ -0,0 +1,275 @@
+use crate::protocol::codec::{put_frame, split_frame};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Longest string a request can carry: its `i16` length and up to `i16::MAX` bytes.
+const MAX_STRING_SIZE: usize = 2 + i16::MAX as usize;
+/// Request header: the api key and version, the correlation id and the client id.
+const REQUEST_HEADER_SIZE: usize = 2 + 2 + 4 + MAX_STRING_SIZE;
+/// Produce fields around the records of a single partition: the transactional id, acks and
+/// timeout, the lengths of the topic and partition arrays, the topic name, the partition index and
+/// the length of its records.
+const PRODUCE_FIELDS_SIZE: usize = MAX_STRING_SIZE + 2 + 4 + 4 + MAX_STRING_SIZE + 4 + 4 + 4;
+/// A record batch header, and the framing of a single record in it: its attributes, and its
+/// length, timestamp and offset deltas, key and value lengths and header count as varints of at
+/// most 10 bytes each.
+const RECORD_BATCH_OVERHEAD: usize = 61 + 1 + 6 * 10;
+
+/// Room a request frame takes besides the value of a produced record.
+const MAX_FRAME_OVERHEAD: usize = REQUEST_HEADER_SIZE + PRODUCE_FIELDS_SIZE + RECORD_BATCH_OVERHEAD;
+
+/// A request as framed by a Kafka client: the header every API shares, followed by the body of
+/// the API and version it names.
+#[derive(Debug)]
+pub struct KafkaRequest {
+    pub api_key: i16,
+    pub api_version: i16,
+    pub correlation_id: i32,
+    pub client_id: Option<String>,
+    pub body: BytesMut,
+}
+
+/// A response body, framed with the correlation id of the request it answers.
+#[derive(Debug)]
+pub struct KafkaResponse {
+    pub correlation_id: i32,
+    pub body: BytesMut,
+}
+
+/// Frames Kafka requests and responses, which like the broker's own protocol are prefixed with
+/// their length. Only non-flexible API versions are supported, so request headers never carry
+/// tagged fields the body would have to skip, except for `ApiVersions` requests whose body isn't
+/// read for versions that are too new. Requests larger than a produce of a single record with a
+/// value of `max_payload_size` bytes could be are rejected before they are buffered.
+pub struct KafkaCodec {
+    max_frame_len: usize,
+}
+
+impl KafkaCodec {
+    pub fn new(max_payload_size: usize) -> Self {
+        KafkaCodec {
+            max_frame_len: max_payload_size.saturating_add(MAX_FRAME_OVERHEAD),
+        }
+    }
+}
+
+impl Decoder for KafkaCodec {
+    type Item = KafkaRequest;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some(mut frame) = split_frame(src, self.max_frame_len)? else {
+            return Ok(None);
+        };
+        let src = &mut frame;
+        let api_key = get_i16(src, "api_key")?;
+        let api_version = get_i16(src, "api_version")?;
+        let correlation_id = get_i32(src, "correlation_id")?;
+        let client_id = get_nullable_string(src, "client_id")?;
+        Ok(Some(KafkaRequest {
+            api_key,
+            api_version,
+            correlation_id,
+            client_id,
+            body: frame,
+        }))
+    }
+}
+
+impl Encoder<KafkaResponse> for KafkaCodec {
+    type Error = std::io::Error;
+
+    fn encode(&mut self, response: KafkaResponse, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        put_frame(dst, |dst| {
+            dst.put_i32(response.correlation_id);
+            dst.put_slice(&response.body);
+        });
+        Ok(())
+    }
+}
+
+fn too_short(name: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Buffer too short for {name}"),
+    )
+}
+
+pub fn get_i8(src: &mut BytesMut, name: &str) -> std::io::Result<i8> {
+    src.try_get_i8().map_err(|_| too_short(name))
+}
+
+pub fn get_bool(src: &mut BytesMut, name: &str) -> std::io::Result<bool> {
+    Ok(get_i8(src, name)? != 0)
+}
+
+pub fn get_i16(src: &mut BytesMut, name: &str) -> std::io::Result<i16> {
+    src.try_get_i16().map_err(|_| too_short(name))
+}
+
+pub fn get_i32(src: &mut BytesMut, name: &str) -> std::io::Result<i32> {
+    src.try_get_i32().map_err(|_| too_short(name))
+}
+
+pub fn get_i64(src: &mut BytesMut, name: &str) -> std::io::Result<i64> {
+    src.try_get_i64().map_err(|_| too_short(name))
+}
+
+pub fn get_string(src: &mut BytesMut, name: &str) -> std::io::Result<String> {
+    get_nullable_string(src, name)?.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Unexpected null {name}"),
+        )
+    })
+}
+
+/// Reads a string prefixed with its length as an `i16`, where `-1` stands for null.
+pub fn get_nullable_string(src: &mut BytesMut, name: &str) -> std::io::Result<Option<String>> {
+    let value_len = get_i16(src, name)?;
+    if value_len < 0 {
+        return Ok(None);
+    }
+    let value_len = value_len as usize;
+    if src.len() < value_len {
+        return Err(too_short(name));
+    }
+    let value = src.split_to(value_len);
+    String::from_utf8(value.to_vec()).map(Some).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Invalid UTF-8 in {name}"),
+        )
+    })
+}
+
+/// Splits off bytes prefixed with their length as an `i32`, where `-1` stands for null.
+pub fn get_nullable_bytes(src: &mut BytesMut, name: &str) -> std::io::Result<Option<Bytes>> {
+    let value_len = get_i32(src, name)?;
+    if value_len < 0 {
+        return Ok(None);
+    }
+    let value_len = value_len as usize;
+    if src.len() < value_len {
+        return Err(too_short(name));
+    }
+    Ok(Some(src.split_to(value_len).freeze()))
+}
+
+/// Reads an array prefixed with its length as an `i32`, where `-1` stands for null.
+pub fn get_nullable_array<T, F>(
+    src: &mut BytesMut,
+    name: &str,
+    mut f: F,
+) -> std::io::Result<Option<Vec<T>>>
+where
+    F: FnMut(&mut BytesMut) -> std::io::Result<T>,
+{
+    let array_len = get_i32(src, name)?;
+    if array_len < 0 {
+        return Ok(None);
+    }
+    // Every element takes at least a byte, which bounds what a bogus length can allocate.
+    let mut values = Vec::with_capacity((array_len as usize).min(src.len()));
+    for _ in 0..array_len {
+        values.push(f(src)?);
+    }
+    Ok(Some(values))
+}
+
+pub fn get_array<T, F>(src: &mut BytesMut, name: &str, f: F) -> std::io::Result<Vec<T>>
+where
+    F: FnMut(&mut BytesMut) -> std::io::Result<T>,
+{
+    Ok(get_nullable_array(src, name, f)?.unwrap_or_default())
+}
+
+pub fn put_bool(dst: &mut BytesMut, value: bool) {
+    dst.put_i8(value as i8);
+}
+
+pub fn put_string(dst: &mut BytesMut, value: &str) {
+    dst.put_i16(value.len() as i16);
+    dst.put_slice(value.as_bytes());
+}
+
+pub fn put_nullable_string(dst: &mut BytesMut, value: Option<&str>) {
+    match value {
+        Some(value) => put_string(dst, value),
+        None => dst.put_i16(-1),
+    }
+}
+
+pub fn put_nullable_bytes(dst: &mut BytesMut, value: Option<&[u8]>) {
+    match value {
+        Some(value) => {
+            dst.put_i32(value.len() as i32);
+            dst.put_slice(value);
+        }
+        None => dst.put_i32(-1),
+    }
+}
+
+pub fn put_array<T, F>(dst: &mut BytesMut, values: &[T], mut f: F)
+where
+    F: FnMut(&mut BytesMut, &T),
+{
+    dst.put_i32(values.len() as i32);
+    for value in values {
+        f(dst, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_request_header_and_keeps_the_body() {
+        let mut src = BytesMut::new();
+        put_frame(&mut src, |dst| {
+            dst.put_i16(3);
+            dst.put_i16(1);
+            dst.put_i32(42);
+            put_nullable_string(dst, Some("kcat"));
+            put_array(dst, &["orders"], |dst, topic| put_string(dst, topic));
+        });
+
+        let mut request = KafkaCodec::new(1024).decode(&mut src).unwrap().unwrap();
+        assert_eq!(request.api_key, 3);
+        assert_eq!(request.api_version, 1);
+        assert_eq!(request.correlation_id, 42);
+        assert_eq!(request.client_id.as_deref(), Some("kcat"));
+        let topics = get_array(&mut request.body, "topics", |src| get_string(src, "topic"));
+        assert_eq!(topics.unwrap(), vec!["orders".to_string()]);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn rejects_request_larger_than_max_payload() {
+        let mut src = BytesMut::from(&i32::MAX.to_be_bytes()[..]);
+        let error = KafkaCodec::new(1024).decode(&mut src).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn waits_for_request_as_large_as_a_produce_of_max_payload() {
+        let frame_len = (1024 + MAX_FRAME_OVERHEAD) as u32;
+        let mut src = BytesMut::from(&frame_len.to_be_bytes()[..]);
+        assert!(KafkaCodec::new(1024).decode(&mut src).unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_null_values() {
+        let mut src = BytesMut::new();
+        put_nullable_string(&mut src, None);
+        put_nullable_bytes(&mut src, None);
+        src.put_i32(-1);
+
+        assert_eq!(get_nullable_string(&mut src, "string").unwrap(), None);
+        assert_eq!(get_nullable_bytes(&mut src, "bytes").unwrap(), None);
+        let array = get_nullable_array(&mut src, "array", |src| get_i32(src, "element"));
+        assert_eq!(array.unwrap(), None);
+        assert!(get_i16(&mut src, "missing").is_err());
+    }
+}
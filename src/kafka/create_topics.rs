@@ -0,0 +1,174 @@
+use crate::auth::AclOperation;
+use crate::compression::Compression;
+use crate::kafka::codec::{
+    get_array, get_bool, get_i16, get_i32, get_nullable_string, get_string, put_array,
+    put_nullable_string, put_string,
+};
+use crate::kafka::{RequestContext, error_code};
+use crate::topic::{TopicAddError, TopicManager, TopicName};
+use bytes::{BufMut, BytesMut};
+
+/// Stands for the broker's default partition count or replication factor.
+const DEFAULT: i32 = -1;
+
+struct CreatableTopic {
+    name: TopicName,
+    num_partitions: i32,
+    replication_factor: i16,
+    has_assignments: bool,
+    configs: Vec<(String, Option<String>)>,
+}
+
+/// Adds topics, which have a single partition with a single replica. Of the topic configs only
+/// `compression.type` is understood.
+pub async fn handle_request<B>(
+    version: i16,
+    body: &mut BytesMut,
+    context: &RequestContext<'_, B>,
+) -> std::io::Result<BytesMut>
+where
+    B: TopicManager,
+{
+    let topics = get_array(body, "topics", |src| {
+        let name = get_string(src, "name")?;
+        let num_partitions = get_i32(src, "num_partitions")?;
+        let replication_factor = get_i16(src, "replication_factor")?;
+        let assignments = get_array(src, "assignments", |src| {
+            let _partition = get_i32(src, "partition")?;
+            get_array(src, "broker_ids", |src| get_i32(src, "broker_id"))
+        })?;
+        let configs = get_array(src, "configs", |src| {
+            Ok((
+                get_string(src, "config_name")?,
+                get_nullable_string(src, "config_value")?,
+            ))
+        })?;
+        Ok(CreatableTopic {
+            name,
+            num_partitions,
+            replication_factor,
+            has_assignments: !assignments.is_empty(),
+            configs,
+        })
+    })?;
+    let _timeout_ms = get_i32(body, "timeout_ms")?;
+    let validate_only = version >= 1 && get_bool(body, "validate_only")?;
+
+    let mut responses = Vec::with_capacity(topics.len());
+    for topic in topics {
+        let result = create_topic(&topic, validate_only, context).await;
+        let (error_code, error_message) = result.err().unwrap_or((error_code::NONE, None));
+        responses.push((topic.name, error_code, error_message));
+    }
+
+    let mut response = BytesMut::new();
+    if version >= 2 {
+        response.put_i32(0); // throttle time
+    }
+    put_array(
+        &mut response,
+        &responses,
+        |dst, (topic, error_code, error_message)| {
+            put_string(dst, topic);
+            dst.put_i16(*error_code);
+            if version >= 1 {
+                put_nullable_string(dst, error_message.as_deref());
+            }
+        },
+    );
+    Ok(response)
+}
+
+async fn create_topic<B>(
+    topic: &CreatableTopic,
+    validate_only: bool,
+    context: &RequestContext<'_, B>,
+) -> Result<(), (i16, Option<String>)>
+where
+    B: TopicManager,
+{
+    if !context
+        .session
+        .is_authorized(AclOperation::AddTopic, &topic.name)
+    {
+        tracing::warn!(
+            "Denied {} on topic {} to {}",
+            AclOperation::AddTopic,
+            topic.name,
+            context.session.principal_name()
+        );
+        return Err((error_code::TOPIC_AUTHORIZATION_FAILED, None));
+    }
+    if topic.name.is_empty() {
+        return Err((
+            error_code::INVALID_TOPIC_EXCEPTION,
+            Some("Topic name must not be empty".to_string()),
+        ));
+    }
+    if topic.num_partitions != 1 && topic.num_partitions != DEFAULT {
+        return Err((
+            error_code::INVALID_PARTITIONS,
+            Some("Topics have a single partition".to_string()),
+        ));
+    }
+    if topic.replication_factor != 1 && i32::from(topic.replication_factor) != DEFAULT {
+        return Err((
+            error_code::INVALID_REPLICATION_FACTOR,
+            Some("Topics have a single replica".to_string()),
+        ));
+    }
+    if topic.has_assignments {
+        return Err((
+            error_code::INVALID_REPLICA_ASSIGNMENT,
+            Some("Replica assignments are not supported".to_string()),
+        ));
+    }
+    let mut compression = None;
+    for (name, value) in &topic.configs {
+        compression = match (name.as_str(), value.as_deref()) {
+            ("compression.type", Some("uncompressed" | "producer") | None) => None,
+            ("compression.type", Some("gzip")) => Some(Compression::Gzip),
+            ("compression.type", Some("snappy")) => Some(Compression::Snappy),
+            ("compression.type", Some("lz4")) => Some(Compression::Lz4),
+            ("compression.type", Some("zstd")) => Some(Compression::Zstd),
+            _ => {
+                return Err((
+                    error_code::INVALID_CONFIG,
+                    Some(format!("Unsupported config {}={:?}", name, value)),
+                ));
+            }
+        };
+    }
+
+    let already_exists = || {
+        (
+            error_code::TOPIC_ALREADY_EXISTS,
+            Some(format!("Topic {} already exists", topic.name)),
+        )
+    };
+    if validate_only {
+        if context.broker.describe_topic(&topic.name).await.is_some() {
+            return Err(already_exists());
+        }
+        return Ok(());
+    }
+    tracing::debug!("Adding new topic over Kafka: {}", topic.name);
+    context
+        .broker
+        .add_topic(&topic.name, None, compression)
+        .await
+        .map_err(|e| match e {
+            TopicAddError::EmptyName => (
+                error_code::INVALID_TOPIC_EXCEPTION,
+                Some("Topic name must not be empty".to_string()),
+            ),
+            TopicAddError::AlreadyExists(_) => already_exists(),
+            TopicAddError::InvalidRetention { retention, limit } => (
+                error_code::INVALID_CONFIG,
+                Some(format!(
+                    "Retention {} is not between 1 and {}",
+                    retention, limit
+                )),
+            ),
+        })
+}
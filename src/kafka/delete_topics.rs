@@ -0,0 +1,48 @@
+use crate::auth::AclOperation;
+use crate::kafka::codec::{get_array, get_i32, get_string, put_array, put_string};
+use crate::kafka::{RequestContext, error_code};
+use crate::topic::TopicManager;
+use bytes::{BufMut, BytesMut};
+
+pub async fn handle_request<B>(
+    version: i16,
+    body: &mut BytesMut,
+    context: &RequestContext<'_, B>,
+) -> std::io::Result<BytesMut>
+where
+    B: TopicManager,
+{
+    let topics = get_array(body, "topic_names", |src| get_string(src, "topic"))?;
+    let _timeout_ms = get_i32(body, "timeout_ms")?;
+
+    let mut responses = Vec::with_capacity(topics.len());
+    for topic in topics {
+        let error_code = if !context
+            .session
+            .is_authorized(AclOperation::DeleteTopic, &topic)
+        {
+            tracing::warn!(
+                "Denied {} on topic {topic} to {}",
+                AclOperation::DeleteTopic,
+                context.session.principal_name()
+            );
+            error_code::TOPIC_AUTHORIZATION_FAILED
+        } else if context.broker.delete_topic(&topic).await {
+            tracing::debug!("Deleted topic over Kafka: {}", topic);
+            error_code::NONE
+        } else {
+            error_code::UNKNOWN_TOPIC_OR_PARTITION
+        };
+        responses.push((topic, error_code));
+    }
+
+    let mut response = BytesMut::new();
+    if version >= 1 {
+        response.put_i32(0); // throttle time
+    }
+    put_array(&mut response, &responses, |dst, (topic, error_code)| {
+        put_string(dst, topic);
+        dst.put_i16(*error_code);
+    });
+    Ok(response)
+}
@@ -0,0 +1,378 @@
+use crate::auth::AclOperation;
+use crate::kafka::codec::{
+    get_array, get_i8, get_i32, get_i64, get_string, put_array, put_nullable_bytes, put_string,
+};
+use crate::kafka::records::{self, FetchedRecord};
+use crate::kafka::{PARTITION, RequestContext, error_code, throttle_time_ms};
+use crate::topic::{IsolationLevel, MessageRecord, TopicManager, TopicName, TopicSubscriber};
+use bytes::{BufMut, BytesMut};
+use futures::FutureExt;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+const READ_COMMITTED: i8 = 1;
+
+/// Most records read from a partition's log at once.
+const READ_BATCH_SIZE: usize = 256;
+
+struct FetchPartition {
+    topic: TopicName,
+    index: i32,
+    fetch_offset: i64,
+    max_bytes: usize,
+}
+
+/// A partition being fetched, collecting records until it is full or the fetch is done.
+struct PartitionFetch {
+    topic: TopicName,
+    index: i32,
+    error_code: i16,
+    /// Offset the next read starts at.
+    next_offset: u64,
+    /// Wakes the fetch up when records are appended to the topic; `None` if it is not read from.
+    appended: Option<watch::Receiver<()>>,
+    records: Vec<FetchedRecord>,
+    bytes: usize,
+    max_bytes: usize,
+    /// No more records fit, or the topic went away.
+    done: bool,
+    /// High watermark, last stable offset and log start offset, once the fetch is done.
+    watermarks: [i64; 3],
+    /// How long the client has to back off before fetching from the topic again.
+    throttle_time: Duration,
+}
+
+impl PartitionFetch {
+    fn failed(partition: FetchPartition, error_code: i16) -> Self {
+        PartitionFetch {
+            topic: partition.topic,
+            index: partition.index,
+            error_code,
+            next_offset: 0,
+            appended: None,
+            records: Vec::new(),
+            bytes: 0,
+            max_bytes: partition.max_bytes,
+            done: true,
+            watermarks: [-1; 3],
+            throttle_time: Duration::ZERO,
+        }
+    }
+
+    /// A partition that is not read from until the client's quotas allow it again.
+    fn throttled(partition: FetchPartition, throttle_time: Duration) -> Self {
+        PartitionFetch {
+            throttle_time,
+            ..PartitionFetch::failed(partition, error_code::NONE)
+        }
+    }
+
+    fn is_waiting(&self) -> bool {
+        !self.done && self.appended.is_some()
+    }
+}
+
+/// Reads records of every requested partition straight from the topic logs, waiting up to
+/// `max_wait_ms` for `min_bytes` to be available. Records are returned as one uncompressed batch
+/// per partition. Fetch sessions are not supported, so every fetch is a full one. Fetched bytes
+/// count towards the consume quotas, and the time the client has to back off for them is
+/// returned as the throttle time.
+pub async fn handle_request<B>(
+    version: i16,
+    body: &mut BytesMut,
+    context: &RequestContext<'_, B>,
+) -> std::io::Result<BytesMut>
+where
+    B: TopicManager + TopicSubscriber,
+{
+    let _replica_id = get_i32(body, "replica_id")?;
+    let max_wait = Duration::from_millis(get_i32(body, "max_wait_ms")?.max(0) as u64);
+    let min_bytes = get_i32(body, "min_bytes")?.max(0) as usize;
+    let max_bytes = get_i32(body, "max_bytes")?.max(0) as usize;
+    let isolation_level = match get_i8(body, "isolation_level")? {
+        READ_COMMITTED => IsolationLevel::ReadCommitted,
+        _ => IsolationLevel::ReadUncommitted,
+    };
+    if version >= 7 {
+        let _session_id = get_i32(body, "session_id")?;
+        let _session_epoch = get_i32(body, "session_epoch")?;
+    }
+    let topics = get_array(body, "topics", |src| {
+        let topic = get_string(src, "topic")?;
+        get_array(src, "partitions", |src| {
+            let index = get_i32(src, "partition")?;
+            if version >= 9 {
+                let _current_leader_epoch = get_i32(src, "current_leader_epoch")?;
+            }
+            let fetch_offset = get_i64(src, "fetch_offset")?;
+            if version >= 5 {
+                let _log_start_offset = get_i64(src, "log_start_offset")?;
+            }
+            let max_bytes = get_i32(src, "partition_max_bytes")?.max(0) as usize;
+            Ok(FetchPartition {
+                topic: topic.clone(),
+                index,
+                fetch_offset,
+                max_bytes,
+            })
+        })
+    })?;
+    // Forgotten topics and the rack id only matter to fetch sessions and follower fetching.
+
+    let mut partitions = Vec::new();
+    for partition in topics.into_iter().flatten() {
+        partitions.push(start_fetch(partition, context).await);
+    }
+    collect_records(
+        &mut partitions,
+        min_bytes,
+        max_bytes,
+        max_wait,
+        isolation_level,
+        context,
+    )
+    .await;
+
+    let mut responses: Vec<(TopicName, Vec<PartitionFetch>)> = Vec::new();
+    for mut partition in partitions {
+        if partition.appended.take().is_some() {
+            partition.throttle_time = context.record_consumed(&partition.topic, partition.bytes);
+        }
+        if let Some(description) = context.broker.describe_topic(&partition.topic).await {
+            partition.watermarks = [
+                description.end_offset as i64,
+                description.last_stable_offset as i64,
+                description.start_offset as i64,
+            ];
+        }
+        match responses.last_mut() {
+            Some((topic, fetched)) if *topic == partition.topic => fetched.push(partition),
+            _ => responses.push((partition.topic.clone(), vec![partition])),
+        }
+    }
+
+    let throttle_time = responses
+        .iter()
+        .flat_map(|(_, partitions)| partitions)
+        .map(|partition| partition.throttle_time)
+        .max()
+        .unwrap_or_default();
+    let mut response = BytesMut::new();
+    response.put_i32(throttle_time_ms(throttle_time));
+    if version >= 7 {
+        response.put_i16(error_code::NONE);
+        response.put_i32(0); // no fetch session
+    }
+    put_array(&mut response, &responses, |dst, (topic, partitions)| {
+        put_string(dst, topic);
+        put_array(dst, partitions, |dst, partition| {
+            let [high_watermark, last_stable_offset, log_start_offset] = partition.watermarks;
+            dst.put_i32(partition.index);
+            dst.put_i16(partition.error_code);
+            dst.put_i64(high_watermark);
+            dst.put_i64(last_stable_offset);
+            if version >= 5 {
+                dst.put_i64(log_start_offset);
+            }
+            dst.put_i32(0); // aborted transactions
+            if version >= 11 {
+                dst.put_i32(-1); // preferred read replica
+            }
+            let mut batch = BytesMut::new();
+            records::encode_batch(&mut batch, &partition.records);
+            put_nullable_bytes(dst, Some(&batch));
+        });
+    });
+    Ok(response)
+}
+
+/// Starts reading the partition at the requested offset, which has to be within the retained
+/// part of the log.
+async fn start_fetch<B>(
+    partition: FetchPartition,
+    context: &RequestContext<'_, B>,
+) -> PartitionFetch
+where
+    B: TopicManager + TopicSubscriber,
+{
+    if !context
+        .session
+        .is_authorized(AclOperation::Subscribe, &partition.topic)
+    {
+        tracing::warn!(
+            "Denied {} on topic {} to {}",
+            AclOperation::Subscribe,
+            partition.topic,
+            context.session.principal_name()
+        );
+        return PartitionFetch::failed(partition, error_code::TOPIC_AUTHORIZATION_FAILED);
+    }
+    let description = match context.broker.describe_topic(&partition.topic).await {
+        Some(description) if partition.index == PARTITION => description,
+        _ => return PartitionFetch::failed(partition, error_code::UNKNOWN_TOPIC_OR_PARTITION),
+    };
+    let in_range = u64::try_from(partition.fetch_offset)
+        .is_ok_and(|offset| (description.start_offset..=description.end_offset).contains(&offset));
+    if !in_range {
+        return PartitionFetch::failed(partition, error_code::OFFSET_OUT_OF_RANGE);
+    }
+    // Consumers in debt get nothing until they paid it back, whether or not they honored the
+    // throttle time of their previous fetch.
+    if let Err(throttle_time) = context.admit(Some(&partition.topic), 0) {
+        return PartitionFetch::throttled(partition, throttle_time);
+    }
+    let throttle_time = context.record_consumed(&partition.topic, 0);
+    if !throttle_time.is_zero() {
+        return PartitionFetch::throttled(partition, throttle_time);
+    }
+    match context.broker.appended(&partition.topic).await {
+        Ok(appended) => PartitionFetch {
+            topic: partition.topic,
+            index: partition.index,
+            error_code: error_code::NONE,
+            next_offset: partition.fetch_offset as u64,
+            appended: Some(appended),
+            records: Vec::new(),
+            bytes: 0,
+            max_bytes: partition.max_bytes,
+            done: false,
+            watermarks: [-1; 3],
+            throttle_time: Duration::ZERO,
+        },
+        Err(_) => PartitionFetch::failed(partition, error_code::UNKNOWN_TOPIC_OR_PARTITION),
+    }
+}
+
+/// Takes what is available right away and then waits for more until `min_bytes` are collected,
+/// `max_wait` passes or the broker shuts down. The first record is always returned, however
+/// large, so that consumers make progress.
+async fn collect_records<B>(
+    partitions: &mut [PartitionFetch],
+    min_bytes: usize,
+    max_bytes: usize,
+    max_wait: Duration,
+    isolation_level: IsolationLevel,
+    context: &RequestContext<'_, B>,
+) where
+    B: TopicSubscriber,
+{
+    let deadline = Instant::now() + max_wait;
+    let mut total_bytes = 0;
+    loop {
+        for partition in partitions
+            .iter_mut()
+            .filter(|partition| partition.is_waiting())
+        {
+            read_records(
+                partition,
+                isolation_level,
+                &mut total_bytes,
+                max_bytes,
+                context,
+            )
+            .await;
+        }
+        if total_bytes >= min_bytes || total_bytes >= max_bytes {
+            return;
+        }
+        let waiting: Vec<_> = partitions
+            .iter_mut()
+            .filter(|partition| partition.is_waiting())
+            .filter_map(|partition| partition.appended.as_mut())
+            .map(|appended| appended.changed().boxed())
+            .collect();
+        if waiting.is_empty() {
+            return;
+        }
+        tokio::select! {
+            // Every waiting partition is read again; the ones without new records are cheap.
+            _ = futures::future::select_all(waiting) => {}
+            _ = tokio::time::sleep_until(deadline) => return,
+            _ = context.shutdown.cancelled() => return,
+        }
+    }
+}
+
+/// Reads the records appended to the partition since the last read, until it is full or there are
+/// no more.
+async fn read_records<B>(
+    partition: &mut PartitionFetch,
+    isolation_level: IsolationLevel,
+    total_bytes: &mut usize,
+    max_bytes: usize,
+    context: &RequestContext<'_, B>,
+) where
+    B: TopicSubscriber,
+{
+    // Appends made from here on wake the fetch up again, even if they are already read below.
+    if let Some(appended) = &mut partition.appended {
+        appended.mark_unchanged();
+    }
+    while !partition.done {
+        let messages = context
+            .broker
+            .fetch(
+                &partition.topic,
+                partition.next_offset,
+                isolation_level,
+                READ_BATCH_SIZE,
+            )
+            .await;
+        let Ok(messages) = messages else {
+            // The topic was deleted while waiting.
+            partition.done = true;
+            return;
+        };
+        if messages.is_empty() {
+            return;
+        }
+        for message in messages {
+            if partition.done {
+                break;
+            }
+            partition.next_offset = message.offset + 1;
+            add_record(partition, message, total_bytes, max_bytes, context);
+        }
+    }
+}
+
+fn add_record<B>(
+    partition: &mut PartitionFetch,
+    message: MessageRecord,
+    total_bytes: &mut usize,
+    max_bytes: usize,
+    context: &RequestContext<'_, B>,
+) {
+    let value = match message
+        .compression
+        .decompress(&message.payload, context.max_payload_size)
+    {
+        Ok(value) => value,
+        Err(e) => {
+            tracing::error!(
+                "Failed to decompress record at offset {} of {}: {}",
+                message.offset,
+                partition.topic,
+                e
+            );
+            partition.error_code = error_code::CORRUPT_MESSAGE;
+            partition.done = true;
+            return;
+        }
+    };
+    let is_first = *total_bytes == 0;
+    let fits = partition.bytes + value.len() <= partition.max_bytes
+        && *total_bytes + value.len() <= max_bytes;
+    if !is_first && !fits {
+        partition.done = true;
+        return;
+    }
+    partition.bytes += value.len();
+    *total_bytes += value.len();
+    partition.records.push(FetchedRecord {
+        offset: message.offset,
+        value: value.into(),
+        traceparent: message.traceparent.as_deref().map(str::to_string),
+    });
+}
@@ -0,0 +1,89 @@
+use crate::auth::AclOperation;
+use crate::kafka::codec::{get_array, get_i8, get_i32, get_i64, get_string, put_array, put_string};
+use crate::kafka::{PARTITION, RequestContext, error_code};
+use crate::topic::{TopicManager, TopicName};
+use bytes::{BufMut, BytesMut};
+
+const LATEST_TIMESTAMP: i64 = -1;
+const EARLIEST_TIMESTAMP: i64 = -2;
+const READ_COMMITTED: i8 = 1;
+
+struct PartitionOffset {
+    index: i32,
+    error_code: i16,
+    offset: i64,
+}
+
+/// Looks up the earliest and latest offsets of partitions. Records keep no timestamps, so any
+/// other timestamp finds no offset.
+pub async fn handle_request<B>(
+    version: i16,
+    body: &mut BytesMut,
+    context: &RequestContext<'_, B>,
+) -> std::io::Result<BytesMut>
+where
+    B: TopicManager,
+{
+    let _replica_id = get_i32(body, "replica_id")?;
+    let read_committed = version >= 2 && get_i8(body, "isolation_level")? == READ_COMMITTED;
+    let topics = get_array(body, "topics", |src| {
+        let topic = get_string(src, "topic")?;
+        let partitions = get_array(src, "partitions", |src| {
+            let index = get_i32(src, "partition")?;
+            if version >= 4 {
+                let _current_leader_epoch = get_i32(src, "current_leader_epoch")?;
+            }
+            Ok((index, get_i64(src, "timestamp")?))
+        })?;
+        Ok((topic, partitions))
+    })?;
+
+    let mut responses: Vec<(TopicName, Vec<PartitionOffset>)> = Vec::with_capacity(topics.len());
+    for (topic, partitions) in topics {
+        let authorized = context
+            .session
+            .is_authorized(AclOperation::Subscribe, &topic);
+        let description = context.broker.describe_topic(&topic).await;
+        let offsets = partitions
+            .into_iter()
+            .map(|(index, timestamp)| {
+                let (error_code, offset) = match &description {
+                    _ if !authorized => (error_code::TOPIC_AUTHORIZATION_FAILED, -1),
+                    Some(description) if index == PARTITION => match timestamp {
+                        LATEST_TIMESTAMP if read_committed => {
+                            (error_code::NONE, description.last_stable_offset as i64)
+                        }
+                        LATEST_TIMESTAMP => (error_code::NONE, description.end_offset as i64),
+                        EARLIEST_TIMESTAMP => (error_code::NONE, description.start_offset as i64),
+                        _ => (error_code::NONE, -1),
+                    },
+                    _ => (error_code::UNKNOWN_TOPIC_OR_PARTITION, -1),
+                };
+                PartitionOffset {
+                    index,
+                    error_code,
+                    offset,
+                }
+            })
+            .collect();
+        responses.push((topic, offsets));
+    }
+
+    let mut response = BytesMut::new();
+    if version >= 2 {
+        response.put_i32(0); // throttle time
+    }
+    put_array(&mut response, &responses, |dst, (topic, partitions)| {
+        put_string(dst, topic);
+        put_array(dst, partitions, |dst, partition| {
+            dst.put_i32(partition.index);
+            dst.put_i16(partition.error_code);
+            dst.put_i64(-1); // timestamp
+            dst.put_i64(partition.offset);
+            if version >= 4 {
+                dst.put_i32(0); // leader epoch
+            }
+        });
+    });
+    Ok(response)
+}
@@ -0,0 +1,111 @@
+use crate::auth::AclOperation;
+use crate::kafka::codec::{
+    get_bool, get_nullable_array, get_string, put_array, put_bool, put_nullable_string, put_string,
+};
+use crate::kafka::{CLUSTER_ID, NODE_ID, PARTITION, RequestContext, error_code};
+use crate::topic::{TopicManager, TopicName};
+use bytes::{BufMut, BytesMut};
+
+/// Stands for authorized operations that were not asked for.
+const OPERATIONS_NOT_REQUESTED: i32 = i32::MIN;
+
+/// Describes the cluster, made of this broker alone, and the requested topics; without a list of
+/// topics every topic the client may see is described. Topics are never created on the fly.
+pub async fn handle_request<B>(
+    version: i16,
+    body: &mut BytesMut,
+    context: &RequestContext<'_, B>,
+) -> std::io::Result<BytesMut>
+where
+    B: TopicManager,
+{
+    let requested = get_nullable_array(body, "topics", |src| get_string(src, "topic"))?;
+    if version >= 4 {
+        let _allow_auto_topic_creation = get_bool(body, "allow_auto_topic_creation")?;
+    }
+    if version >= 8 {
+        let _include_cluster_operations = get_bool(body, "include_cluster_operations")?;
+        let _include_topic_operations = get_bool(body, "include_topic_operations")?;
+    }
+    // Before version 1 an empty list stood for every topic.
+    let requested = requested.filter(|topics| version >= 1 || !topics.is_empty());
+
+    let session = context.session;
+    let topics: Vec<(TopicName, i16)> = match requested {
+        Some(topics) => {
+            let mut described = Vec::with_capacity(topics.len());
+            for topic in topics {
+                let error_code = if !session.is_authorized(AclOperation::ListTopics, &topic) {
+                    error_code::TOPIC_AUTHORIZATION_FAILED
+                } else if context.broker.describe_topic(&topic).await.is_none() {
+                    error_code::UNKNOWN_TOPIC_OR_PARTITION
+                } else {
+                    error_code::NONE
+                };
+                described.push((topic, error_code));
+            }
+            described
+        }
+        None => {
+            let mut topics = context.broker.list_topics().await;
+            topics.retain(|topic| session.is_authorized(AclOperation::ListTopics, topic));
+            topics.sort();
+            topics
+                .into_iter()
+                .map(|topic| (topic, error_code::NONE))
+                .collect()
+        }
+    };
+    tracing::debug!("Describing {} topics over Kafka", topics.len());
+
+    let mut response = BytesMut::new();
+    if version >= 3 {
+        response.put_i32(0); // throttle time
+    }
+    put_array(&mut response, &[context.advertised], |dst, address| {
+        dst.put_i32(NODE_ID);
+        put_string(dst, &address.ip().to_string());
+        dst.put_i32(address.port().into());
+        if version >= 1 {
+            put_nullable_string(dst, None); // rack
+        }
+    });
+    if version >= 2 {
+        put_nullable_string(&mut response, Some(CLUSTER_ID));
+    }
+    if version >= 1 {
+        response.put_i32(NODE_ID); // controller
+    }
+    put_array(&mut response, &topics, |dst, (topic, error_code)| {
+        dst.put_i16(*error_code);
+        put_string(dst, topic);
+        if version >= 1 {
+            put_bool(dst, false); // internal
+        }
+        let partitions: &[i32] = if *error_code == error_code::NONE {
+            &[PARTITION]
+        } else {
+            &[]
+        };
+        put_array(dst, partitions, |dst, partition| {
+            dst.put_i16(error_code::NONE);
+            dst.put_i32(*partition);
+            dst.put_i32(NODE_ID); // leader
+            if version >= 7 {
+                dst.put_i32(0); // leader epoch
+            }
+            put_array(dst, &[NODE_ID], |dst, node| dst.put_i32(*node)); // replicas
+            put_array(dst, &[NODE_ID], |dst, node| dst.put_i32(*node)); // in-sync replicas
+            if version >= 5 {
+                put_array(dst, &[] as &[i32], |dst, node| dst.put_i32(*node)); // offline
+            }
+        });
+        if version >= 8 {
+            dst.put_i32(OPERATIONS_NOT_REQUESTED);
+        }
+    });
+    if version >= 8 {
+        response.put_i32(OPERATIONS_NOT_REQUESTED);
+    }
+    Ok(response)
+}
@@ -0,0 +1,296 @@
+//! A listener speaking the subset of the Apache Kafka protocol that producers, consumers and admin
+//! tools need, mapped onto the same broker traits as kafkalite's own protocol. Every topic is
+//! presented as a single partition `0` led by this broker, the only node of its cluster.
+
+mod api_versions;
+mod codec;
+mod create_topics;
+mod delete_topics;
+mod fetch;
+mod list_offsets;
+mod metadata;
+mod produce;
+mod records;
+
+use crate::metrics::BrokerMetrics;
+use crate::session::Session;
+use crate::topic::{TopicManager, TopicName, TopicPublisher, TopicSubscriber};
+use bytes::BytesMut;
+use codec::{KafkaCodec, KafkaRequest, KafkaResponse};
+use futures::{SinkExt, StreamExt};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::Framed;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+const PRODUCE: i16 = 0;
+const FETCH: i16 = 1;
+const LIST_OFFSETS: i16 = 2;
+const METADATA: i16 = 3;
+const API_VERSIONS: i16 = 18;
+const CREATE_TOPICS: i16 = 19;
+const DELETE_TOPICS: i16 = 20;
+
+/// Id of the only node of the cluster.
+const NODE_ID: i32 = 0;
+/// The only partition of every topic.
+const PARTITION: i32 = 0;
+const CLUSTER_ID: &str = "kafkalite";
+
+/// An API the listener serves, with the range of versions it understands. Only versions without
+/// tagged fields are supported.
+struct Api {
+    key: i16,
+    name: &'static str,
+    min_version: i16,
+    max_version: i16,
+}
+
+const APIS: &[Api] = &[
+    Api {
+        key: PRODUCE,
+        name: "Produce",
+        min_version: 3,
+        max_version: 8,
+    },
+    Api {
+        key: FETCH,
+        name: "Fetch",
+        min_version: 4,
+        max_version: 11,
+    },
+    Api {
+        key: LIST_OFFSETS,
+        name: "ListOffsets",
+        min_version: 1,
+        max_version: 5,
+    },
+    Api {
+        key: METADATA,
+        name: "Metadata",
+        min_version: 0,
+        max_version: 8,
+    },
+    Api {
+        key: API_VERSIONS,
+        name: "ApiVersions",
+        min_version: 0,
+        max_version: 2,
+    },
+    Api {
+        key: CREATE_TOPICS,
+        name: "CreateTopics",
+        min_version: 0,
+        max_version: 4,
+    },
+    Api {
+        key: DELETE_TOPICS,
+        name: "DeleteTopics",
+        min_version: 0,
+        max_version: 3,
+    },
+];
+
+/// Error codes as defined by the Kafka protocol.
+mod error_code {
+    pub const UNKNOWN_SERVER_ERROR: i16 = -1;
+    pub const NONE: i16 = 0;
+    pub const OFFSET_OUT_OF_RANGE: i16 = 1;
+    pub const CORRUPT_MESSAGE: i16 = 2;
+    pub const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+    pub const MESSAGE_TOO_LARGE: i16 = 10;
+    pub const INVALID_TOPIC_EXCEPTION: i16 = 17;
+    pub const TOPIC_AUTHORIZATION_FAILED: i16 = 29;
+    pub const UNSUPPORTED_VERSION: i16 = 35;
+    pub const TOPIC_ALREADY_EXISTS: i16 = 36;
+    pub const INVALID_PARTITIONS: i16 = 37;
+    pub const INVALID_REPLICATION_FACTOR: i16 = 38;
+    pub const INVALID_REPLICA_ASSIGNMENT: i16 = 39;
+    pub const INVALID_CONFIG: i16 = 40;
+    pub const UNSUPPORTED_FOR_MESSAGE_FORMAT: i16 = 43;
+    pub const UNSUPPORTED_COMPRESSION_TYPE: i16 = 76;
+    pub const THROTTLING_QUOTA_EXCEEDED: i16 = 89;
+}
+
+/// What the requests of a connection are served with.
+struct RequestContext<'a, B> {
+    broker: &'a B,
+    metrics: &'a BrokerMetrics,
+    session: &'a Session,
+    /// Most bytes a payload may decompress to.
+    max_payload_size: usize,
+    /// Address clients are told to reach the broker at, the one they connected to.
+    advertised: SocketAddr,
+    /// Cancelled once the broker starts shutting down, which ends long-polling fetches early.
+    shutdown: &'a CancellationToken,
+}
+
+impl<B> RequestContext<'_, B> {
+    /// Admits a request touching `topic` and producing `produce_bytes` under the session's
+    /// quotas, or returns how long the client has to back off.
+    fn admit(&self, topic: Option<&TopicName>, produce_bytes: usize) -> Result<(), Duration> {
+        match &self.session.quotas {
+            Some(quotas) => {
+                quotas.admit_request(&self.session.client_identity(), topic, produce_bytes)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// How long the client has to back off before its next request touching `topic` is admitted.
+    fn throttle_time(&self, topic: Option<&TopicName>) -> Duration {
+        match &self.session.quotas {
+            Some(quotas) => quotas.throttle_time(&self.session.client_identity(), topic),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Records `bytes` fetched from `topic` and returns how long the client has to back off to
+    /// stay within its consume quotas.
+    fn record_consumed(&self, topic: &TopicName, bytes: usize) -> Duration {
+        match &self.session.quotas {
+            Some(quotas) => quotas.record_consumed(&self.session.client_identity(), topic, bytes),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// Throttle times are reported in whole milliseconds.
+fn throttle_time_ms(throttle_time: Duration) -> i32 {
+    throttle_time.as_millis().try_into().unwrap_or(i32::MAX)
+}
+
+/// Serves Kafka requests one after the other, the order clients expect their responses in, until
+/// the client disconnects, idles for `connection_timeout` or the broker shuts down.
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_connection<S, B>(
+    socket: S,
+    client_addr: &str,
+    advertised: SocketAddr,
+    session: &Session,
+    broker: &B,
+    metrics: &BrokerMetrics,
+    max_payload_size: usize,
+    connection_timeout: Duration,
+    shutdown: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    B: TopicManager + TopicPublisher + TopicSubscriber,
+{
+    let mut framed = Framed::new(socket, KafkaCodec::new(max_payload_size));
+    let context = RequestContext {
+        broker,
+        metrics,
+        session,
+        max_payload_size,
+        advertised,
+        shutdown,
+    };
+    loop {
+        let request = tokio::select! {
+            request = tokio::time::timeout(connection_timeout, framed.next()) => request,
+            _ = shutdown.cancelled() => {
+                tracing::debug!("Closing Kafka connection with {client_addr} for shutdown");
+                break;
+            }
+        };
+        let request = match request {
+            Ok(Some(request)) => request?,
+            Ok(None) => {
+                tracing::debug!("Connection with {client_addr} closed");
+                break;
+            }
+            Err(_) => {
+                tracing::warn!("Connection with {client_addr} timed out after being idle");
+                break;
+            }
+        };
+        let correlation_id = request.correlation_id;
+        if let Some(body) = dispatch_request(request, &context).await? {
+            framed
+                .send(KafkaResponse {
+                    correlation_id,
+                    body,
+                })
+                .await?;
+        }
+    }
+    framed.close().await?;
+    Ok(())
+}
+
+/// Answers a request with its response body, or `None` for requests that go unanswered. Unknown
+/// APIs and unsupported versions fail, closing the connection as Kafka brokers do.
+async fn dispatch_request<B>(
+    mut request: KafkaRequest,
+    context: &RequestContext<'_, B>,
+) -> std::io::Result<Option<BytesMut>>
+where
+    B: TopicManager + TopicPublisher + TopicSubscriber,
+{
+    let version = request.api_version;
+    let Some(api) = APIS.iter().find(|api| api.key == request.api_key) else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Unsupported Kafka API {}", request.api_key),
+        ));
+    };
+    // Clients that are newer than the listener learn which versions to use from the error.
+    if api.key != API_VERSIONS && !(api.min_version..=api.max_version).contains(&version) {
+        context.metrics.record_request_error(api.name);
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Unsupported version {} of Kafka API {}", version, api.name),
+        ));
+    }
+    let span = tracing::info_span!(
+        "request",
+        request = api.name,
+        version,
+        client_id = request.client_id.as_deref().unwrap_or_default(),
+    );
+    let body = &mut request.body;
+    // Produce and fetch requests are admitted per topic and report their throttle time. Other
+    // requests have no topic to account to and are held back until the client's quotas allow
+    // them, as Kafka brokers mute throttled connections.
+    if api.key != PRODUCE
+        && api.key != FETCH
+        && let Err(throttle_time) = context.admit(None, 0)
+    {
+        tokio::select! {
+            _ = tokio::time::sleep(throttle_time) => {}
+            _ = context.shutdown.cancelled() => {}
+        }
+    }
+    let response = async {
+        match api.key {
+            PRODUCE => produce::handle_request(version, body, context).await,
+            FETCH => fetch::handle_request(version, body, context)
+                .await
+                .map(Some),
+            LIST_OFFSETS => list_offsets::handle_request(version, body, context)
+                .await
+                .map(Some),
+            METADATA => metadata::handle_request(version, body, context)
+                .await
+                .map(Some),
+            API_VERSIONS => Ok(Some(api_versions::handle_request(version))),
+            CREATE_TOPICS => create_topics::handle_request(version, body, context)
+                .await
+                .map(Some),
+            DELETE_TOPICS => delete_topics::handle_request(version, body, context)
+                .await
+                .map(Some),
+            _ => unreachable!("every API in APIS is dispatched"),
+        }
+    }
+    .instrument(span)
+    .await;
+    if response.is_err() {
+        context.metrics.record_request_error(api.name);
+    }
+    response
+}
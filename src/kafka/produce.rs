@@ -0,0 +1,217 @@
+use crate::auth::AclOperation;
+use crate::compression::Compression;
+use crate::kafka::codec::{
+    get_array, get_i16, get_i32, get_nullable_bytes, get_nullable_string, get_string, put_array,
+    put_nullable_string, put_string,
+};
+use crate::kafka::records::{self, Record, RecordsError};
+use crate::kafka::{PARTITION, RequestContext, error_code, throttle_time_ms};
+use crate::telemetry;
+use crate::topic::{TopicManager, TopicName, TopicPublishError, TopicPublisher};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+/// Producers asking for no acknowledgement get no response at all.
+const NO_ACKS: i16 = 0;
+
+struct PartitionData {
+    index: i32,
+    records: Option<Bytes>,
+}
+
+struct PartitionResponse {
+    index: i32,
+    error_code: i16,
+    base_offset: i64,
+    log_start_offset: i64,
+    /// How long the client has to back off before producing to the topic again.
+    throttle_time: Duration,
+}
+
+/// Appends the records of every partition, each record value as a message of its own; keys,
+/// timestamps and headers other than `traceparent` are dropped. Every topic is admitted under
+/// the quotas as a request of its own, and records of throttled topics are not appended.
+pub async fn handle_request<B>(
+    version: i16,
+    body: &mut BytesMut,
+    context: &RequestContext<'_, B>,
+) -> std::io::Result<Option<BytesMut>>
+where
+    B: TopicManager + TopicPublisher,
+{
+    let _transactional_id = get_nullable_string(body, "transactional_id")?;
+    let acks = get_i16(body, "acks")?;
+    let _timeout_ms = get_i32(body, "timeout_ms")?;
+    let topics = get_array(body, "topic_data", |src| {
+        let topic = get_string(src, "topic")?;
+        let partitions = get_array(src, "partition_data", |src| {
+            Ok(PartitionData {
+                index: get_i32(src, "partition")?,
+                records: get_nullable_bytes(src, "records")?,
+            })
+        })?;
+        Ok((topic, partitions))
+    })?;
+
+    let mut responses = Vec::with_capacity(topics.len());
+    for (topic, partitions) in topics {
+        let mut partition_responses = Vec::with_capacity(partitions.len());
+        for partition in partitions {
+            partition_responses.push(produce_partition(&topic, partition, context).await);
+        }
+        responses.push((topic, partition_responses));
+    }
+    if acks == NO_ACKS {
+        return Ok(None);
+    }
+    let throttle_time = responses
+        .iter()
+        .flat_map(|(_, partitions)| partitions)
+        .map(|partition| partition.throttle_time)
+        .max()
+        .unwrap_or_default();
+
+    let mut response = BytesMut::new();
+    put_array(&mut response, &responses, |dst, (topic, partitions)| {
+        put_string(dst, topic);
+        put_array(dst, partitions, |dst, partition| {
+            dst.put_i32(partition.index);
+            dst.put_i16(partition.error_code);
+            dst.put_i64(partition.base_offset);
+            dst.put_i64(-1); // log append time, as records keep no timestamps
+            if version >= 5 {
+                dst.put_i64(partition.log_start_offset);
+            }
+            if version >= 8 {
+                dst.put_i32(0); // record errors
+                put_nullable_string(dst, None);
+            }
+        });
+    });
+    response.put_i32(throttle_time_ms(throttle_time));
+    Ok(Some(response))
+}
+
+async fn produce_partition<B>(
+    topic: &TopicName,
+    partition: PartitionData,
+    context: &RequestContext<'_, B>,
+) -> PartitionResponse
+where
+    B: TopicManager + TopicPublisher,
+{
+    let mut response = PartitionResponse {
+        index: partition.index,
+        error_code: error_code::NONE,
+        base_offset: -1,
+        log_start_offset: -1,
+        throttle_time: Duration::ZERO,
+    };
+    if !context.session.is_authorized(AclOperation::Publish, topic) {
+        tracing::warn!(
+            "Denied {} on topic {topic} to {}",
+            AclOperation::Publish,
+            context.session.principal_name()
+        );
+        response.error_code = error_code::TOPIC_AUTHORIZATION_FAILED;
+        return response;
+    }
+    if partition.index != PARTITION || !context.broker.topic_exists(topic).await {
+        response.error_code = error_code::UNKNOWN_TOPIC_OR_PARTITION;
+        return response;
+    }
+    let produce_bytes = partition.records.as_ref().map_or(0, Bytes::len);
+    if let Err(throttle_time) = context.admit(Some(topic), produce_bytes) {
+        tracing::debug!("Throttled producing to {topic} for {throttle_time:?}");
+        response.error_code = error_code::THROTTLING_QUOTA_EXCEEDED;
+        response.throttle_time = throttle_time;
+        return response;
+    }
+    response.throttle_time = context.throttle_time(Some(topic));
+    let records = match records::decode_batches(
+        partition.records.unwrap_or_default(),
+        context.max_payload_size,
+    ) {
+        Ok(records) => records,
+        Err(e) => {
+            tracing::warn!("Rejected records produced to {topic}: {e}");
+            response.error_code = records_error_code(&e);
+            return response;
+        }
+    };
+    tracing::debug!(
+        "Publishing {} records to {} over Kafka",
+        records.len(),
+        topic
+    );
+    for record in records {
+        match publish_record(topic, record, context).await {
+            Ok(offset) if response.base_offset < 0 => response.base_offset = offset as i64,
+            Ok(_) => {}
+            Err(e) => {
+                response.error_code = publish_error_code(&e);
+                break;
+            }
+        }
+    }
+    if let Some(description) = context.broker.describe_topic(topic).await {
+        response.log_start_offset = description.start_offset as i64;
+    }
+    response
+}
+
+async fn publish_record<B>(
+    topic: &TopicName,
+    record: Record,
+    context: &RequestContext<'_, B>,
+) -> Result<u64, TopicPublishError>
+where
+    B: TopicPublisher,
+{
+    let traceparent = record
+        .headers
+        .iter()
+        .find(|header| header.key == "traceparent")
+        .and_then(|header| header.value.as_deref())
+        .and_then(|value| std::str::from_utf8(value).ok());
+    let span = tracing::info_span!(target: telemetry::TARGET, "append", topic = %topic);
+    let traceparent = telemetry::continue_trace(&span, traceparent);
+    let started = Instant::now();
+    let offset = context
+        .broker
+        .publish(
+            topic,
+            record.value.unwrap_or_default(),
+            Compression::None,
+            traceparent,
+        )
+        .instrument(span)
+        .await?;
+    context
+        .metrics
+        .observe_publish_latency(topic, started.elapsed());
+    Ok(offset)
+}
+
+fn records_error_code(e: &RecordsError) -> i16 {
+    match e {
+        RecordsError::Corrupt(_) => error_code::CORRUPT_MESSAGE,
+        RecordsError::UnsupportedMagic(_) | RecordsError::Transactional => {
+            error_code::UNSUPPORTED_FOR_MESSAGE_FORMAT
+        }
+        RecordsError::UnsupportedCompression(_) => error_code::UNSUPPORTED_COMPRESSION_TYPE,
+        RecordsError::TooLarge(_) => error_code::MESSAGE_TOO_LARGE,
+    }
+}
+
+fn publish_error_code(e: &TopicPublishError) -> i16 {
+    match e {
+        TopicPublishError::TopicNotFound(_) | TopicPublishError::TopicRecreated(_) => {
+            error_code::UNKNOWN_TOPIC_OR_PARTITION
+        }
+        TopicPublishError::PayloadTooLarge { .. } => error_code::MESSAGE_TOO_LARGE,
+        TopicPublishError::InvalidPayload(..) => error_code::CORRUPT_MESSAGE,
+        TopicPublishError::TransactionNotFound(_) => error_code::UNKNOWN_SERVER_ERROR,
+    }
+}
@@ -0,0 +1,385 @@
+use crate::compression::read_bounded;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Only record batches of magic 2 are understood; older message sets predate the protocol
+/// versions the listener supports.
+const MAGIC: i8 = 2;
+/// Bytes from the start of a batch up to and including its length.
+const BATCH_LENGTH_OFFSET: usize = 12;
+/// Bytes of the batch header that precede the CRC-checked part.
+const CRC_OFFSET: usize = 21;
+const COMPRESSION_MASK: i16 = 0x07;
+const TRANSACTIONAL_FLAG: i16 = 0x10;
+const CONTROL_FLAG: i16 = 0x20;
+/// Framing some producers wrap snappy-compressed batches in.
+const XERIAL_SNAPPY_MAGIC: &[u8] = b"\x82SNAPPY\x00";
+
+/// A record of a produced batch, with only the parts kafkalite keeps.
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub value: Option<Bytes>,
+    pub headers: Vec<RecordHeader>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RecordHeader {
+    pub key: String,
+    pub value: Option<Bytes>,
+}
+
+/// A record read from a topic, ready to be handed to a consumer.
+pub struct FetchedRecord {
+    pub offset: u64,
+    pub value: Bytes,
+    pub traceparent: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum RecordsError {
+    Corrupt(String),
+    UnsupportedMagic(i8),
+    UnsupportedCompression(i16),
+    /// Transactional and control batches need a transaction coordinator the listener lacks.
+    Transactional,
+    /// The batches decompress to more than the given number of bytes.
+    TooLarge(usize),
+}
+
+impl std::fmt::Display for RecordsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordsError::Corrupt(message) => write!(f, "Corrupt record batch: {message}"),
+            RecordsError::UnsupportedMagic(magic) => {
+                write!(f, "Record batches of magic {magic} are not supported")
+            }
+            RecordsError::UnsupportedCompression(codec) => {
+                write!(f, "Compression codec {codec} is not supported")
+            }
+            RecordsError::Transactional => write!(f, "Transactional batches are not supported"),
+            RecordsError::TooLarge(limit) => {
+                write!(f, "Record batches decompress to more than {limit} bytes")
+            }
+        }
+    }
+}
+
+fn corrupt(message: impl Into<String>) -> RecordsError {
+    RecordsError::Corrupt(message.into())
+}
+
+/// Reads every record of the batches a producer sent for a partition, checking their CRC and
+/// decompressing them to at most `max_decompressed_len` bytes in total.
+pub fn decode_batches(
+    mut src: Bytes,
+    max_decompressed_len: usize,
+) -> Result<Vec<Record>, RecordsError> {
+    let mut records = Vec::new();
+    let mut budget = max_decompressed_len;
+    while !src.is_empty() {
+        if src.len() < BATCH_LENGTH_OFFSET {
+            return Err(corrupt("truncated batch header"));
+        }
+        let batch_length = (&src[8..BATCH_LENGTH_OFFSET]).get_i32();
+        let batch_length = usize::try_from(batch_length).map_err(|_| corrupt("negative length"))?;
+        if src.len() < BATCH_LENGTH_OFFSET + batch_length {
+            return Err(corrupt("truncated batch"));
+        }
+        let batch = src.split_to(BATCH_LENGTH_OFFSET + batch_length);
+        decode_batch(batch, &mut records, &mut budget)?;
+    }
+    Ok(records)
+}
+
+fn decode_batch(
+    batch: Bytes,
+    records: &mut Vec<Record>,
+    budget: &mut usize,
+) -> Result<(), RecordsError> {
+    if batch.len() < 61 {
+        return Err(corrupt("truncated batch header"));
+    }
+    let mut header = &batch[BATCH_LENGTH_OFFSET..];
+    let _partition_leader_epoch = header.get_i32();
+    let magic = header.get_i8();
+    if magic != MAGIC {
+        return Err(RecordsError::UnsupportedMagic(magic));
+    }
+    let crc = header.get_u32();
+    if crc32c::crc32c(&batch[CRC_OFFSET..]) != crc {
+        return Err(corrupt("CRC mismatch"));
+    }
+    let attributes = header.get_i16();
+    if attributes & (TRANSACTIONAL_FLAG | CONTROL_FLAG) != 0 {
+        return Err(RecordsError::Transactional);
+    }
+    // Offsets, timestamps and producer state are assigned by kafkalite itself.
+    header.advance(4 + 8 + 8 + 8 + 2 + 4);
+    let record_count = header.get_i32();
+    let body = decompress(attributes & COMPRESSION_MASK, &batch[61..], *budget)?;
+    *budget -= body.len();
+    let mut body = Bytes::from(body);
+    for _ in 0..record_count {
+        records.push(decode_record(&mut body)?);
+    }
+    if !body.is_empty() {
+        return Err(corrupt("trailing bytes after records"));
+    }
+    Ok(())
+}
+
+fn decompress(codec: i16, body: &[u8], limit: usize) -> Result<Vec<u8>, RecordsError> {
+    let decompressed = match codec {
+        0 => Ok(body.to_vec()),
+        1 => read_bounded(flate2::read::GzDecoder::new(body), limit),
+        2 => return decompress_snappy(body, limit),
+        3 => read_bounded(lz4_flex::frame::FrameDecoder::new(body), limit),
+        4 => {
+            zstd::stream::read::Decoder::new(body).and_then(|decoder| read_bounded(decoder, limit))
+        }
+        codec => return Err(RecordsError::UnsupportedCompression(codec)),
+    };
+    let decompressed =
+        decompressed.map_err(|e| corrupt(format!("failed to decompress records: {e}")))?;
+    check_decompressed_len(decompressed.len(), limit)?;
+    Ok(decompressed)
+}
+
+fn check_decompressed_len(len: usize, limit: usize) -> Result<(), RecordsError> {
+    if len > limit {
+        return Err(RecordsError::TooLarge(limit));
+    }
+    Ok(())
+}
+
+/// Snappy batches are either raw or, as the Java client writes them, in xerial's framing: a
+/// header followed by blocks prefixed with their length. Raw snappy states its decompressed
+/// length up front, so it is checked against `limit` before anything is allocated.
+fn decompress_snappy(body: &[u8], limit: usize) -> Result<Vec<u8>, RecordsError> {
+    let failed = |e: snap::Error| corrupt(format!("failed to decompress records: {e}"));
+    let truncated = || corrupt("truncated xerial snappy block");
+    let mut decoder = snap::raw::Decoder::new();
+    if !body.starts_with(XERIAL_SNAPPY_MAGIC) {
+        check_decompressed_len(snap::raw::decompress_len(body).map_err(failed)?, limit)?;
+        return decoder.decompress_vec(body).map_err(failed);
+    }
+    let mut blocks = body.get(16..).ok_or_else(truncated)?;
+    let mut decompressed = Vec::new();
+    while blocks.has_remaining() {
+        let block_len = blocks.try_get_u32().map_err(|_| truncated())? as usize;
+        let block = blocks.get(..block_len).ok_or_else(truncated)?;
+        let block_decompressed_len = snap::raw::decompress_len(block).map_err(failed)?;
+        check_decompressed_len(decompressed.len() + block_decompressed_len, limit)?;
+        decompressed.extend(decoder.decompress_vec(block).map_err(failed)?);
+        blocks.advance(block_len);
+    }
+    Ok(decompressed)
+}
+
+fn decode_record(src: &mut Bytes) -> Result<Record, RecordsError> {
+    let record_len = get_varint(src)?;
+    let record_len = usize::try_from(record_len).map_err(|_| corrupt("negative record length"))?;
+    if src.len() < record_len {
+        return Err(corrupt("truncated record"));
+    }
+    let mut record = src.split_to(record_len);
+    let src = &mut record;
+    if !src.has_remaining() {
+        return Err(corrupt("truncated record"));
+    }
+    let _attributes = src.get_i8();
+    let _timestamp_delta = get_varint(src)?;
+    let _offset_delta = get_varint(src)?;
+    let _key = get_varint_bytes(src)?;
+    let value = get_varint_bytes(src)?;
+    let header_count = get_varint(src)?;
+    let mut headers = Vec::new();
+    for _ in 0..header_count {
+        let key = get_varint_bytes(src)?.ok_or_else(|| corrupt("null header key"))?;
+        let key = String::from_utf8(key.to_vec()).map_err(|_| corrupt("invalid header key"))?;
+        let value = get_varint_bytes(src)?;
+        headers.push(RecordHeader { key, value });
+    }
+    Ok(Record { value, headers })
+}
+
+/// Writes `records` as a single uncompressed batch, each record keeping its topic offset.
+pub fn encode_batch(dst: &mut BytesMut, records: &[FetchedRecord]) {
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        return;
+    };
+    let start = dst.len();
+    dst.put_i64(first.offset as i64);
+    dst.put_i32(0); // batch length, filled in below
+    dst.put_i32(0); // partition leader epoch
+    dst.put_i8(MAGIC);
+    dst.put_u32(0); // crc, filled in below
+    dst.put_i16(0); // attributes: uncompressed, not transactional
+    dst.put_i32((last.offset - first.offset) as i32);
+    dst.put_i64(-1); // base timestamp, as records have none
+    dst.put_i64(-1); // max timestamp
+    dst.put_i64(-1); // producer id
+    dst.put_i16(-1); // producer epoch
+    dst.put_i32(-1); // base sequence
+    dst.put_i32(records.len() as i32);
+    let mut record = BytesMut::new();
+    for fetched in records {
+        record.clear();
+        record.put_i8(0); // attributes
+        put_varint(&mut record, 0); // timestamp delta
+        put_varint(&mut record, (fetched.offset - first.offset) as i64);
+        put_varint(&mut record, -1); // null key
+        put_varint_bytes(&mut record, &fetched.value);
+        match &fetched.traceparent {
+            Some(traceparent) => {
+                put_varint(&mut record, 1);
+                put_varint_bytes(&mut record, b"traceparent");
+                put_varint_bytes(&mut record, traceparent.as_bytes());
+            }
+            None => put_varint(&mut record, 0),
+        }
+        put_varint(dst, record.len() as i64);
+        dst.put_slice(&record);
+    }
+    let batch_length = (dst.len() - start - BATCH_LENGTH_OFFSET) as i32;
+    dst[start + 8..start + BATCH_LENGTH_OFFSET].copy_from_slice(&batch_length.to_be_bytes());
+    let crc = crc32c::crc32c(&dst[start + CRC_OFFSET..]);
+    dst[start + 17..start + CRC_OFFSET].copy_from_slice(&crc.to_be_bytes());
+}
+
+/// Reads a zigzag-encoded variable-length integer.
+fn get_varint(src: &mut Bytes) -> Result<i64, RecordsError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = src.try_get_u8().map_err(|_| corrupt("truncated varint"))?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+        }
+    }
+    Err(corrupt("varint too long"))
+}
+
+fn get_varint_bytes(src: &mut Bytes) -> Result<Option<Bytes>, RecordsError> {
+    let len = get_varint(src)?;
+    if len < 0 {
+        return Ok(None);
+    }
+    let len = len as usize;
+    if src.len() < len {
+        return Err(corrupt("truncated record field"));
+    }
+    Ok(Some(src.split_to(len)))
+}
+
+fn put_varint(dst: &mut BytesMut, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x80 {
+        dst.put_u8((value as u8) | 0x80);
+        value >>= 7;
+    }
+    dst.put_u8(value as u8);
+}
+
+fn put_varint_bytes(dst: &mut BytesMut, value: &[u8]) {
+    put_varint(dst, value.len() as i64);
+    dst.put_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_crc32c() {
+        assert_eq!(crc32c::crc32c(b"123456789"), 0xE3069283);
+    }
+
+    #[test]
+    fn encodes_varints_with_zigzag() {
+        for (value, encoded) in [
+            (0, &[0x00][..]),
+            (-1, &[0x01]),
+            (1, &[0x02]),
+            (300, &[0xd8, 0x04]),
+        ] {
+            let mut dst = BytesMut::new();
+            put_varint(&mut dst, value);
+            assert_eq!(&dst[..], encoded);
+            assert_eq!(get_varint(&mut dst.freeze()).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn reads_back_encoded_batch() {
+        let mut dst = BytesMut::new();
+        encode_batch(
+            &mut dst,
+            &[
+                FetchedRecord {
+                    offset: 7,
+                    value: Bytes::from_static(b"first"),
+                    traceparent: None,
+                },
+                FetchedRecord {
+                    offset: 8,
+                    value: Bytes::from_static(b"second"),
+                    traceparent: Some("00-trace".to_string()),
+                },
+            ],
+        );
+
+        let records = decode_batches(dst.freeze(), 1024).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Record {
+                    value: Some(Bytes::from_static(b"first")),
+                    headers: vec![],
+                },
+                Record {
+                    value: Some(Bytes::from_static(b"second")),
+                    headers: vec![RecordHeader {
+                        key: "traceparent".to_string(),
+                        value: Some(Bytes::from_static(b"00-trace")),
+                    }],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_batch_with_wrong_crc() {
+        let mut dst = BytesMut::new();
+        let record = FetchedRecord {
+            offset: 0,
+            value: Bytes::from_static(b"value"),
+            traceparent: None,
+        };
+        encode_batch(&mut dst, &[record]);
+        let last = dst.len() - 2;
+        dst[last] ^= 0xff;
+
+        assert!(matches!(
+            decode_batches(dst.freeze(), 1024),
+            Err(RecordsError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_batches_decompressing_beyond_limit() {
+        let mut dst = BytesMut::new();
+        let records: Vec<_> = (0..4)
+            .map(|offset| FetchedRecord {
+                offset,
+                value: Bytes::from(vec![0; 512]),
+                traceparent: None,
+            })
+            .collect();
+        encode_batch(&mut dst, &records);
+
+        assert!(matches!(
+            decode_batches(dst.freeze(), 1024),
+            Err(RecordsError::TooLarge(1024))
+        ));
+    }
+}
@@ -1,9 +1,22 @@
+mod auth;
 mod broker;
+pub mod compression;
 pub mod config;
+mod connections;
 mod handler;
+mod http;
+mod kafka;
+pub mod logging;
+mod metrics;
+mod mqtt;
 pub mod protocol;
+mod quota;
+pub mod readiness;
 mod router;
 mod server;
+mod session;
 pub mod shutdown;
 pub mod startup;
+mod telemetry;
+mod tls;
 mod topic;
@@ -0,0 +1,84 @@
+use crate::config::{LogFormat, LoggingConfig};
+use crate::telemetry;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// Keeps spans being exported for as long as it is held; dropping it flushes the ones still
+/// buffered.
+pub struct LoggingGuard {
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl LoggingGuard {
+    /// Exports all spans that have ended so far, blocking until the collector took them.
+    pub fn flush(&self) {
+        if let Some(tracer_provider) = &self.tracer_provider
+            && let Err(e) = tracer_provider.force_flush()
+        {
+            tracing::warn!("Failed to export spans: {e}");
+        }
+    }
+}
+
+impl Drop for LoggingGuard {
+    fn drop(&mut self) {
+        if let Some(tracer_provider) = self.tracer_provider.take() {
+            let _ = tracer_provider.shutdown();
+        }
+    }
+}
+
+/// Installs the global subscriber writing the broker's logs to stdout and, if configured,
+/// exporting its spans over OTLP. Events are annotated with the spans they happened in, such as
+/// the connection and request being served.
+pub fn init(config: &LoggingConfig) -> Result<LoggingGuard, Box<dyn std::error::Error>> {
+    let filter = env_filter(config)?;
+    let output: Box<dyn Layer<Registry> + Send + Sync> = match config.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
+    let tracer_provider = config
+        .otlp_endpoint
+        .as_deref()
+        .map(telemetry::tracer_provider)
+        .transpose()?;
+    tracing_subscriber::registry()
+        .with(output.with_filter(filter))
+        .with(tracer_provider.as_ref().map(telemetry::layer))
+        .try_init()?;
+    Ok(LoggingGuard { tracer_provider })
+}
+
+fn env_filter(config: &LoggingConfig) -> Result<EnvFilter, Box<dyn std::error::Error>> {
+    let filter = EnvFilter::builder()
+        .with_default_directive(config.level.into())
+        .parse(config.filter.as_deref().unwrap_or_default())?;
+    Ok(filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::Level;
+    use tracing::level_filters::LevelFilter;
+
+    #[test]
+    fn filter_directives_refine_the_level() {
+        let config = LoggingConfig {
+            level: Level::WARN,
+            filter: Some("kafkalite::server=debug".to_string()),
+            ..LoggingConfig::default()
+        };
+        let filter = env_filter(&config).unwrap();
+        assert_eq!(filter.max_level_hint(), Some(LevelFilter::DEBUG));
+
+        let filter = env_filter(&LoggingConfig::default()).unwrap();
+        assert_eq!(filter.max_level_hint(), Some(LevelFilter::INFO));
+    }
+}
@@ -1,9 +1,12 @@
 use kafkalite::config::BrokerConfig;
-use std::time::Duration;
+use kafkalite::readiness::Readiness;
+use std::sync::Arc;
 
 #[tokio::main]
 pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = BrokerConfig::new(9000, Duration::from_secs(10));
-    let shutdown_signal = kafkalite::shutdown::ctrl_c_shutdown_signal();
-    kafkalite::startup::run_broker(config, shutdown_signal).await
+    let config = BrokerConfig::load()?;
+    let _logging = kafkalite::logging::init(&config.logging)?;
+    let shutdown_signal = kafkalite::shutdown::termination_signal();
+    let readiness = Arc::new(Readiness::new());
+    kafkalite::startup::run_broker(config, shutdown_signal, readiness).await
 }
@@ -0,0 +1,290 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::time::Duration;
+
+/// The broker's Prometheus metrics. Every broker has its own registry, so that several brokers
+/// in one process (as in tests) don't mix up their numbers.
+pub struct BrokerMetrics {
+    registry: Registry,
+    messages_in: IntCounterVec,
+    bytes_in: IntCounterVec,
+    messages_out: IntCounterVec,
+    bytes_out: IntCounterVec,
+    messages_skipped: IntCounterVec,
+    publish_latency: HistogramVec,
+    subscribers: IntGaugeVec,
+    retained_records: IntGaugeVec,
+    retained_bytes: IntGaugeVec,
+    active_connections: IntGaugeVec,
+    request_errors: IntCounterVec,
+}
+
+/// The metrics of a single topic, bound to its label so that the hot paths don't have to look
+/// them up by name.
+pub struct TopicMetrics {
+    pub messages_in: IntCounter,
+    pub bytes_in: IntCounter,
+    pub messages_out: IntCounter,
+    pub bytes_out: IntCounter,
+    pub messages_skipped: IntCounter,
+    pub subscribers: IntGauge,
+    pub retained_records: IntGauge,
+    pub retained_bytes: IntGauge,
+}
+
+impl BrokerMetrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("kafkalite".to_string()), None)
+            .expect("Invalid metrics prefix");
+        let metrics = BrokerMetrics {
+            messages_in: counter_vec(
+                "messages_in_total",
+                "Messages published to a topic",
+                &["topic"],
+            ),
+            bytes_in: counter_vec(
+                "bytes_in_total",
+                "Payload bytes published to a topic",
+                &["topic"],
+            ),
+            messages_out: counter_vec(
+                "messages_out_total",
+                "Messages delivered to subscribers of a topic",
+                &["topic"],
+            ),
+            bytes_out: counter_vec(
+                "bytes_out_total",
+                "Payload bytes delivered to subscribers of a topic",
+                &["topic"],
+            ),
+            messages_skipped: counter_vec(
+                "messages_skipped_total",
+                "Messages evicted by retention before a subscriber of a topic read them",
+                &["topic"],
+            ),
+            publish_latency: HistogramVec::new(
+                HistogramOpts::new(
+                    "publish_latency_seconds",
+                    "Time taken to serve a publish request",
+                )
+                .buckets(prometheus::exponential_buckets(0.00001, 4.0, 10).unwrap()),
+                &["topic"],
+            )
+            .expect("Invalid histogram"),
+            subscribers: gauge_vec("subscribers", "Subscribers of a topic", &["topic"]),
+            retained_records: gauge_vec(
+                "retained_records",
+                "Records retained in a topic",
+                &["topic"],
+            ),
+            retained_bytes: gauge_vec(
+                "retained_bytes",
+                "Payload bytes retained in a topic",
+                &["topic"],
+            ),
+            active_connections: gauge_vec(
+                "active_connections",
+                "Open client connections of a listener",
+                &["listener"],
+            ),
+            request_errors: counter_vec(
+                "request_errors_total",
+                "Requests answered with an error",
+                &["request"],
+            ),
+            registry,
+        };
+        metrics.register_all();
+        metrics
+    }
+
+    fn register_all(&self) {
+        let collectors: [Box<dyn prometheus::core::Collector>; 11] = [
+            Box::new(self.messages_in.clone()),
+            Box::new(self.bytes_in.clone()),
+            Box::new(self.messages_out.clone()),
+            Box::new(self.bytes_out.clone()),
+            Box::new(self.messages_skipped.clone()),
+            Box::new(self.publish_latency.clone()),
+            Box::new(self.subscribers.clone()),
+            Box::new(self.retained_records.clone()),
+            Box::new(self.retained_bytes.clone()),
+            Box::new(self.active_connections.clone()),
+            Box::new(self.request_errors.clone()),
+        ];
+        for collector in collectors {
+            self.registry
+                .register(collector)
+                .expect("Metric registered twice");
+        }
+    }
+
+    pub fn topic(&self, topic: &str) -> TopicMetrics {
+        TopicMetrics {
+            messages_in: self.messages_in.with_label_values(&[topic]),
+            bytes_in: self.bytes_in.with_label_values(&[topic]),
+            messages_out: self.messages_out.with_label_values(&[topic]),
+            bytes_out: self.bytes_out.with_label_values(&[topic]),
+            messages_skipped: self.messages_skipped.with_label_values(&[topic]),
+            subscribers: self.subscribers.with_label_values(&[topic]),
+            retained_records: self.retained_records.with_label_values(&[topic]),
+            retained_bytes: self.retained_bytes.with_label_values(&[topic]),
+        }
+    }
+
+    /// Drops the series of a deleted topic.
+    pub fn remove_topic(&self, topic: &str) {
+        let vecs = [
+            &self.messages_in,
+            &self.bytes_in,
+            &self.messages_out,
+            &self.bytes_out,
+            &self.messages_skipped,
+        ];
+        for vec in vecs {
+            let _ = vec.remove_label_values(&[topic]);
+        }
+        for vec in [
+            &self.subscribers,
+            &self.retained_records,
+            &self.retained_bytes,
+        ] {
+            let _ = vec.remove_label_values(&[topic]);
+        }
+        let _ = self.publish_latency.remove_label_values(&[topic]);
+    }
+
+    pub fn observe_publish_latency(&self, topic: &str, latency: Duration) {
+        self.publish_latency
+            .with_label_values(&[topic])
+            .observe(latency.as_secs_f64());
+    }
+
+    pub fn record_request_error(&self, request: &str) {
+        self.request_errors.with_label_values(&[request]).inc();
+    }
+
+    /// Counts a connection of the listener as active until the returned guard is dropped.
+    pub fn connection_opened(&self, listener: &str) -> ActiveConnection {
+        let gauge = self.active_connections.with_label_values(&[listener]);
+        gauge.inc();
+        ActiveConnection(gauge)
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Failed to encode metrics");
+        String::from_utf8(buffer).expect("Metrics are not valid UTF-8")
+    }
+}
+
+impl Default for BrokerMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TopicMetrics {
+    /// Metrics that are not registered anywhere, for topics living outside a broker.
+    pub fn detached() -> Self {
+        TopicMetrics {
+            messages_in: IntCounter::new("messages_in_total", "detached").unwrap(),
+            bytes_in: IntCounter::new("bytes_in_total", "detached").unwrap(),
+            messages_out: IntCounter::new("messages_out_total", "detached").unwrap(),
+            bytes_out: IntCounter::new("bytes_out_total", "detached").unwrap(),
+            messages_skipped: IntCounter::new("messages_skipped_total", "detached").unwrap(),
+            subscribers: IntGauge::new("subscribers", "detached").unwrap(),
+            retained_records: IntGauge::new("retained_records", "detached").unwrap(),
+            retained_bytes: IntGauge::new("retained_bytes", "detached").unwrap(),
+        }
+    }
+}
+
+/// The metrics a subscription updates as it reads and delivers the records of its topic. They
+/// stay bound to the topic's series, so deliveries after the topic is deleted don't revive them.
+#[derive(Clone)]
+pub struct SubscriptionMetrics {
+    pub messages_out: IntCounter,
+    pub bytes_out: IntCounter,
+    pub messages_skipped: IntCounter,
+}
+
+impl TopicMetrics {
+    pub fn subscription(&self) -> SubscriptionMetrics {
+        SubscriptionMetrics {
+            messages_out: self.messages_out.clone(),
+            bytes_out: self.bytes_out.clone(),
+            messages_skipped: self.messages_skipped.clone(),
+        }
+    }
+}
+
+pub struct ActiveConnection(IntGauge);
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+fn counter_vec(name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    IntCounterVec::new(Opts::new(name, help), labels).expect("Invalid counter")
+}
+
+fn gauge_vec(name: &str, help: &str, labels: &[&str]) -> IntGaugeVec {
+    IntGaugeVec::new(Opts::new(name, help), labels).expect("Invalid gauge")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_topic_metrics_until_topic_is_removed() {
+        let metrics = BrokerMetrics::new();
+        let topic = metrics.topic("orders");
+        topic.messages_in.inc();
+        topic.bytes_in.inc_by(42);
+        let subscription = topic.subscription();
+        subscription.messages_out.inc();
+        subscription.bytes_out.inc_by(42);
+        metrics.record_request_error("Publish");
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("kafkalite_messages_in_total{topic=\"orders\"} 1"));
+        assert!(rendered.contains("kafkalite_bytes_in_total{topic=\"orders\"} 42"));
+        assert!(rendered.contains("kafkalite_bytes_out_total{topic=\"orders\"} 42"));
+        assert!(rendered.contains("kafkalite_request_errors_total{request=\"Publish\"} 1"));
+
+        metrics.remove_topic("orders");
+        assert!(!metrics.render().contains("topic=\"orders\""));
+
+        // Subscriptions outliving their topic keep counting without reviving its series.
+        subscription.messages_out.inc();
+        assert!(!metrics.render().contains("topic=\"orders\""));
+    }
+
+    #[test]
+    fn active_connections_are_counted_while_guards_live() {
+        let metrics = BrokerMetrics::new();
+        let first = metrics.connection_opened("default");
+        let _second = metrics.connection_opened("default");
+        assert!(
+            metrics
+                .render()
+                .contains("kafkalite_active_connections{listener=\"default\"} 2")
+        );
+
+        drop(first);
+        assert!(
+            metrics
+                .render()
+                .contains("kafkalite_active_connections{listener=\"default\"} 1")
+        );
+    }
+}
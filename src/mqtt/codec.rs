@@ -0,0 +1,410 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const UNSUBSCRIBE: u8 = 10;
+const UNSUBACK: u8 = 11;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+const DISCONNECT: u8 = 14;
+
+/// Largest remaining length MQTT can express in its four length bytes.
+const MAX_REMAINING_LENGTH: usize = 268_435_455;
+/// Room a packet takes besides a payload: enough for the six strings and fixed fields of a
+/// CONNECT, more than a PUBLISH needs for its topic name and packet id.
+const MAX_PACKET_OVERHEAD: usize = 6 * (2 + u16::MAX as usize) + 4;
+
+/// Quality of service of a message, of which only the first two levels are supported.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum QoS {
+    AtMostOnce,
+    AtLeastOnce,
+}
+
+/// A control packet sent by an MQTT client. `QoS` 2 packets are only decoded so that they can be
+/// turned down.
+#[derive(Debug, PartialEq)]
+pub enum ClientPacket {
+    Connect {
+        protocol_name: String,
+        protocol_level: u8,
+        clean_session: bool,
+        keep_alive: u16,
+        client_id: String,
+        username: Option<String>,
+        password: Option<Bytes>,
+    },
+    Publish {
+        topic: String,
+        /// `None` when the client asked for `QoS` 2.
+        qos: Option<QoS>,
+        packet_id: Option<u16>,
+        payload: Bytes,
+    },
+    PubAck {
+        packet_id: u16,
+    },
+    Subscribe {
+        packet_id: u16,
+        /// Filters with the `QoS` requested for them, 2 meaning exactly once.
+        filters: Vec<(String, u8)>,
+    },
+    Unsubscribe {
+        packet_id: u16,
+        filters: Vec<String>,
+    },
+    PingReq,
+    Disconnect,
+}
+
+impl ClientPacket {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClientPacket::Connect { .. } => "CONNECT",
+            ClientPacket::Publish { .. } => "PUBLISH",
+            ClientPacket::PubAck { .. } => "PUBACK",
+            ClientPacket::Subscribe { .. } => "SUBSCRIBE",
+            ClientPacket::Unsubscribe { .. } => "UNSUBSCRIBE",
+            ClientPacket::PingReq => "PINGREQ",
+            ClientPacket::Disconnect => "DISCONNECT",
+        }
+    }
+}
+
+/// A control packet sent by the broker.
+#[derive(Debug, PartialEq)]
+pub enum ServerPacket {
+    ConnAck {
+        return_code: u8,
+    },
+    Publish {
+        topic: String,
+        qos: QoS,
+        packet_id: Option<u16>,
+        retain: bool,
+        payload: Bytes,
+    },
+    PubAck {
+        packet_id: u16,
+    },
+    SubAck {
+        packet_id: u16,
+        return_codes: Vec<u8>,
+    },
+    UnsubAck {
+        packet_id: u16,
+    },
+    PingResp,
+}
+
+/// Frames MQTT 3.1.1 control packets: a fixed header with the packet type and flags, followed by
+/// the length of the rest of the packet as a variable-length integer. Packets larger than a
+/// publish of `max_payload_size` bytes could be are rejected before they are buffered.
+pub struct MqttCodec {
+    max_remaining_len: usize,
+}
+
+impl MqttCodec {
+    pub fn new(max_payload_size: usize) -> Self {
+        MqttCodec {
+            max_remaining_len: max_payload_size.saturating_add(MAX_PACKET_OVERHEAD),
+        }
+    }
+}
+
+impl Decoder for MqttCodec {
+    type Item = ClientPacket;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some((header_len, remaining_len)) = get_fixed_header(src)? else {
+            return Ok(None);
+        };
+        if remaining_len > self.max_remaining_len {
+            return Err(invalid_data(&format!(
+                "Packet of {remaining_len} bytes exceeds the limit of {} bytes",
+                self.max_remaining_len
+            )));
+        }
+        if src.len() < header_len + remaining_len {
+            return Ok(None);
+        }
+        let first_byte = src[0];
+        src.advance(header_len);
+        let mut packet = src.split_to(remaining_len);
+        decode_packet(first_byte >> 4, first_byte & 0x0f, &mut packet).map(Some)
+    }
+}
+
+/// Reads the length of the fixed header and of what follows it, or `None` while the header is
+/// still being received.
+fn get_fixed_header(src: &BytesMut) -> std::io::Result<Option<(usize, usize)>> {
+    let mut remaining_len = 0;
+    for (i, byte) in src.iter().skip(1).take(4).enumerate() {
+        remaining_len |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((i + 2, remaining_len)));
+        }
+    }
+    if src.len() > 4 {
+        return Err(invalid_data("Malformed remaining length"));
+    }
+    Ok(None)
+}
+
+fn decode_packet(packet_type: u8, flags: u8, src: &mut BytesMut) -> std::io::Result<ClientPacket> {
+    match packet_type {
+        CONNECT => decode_connect(src),
+        PUBLISH => {
+            let qos_bits = (flags >> 1) & 0x03;
+            if qos_bits == 3 {
+                return Err(invalid_data("Invalid QoS 3"));
+            }
+            let topic = get_string(src, "topic")?;
+            let packet_id = if qos_bits > 0 {
+                Some(get_u16(src, "packet_id")?)
+            } else {
+                None
+            };
+            Ok(ClientPacket::Publish {
+                topic,
+                qos: match qos_bits {
+                    0 => Some(QoS::AtMostOnce),
+                    1 => Some(QoS::AtLeastOnce),
+                    _ => None,
+                },
+                packet_id,
+                payload: src.split().freeze(),
+            })
+        }
+        PUBACK => Ok(ClientPacket::PubAck {
+            packet_id: get_u16(src, "packet_id")?,
+        }),
+        SUBSCRIBE => {
+            let packet_id = get_u16(src, "packet_id")?;
+            let mut filters = Vec::new();
+            while !src.is_empty() {
+                let filter = get_string(src, "topic_filter")?;
+                let qos = src.try_get_u8().map_err(|_| too_short("requested_qos"))?;
+                filters.push((filter, qos));
+            }
+            if filters.is_empty() {
+                return Err(invalid_data("SUBSCRIBE without topic filters"));
+            }
+            Ok(ClientPacket::Subscribe { packet_id, filters })
+        }
+        UNSUBSCRIBE => {
+            let packet_id = get_u16(src, "packet_id")?;
+            let mut filters = Vec::new();
+            while !src.is_empty() {
+                filters.push(get_string(src, "topic_filter")?);
+            }
+            if filters.is_empty() {
+                return Err(invalid_data("UNSUBSCRIBE without topic filters"));
+            }
+            Ok(ClientPacket::Unsubscribe { packet_id, filters })
+        }
+        PINGREQ => Ok(ClientPacket::PingReq),
+        DISCONNECT => Ok(ClientPacket::Disconnect),
+        packet_type => Err(invalid_data(&format!(
+            "Unsupported packet type {packet_type}"
+        ))),
+    }
+}
+
+fn decode_connect(src: &mut BytesMut) -> std::io::Result<ClientPacket> {
+    let protocol_name = get_string(src, "protocol_name")?;
+    let protocol_level = src.try_get_u8().map_err(|_| too_short("protocol_level"))?;
+    let connect_flags = src.try_get_u8().map_err(|_| too_short("connect_flags"))?;
+    let keep_alive = get_u16(src, "keep_alive")?;
+    let client_id = get_string(src, "client_id")?;
+    // Will messages are read past, as the broker does not publish them.
+    if connect_flags & 0x04 != 0 {
+        get_string(src, "will_topic")?;
+        get_bytes(src, "will_message")?;
+    }
+    let username = if connect_flags & 0x80 != 0 {
+        Some(get_string(src, "username")?)
+    } else {
+        None
+    };
+    let password = if connect_flags & 0x40 != 0 {
+        Some(get_bytes(src, "password")?)
+    } else {
+        None
+    };
+    Ok(ClientPacket::Connect {
+        protocol_name,
+        protocol_level,
+        clean_session: connect_flags & 0x02 != 0,
+        keep_alive,
+        client_id,
+        username,
+        password,
+    })
+}
+
+impl Encoder<ServerPacket> for MqttCodec {
+    type Error = std::io::Error;
+
+    fn encode(&mut self, packet: ServerPacket, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut body = BytesMut::new();
+        let first_byte = match packet {
+            ServerPacket::ConnAck { return_code } => {
+                body.put_u8(0); // no session present
+                body.put_u8(return_code);
+                CONNACK << 4
+            }
+            ServerPacket::Publish {
+                topic,
+                qos,
+                packet_id,
+                retain,
+                payload,
+            } => {
+                put_string(&mut body, &topic);
+                if let Some(packet_id) = packet_id {
+                    body.put_u16(packet_id);
+                }
+                body.put_slice(&payload);
+                let qos_bits = match qos {
+                    QoS::AtMostOnce => 0,
+                    QoS::AtLeastOnce => 1 << 1,
+                };
+                PUBLISH << 4 | qos_bits | u8::from(retain)
+            }
+            ServerPacket::PubAck { packet_id } => {
+                body.put_u16(packet_id);
+                PUBACK << 4
+            }
+            ServerPacket::SubAck {
+                packet_id,
+                return_codes,
+            } => {
+                body.put_u16(packet_id);
+                body.put_slice(&return_codes);
+                SUBACK << 4
+            }
+            ServerPacket::UnsubAck { packet_id } => {
+                body.put_u16(packet_id);
+                UNSUBACK << 4
+            }
+            ServerPacket::PingResp => PINGRESP << 4,
+        };
+        if body.len() > MAX_REMAINING_LENGTH {
+            return Err(invalid_data("Packet too large"));
+        }
+        dst.reserve(5 + body.len());
+        dst.put_u8(first_byte);
+        let mut remaining_len = body.len();
+        loop {
+            let byte = (remaining_len % 0x80) as u8;
+            remaining_len /= 0x80;
+            if remaining_len == 0 {
+                dst.put_u8(byte);
+                break;
+            }
+            dst.put_u8(byte | 0x80);
+        }
+        dst.put_slice(&body);
+        Ok(())
+    }
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn too_short(name: &str) -> std::io::Error {
+    invalid_data(&format!("Buffer too short for {name}"))
+}
+
+fn get_u16(src: &mut BytesMut, name: &str) -> std::io::Result<u16> {
+    src.try_get_u16().map_err(|_| too_short(name))
+}
+
+fn get_bytes(src: &mut BytesMut, name: &str) -> std::io::Result<Bytes> {
+    let value_len = get_u16(src, name)? as usize;
+    if src.len() < value_len {
+        return Err(too_short(name));
+    }
+    Ok(src.split_to(value_len).freeze())
+}
+
+fn get_string(src: &mut BytesMut, name: &str) -> std::io::Result<String> {
+    let value = get_bytes(src, name)?;
+    String::from_utf8(value.to_vec()).map_err(|_| invalid_data(&format!("Invalid UTF-8 in {name}")))
+}
+
+fn put_string(dst: &mut BytesMut, value: &str) {
+    dst.put_u16(value.len() as u16);
+    dst.put_slice(value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_connect_with_credentials() {
+        let mut src = BytesMut::new();
+        src.put_slice(&[0x10, 33]);
+        put_string(&mut src, "MQTT");
+        src.put_slice(&[4, 0xc2, 0, 60]);
+        put_string(&mut src, "sensor");
+        put_string(&mut src, "alice");
+        put_string(&mut src, "secret");
+
+        assert_eq!(
+            MqttCodec::new(1024).decode(&mut src).unwrap(),
+            Some(ClientPacket::Connect {
+                protocol_name: "MQTT".to_string(),
+                protocol_level: 4,
+                clean_session: true,
+                keep_alive: 60,
+                client_id: "sensor".to_string(),
+                username: Some("alice".to_string()),
+                password: Some(Bytes::from_static(b"secret")),
+            })
+        );
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn waits_for_complete_packet() {
+        let mut codec = MqttCodec::new(1024);
+        let mut src = BytesMut::from(&[0x32, 0x89][..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        src.put_u8(0x01);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_packet_larger_than_max_payload() {
+        let mut src = BytesMut::from(&[0x30, 0xff, 0xff, 0xff, 0x7f][..]);
+        let error = MqttCodec::new(1024).decode(&mut src).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn encodes_publish_with_long_remaining_length() {
+        let mut dst = BytesMut::new();
+        let packet = ServerPacket::Publish {
+            topic: "a/b".to_string(),
+            qos: QoS::AtLeastOnce,
+            packet_id: Some(7),
+            retain: true,
+            payload: Bytes::from(vec![0; 200]),
+        };
+        MqttCodec::new(1024).encode(packet, &mut dst).unwrap();
+
+        assert_eq!(&dst[..3], &[0x33, 0xcf, 0x01]);
+        assert_eq!(&dst[3..10], &[0, 3, b'a', b'/', b'b', 0, 7]);
+        assert_eq!(dst.len(), 3 + 207);
+    }
+}
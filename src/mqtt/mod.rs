@@ -0,0 +1,607 @@
+//! A listener speaking MQTT 3.1.1, mapping the topic names devices publish and subscribe to onto
+//! topics of the same name. Sessions are always clean: subscriptions last as long as their
+//! connection, messages are delivered at most `QoS` 1 and will messages are never published.
+
+mod codec;
+mod topic_filter;
+
+use crate::auth::AclOperation;
+use crate::broker::Broker;
+use crate::compression::Compression;
+use crate::connections::ConnectionPermit;
+use crate::metrics::BrokerMetrics;
+use crate::session::{ClientRegistry, Session};
+use crate::telemetry;
+use crate::topic::{
+    ClientId, IsolationLevel, MessageRecord, Subscription, TopicManager, TopicName,
+    TopicPublishError, TopicPublisher, TopicSubscriber,
+};
+use bytes::{Bytes, BytesMut};
+use codec::{ClientPacket, MqttCodec, QoS, ServerPacket};
+use futures::{SinkExt, StreamExt};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tokio_util::codec::Framed;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::Instrument;
+
+/// Protocol names and levels of MQTT 3.1.1 and of MQTT 3.1, whose packets are served alike.
+const PROTOCOLS: &[(&str, u8)] = &[("MQTT", 4), ("MQIsdp", 3)];
+
+/// Return codes of CONNACK and SUBACK packets.
+mod return_code {
+    pub const ACCEPTED: u8 = 0;
+    pub const UNACCEPTABLE_PROTOCOL_VERSION: u8 = 1;
+    pub const IDENTIFIER_REJECTED: u8 = 2;
+    pub const BAD_USERNAME_OR_PASSWORD: u8 = 4;
+    pub const NOT_AUTHORIZED: u8 = 5;
+    pub const SUBSCRIBE_FAILURE: u8 = 0x80;
+}
+
+/// A topic sent to the client because it matches one of its topic filters.
+struct Delivery {
+    filter: String,
+    qos: QoS,
+    client_id: ClientId,
+    subscription: Subscription,
+    /// Records below this offset were already in the log at subscription time, and are sent as
+    /// retained messages.
+    retained_until: u64,
+}
+
+/// An MQTT connection once the client is connected. Its subscriptions are removed and their
+/// client ids released when it drops, even if its task is aborted.
+struct Connection<'a, S> {
+    framed: Framed<S, MqttCodec>,
+    client_addr: &'a str,
+    session: &'a Session,
+    broker: Arc<Broker>,
+    cleanup_tasks: TaskTracker,
+    metrics: &'a BrokerMetrics,
+    /// Most bytes a delivered payload may decompress to.
+    max_payload_size: usize,
+    deliveries: Vec<Delivery>,
+    /// Filters with wildcards, which are matched again against every topic added later on.
+    wildcard_filters: Vec<(String, QoS)>,
+    topics_added: watch::Receiver<()>,
+    /// Deliveries are held back until then to stay within the consume quotas.
+    deliveries_paused_until: Option<Instant>,
+    last_packet_id: u16,
+}
+
+/// Serves an MQTT client from its CONNECT until it disconnects, goes quiet for longer than its
+/// keep-alive allows or the broker shuts down. Clients keeping no keep-alive are held to
+/// `connection_timeout` instead.
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_connection<S>(
+    socket: S,
+    client_addr: &str,
+    mut session: Session,
+    permit: &ConnectionPermit,
+    broker: &Arc<Broker>,
+    cleanup_tasks: &TaskTracker,
+    metrics: &BrokerMetrics,
+    max_payload_size: usize,
+    connection_timeout: Duration,
+    shutdown: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut framed = Framed::new(socket, MqttCodec::new(max_payload_size));
+    let packet = tokio::select! {
+        packet = tokio::time::timeout(connection_timeout, framed.next()) => packet,
+        _ = shutdown.cancelled() => return Ok(()),
+    };
+    let packet = match packet {
+        Ok(Some(packet)) => packet?,
+        Ok(None) => return Ok(()),
+        Err(_) => {
+            tracing::warn!("Connection with {client_addr} timed out before CONNECT");
+            return Ok(());
+        }
+    };
+    let ClientPacket::Connect {
+        protocol_name,
+        protocol_level,
+        clean_session,
+        keep_alive,
+        client_id,
+        username,
+        password,
+    } = packet
+    else {
+        return Err(format!("Expected CONNECT, got {}", packet.name()).into());
+    };
+
+    let return_code = if !PROTOCOLS.contains(&(protocol_name.as_str(), protocol_level)) {
+        return_code::UNACCEPTABLE_PROTOCOL_VERSION
+    } else if client_id.is_empty() && !clean_session {
+        // Only clean sessions can do without a client id, as nothing is kept under it.
+        return_code::IDENTIFIER_REJECTED
+    } else {
+        authenticate(&mut session, username, password)
+    };
+    framed.send(ServerPacket::ConnAck { return_code }).await?;
+    if return_code != return_code::ACCEPTED {
+        tracing::warn!("Refused MQTT connection from {client_addr} with return code {return_code}");
+        return Ok(framed.close().await?);
+    }
+    permit.identify(None, session.principal.as_ref());
+    tracing::debug!(
+        "MQTT client {:?} connected from {} as {}",
+        client_id,
+        client_addr,
+        session.principal_name()
+    );
+
+    let idle_timeout = match keep_alive {
+        0 => connection_timeout,
+        keep_alive => Duration::from_secs(u64::from(keep_alive)) * 3 / 2,
+    };
+    let mut connection = Connection {
+        framed,
+        client_addr,
+        session: &session,
+        broker: Arc::clone(broker),
+        cleanup_tasks: cleanup_tasks.clone(),
+        metrics,
+        max_payload_size,
+        deliveries: Vec::new(),
+        wildcard_filters: Vec::new(),
+        topics_added: broker.topics_added(),
+        deliveries_paused_until: None,
+        last_packet_id: 0,
+    };
+    connection.serve(idle_timeout, shutdown).await?;
+    let _ = connection.framed.close().await;
+    Ok(())
+}
+
+/// Checks the credentials of a CONNECT, if the broker requires any, and returns the CONNACK return
+/// code for them. The password is checked like the one of a SASL PLAIN exchange.
+fn authenticate(session: &mut Session, username: Option<String>, password: Option<Bytes>) -> u8 {
+    let Some(authenticator) = &session.authenticator else {
+        return return_code::ACCEPTED;
+    };
+    let Some(username) = username else {
+        return return_code::NOT_AUTHORIZED;
+    };
+    let mut message = BytesMut::new();
+    message.extend_from_slice(b"\0");
+    message.extend_from_slice(username.as_bytes());
+    message.extend_from_slice(b"\0");
+    message.extend_from_slice(&password.unwrap_or_default());
+    match authenticator.authenticate_plain(&message) {
+        Ok(principal) => {
+            tracing::info!("Authenticated {principal} over MQTT");
+            session.principal = Some(principal);
+            return_code::ACCEPTED
+        }
+        Err(e) => {
+            tracing::warn!("Failed to authenticate {username} over MQTT: {e}");
+            return_code::BAD_USERNAME_OR_PASSWORD
+        }
+    }
+}
+
+impl<S> Connection<'_, S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    async fn serve(
+        &mut self,
+        idle_timeout: Duration,
+        shutdown: &CancellationToken,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut idle_deadline = Instant::now() + idle_timeout;
+        loop {
+            tokio::select! {
+                packet = self.framed.next() => {
+                    let Some(packet) = packet else {
+                        tracing::debug!("Connection with {} closed", self.client_addr);
+                        return Ok(());
+                    };
+                    let packet = packet?;
+                    let name = packet.name();
+                    match self.handle_packet(packet).await {
+                        // Publishes held back by quotas don't count against the keep-alive.
+                        Ok(true) => idle_deadline = Instant::now() + idle_timeout,
+                        Ok(false) => return Ok(()),
+                        Err(e) => {
+                            self.metrics.record_request_error(name);
+                            return Err(e.into());
+                        }
+                    }
+                }
+                Ok(()) = self.topics_added.changed(), if !self.wildcard_filters.is_empty() => {
+                    self.subscribe_added_topics().await;
+                }
+                (i, message) = next_delivery(&mut self.deliveries),
+                    if self.deliveries_paused_until.is_none() =>
+                {
+                    self.deliver(i, message).await?;
+                }
+                _ = tokio::time::sleep_until(self.deliveries_paused_until.unwrap_or(idle_deadline)),
+                    if self.deliveries_paused_until.is_some() =>
+                {
+                    self.deliveries_paused_until = None;
+                }
+                _ = tokio::time::sleep_until(idle_deadline) => {
+                    tracing::warn!(
+                        "Connection with {} timed out after missing its keep-alive",
+                        self.client_addr
+                    );
+                    return Ok(());
+                }
+                _ = shutdown.cancelled() => {
+                    tracing::debug!(
+                        "Closing MQTT connection with {} for shutdown",
+                        self.client_addr
+                    );
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Handles a packet sent after CONNECT, returning `false` once the client disconnected. MQTT
+    /// has no way of turning down a publish, so publishes that fail close the connection.
+    async fn handle_packet(&mut self, packet: ClientPacket) -> Result<bool, String> {
+        match packet {
+            ClientPacket::Connect { .. } => return Err("Unexpected second CONNECT".to_string()),
+            ClientPacket::Publish {
+                topic,
+                qos,
+                packet_id,
+                payload,
+            } => {
+                self.publish(&topic, qos, payload).await?;
+                if let Some(packet_id) = packet_id {
+                    self.send(ServerPacket::PubAck { packet_id }).await?;
+                }
+            }
+            // Deliveries are never sent again, so there is nothing to track acknowledgements for.
+            ClientPacket::PubAck { .. } => {}
+            ClientPacket::Subscribe { packet_id, filters } => {
+                let mut return_codes = Vec::with_capacity(filters.len());
+                for (filter, requested_qos) in filters {
+                    return_codes.push(self.subscribe(filter, requested_qos).await);
+                }
+                self.send(ServerPacket::SubAck {
+                    packet_id,
+                    return_codes,
+                })
+                .await?;
+            }
+            ClientPacket::Unsubscribe { packet_id, filters } => {
+                for filter in filters {
+                    self.unsubscribe(&filter).await;
+                }
+                self.send(ServerPacket::UnsubAck { packet_id }).await?;
+            }
+            ClientPacket::PingReq => self.send(ServerPacket::PingResp).await?,
+            ClientPacket::Disconnect => {
+                tracing::debug!("MQTT client at {} disconnected", self.client_addr);
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn publish(
+        &mut self,
+        topic: &TopicName,
+        qos: Option<QoS>,
+        payload: Bytes,
+    ) -> Result<(), String> {
+        if qos.is_none() {
+            return Err(format!(
+                "Publish to {topic} asked for QoS 2, which is not supported"
+            ));
+        }
+        if !topic_filter::is_valid_topic_name(topic) {
+            return Err(format!("Invalid topic name {topic:?}"));
+        }
+        if !self.session.is_authorized(AclOperation::Publish, topic) {
+            return Err(format!(
+                "Denied {} on topic {topic} to {}",
+                AclOperation::Publish,
+                self.session.principal_name()
+            ));
+        }
+        if !self.broker.topic_exists(topic).await {
+            return Err(format!("Topic {topic} not found"));
+        }
+        self.admit(topic, payload.len()).await;
+        tracing::debug!("Publishing to {} over MQTT", topic);
+        let span = tracing::info_span!(target: telemetry::TARGET, "append", topic = %topic);
+        let traceparent = telemetry::continue_trace(&span, None);
+        let started = std::time::Instant::now();
+        self.broker
+            .publish(topic, payload, Compression::None, traceparent)
+            .instrument(span)
+            .await
+            .map_err(publish_error_message)?;
+        self.metrics
+            .observe_publish_latency(topic, started.elapsed());
+        Ok(())
+    }
+
+    /// Subscribes to the topics `filter` matches and returns the SUBACK return code for it. The
+    /// last record of every topic is sent first, as its retained message. Filters with wildcards
+    /// also subscribe to matching topics added later on.
+    async fn subscribe(&mut self, filter: String, requested_qos: u8) -> u8 {
+        let qos = match requested_qos {
+            0 => QoS::AtMostOnce,
+            1 | 2 => QoS::AtLeastOnce,
+            _ => return return_code::SUBSCRIBE_FAILURE,
+        };
+        if !topic_filter::is_valid(&filter) {
+            tracing::warn!("Rejected invalid topic filter {filter:?}");
+            return return_code::SUBSCRIBE_FAILURE;
+        }
+        self.unsubscribe(&filter).await;
+
+        if topic_filter::has_wildcards(&filter) {
+            for topic in self.matching_topics(&filter).await {
+                self.subscribe_topic(&filter, qos, topic, true).await;
+            }
+            self.wildcard_filters.push((filter, qos));
+        } else if !self.session.is_authorized(AclOperation::Subscribe, &filter) {
+            tracing::warn!(
+                "Denied {} on topic {filter} to {}",
+                AclOperation::Subscribe,
+                self.session.principal_name()
+            );
+            return return_code::SUBSCRIBE_FAILURE;
+        } else if !self
+            .subscribe_topic(&filter, qos, filter.clone(), true)
+            .await
+        {
+            tracing::warn!("Rejected subscription to unknown topic {filter}");
+            return return_code::SUBSCRIBE_FAILURE;
+        }
+        match qos {
+            QoS::AtMostOnce => 0,
+            QoS::AtLeastOnce => 1,
+        }
+    }
+
+    /// Topics matching the wildcard `filter` that aren't delivered for it yet. Wildcards quietly
+    /// leave out the topics the client may not subscribe to. Takes `&mut self` for the same
+    /// reason as [`Self::admit`].
+    async fn matching_topics(&mut self, filter: &str) -> Vec<TopicName> {
+        self.broker
+            .list_topics()
+            .await
+            .into_iter()
+            .filter(|topic| topic_filter::matches(filter, topic))
+            .filter(|topic| self.session.is_authorized(AclOperation::Subscribe, topic))
+            .filter(|topic| {
+                !self.deliveries.iter().any(|delivery| {
+                    delivery.filter == filter && &delivery.subscription.topic_name == topic
+                })
+            })
+            .collect()
+    }
+
+    /// Subscribes the wildcard filters to the topics added since they were last matched, from
+    /// their first record on, as none of them were there to be retained.
+    async fn subscribe_added_topics(&mut self) {
+        for (filter, qos) in self.wildcard_filters.clone() {
+            for topic in self.matching_topics(&filter).await {
+                self.subscribe_topic(&filter, qos, topic, false).await;
+            }
+        }
+    }
+
+    /// Starts delivering `topic` for `filter`, with its last record sent as retained message if
+    /// `retained` is set. Returns whether the topic exists.
+    async fn subscribe_topic(
+        &mut self,
+        filter: &str,
+        qos: QoS,
+        topic: TopicName,
+        retained: bool,
+    ) -> bool {
+        let Some(description) = self.broker.describe_topic(&topic).await else {
+            return false;
+        };
+        let (from_offset, retained_until) = if retained {
+            let retained_until = description.last_stable_offset;
+            let from_offset = retained_until
+                .saturating_sub(1)
+                .max(description.start_offset);
+            (from_offset, retained_until)
+        } else {
+            (description.start_offset, description.start_offset)
+        };
+        let client_id = self.broker.register_new_client().await;
+        let Ok(subscription) = self
+            .broker
+            .subscribe(
+                &topic,
+                Some(from_offset),
+                IsolationLevel::ReadCommitted,
+                client_id,
+            )
+            .await
+        else {
+            self.broker.deregister_client(client_id).await;
+            return false;
+        };
+        tracing::debug!("Subscribed MQTT filter {} to topic {}", filter, topic);
+        self.deliveries.push(Delivery {
+            filter: filter.to_string(),
+            qos,
+            client_id,
+            subscription,
+            retained_until,
+        });
+        true
+    }
+
+    async fn unsubscribe(&mut self, filter: &str) {
+        self.wildcard_filters
+            .retain(|(wildcard_filter, _)| wildcard_filter != filter);
+        let (removed, kept) = std::mem::take(&mut self.deliveries)
+            .into_iter()
+            .partition(|delivery| delivery.filter == filter);
+        self.deliveries = kept;
+        for delivery in removed {
+            let topic = &delivery.subscription.topic_name;
+            let _ = self.broker.unsubscribe(topic, delivery.client_id).await;
+            self.broker.deregister_client(delivery.client_id).await;
+        }
+    }
+
+    /// Waits until the session's quotas admit publishing `produce_bytes` to `topic`. MQTT has no
+    /// way of telling a client to back off, so its packets are left unread until then instead.
+    /// Takes `&mut self` so that the connection doesn't have to be `Sync` while it waits.
+    async fn admit(&mut self, topic: &TopicName, produce_bytes: usize) {
+        let Some(quotas) = &self.session.quotas else {
+            return;
+        };
+        let client = self.session.client_identity();
+        while let Err(throttle_time) = quotas.admit_request(&client, Some(topic), produce_bytes) {
+            tracing::debug!(
+                "Throttled MQTT client {} for {throttle_time:?}",
+                self.client_addr
+            );
+            tokio::time::sleep(throttle_time).await;
+        }
+    }
+
+    /// Sends a record of the `i`th delivery to the client, or drops the delivery once its topic
+    /// was deleted. Deliveries over the consume quotas pause the following ones.
+    async fn deliver(
+        &mut self,
+        i: usize,
+        message: Option<MessageRecord>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(message) = message else {
+            let delivery = self.deliveries.swap_remove(i);
+            tracing::debug!(
+                "Topic {} of MQTT filter {} was deleted",
+                delivery.subscription.topic_name,
+                delivery.filter
+            );
+            self.broker.deregister_client(delivery.client_id).await;
+            return Ok(());
+        };
+        let delivery = &self.deliveries[i];
+        let topic = delivery.subscription.topic_name.clone();
+        let qos = delivery.qos;
+        let retain = message.offset < delivery.retained_until;
+        let payload = match message
+            .compression
+            .decompress(&message.payload, self.max_payload_size)
+        {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::warn!(
+                    "Skipped record {} of {} that failed to decompress: {}",
+                    message.offset,
+                    topic,
+                    e
+                );
+                return Ok(());
+            }
+        };
+        let packet_id = match qos {
+            QoS::AtMostOnce => None,
+            QoS::AtLeastOnce => Some(self.next_packet_id()),
+        };
+        self.framed
+            .send(ServerPacket::Publish {
+                topic: topic.clone(),
+                qos,
+                packet_id,
+                retain,
+                payload: Bytes::from(payload),
+            })
+            .await?;
+        self.deliveries[i]
+            .subscription
+            .record_delivered(message.payload.len());
+        if let Some(quotas) = &self.session.quotas {
+            let client = self.session.client_identity();
+            let delay = quotas.record_consumed(&client, &topic, message.payload.len());
+            if !delay.is_zero() {
+                self.deliveries_paused_until = Some(Instant::now() + delay);
+            }
+        }
+        Ok(())
+    }
+
+    /// Packet ids of deliveries count up, skipping 0, which MQTT leaves unused.
+    fn next_packet_id(&mut self) -> u16 {
+        self.last_packet_id = self.last_packet_id.checked_add(1).unwrap_or(1);
+        self.last_packet_id
+    }
+
+    async fn send(&mut self, packet: ServerPacket) -> Result<(), String> {
+        self.framed.send(packet).await.map_err(|e| e.to_string())
+    }
+}
+
+impl<S> Drop for Connection<'_, S> {
+    fn drop(&mut self) {
+        if self.deliveries.is_empty() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let broker = Arc::clone(&self.broker);
+        let deliveries: Vec<_> = self
+            .deliveries
+            .drain(..)
+            .map(|delivery| (delivery.subscription.topic_name.clone(), delivery.client_id))
+            .collect();
+        self.cleanup_tasks.spawn_on(
+            async move {
+                for (topic, client_id) in deliveries {
+                    let _ = broker.unsubscribe(&topic, client_id).await;
+                    broker.deregister_client(client_id).await;
+                }
+            },
+            &runtime,
+        );
+    }
+}
+
+/// Waits for the next record of any delivery, returning which delivery it is for.
+async fn next_delivery(deliveries: &mut [Delivery]) -> (usize, Option<MessageRecord>) {
+    if deliveries.is_empty() {
+        return std::future::pending().await;
+    }
+    let waiting = deliveries
+        .iter_mut()
+        .map(|delivery| Box::pin(delivery.subscription.recv()));
+    let (message, i, _) = futures::future::select_all(waiting).await;
+    (i, message)
+}
+
+fn publish_error_message(e: TopicPublishError) -> String {
+    match e {
+        TopicPublishError::TopicNotFound(topic_name) => format!("Topic {topic_name} not found"),
+        TopicPublishError::TransactionNotFound(transaction_id) => {
+            format!("Transaction {transaction_id} not found")
+        }
+        TopicPublishError::InvalidPayload(topic_name, e) => {
+            format!("Failed to recompress payload for topic {topic_name}: {e}")
+        }
+        TopicPublishError::PayloadTooLarge { size, limit } => {
+            format!("Payload of {size} bytes exceeds the limit of {limit} bytes")
+        }
+        TopicPublishError::TopicRecreated(topic_name) => {
+            format!("Topic {topic_name} was re-created during the transaction")
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! MQTT topic filters, matched level by level against topic names split on `/`: `+` matches any
+//! single level and a trailing `#` any number of levels, including none.
+
+const SINGLE_LEVEL: &str = "+";
+const MULTI_LEVEL: &str = "#";
+
+/// Whether `filter` is well formed: not empty, with `#` only as its last level and wildcards
+/// always taking up a whole level.
+pub fn is_valid(filter: &str) -> bool {
+    if filter.is_empty() {
+        return false;
+    }
+    let mut levels = filter.split('/').peekable();
+    while let Some(level) = levels.next() {
+        let is_last = levels.peek().is_none();
+        match level {
+            SINGLE_LEVEL => {}
+            MULTI_LEVEL if is_last => {}
+            level if level.contains(['+', '#']) => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+/// Whether `name` can be published to, which rules out names with wildcards.
+pub fn is_valid_topic_name(name: &str) -> bool {
+    !name.is_empty() && !has_wildcards(name)
+}
+
+pub fn has_wildcards(filter: &str) -> bool {
+    filter.contains(['+', '#'])
+}
+
+/// Whether the valid `filter` matches `topic`. Topics starting with `$` are only matched by
+/// filters naming their first level, as MQTT reserves them for the broker's own use.
+pub fn matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && filter.starts_with(['+', '#']) {
+        return false;
+    }
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        if level == MULTI_LEVEL {
+            return true;
+        }
+        match topic_levels.next() {
+            Some(topic_level) if level == SINGLE_LEVEL || level == topic_level => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_wildcard_placement() {
+        assert!(is_valid("sensors/+/temperature"));
+        assert!(is_valid("sensors/#"));
+        assert!(is_valid("#"));
+        assert!(is_valid("+"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("sensors/#/temperature"));
+        assert!(!is_valid("sensors/kitchen+"));
+        assert!(!is_valid("sensors#"));
+    }
+
+    #[test]
+    fn matches_levels_with_wildcards() {
+        assert!(matches(
+            "sensors/+/temperature",
+            "sensors/kitchen/temperature"
+        ));
+        assert!(!matches(
+            "sensors/+/temperature",
+            "sensors/kitchen/humidity"
+        ));
+        assert!(!matches("sensors/+", "sensors/kitchen/temperature"));
+        assert!(matches("sensors/#", "sensors/kitchen/temperature"));
+        assert!(matches("sensors/#", "sensors"));
+        assert!(!matches("sensors/#", "sensorsx"));
+        assert!(matches("sensors", "sensors"));
+        assert!(!matches("sensors", "sensors/kitchen"));
+        assert!(matches("+/+", "/kitchen"));
+    }
+
+    #[test]
+    fn wildcards_skip_reserved_topics() {
+        assert!(!matches("#", "$SYS/uptime"));
+        assert!(!matches("+/uptime", "$SYS/uptime"));
+        assert!(matches("$SYS/#", "$SYS/uptime"));
+    }
+}
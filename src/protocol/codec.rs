@@ -1,8 +1,57 @@
-use bytes::{Buf, BufMut, BytesMut};
+use crate::compression::Compression;
+use crate::topic::IsolationLevel;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use uuid::Uuid;
 
+const FRAME_LEN_SIZE: usize = 4;
+
+/// Room a request frame takes besides its payload: the frame type, a topic name and a traceparent
+/// with their lengths, the payload length and the compression.
+pub const MAX_FRAME_OVERHEAD: usize = 1 + 2 * (2 + u16::MAX as usize) + 4 + 1 + 1;
+
+/// Splits off the next complete frame, or returns `None` while the frame is still being received.
+/// Every frame is prefixed with its length, so a frame split across reads is never parsed early.
+/// Frames declaring more than `max_frame_len` bytes are rejected before any of them is buffered.
+pub fn split_frame(src: &mut BytesMut, max_frame_len: usize) -> std::io::Result<Option<BytesMut>> {
+    if src.len() < FRAME_LEN_SIZE {
+        return Ok(None);
+    }
+    let mut frame_len_bytes = [0u8; FRAME_LEN_SIZE];
+    frame_len_bytes.copy_from_slice(&src[..FRAME_LEN_SIZE]);
+    let frame_len = u32::from_be_bytes(frame_len_bytes) as usize;
+    if frame_len > max_frame_len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Frame of {frame_len} bytes exceeds the limit of {max_frame_len} bytes"),
+        ));
+    }
+    // The buffer grows with what is actually received instead of with what the length claims.
+    if src.len() < FRAME_LEN_SIZE + frame_len {
+        return Ok(None);
+    }
+    src.advance(FRAME_LEN_SIZE);
+    Ok(Some(src.split_to(frame_len)))
+}
+
+/// Writes whatever `f` puts into `dst` as a single length-prefixed frame.
+pub fn put_frame<F>(dst: &mut BytesMut, f: F)
+where
+    F: FnOnce(&mut BytesMut),
+{
+    let start = dst.len();
+    dst.put_u32(0);
+    f(dst);
+    let frame_len = (dst.len() - start - FRAME_LEN_SIZE) as u32;
+    dst[start..start + FRAME_LEN_SIZE].copy_from_slice(&frame_len.to_be_bytes());
+}
+
+pub fn get_frame_type(src: &mut BytesMut) -> std::io::Result<u8> {
+    src.try_get_u8()
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Empty frame"))
+}
+
 pub fn get_u16_as_string(src: &mut BytesMut, name: &str) -> std::io::Result<String> {
-    let value_len = src.get_u16() as usize;
+    let value_len = get_u16(src, name)? as usize;
     if src.len() < value_len {
         return Err(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
@@ -18,28 +67,51 @@ pub fn get_u16_as_string(src: &mut BytesMut, name: &str) -> std::io::Result<Stri
     })
 }
 
-pub fn get_u32_as_vec(src: &mut BytesMut, name: &str) -> std::io::Result<Vec<u8>> {
-    let value_len = src.get_u32() as usize;
+pub fn get_u16_as_string_option(src: &mut BytesMut, name: &str) -> std::io::Result<Option<String>> {
+    get_option(src, |src| get_u16_as_string(src, name))
+}
+
+/// Splits the value off the frame buffer without copying it.
+pub fn get_u32_as_bytes(src: &mut BytesMut, name: &str) -> std::io::Result<Bytes> {
+    let value_len = get_u32(src, name)? as usize;
     if src.len() < value_len {
         return Err(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
             format!("Buffer too short for {name}"),
         ));
     }
-    Ok(src.split_to(value_len).to_vec())
+    Ok(src.split_to(value_len).freeze())
 }
 
-pub fn get_u64_option(src: &mut BytesMut, name: &str) -> std::io::Result<Option<u64>> {
-    let get_u64 = |src: &mut BytesMut| -> std::io::Result<u64> {
-        src.try_get_u64().map_err(|_| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("Buffer too short for {name}"),
-            )
-        })
-    };
+pub fn get_u16(src: &mut BytesMut, name: &str) -> std::io::Result<u16> {
+    src.try_get_u16().map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Buffer too short for {name}"),
+        )
+    })
+}
 
-    get_option(src, get_u64)
+pub fn get_u32(src: &mut BytesMut, name: &str) -> std::io::Result<u32> {
+    src.try_get_u32().map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Buffer too short for {name}"),
+        )
+    })
+}
+
+pub fn get_u64(src: &mut BytesMut, name: &str) -> std::io::Result<u64> {
+    src.try_get_u64().map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Buffer too short for {name}"),
+        )
+    })
+}
+
+pub fn get_u64_option(src: &mut BytesMut, name: &str) -> std::io::Result<Option<u64>> {
+    get_option(src, |src| get_u64(src, name))
 }
 
 pub fn get_option<T, F>(src: &mut BytesMut, f: F) -> std::io::Result<Option<T>>
@@ -61,7 +133,7 @@ where
 }
 
 pub fn get_vec_of_strings(src: &mut BytesMut, name: &str) -> std::io::Result<Vec<String>> {
-    let vec_len = src.get_u16() as usize;
+    let vec_len = get_u16(src, name)? as usize;
     let mut values = Vec::with_capacity(vec_len);
 
     for _ in 0..vec_len {
@@ -89,11 +161,59 @@ pub fn get_uuid(src: &mut BytesMut, name: &str) -> std::io::Result<Uuid> {
     })
 }
 
+pub fn get_isolation_level(src: &mut BytesMut, name: &str) -> std::io::Result<IsolationLevel> {
+    let value = src.try_get_u8().map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Buffer too short for {name}"),
+        )
+    })?;
+    match value {
+        0 => Ok(IsolationLevel::ReadUncommitted),
+        1 => Ok(IsolationLevel::ReadCommitted),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Invalid isolation level in {name}"),
+        )),
+    }
+}
+
+pub fn get_compression(src: &mut BytesMut, name: &str) -> std::io::Result<Compression> {
+    let value = src.try_get_u8().map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Buffer too short for {name}"),
+        )
+    })?;
+    match value {
+        0 => Ok(Compression::None),
+        1 => Ok(Compression::Gzip),
+        2 => Ok(Compression::Snappy),
+        3 => Ok(Compression::Lz4),
+        4 => Ok(Compression::Zstd),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Unknown compression codec in {name}"),
+        )),
+    }
+}
+
+pub fn get_compression_option(
+    src: &mut BytesMut,
+    name: &str,
+) -> std::io::Result<Option<Compression>> {
+    get_option(src, |src| get_compression(src, name))
+}
+
 pub fn put_u16_len_string(dst: &mut BytesMut, value: &str) {
     dst.put_u16(value.len() as u16);
     dst.put_slice(value.as_bytes());
 }
 
+pub fn put_u16_len_string_option(dst: &mut BytesMut, value: Option<&str>) {
+    put_option(dst, value, put_u16_len_string)
+}
+
 pub fn put_u32_len_vec(dst: &mut BytesMut, value: &[u8]) {
     dst.put_u32(value.len() as u32);
     dst.put_slice(value);
@@ -111,6 +231,28 @@ pub fn put_uuid(dst: &mut BytesMut, uuid: Uuid) {
     dst.put_slice(uuid.as_bytes());
 }
 
+pub fn put_isolation_level(dst: &mut BytesMut, isolation_level: IsolationLevel) {
+    match isolation_level {
+        IsolationLevel::ReadUncommitted => dst.put_u8(0),
+        IsolationLevel::ReadCommitted => dst.put_u8(1),
+    }
+}
+
+pub fn put_compression(dst: &mut BytesMut, compression: Compression) {
+    let value = match compression {
+        Compression::None => 0,
+        Compression::Gzip => 1,
+        Compression::Snappy => 2,
+        Compression::Lz4 => 3,
+        Compression::Zstd => 4,
+    };
+    dst.put_u8(value);
+}
+
+pub fn put_compression_option(dst: &mut BytesMut, compression: Option<Compression>) {
+    put_option(dst, compression, put_compression)
+}
+
 pub fn put_u64_option(dst: &mut BytesMut, value: Option<u64>) {
     let put_u64 = |dst: &mut BytesMut, value: u64| {
         dst.put_u64(value);
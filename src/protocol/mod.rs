@@ -1,3 +1,3 @@
-mod codec;
+pub(crate) mod codec;
 pub mod request;
 pub mod response;
@@ -1,9 +1,15 @@
+use crate::compression::Compression;
+use crate::config::DEFAULT_MAX_PAYLOAD_SIZE;
 use crate::protocol::codec::{
-    get_u16_as_string, get_u32_as_vec, get_u64_option, get_uuid, put_u16_len_string,
-    put_u32_len_vec, put_u64_option, put_uuid,
+    MAX_FRAME_OVERHEAD, get_compression, get_compression_option, get_frame_type,
+    get_isolation_level, get_u16_as_string, get_u16_as_string_option, get_u32_as_bytes,
+    get_u64_option, get_uuid, put_compression, put_compression_option, put_frame,
+    put_isolation_level, put_u16_len_string, put_u16_len_string_option, put_u32_len_vec,
+    put_u64_option, put_uuid, split_frame,
 };
+pub use crate::topic::IsolationLevel;
 use crate::topic::{ClientId, TopicName};
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
 #[derive(PartialEq, Debug, Clone)]
@@ -11,7 +17,8 @@ pub enum Request {
     Ping,
     AddTopic {
         topic: TopicName,
-        retention: u64,
+        retention: Option<u64>,
+        compression: Option<Compression>,
     },
     ListTopics,
     DeleteTopic {
@@ -19,17 +26,59 @@ pub enum Request {
     },
     Publish {
         topic: TopicName,
-        payload: Vec<u8>,
+        payload: Bytes,
+        compression: Compression,
+        /// W3C trace context of the producer, e.g.
+        /// `00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01`.
+        traceparent: Option<String>,
     },
     Subscribe {
         topic: TopicName,
         client_id: ClientId,
         from_offset: Option<u64>,
+        isolation_level: IsolationLevel,
     },
     Unsubscribe {
         topic: TopicName,
         client_id: ClientId,
     },
+    BeginTransaction,
+    CommitTransaction,
+    AbortTransaction,
+    SaslHandshake {
+        mechanism: String,
+    },
+    SaslAuthenticate {
+        auth_bytes: Bytes,
+    },
+    RegisterClient {
+        client_id: ClientId,
+    },
+    /// Keeps the connection alive; unlike `Ping` it gets no response, so that it doesn't get in
+    /// the way of a subscriber's stream of messages.
+    Heartbeat,
+}
+
+impl Request {
+    /// Name of the request type, as used in logs and metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Request::Ping => "Ping",
+            Request::AddTopic { .. } => "AddTopic",
+            Request::ListTopics => "ListTopics",
+            Request::DeleteTopic { .. } => "DeleteTopic",
+            Request::Publish { .. } => "Publish",
+            Request::Subscribe { .. } => "Subscribe",
+            Request::Unsubscribe { .. } => "Unsubscribe",
+            Request::BeginTransaction => "BeginTransaction",
+            Request::CommitTransaction => "CommitTransaction",
+            Request::AbortTransaction => "AbortTransaction",
+            Request::SaslHandshake { .. } => "SaslHandshake",
+            Request::SaslAuthenticate { .. } => "SaslAuthenticate",
+            Request::RegisterClient { .. } => "RegisterClient",
+            Request::Heartbeat => "Heartbeat",
+        }
+    }
 }
 
 const PING_TYPE: u8 = 0x01;
@@ -39,24 +88,55 @@ const DELETE_TOPIC_TYPE: u8 = 0x07;
 const PUBLISH_TYPE: u8 = 0x09;
 const SUBSCRIBE_TYPE: u8 = 0x11;
 const UNSUBSCRIBE_TYPE: u8 = 0x13;
+const BEGIN_TRANSACTION_TYPE: u8 = 0x15;
+const COMMIT_TRANSACTION_TYPE: u8 = 0x17;
+const ABORT_TRANSACTION_TYPE: u8 = 0x19;
+const SASL_HANDSHAKE_TYPE: u8 = 0x1B;
+const SASL_AUTHENTICATE_TYPE: u8 = 0x1D;
+const REGISTER_CLIENT_TYPE: u8 = 0x1F;
+const HEARTBEAT_TYPE: u8 = 0x21;
+
+/// Frames requests. Decoding rejects frames larger than a publish of `max_payload_size` bytes
+/// could be, so that clients can't make the broker buffer more than that.
+pub struct RequestCodec {
+    max_frame_len: usize,
+}
 
-pub struct RequestCodec;
+impl RequestCodec {
+    pub fn new(max_payload_size: usize) -> Self {
+        RequestCodec {
+            max_frame_len: max_payload_size.saturating_add(MAX_FRAME_OVERHEAD),
+        }
+    }
+}
+
+impl Default for RequestCodec {
+    fn default() -> Self {
+        RequestCodec::new(DEFAULT_MAX_PAYLOAD_SIZE)
+    }
+}
 
 impl Decoder for RequestCodec {
     type Item = Request;
     type Error = std::io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if src.is_empty() {
+        let Some(mut frame) = split_frame(src, self.max_frame_len)? else {
             return Ok(None);
-        }
-        let request_type = src.get_u8();
+        };
+        let src = &mut frame;
+        let request_type = get_frame_type(src)?;
         match request_type {
             PING_TYPE => Ok(Some(Request::Ping)),
             ADD_TOPIC_TYPE => {
                 let topic = get_u16_as_string(src, "topic")?;
-                let retention = src.get_u64();
-                Ok(Some(Request::AddTopic { topic, retention }))
+                let retention = get_u64_option(src, "retention")?;
+                let compression = get_compression_option(src, "compression")?;
+                Ok(Some(Request::AddTopic {
+                    topic,
+                    retention,
+                    compression,
+                }))
             }
             LIST_TOPICS_TYPE => Ok(Some(Request::ListTopics)),
             DELETE_TOPIC_TYPE => {
@@ -65,18 +145,27 @@ impl Decoder for RequestCodec {
             }
             PUBLISH_TYPE => {
                 let topic = get_u16_as_string(src, "topic")?;
-                let payload = get_u32_as_vec(src, "payload")?;
-                let request = Request::Publish { topic, payload };
+                let payload = get_u32_as_bytes(src, "payload")?;
+                let compression = get_compression(src, "compression")?;
+                let traceparent = get_u16_as_string_option(src, "traceparent")?;
+                let request = Request::Publish {
+                    topic,
+                    payload,
+                    compression,
+                    traceparent,
+                };
                 Ok(Some(request))
             }
             SUBSCRIBE_TYPE => {
                 let topic = get_u16_as_string(src, "topic")?;
                 let client_id = get_uuid(src, "client_id")?;
                 let from_offset = get_u64_option(src, "from_offset")?;
+                let isolation_level = get_isolation_level(src, "isolation_level")?;
                 let request = Request::Subscribe {
                     topic,
                     client_id,
                     from_offset,
+                    isolation_level,
                 };
                 Ok(Some(request))
             }
@@ -86,6 +175,22 @@ impl Decoder for RequestCodec {
                 let request = Request::Unsubscribe { topic, client_id };
                 Ok(Some(request))
             }
+            BEGIN_TRANSACTION_TYPE => Ok(Some(Request::BeginTransaction)),
+            COMMIT_TRANSACTION_TYPE => Ok(Some(Request::CommitTransaction)),
+            ABORT_TRANSACTION_TYPE => Ok(Some(Request::AbortTransaction)),
+            SASL_HANDSHAKE_TYPE => {
+                let mechanism = get_u16_as_string(src, "mechanism")?;
+                Ok(Some(Request::SaslHandshake { mechanism }))
+            }
+            SASL_AUTHENTICATE_TYPE => {
+                let auth_bytes = get_u32_as_bytes(src, "auth_bytes")?;
+                Ok(Some(Request::SaslAuthenticate { auth_bytes }))
+            }
+            REGISTER_CLIENT_TYPE => {
+                let client_id = get_uuid(src, "client_id")?;
+                Ok(Some(Request::RegisterClient { client_id }))
+            }
+            HEARTBEAT_TYPE => Ok(Some(Request::Heartbeat)),
             _ => {
                 let unknown_request_type =
                     std::io::Error::new(std::io::ErrorKind::InvalidData, "Unknown request type");
@@ -99,12 +204,17 @@ impl Encoder<Request> for RequestCodec {
     type Error = std::io::Error;
 
     fn encode(&mut self, request: Request, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        match request {
+        put_frame(dst, |dst| match request {
             Request::Ping => dst.put_u8(PING_TYPE),
-            Request::AddTopic { topic, retention } => {
+            Request::AddTopic {
+                topic,
+                retention,
+                compression,
+            } => {
                 dst.put_u8(ADD_TOPIC_TYPE);
                 put_u16_len_string(dst, &topic);
-                dst.put_u64(retention);
+                put_u64_option(dst, retention);
+                put_compression_option(dst, compression);
             }
             Request::ListTopics => {
                 dst.put_u8(LIST_TOPICS_TYPE);
@@ -113,27 +223,52 @@ impl Encoder<Request> for RequestCodec {
                 dst.put_u8(DELETE_TOPIC_TYPE);
                 put_u16_len_string(dst, &topic);
             }
-            Request::Publish { topic, payload } => {
+            Request::Publish {
+                topic,
+                payload,
+                compression,
+                traceparent,
+            } => {
                 dst.put_u8(PUBLISH_TYPE);
                 put_u16_len_string(dst, &topic);
                 put_u32_len_vec(dst, &payload);
+                put_compression(dst, compression);
+                put_u16_len_string_option(dst, traceparent.as_deref());
             }
             Request::Subscribe {
                 topic,
                 client_id,
                 from_offset,
+                isolation_level,
             } => {
                 dst.put_u8(SUBSCRIBE_TYPE);
                 put_u16_len_string(dst, &topic);
                 put_uuid(dst, client_id);
                 put_u64_option(dst, from_offset);
+                put_isolation_level(dst, isolation_level);
             }
             Request::Unsubscribe { topic, client_id } => {
                 dst.put_u8(UNSUBSCRIBE_TYPE);
                 put_u16_len_string(dst, &topic);
                 put_uuid(dst, client_id);
             }
-        }
+            Request::Heartbeat => dst.put_u8(HEARTBEAT_TYPE),
+            Request::BeginTransaction => dst.put_u8(BEGIN_TRANSACTION_TYPE),
+            Request::CommitTransaction => dst.put_u8(COMMIT_TRANSACTION_TYPE),
+            Request::AbortTransaction => dst.put_u8(ABORT_TRANSACTION_TYPE),
+            Request::SaslHandshake { mechanism } => {
+                dst.put_u8(SASL_HANDSHAKE_TYPE);
+                put_u16_len_string(dst, &mechanism);
+            }
+            Request::SaslAuthenticate { auth_bytes } => {
+                dst.put_u8(SASL_AUTHENTICATE_TYPE);
+                put_u32_len_vec(dst, &auth_bytes);
+            }
+            Request::RegisterClient { client_id } => {
+                dst.put_u8(REGISTER_CLIENT_TYPE);
+                put_uuid(dst, client_id);
+            }
+        });
         Ok(())
     }
 }
@@ -141,17 +276,48 @@ impl Encoder<Request> for RequestCodec {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bytes::Bytes;
     use uuid::Uuid;
 
     #[test]
     fn failed_on_decoding_unsupported_request_test() {
-        let mut codec = RequestCodec;
-        let mut bytes = BytesMut::from(vec![0xFF].as_slice());
+        let mut codec = RequestCodec::default();
+        let mut bytes = framed(BytesMut::from(vec![0xFF].as_slice()));
         let request = codec.decode(&mut bytes);
         assert!(request.is_err());
     }
 
+    #[test]
+    fn rejects_frame_larger_than_max_payload_before_buffering_it_test() {
+        let mut codec = RequestCodec::new(1024);
+        let mut bytes = BytesMut::from(&u32::MAX.to_be_bytes()[..]);
+        let request = codec.decode(&mut bytes);
+        assert_eq!(request.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert!(bytes.capacity() < 1024);
+    }
+
+    #[test]
+    fn waits_for_complete_frame_before_decoding_request_test() {
+        let mut codec = RequestCodec::default();
+        let mut encoded = BytesMut::new();
+        let request = Request::Publish {
+            topic: "test-topic-name".to_string(),
+            payload: Bytes::from(vec![7; 1024]),
+            compression: Compression::None,
+            traceparent: None,
+        };
+        codec
+            .encode(request.clone(), &mut encoded)
+            .expect("Failed to encode request");
+
+        let mut received = encoded.split_to(512);
+        assert!(codec.decode(&mut received).unwrap().is_none());
+
+        received.unsplit(encoded);
+        let decoded = codec.decode(&mut received).unwrap();
+        assert_eq!(decoded, Some(request));
+        assert!(received.is_empty());
+    }
+
     #[test]
     fn decode_ping_request_test() {
         let mut bytes = BytesMut::from(vec![PING_TYPE].as_slice());
@@ -161,14 +327,25 @@ mod tests {
     #[test]
     fn decode_add_topic_request_test() {
         let topic = "test-topic-name".to_string();
-        let retention = 1024;
+        let retention = Some(1024);
+        let compression = Some(Compression::Zstd);
 
         let mut bytes = BytesMut::from(vec![ADD_TOPIC_TYPE].as_slice());
         bytes.put_u16(topic.len() as u16);
         bytes.put_slice(topic.as_bytes());
-        bytes.put_u64(retention);
+        bytes.put_u8(1);
+        bytes.put_u64(1024);
+        bytes.put_u8(1);
+        bytes.put_u8(4);
 
-        decode_request_test(&mut bytes, Request::AddTopic { topic, retention });
+        decode_request_test(
+            &mut bytes,
+            Request::AddTopic {
+                topic,
+                retention,
+                compression,
+            },
+        );
     }
 
     #[test]
@@ -191,15 +368,29 @@ mod tests {
     #[test]
     fn decode_publish_request_test() {
         let topic = "test-topic-name".to_string();
-        let payload = b"test-payload".to_vec();
+        let payload = Bytes::from_static(b"test-payload");
+        let compression = Compression::Gzip;
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".to_string();
 
         let mut bytes = BytesMut::from(vec![PUBLISH_TYPE].as_slice());
         bytes.put_u16(topic.len() as u16);
         bytes.put_slice(topic.as_bytes());
         bytes.put_u32(payload.len() as u32);
-        bytes.put_slice(payload.as_slice());
+        bytes.put_slice(&payload);
+        bytes.put_u8(1);
+        bytes.put_u8(1);
+        bytes.put_u16(traceparent.len() as u16);
+        bytes.put_slice(traceparent.as_bytes());
 
-        decode_request_test(&mut bytes, Request::Publish { topic, payload });
+        decode_request_test(
+            &mut bytes,
+            Request::Publish {
+                topic,
+                payload,
+                compression,
+                traceparent: Some(traceparent),
+            },
+        );
     }
 
     #[test]
@@ -207,12 +398,14 @@ mod tests {
         let topic = "test-topic-name".to_string();
         let client_id = Uuid::new_v4();
         let from_offset = None;
+        let isolation_level = IsolationLevel::ReadUncommitted;
 
         let mut bytes = BytesMut::from(vec![SUBSCRIBE_TYPE].as_slice());
         bytes.put_u16(topic.len() as u16);
         bytes.put_slice(topic.as_bytes());
         bytes.put_slice(client_id.as_bytes());
         bytes.put_u8(0);
+        bytes.put_u8(0);
 
         decode_request_test(
             &mut bytes,
@@ -220,6 +413,7 @@ mod tests {
                 topic,
                 client_id,
                 from_offset,
+                isolation_level,
             },
         );
     }
@@ -229,6 +423,7 @@ mod tests {
         let topic = "test-topic-name".to_string();
         let client_id = Uuid::new_v4();
         let from_offset = Some(25);
+        let isolation_level = IsolationLevel::ReadCommitted;
 
         let mut bytes = BytesMut::from(vec![SUBSCRIBE_TYPE].as_slice());
         bytes.put_u16(topic.len() as u16);
@@ -236,6 +431,7 @@ mod tests {
         bytes.put_slice(client_id.as_bytes());
         bytes.put_u8(1);
         bytes.put_u64(25);
+        bytes.put_u8(1);
 
         decode_request_test(
             &mut bytes,
@@ -243,6 +439,7 @@ mod tests {
                 topic,
                 client_id,
                 from_offset,
+                isolation_level,
             },
         );
     }
@@ -260,6 +457,108 @@ mod tests {
         decode_request_test(&mut bytes, Request::Unsubscribe { topic, client_id });
     }
 
+    #[test]
+    fn decode_transaction_requests_test() {
+        let mut bytes = BytesMut::from(vec![BEGIN_TRANSACTION_TYPE].as_slice());
+        decode_request_test(&mut bytes, Request::BeginTransaction);
+        let mut bytes = BytesMut::from(vec![COMMIT_TRANSACTION_TYPE].as_slice());
+        decode_request_test(&mut bytes, Request::CommitTransaction);
+        let mut bytes = BytesMut::from(vec![ABORT_TRANSACTION_TYPE].as_slice());
+        decode_request_test(&mut bytes, Request::AbortTransaction);
+    }
+
+    #[test]
+    fn decode_sasl_requests_test() {
+        let mechanism = "SCRAM-SHA-256".to_string();
+        let mut bytes = BytesMut::from(vec![SASL_HANDSHAKE_TYPE].as_slice());
+        bytes.put_u16(mechanism.len() as u16);
+        bytes.put_slice(mechanism.as_bytes());
+        decode_request_test(&mut bytes, Request::SaslHandshake { mechanism });
+
+        let auth_bytes = Bytes::from_static(b"n,,n=user,r=nonce");
+        let mut bytes = BytesMut::from(vec![SASL_AUTHENTICATE_TYPE].as_slice());
+        bytes.put_u32(auth_bytes.len() as u32);
+        bytes.put_slice(&auth_bytes);
+        decode_request_test(&mut bytes, Request::SaslAuthenticate { auth_bytes });
+    }
+
+    #[test]
+    fn decode_register_client_request_test() {
+        let client_id = Uuid::new_v4();
+
+        let mut bytes = BytesMut::from(vec![REGISTER_CLIENT_TYPE].as_slice());
+        bytes.put_slice(client_id.as_bytes());
+
+        decode_request_test(&mut bytes, Request::RegisterClient { client_id });
+    }
+
+    #[test]
+    fn decode_heartbeat_request_test() {
+        let mut bytes = BytesMut::from(vec![HEARTBEAT_TYPE].as_slice());
+        decode_request_test(&mut bytes, Request::Heartbeat);
+    }
+
+    #[test]
+    fn rejects_truncated_add_topic_request_test() {
+        decode_truncated_request_test(Request::AddTopic {
+            topic: "test-topic-name".to_string(),
+            retention: Some(1024),
+            compression: Some(Compression::Gzip),
+        });
+    }
+
+    #[test]
+    fn rejects_truncated_delete_topic_request_test() {
+        decode_truncated_request_test(Request::DeleteTopic {
+            topic: "test-topic-name".to_string(),
+        });
+    }
+
+    #[test]
+    fn rejects_truncated_publish_request_test() {
+        decode_truncated_request_test(Request::Publish {
+            topic: "test-topic-name".to_string(),
+            payload: Bytes::from_static(b"test-payload"),
+            compression: Compression::None,
+            traceparent: Some("00-trace-span-01".to_string()),
+        });
+    }
+
+    #[test]
+    fn rejects_truncated_subscribe_request_test() {
+        decode_truncated_request_test(Request::Subscribe {
+            topic: "test-topic-name".to_string(),
+            client_id: Uuid::new_v4(),
+            from_offset: Some(7),
+            isolation_level: IsolationLevel::ReadCommitted,
+        });
+    }
+
+    #[test]
+    fn rejects_truncated_unsubscribe_request_test() {
+        decode_truncated_request_test(Request::Unsubscribe {
+            topic: "test-topic-name".to_string(),
+            client_id: Uuid::new_v4(),
+        });
+    }
+
+    #[test]
+    fn rejects_truncated_sasl_requests_test() {
+        decode_truncated_request_test(Request::SaslHandshake {
+            mechanism: "SCRAM-SHA-256".to_string(),
+        });
+        decode_truncated_request_test(Request::SaslAuthenticate {
+            auth_bytes: Bytes::from_static(b"n,,n=user,r=nonce"),
+        });
+    }
+
+    #[test]
+    fn rejects_truncated_register_client_request_test() {
+        decode_truncated_request_test(Request::RegisterClient {
+            client_id: Uuid::new_v4(),
+        });
+    }
+
     #[test]
     fn encode_ping_request_test() {
         let expected_bytes = BytesMut::from(vec![PING_TYPE].as_slice()).freeze();
@@ -269,15 +568,24 @@ mod tests {
     #[test]
     fn encode_add_topic_request_test() {
         let topic = "test-topic-name".to_string();
-        let retention = 1024;
+        let retention = None;
+        let compression = None;
 
         let mut expected_bytes = BytesMut::from(vec![ADD_TOPIC_TYPE].as_slice());
         expected_bytes.put_u16(topic.len() as u16);
         expected_bytes.put_slice(topic.as_bytes());
-        expected_bytes.put_u64(retention);
+        expected_bytes.put_u8(0);
+        expected_bytes.put_u8(0);
         let expected_bytes = expected_bytes.freeze();
 
-        encode_request_test(Request::AddTopic { topic, retention }, expected_bytes);
+        encode_request_test(
+            Request::AddTopic {
+                topic,
+                retention,
+                compression,
+            },
+            expected_bytes,
+        );
     }
 
     #[test]
@@ -301,16 +609,27 @@ mod tests {
     #[test]
     fn encode_publish_request_test() {
         let topic = "test-topic-name".to_string();
-        let payload = b"test-payload".to_vec();
+        let payload = Bytes::from_static(b"test-payload");
+        let compression = Compression::Snappy;
 
         let mut expected_bytes = BytesMut::from(vec![PUBLISH_TYPE].as_slice());
         expected_bytes.put_u16(topic.len() as u16);
         expected_bytes.put_slice(topic.as_bytes());
         expected_bytes.put_u32(payload.len() as u32);
-        expected_bytes.put_slice(payload.as_slice());
+        expected_bytes.put_slice(&payload);
+        expected_bytes.put_u8(2);
+        expected_bytes.put_u8(0);
         let expected_bytes = expected_bytes.freeze();
 
-        encode_request_test(Request::Publish { topic, payload }, expected_bytes);
+        encode_request_test(
+            Request::Publish {
+                topic,
+                payload,
+                compression,
+                traceparent: None,
+            },
+            expected_bytes,
+        );
     }
 
     #[test]
@@ -318,12 +637,14 @@ mod tests {
         let topic = "test-topic-name".to_string();
         let client_id = Uuid::new_v4();
         let from_offset = None;
+        let isolation_level = IsolationLevel::ReadUncommitted;
 
         let mut expected_bytes = BytesMut::from(vec![SUBSCRIBE_TYPE].as_slice());
         expected_bytes.put_u16(topic.len() as u16);
         expected_bytes.put_slice(topic.as_bytes());
         expected_bytes.put_slice(client_id.as_bytes());
         expected_bytes.put_u8(0);
+        expected_bytes.put_u8(0);
         let expected_bytes = expected_bytes.freeze();
 
         encode_request_test(
@@ -331,6 +652,7 @@ mod tests {
                 topic,
                 client_id,
                 from_offset,
+                isolation_level,
             },
             expected_bytes,
         );
@@ -341,6 +663,7 @@ mod tests {
         let topic = "test-topic-name".to_string();
         let client_id = Uuid::new_v4();
         let from_offset = Some(20);
+        let isolation_level = IsolationLevel::ReadCommitted;
 
         let mut expected_bytes = BytesMut::from(vec![SUBSCRIBE_TYPE].as_slice());
         expected_bytes.put_u16(topic.len() as u16);
@@ -348,6 +671,7 @@ mod tests {
         expected_bytes.put_slice(client_id.as_bytes());
         expected_bytes.put_u8(1);
         expected_bytes.put_u64(20);
+        expected_bytes.put_u8(1);
         let expected_bytes = expected_bytes.freeze();
 
         encode_request_test(
@@ -355,6 +679,7 @@ mod tests {
                 topic,
                 client_id,
                 from_offset,
+                isolation_level,
             },
             expected_bytes,
         );
@@ -374,10 +699,58 @@ mod tests {
         encode_request_test(Request::Unsubscribe { topic, client_id }, expected_bytes);
     }
 
+    #[test]
+    fn encode_transaction_requests_test() {
+        let expected_bytes = BytesMut::from(vec![BEGIN_TRANSACTION_TYPE].as_slice()).freeze();
+        encode_request_test(Request::BeginTransaction, expected_bytes);
+        let expected_bytes = BytesMut::from(vec![COMMIT_TRANSACTION_TYPE].as_slice()).freeze();
+        encode_request_test(Request::CommitTransaction, expected_bytes);
+        let expected_bytes = BytesMut::from(vec![ABORT_TRANSACTION_TYPE].as_slice()).freeze();
+        encode_request_test(Request::AbortTransaction, expected_bytes);
+    }
+
+    #[test]
+    fn encode_sasl_requests_test() {
+        let mechanism = "PLAIN".to_string();
+        let mut expected_bytes = BytesMut::from(vec![SASL_HANDSHAKE_TYPE].as_slice());
+        expected_bytes.put_u16(mechanism.len() as u16);
+        expected_bytes.put_slice(mechanism.as_bytes());
+        encode_request_test(
+            Request::SaslHandshake { mechanism },
+            expected_bytes.freeze(),
+        );
+
+        let auth_bytes = Bytes::from_static(b"\0user\0password");
+        let mut expected_bytes = BytesMut::from(vec![SASL_AUTHENTICATE_TYPE].as_slice());
+        expected_bytes.put_u32(auth_bytes.len() as u32);
+        expected_bytes.put_slice(&auth_bytes);
+        encode_request_test(
+            Request::SaslAuthenticate { auth_bytes },
+            expected_bytes.freeze(),
+        );
+    }
+
+    #[test]
+    fn encode_register_client_request_test() {
+        let client_id = Uuid::new_v4();
+
+        let mut expected_bytes = BytesMut::from(vec![REGISTER_CLIENT_TYPE].as_slice());
+        expected_bytes.put_slice(client_id.as_bytes());
+        let expected_bytes = expected_bytes.freeze();
+
+        encode_request_test(Request::RegisterClient { client_id }, expected_bytes);
+    }
+
+    #[test]
+    fn encode_heartbeat_request_test() {
+        let expected_bytes = BytesMut::from(vec![HEARTBEAT_TYPE].as_slice()).freeze();
+        encode_request_test(Request::Heartbeat, expected_bytes)
+    }
+
     fn decode_request_test(bytes: &mut BytesMut, expected_request: Request) {
-        let mut codec = RequestCodec;
+        let mut codec = RequestCodec::default();
         let request = codec
-            .decode(bytes)
+            .decode(&mut framed(bytes.split()))
             .expect("Failed to decode request")
             .expect("Empty request");
         assert_eq!(
@@ -387,16 +760,45 @@ mod tests {
         );
     }
 
+    /// Cuts the encoded request short at every length past its type and re-frames what is left,
+    /// so each field is in turn missing or incomplete.
+    fn decode_truncated_request_test(request: Request) {
+        let mut codec = RequestCodec::default();
+        let mut encoded = BytesMut::new();
+        codec
+            .encode(request.clone(), &mut encoded)
+            .expect("Failed to encode request");
+        let body = encoded.split_off(4);
+        for len in 1..body.len() {
+            let result = codec.decode(&mut framed(BytesMut::from(&body[..len])));
+            assert_eq!(
+                result.map_err(|e| e.kind()),
+                Err(std::io::ErrorKind::InvalidData),
+                "decoded {:?} request truncated to {} bytes",
+                request,
+                len
+            );
+        }
+    }
+
     fn encode_request_test(request: Request, expected_bytes: Bytes) {
-        let mut codec = RequestCodec;
+        let mut codec = RequestCodec::default();
         let mut bytes = BytesMut::new();
         codec
             .encode(request.clone(), &mut bytes)
             .expect("Failed to encode request");
         assert_eq!(
-            expected_bytes, bytes,
+            framed(BytesMut::from(expected_bytes)),
+            bytes,
             "failed to encode {:?} request",
             request
         );
     }
+
+    fn framed(bytes: BytesMut) -> BytesMut {
+        let mut framed = BytesMut::new();
+        framed.put_u32(bytes.len() as u32);
+        framed.put_slice(&bytes);
+        framed
+    }
 }
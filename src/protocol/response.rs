@@ -1,9 +1,11 @@
+use crate::compression::Compression;
 use crate::protocol::codec::{
-    get_u16_as_string, get_u32_as_vec, get_vec_of_strings, put_u16_len_string, put_u32_len_vec,
-    put_vec_of_strings,
+    get_compression, get_frame_type, get_u16_as_string, get_u16_as_string_option, get_u32,
+    get_u32_as_bytes, get_u64, get_vec_of_strings, put_compression, put_frame, put_u16_len_string,
+    put_u16_len_string_option, put_u32_len_vec, put_vec_of_strings, split_frame,
 };
 use crate::topic::TopicName;
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
 #[derive(PartialEq, Debug, Clone)]
@@ -16,12 +18,25 @@ pub enum Response {
     Nack,
     Message {
         topic: String,
-        payload: Vec<u8>,
+        payload: Bytes,
         offset: u64,
+        compression: Compression,
+        /// W3C trace context of the delivery, for consumers to continue the producer's trace.
+        traceparent: Option<String>,
     },
     TopicsList {
         topics: Vec<TopicName>,
     },
+    SaslAuthenticate {
+        auth_bytes: Bytes,
+    },
+    /// The request was not served because the client is over a quota; it may be retried after
+    /// the given time.
+    Throttled {
+        throttle_time_ms: u32,
+    },
+    /// Sent unprompted before the broker closes the connection because it is shutting down.
+    ShuttingDown,
 }
 
 const ERROR_TYPE: u8 = 0x00;
@@ -30,6 +45,9 @@ const ACK_TYPE: u8 = 0x04;
 const NACK_TYPE: u8 = 0x06;
 const MESSAGE_TYPE: u8 = 0x08;
 const TOPICS_LIST_TYPE: u8 = 0x10;
+const SASL_AUTHENTICATE_TYPE: u8 = 0x12;
+const THROTTLED_TYPE: u8 = 0x14;
+const SHUTTING_DOWN_TYPE: u8 = 0x16;
 
 pub struct ResponseCodec;
 
@@ -38,10 +56,12 @@ impl Decoder for ResponseCodec {
     type Error = std::io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if src.is_empty() {
+        // Clients trust the broker they connect to with the size of its responses.
+        let Some(mut frame) = split_frame(src, u32::MAX as usize)? else {
             return Ok(None);
-        }
-        let response_type = src.get_u8();
+        };
+        let src = &mut frame;
+        let response_type = get_frame_type(src)?;
         match response_type {
             ERROR_TYPE => {
                 let message = get_u16_as_string(src, "message")?;
@@ -52,12 +72,16 @@ impl Decoder for ResponseCodec {
             NACK_TYPE => Ok(Some(Response::Nack)),
             MESSAGE_TYPE => {
                 let topic = get_u16_as_string(src, "topic")?;
-                let payload = get_u32_as_vec(src, "payload")?;
-                let offset = src.get_u64();
+                let payload = get_u32_as_bytes(src, "payload")?;
+                let offset = get_u64(src, "offset")?;
+                let compression = get_compression(src, "compression")?;
+                let traceparent = get_u16_as_string_option(src, "traceparent")?;
                 let response = Response::Message {
                     topic,
                     payload,
                     offset,
+                    compression,
+                    traceparent,
                 };
                 Ok(Some(response))
             }
@@ -66,6 +90,15 @@ impl Decoder for ResponseCodec {
                 let response = Response::TopicsList { topics };
                 Ok(Some(response))
             }
+            SASL_AUTHENTICATE_TYPE => {
+                let auth_bytes = get_u32_as_bytes(src, "auth_bytes")?;
+                Ok(Some(Response::SaslAuthenticate { auth_bytes }))
+            }
+            THROTTLED_TYPE => {
+                let throttle_time_ms = get_u32(src, "throttle_time_ms")?;
+                Ok(Some(Response::Throttled { throttle_time_ms }))
+            }
+            SHUTTING_DOWN_TYPE => Ok(Some(Response::ShuttingDown)),
             _ => {
                 let unknown_request_type =
                     std::io::Error::new(std::io::ErrorKind::InvalidData, "Unknown response type");
@@ -79,7 +112,7 @@ impl Encoder<Response> for ResponseCodec {
     type Error = std::io::Error;
 
     fn encode(&mut self, response: Response, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        match response {
+        put_frame(dst, |dst| match response {
             Response::Error { message } => {
                 dst.put_u8(ERROR_TYPE);
                 put_u16_len_string(dst, &message);
@@ -91,17 +124,30 @@ impl Encoder<Response> for ResponseCodec {
                 topic,
                 payload,
                 offset,
+                compression,
+                traceparent,
             } => {
                 dst.put_u8(MESSAGE_TYPE);
                 put_u16_len_string(dst, &topic);
                 put_u32_len_vec(dst, &payload);
                 dst.put_u64(offset);
+                put_compression(dst, compression);
+                put_u16_len_string_option(dst, traceparent.as_deref());
             }
             Response::TopicsList { topics } => {
                 dst.put_u8(TOPICS_LIST_TYPE);
                 put_vec_of_strings(dst, topics.as_slice());
             }
-        }
+            Response::SaslAuthenticate { auth_bytes } => {
+                dst.put_u8(SASL_AUTHENTICATE_TYPE);
+                put_u32_len_vec(dst, &auth_bytes);
+            }
+            Response::Throttled { throttle_time_ms } => {
+                dst.put_u8(THROTTLED_TYPE);
+                dst.put_u32(throttle_time_ms);
+            }
+            Response::ShuttingDown => dst.put_u8(SHUTTING_DOWN_TYPE),
+        });
         Ok(())
     }
 }
@@ -109,12 +155,11 @@ impl Encoder<Response> for ResponseCodec {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bytes::Bytes;
 
     #[test]
     fn failed_on_decoding_unsupported_response_test() {
         let mut codec = ResponseCodec;
-        let mut bytes = BytesMut::from(vec![0xFF].as_slice());
+        let mut bytes = framed(BytesMut::from(vec![0xFF].as_slice()));
         let response = codec.decode(&mut bytes);
         assert!(response.is_err());
     }
@@ -140,15 +185,18 @@ mod tests {
     #[test]
     fn decode_message_response_test() {
         let topic = "test-topic-name".to_string();
-        let payload = b"test-payload".to_vec();
+        let payload = Bytes::from_static(b"test-payload");
         let offset = 0;
+        let compression = Compression::Lz4;
 
         let mut bytes = BytesMut::from(vec![MESSAGE_TYPE].as_slice());
         bytes.put_u16(topic.len() as u16);
         bytes.put_slice(topic.as_bytes());
         bytes.put_u32(payload.len() as u32);
-        bytes.put_slice(payload.as_slice());
+        bytes.put_slice(&payload);
         bytes.put_u64(offset);
+        bytes.put_u8(3);
+        bytes.put_u8(0);
 
         decode_response_test(
             &mut bytes,
@@ -156,6 +204,8 @@ mod tests {
                 topic,
                 payload,
                 offset,
+                compression,
+                traceparent: None,
             },
         );
     }
@@ -178,6 +228,36 @@ mod tests {
         decode_response_test(&mut bytes, Response::TopicsList { topics });
     }
 
+    #[test]
+    fn decode_sasl_authenticate_response_test() {
+        let auth_bytes = Bytes::from_static(b"v=server-signature");
+
+        let mut bytes = BytesMut::from(vec![SASL_AUTHENTICATE_TYPE].as_slice());
+        bytes.put_u32(auth_bytes.len() as u32);
+        bytes.put_slice(&auth_bytes);
+
+        decode_response_test(&mut bytes, Response::SaslAuthenticate { auth_bytes });
+    }
+
+    #[test]
+    fn decode_throttled_response_test() {
+        let mut bytes = BytesMut::from(vec![THROTTLED_TYPE].as_slice());
+        bytes.put_u32(250);
+
+        decode_response_test(
+            &mut bytes,
+            Response::Throttled {
+                throttle_time_ms: 250,
+            },
+        );
+    }
+
+    #[test]
+    fn decode_shutting_down_response_test() {
+        let mut bytes = BytesMut::from(vec![SHUTTING_DOWN_TYPE].as_slice());
+        decode_response_test(&mut bytes, Response::ShuttingDown);
+    }
+
     #[test]
     fn encode_pong_response_test() {
         let expected_bytes = BytesMut::from(vec![PONG_TYPE].as_slice()).freeze();
@@ -199,15 +279,21 @@ mod tests {
     #[test]
     fn encode_message_response_test() {
         let topic = "test-topic-name".to_string();
-        let payload = b"test-payload".to_vec();
+        let payload = Bytes::from_static(b"test-payload");
         let offset = 0;
+        let compression = Compression::None;
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".to_string();
 
         let mut expected_bytes = BytesMut::from(vec![MESSAGE_TYPE].as_slice());
         expected_bytes.put_u16(topic.len() as u16);
         expected_bytes.put_slice(topic.as_bytes());
         expected_bytes.put_u32(payload.len() as u32);
-        expected_bytes.put_slice(payload.as_slice());
+        expected_bytes.put_slice(&payload);
         expected_bytes.put_u64(offset);
+        expected_bytes.put_u8(0);
+        expected_bytes.put_u8(1);
+        expected_bytes.put_u16(traceparent.len() as u16);
+        expected_bytes.put_slice(traceparent.as_bytes());
         let expected_bytes = expected_bytes.freeze();
 
         encode_response_test(
@@ -215,6 +301,8 @@ mod tests {
                 topic,
                 payload,
                 offset,
+                compression,
+                traceparent: Some(traceparent),
             },
             expected_bytes,
         );
@@ -239,10 +327,42 @@ mod tests {
         encode_response_test(Response::TopicsList { topics }, expected_bytes);
     }
 
+    #[test]
+    fn encode_sasl_authenticate_response_test() {
+        let auth_bytes = Bytes::from_static(b"r=nonce,s=salt,i=4096");
+
+        let mut expected_bytes = BytesMut::from(vec![SASL_AUTHENTICATE_TYPE].as_slice());
+        expected_bytes.put_u32(auth_bytes.len() as u32);
+        expected_bytes.put_slice(&auth_bytes);
+        let expected_bytes = expected_bytes.freeze();
+
+        encode_response_test(Response::SaslAuthenticate { auth_bytes }, expected_bytes);
+    }
+
+    #[test]
+    fn encode_throttled_response_test() {
+        let mut expected_bytes = BytesMut::from(vec![THROTTLED_TYPE].as_slice());
+        expected_bytes.put_u32(1000);
+        let expected_bytes = expected_bytes.freeze();
+
+        encode_response_test(
+            Response::Throttled {
+                throttle_time_ms: 1000,
+            },
+            expected_bytes,
+        );
+    }
+
+    #[test]
+    fn encode_shutting_down_response_test() {
+        let expected_bytes = BytesMut::from(vec![SHUTTING_DOWN_TYPE].as_slice()).freeze();
+        encode_response_test(Response::ShuttingDown, expected_bytes);
+    }
+
     fn decode_response_test(bytes: &mut BytesMut, expected_response: Response) {
         let mut codec = ResponseCodec;
         let request = codec
-            .decode(bytes)
+            .decode(&mut framed(bytes.split()))
             .expect("Failed to decode response")
             .expect("Empty response");
         assert_eq!(
@@ -259,9 +379,17 @@ mod tests {
             .encode(response.clone(), &mut bytes)
             .expect("Failed to encode response");
         assert_eq!(
-            expected_bytes, bytes,
+            framed(BytesMut::from(expected_bytes)),
+            bytes,
             "failed to encode {:?} response",
             response
         );
     }
+
+    fn framed(bytes: BytesMut) -> BytesMut {
+        let mut framed = BytesMut::new();
+        framed.put_u32(bytes.len() as u32);
+        framed.put_slice(&bytes);
+        framed
+    }
 }
@@ -0,0 +1,295 @@
+use crate::config::{QuotaConfig, QuotaLimits};
+use crate::topic::TopicName;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum QuotaKind {
+    ProduceBytes,
+    ConsumeBytes,
+    Requests,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum QuotaScope {
+    Client(String),
+    Topic(TopicName),
+    Global,
+}
+
+/// How often buckets are checked for having refilled completely.
+const EVICTION_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The buckets of every client and topic that recently used their quotas.
+struct Buckets {
+    buckets: HashMap<(QuotaScope, QuotaKind), TokenBucket>,
+    evicted_at: Instant,
+}
+
+impl Buckets {
+    /// Drops the buckets that refilled completely. A full bucket is no different from the one
+    /// created on next use, so only clients and topics that went quiet are forgotten, and the
+    /// buckets of short-lived client ids or made-up names don't pile up.
+    fn evict_full(&mut self, now: Instant) {
+        if now.saturating_duration_since(self.evicted_at) < EVICTION_INTERVAL {
+            return;
+        }
+        self.buckets.retain(|_, bucket| !bucket.is_full(now));
+        self.evicted_at = now;
+    }
+}
+
+/// Tracks how much of their quotas clients use. Usage is recorded against token buckets that
+/// allow bursts of up to one second worth of the rate; a bucket that went into debt throttles
+/// whoever it applies to until it has been paid back.
+pub struct QuotaManager {
+    config: QuotaConfig,
+    buckets: Mutex<Buckets>,
+}
+
+impl QuotaManager {
+    pub fn new(config: QuotaConfig) -> Self {
+        QuotaManager {
+            config,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                evicted_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Admits a request from `client`, touching `topic` and publishing `produce_bytes` of payload,
+    /// unless one of the quotas it falls under is exhausted. Returns how long the client has to
+    /// back off otherwise; throttled requests are not counted.
+    pub fn admit_request(
+        &self,
+        client: &str,
+        topic: Option<&TopicName>,
+        produce_bytes: usize,
+    ) -> Result<(), Duration> {
+        let mut usage = vec![(QuotaKind::Requests, 1)];
+        if produce_bytes > 0 {
+            usage.push((QuotaKind::ProduceBytes, produce_bytes as u64));
+        }
+        let now = Instant::now();
+        let mut buckets = self.lock_buckets();
+        buckets.evict_full(now);
+        let buckets = &mut buckets.buckets;
+        let keys: Vec<_> = usage
+            .iter()
+            .flat_map(|(kind, amount)| {
+                self.scopes(client, topic)
+                    .into_iter()
+                    .filter_map(move |scope| {
+                        let rate = self.rate(&scope, *kind)?;
+                        Some(((scope, *kind), rate, *amount))
+                    })
+            })
+            .collect();
+        let throttle_time = keys
+            .iter()
+            .filter_map(|(key, _, _)| buckets.get_mut(key)?.throttle_time(now))
+            .max();
+        if let Some(throttle_time) = throttle_time {
+            return Err(throttle_time);
+        }
+        for (key, rate, amount) in keys {
+            buckets
+                .entry(key)
+                .or_insert_with(|| TokenBucket::new(rate, now))
+                .consume(amount, now);
+        }
+        Ok(())
+    }
+
+    /// How long the next request of `client` touching `topic` would be throttled for, without
+    /// counting anything.
+    pub fn throttle_time(&self, client: &str, topic: Option<&TopicName>) -> Duration {
+        let now = Instant::now();
+        let buckets = &mut self.lock_buckets().buckets;
+        self.scopes(client, topic)
+            .into_iter()
+            .flat_map(|scope| {
+                [QuotaKind::Requests, QuotaKind::ProduceBytes].map(|kind| (scope.clone(), kind))
+            })
+            .filter_map(|key| buckets.get_mut(&key)?.throttle_time(now))
+            .max()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    /// Records `bytes` delivered to `client` from `topic` and returns how long to hold back the
+    /// next delivery to stay within the consume quotas.
+    pub fn record_consumed(&self, client: &str, topic: &TopicName, bytes: usize) -> Duration {
+        let now = Instant::now();
+        let mut buckets = self.lock_buckets();
+        buckets.evict_full(now);
+        let buckets = &mut buckets.buckets;
+        self.scopes(client, Some(topic))
+            .into_iter()
+            .filter_map(|scope| {
+                let rate = self.rate(&scope, QuotaKind::ConsumeBytes)?;
+                let bucket = buckets
+                    .entry((scope, QuotaKind::ConsumeBytes))
+                    .or_insert_with(|| TokenBucket::new(rate, now));
+                bucket.consume(bytes as u64, now);
+                bucket.throttle_time(now)
+            })
+            .max()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    fn scopes(&self, client: &str, topic: Option<&TopicName>) -> Vec<QuotaScope> {
+        let mut scopes = vec![QuotaScope::Client(client.to_string()), QuotaScope::Global];
+        scopes.extend(topic.map(|topic| QuotaScope::Topic(topic.clone())));
+        scopes
+    }
+
+    fn rate(&self, scope: &QuotaScope, kind: QuotaKind) -> Option<u64> {
+        let limits: &QuotaLimits = match scope {
+            QuotaScope::Client(_) => &self.config.client,
+            QuotaScope::Topic(_) => &self.config.topic,
+            QuotaScope::Global => &self.config.global,
+        };
+        match kind {
+            QuotaKind::ProduceBytes => limits.produce_byte_rate,
+            QuotaKind::ConsumeBytes => limits.consume_byte_rate,
+            QuotaKind::Requests => limits.request_rate,
+        }
+    }
+
+    fn lock_buckets(&self) -> MutexGuard<'_, Buckets> {
+        self.buckets.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Holds up to one second worth of `rate` tokens and refills continuously. Consuming never fails,
+/// it may only leave the bucket in debt.
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            refilled_at: now,
+        }
+    }
+
+    fn consume(&mut self, amount: u64, now: Instant) {
+        self.refill(now);
+        self.tokens -= amount as f64;
+    }
+
+    /// How long until the bucket is out of debt, if it is in debt.
+    fn throttle_time(&mut self, now: Instant) -> Option<Duration> {
+        self.refill(now);
+        (self.tokens < 0.0).then(|| Duration::from_secs_f64(-self.tokens / self.rate))
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.rate
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_throttles_until_debt_is_paid_back() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(100, start);
+
+        bucket.consume(100, start);
+        assert_eq!(bucket.throttle_time(start), None);
+
+        bucket.consume(50, start);
+        assert_eq!(
+            bucket.throttle_time(start),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            bucket.throttle_time(start + Duration::from_millis(200)),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(
+            bucket.throttle_time(start + Duration::from_millis(500)),
+            None
+        );
+    }
+
+    #[test]
+    fn token_bucket_does_not_save_up_more_than_one_second_of_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(100, start);
+
+        let later = start + Duration::from_secs(10);
+        bucket.consume(200, later);
+        assert_eq!(bucket.throttle_time(later), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn quotas_apply_per_client_and_globally() {
+        let quotas = QuotaManager::new(QuotaConfig {
+            client: QuotaLimits {
+                produce_byte_rate: Some(100),
+                ..QuotaLimits::default()
+            },
+            global: QuotaLimits {
+                produce_byte_rate: Some(150),
+                ..QuotaLimits::default()
+            },
+            ..QuotaConfig::default()
+        });
+        let topic = "orders".to_string();
+
+        assert!(quotas.admit_request("alice", Some(&topic), 120).is_ok());
+        assert!(quotas.admit_request("alice", Some(&topic), 10).is_err());
+        assert!(quotas.admit_request("bob", Some(&topic), 80).is_ok());
+        assert!(quotas.admit_request("bob", Some(&topic), 10).is_err());
+        assert!(quotas.admit_request("bob", None, 0).is_ok());
+    }
+
+    #[test]
+    fn buckets_are_evicted_once_refilled() {
+        let quotas = QuotaManager::new(QuotaConfig {
+            client: QuotaLimits {
+                request_rate: Some(10),
+                ..QuotaLimits::default()
+            },
+            ..QuotaConfig::default()
+        });
+        for client in ["alice", "bob", "carol"] {
+            assert!(quotas.admit_request(client, None, 0).is_ok());
+        }
+        let mut buckets = quotas.lock_buckets();
+        assert_eq!(buckets.buckets.len(), 3);
+
+        let refilled = Instant::now() + EVICTION_INTERVAL;
+        buckets
+            .buckets
+            .get_mut(&(QuotaScope::Client("alice".to_string()), QuotaKind::Requests))
+            .unwrap()
+            .consume(100, refilled);
+        buckets.evict_full(refilled);
+        let remaining: Vec<_> = buckets.buckets.keys().cloned().collect();
+        assert_eq!(
+            remaining,
+            vec![(QuotaScope::Client("alice".to_string()), QuotaKind::Requests)]
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use tokio::sync::watch;
+
+/// Whether the broker is ready to serve clients: it is once every listener is bound, and stops
+/// being ready as soon as it starts shutting down. State is in memory only, so there is nothing
+/// to recover before.
+pub struct Readiness {
+    ready: watch::Sender<bool>,
+}
+
+impl Readiness {
+    pub fn new() -> Self {
+        Readiness {
+            ready: watch::Sender::new(false),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    /// Waits until the broker is ready.
+    pub async fn wait(&self) {
+        let mut ready = self.ready.subscribe();
+        let _ = ready.wait_for(|ready| *ready).await;
+    }
+
+    pub(crate) fn set_ready(&self, ready: bool) {
+        self.ready.send_replace(ready);
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn waiters_are_released_once_ready() {
+        let readiness = Arc::new(Readiness::new());
+        let waiter = tokio::spawn({
+            let readiness = Arc::clone(&readiness);
+            async move { readiness.wait().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+
+        readiness.set_ready(true);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("Waiter was not released")
+            .unwrap();
+        assert!(readiness.is_ready());
+
+        readiness.set_ready(false);
+        assert!(!readiness.is_ready());
+    }
+}
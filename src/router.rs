@@ -1,34 +1,197 @@
-use crate::handler::{add_topic, delete_topic, list_topics, ping, publish, subscribe, unsubscribe};
+use crate::auth::AclOperation;
+use crate::handler::{
+    abort_transaction, add_topic, begin_transaction, commit_transaction, delete_topic, heartbeat,
+    list_topics, ping, publish, register_client, sasl_authenticate, sasl_handshake, subscribe,
+    unsubscribe,
+};
+use crate::metrics::BrokerMetrics;
 use crate::protocol::request::Request;
 use crate::protocol::response::Response;
 use crate::server::BrokerResponse;
-use crate::topic::{TopicManager, TopicPublisher, TopicSubscriber};
+use crate::session::{ClientRegistry, Session};
+use crate::topic::{
+    TopicManager, TopicName, TopicPublisher, TopicSubscriber, TopicTransactionCoordinator,
+};
+use std::time::Instant;
 
-pub async fn route_broker_request<B>(request: Request, broker: &B) -> BrokerResponse
+pub async fn route_broker_request<B>(
+    request: Request,
+    session: &mut Session,
+    broker: &B,
+    metrics: &BrokerMetrics,
+) -> BrokerResponse
 where
-    B: TopicManager + TopicPublisher + TopicSubscriber,
+    B: TopicManager
+        + TopicPublisher
+        + TopicSubscriber
+        + TopicTransactionCoordinator
+        + ClientRegistry,
 {
+    let request_name = request.name();
+    let published_topic = match &request {
+        Request::Publish { topic, .. } => Some(topic.clone()),
+        _ => None,
+    };
+    let started_at = Instant::now();
+
+    let response = dispatch_request(request, session, broker).await;
+
+    // Only publishes that made it into a topic are timed, so that requests naming topics that
+    // don't exist (or that the client may not publish to) never add series.
+    if let Some(topic) = published_topic
+        && let BrokerResponse::BasicResponse(Response::Ack) = response
+    {
+        metrics.observe_publish_latency(&topic, started_at.elapsed());
+    }
+    if let BrokerResponse::BasicResponse(Response::Error { .. }) = response {
+        metrics.record_request_error(request_name);
+    }
+    response
+}
+
+async fn dispatch_request<B>(request: Request, session: &mut Session, broker: &B) -> BrokerResponse
+where
+    B: TopicManager
+        + TopicPublisher
+        + TopicSubscriber
+        + TopicTransactionCoordinator
+        + ClientRegistry,
+{
+    if !session.is_authenticated() && !is_authentication_request(&request) {
+        return BrokerResponse::BasicResponse(Response::Error {
+            message: "Authentication required".to_string(),
+        });
+    }
+    if let Some((operation, topic)) = required_permission(&request)
+        && !session.is_authorized(operation, topic)
+    {
+        tracing::warn!(
+            "Denied {operation} on topic {topic} to {}",
+            session.principal_name()
+        );
+        return BrokerResponse::BasicResponse(Response::Error {
+            message: format!("Not authorized to perform {operation} on topic {topic}"),
+        });
+    }
+    if let Some(quotas) = &session.quotas {
+        // Only topics that exist are accounted to, so that requests naming made-up topics don't
+        // leave buckets behind for them.
+        let topic = match required_permission(&request) {
+            Some((_, topic)) if broker.topic_exists(topic).await => Some(topic),
+            _ => None,
+        };
+        let produce_bytes = match &request {
+            Request::Publish { payload, .. } => payload.len(),
+            _ => 0,
+        };
+        if let Err(throttle_time) =
+            quotas.admit_request(&session.client_identity(), topic, produce_bytes)
+        {
+            tracing::debug!(
+                "Throttling {} for {:?}",
+                session.client_identity(),
+                throttle_time
+            );
+            return BrokerResponse::BasicResponse(Response::Throttled {
+                throttle_time_ms: throttle_time.as_millis().clamp(1, u32::MAX as u128) as u32,
+            });
+        }
+    }
+
     match request {
         Request::Ping => ping().await,
-        Request::AddTopic { topic, retention } => {
-            unwrap_response(add_topic(topic, retention, broker).await)
+        Request::Heartbeat => heartbeat().await,
+        Request::AddTopic {
+            topic,
+            retention,
+            compression,
+        } => unwrap_response(add_topic(topic, retention, compression, broker).await),
+        Request::ListTopics => {
+            list_topics(broker, |topic| {
+                session.is_authorized(AclOperation::ListTopics, topic)
+            })
+            .await
         }
-        Request::ListTopics => list_topics(broker).await,
         Request::DeleteTopic { topic } => unwrap_response(delete_topic(topic, broker).await),
-        Request::Publish { topic, payload } => {
-            unwrap_response(publish(topic, payload, broker).await)
-        }
+        Request::Publish {
+            topic,
+            payload,
+            compression,
+            traceparent,
+        } => unwrap_response(
+            publish(
+                topic,
+                payload,
+                compression,
+                traceparent,
+                session.transaction_id,
+                broker,
+            )
+            .await,
+        ),
         Request::Subscribe {
             topic,
             client_id,
             from_offset,
-        } => unwrap_response(subscribe(topic, client_id, from_offset, broker).await),
+            isolation_level,
+        } => unwrap_response(
+            subscribe(
+                topic,
+                client_id,
+                from_offset,
+                isolation_level,
+                session,
+                broker,
+            )
+            .await,
+        ),
         Request::Unsubscribe { topic, client_id } => {
-            unwrap_response(unsubscribe(topic, client_id, broker).await)
+            unwrap_response(unsubscribe(topic, client_id, session, broker).await)
+        }
+        Request::RegisterClient { client_id } => {
+            unwrap_response(register_client(client_id, session, broker).await)
+        }
+        Request::BeginTransaction => unwrap_response(begin_transaction(session, broker).await),
+        Request::CommitTransaction => unwrap_response(commit_transaction(session, broker).await),
+        Request::AbortTransaction => unwrap_response(abort_transaction(session, broker).await),
+        Request::SaslHandshake { mechanism } => {
+            unwrap_response(sasl_handshake(mechanism, session).await)
+        }
+        Request::SaslAuthenticate { auth_bytes } => {
+            unwrap_response(sasl_authenticate(auth_bytes, session).await)
         }
     }
 }
 
+/// The ACL operation a request needs on a topic; requests that don't touch a topic need none.
+/// `ListTopics` is checked on every topic instead, leaving out those the caller may not list.
+fn required_permission(request: &Request) -> Option<(AclOperation, &TopicName)> {
+    match request {
+        Request::AddTopic { topic, .. } => Some((AclOperation::AddTopic, topic)),
+        Request::DeleteTopic { topic } => Some((AclOperation::DeleteTopic, topic)),
+        Request::Publish { topic, .. } => Some((AclOperation::Publish, topic)),
+        Request::Subscribe { topic, .. } | Request::Unsubscribe { topic, .. } => {
+            Some((AclOperation::Subscribe, topic))
+        }
+        Request::Ping
+        | Request::Heartbeat
+        | Request::ListTopics
+        | Request::BeginTransaction
+        | Request::CommitTransaction
+        | Request::AbortTransaction
+        | Request::SaslHandshake { .. }
+        | Request::SaslAuthenticate { .. }
+        | Request::RegisterClient { .. } => None,
+    }
+}
+
+fn is_authentication_request(request: &Request) -> bool {
+    matches!(
+        request,
+        Request::SaslHandshake { .. } | Request::SaslAuthenticate { .. }
+    )
+}
+
 fn unwrap_response<E: IntoResponse>(maybe_response: Result<BrokerResponse, E>) -> BrokerResponse {
     maybe_response.unwrap_or_else(|e| BrokerResponse::BasicResponse(e.into_response()))
 }
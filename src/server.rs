@@ -1,77 +1,623 @@
+use crate::auth::{Authenticator, Authorizer};
 use crate::broker::Broker;
-use crate::config::BrokerConfig;
+use crate::config::{BrokerConfig, ListenerConfig, ListenerProtocol};
+use crate::connections::{ConnectionLimitError, ConnectionPermit, ConnectionTracker};
+use crate::http::{self, HttpEndpoints, HttpState};
+use crate::kafka;
+use crate::mqtt;
 use crate::protocol::request::RequestCodec;
 use crate::protocol::response::{Response, ResponseCodec};
+use crate::quota::QuotaManager;
+use crate::readiness::Readiness;
 use crate::router;
-use crate::topic::Subscription;
+use crate::session::{ClientRegistry, Session};
+use crate::telemetry;
+use crate::tls;
+use crate::topic::{
+    ClientId, Subscription, TopicName, TopicSubscriber, TopicTransactionCoordinator, TransactionId,
+};
 use futures::{SinkExt, StreamExt};
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::io::WriteHalf;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc::UnboundedSender;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::Notify;
+use tokio::sync::mpsc::Sender;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
+use tokio_rustls::TlsAcceptor;
 use tokio_util::codec::{FramedRead, FramedWrite};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{Instrument, Span};
 
 pub async fn start_broker_server(
     broker: Broker,
     config: BrokerConfig,
     shutdown_signal: Arc<tokio::sync::Notify>,
+    readiness: Arc<Readiness>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let broker = Arc::new(broker);
-    let config = Arc::new(config);
-    let listener = TcpListener::bind(("0.0.0.0", config.port)).await?;
-    tracing::info!("Broker listening on port {}", config.port);
+    let authenticator = match &config.credentials_file {
+        Some(path) => Some(Arc::new(Authenticator::from_file(path)?)),
+        None => None,
+    };
+    let authorizer = match &config.acl_file {
+        Some(path) => Some(Arc::new(Authorizer::from_file(path)?)),
+        None => None,
+    };
+    let quotas = config
+        .quotas
+        .is_enabled()
+        .then(|| Arc::new(QuotaManager::new(config.quotas.clone())));
+    let connections = Arc::new(ConnectionTracker::new(
+        config.max_connections,
+        config.max_connections_per_ip,
+    ));
+    let shutdown = CancellationToken::new();
+    let connection_tasks = TaskTracker::new();
+    tokio::spawn(expire_transactions(Arc::clone(&broker), shutdown.clone()));
 
+    // The HTTP endpoints outlive the listeners, so that the drain can still be observed.
+    let http_shutdown = CancellationToken::new();
+    let http_server = match config.http_address {
+        Some(address) => {
+            let listener = TcpListener::bind(address).await?;
+            tracing::info!("HTTP endpoints listening on {}", listener.local_addr()?);
+            let state = HttpState {
+                broker: Arc::clone(&broker),
+                connections: Arc::clone(&connections),
+                readiness: Arc::clone(&readiness),
+                authenticator: authenticator.clone(),
+                authorizer: authorizer.clone(),
+                quotas: quotas.clone(),
+                shutdown: shutdown.clone(),
+            };
+            let endpoints = HttpEndpoints {
+                admin_api: config.admin_api,
+                rest_proxy: config.rest_proxy,
+                websocket_gateway: config.websocket_gateway,
+            };
+            let router = http::router(state, endpoints);
+            Some(tokio::spawn(http::serve(
+                listener,
+                router,
+                http_shutdown.clone(),
+            )))
+        }
+        None => None,
+    };
+
+    let mut listeners = Vec::with_capacity(config.listeners.len());
+    let mut socket_paths = Vec::new();
+    for listener_config in config.listeners {
+        let tls = listener_config
+            .tls
+            .as_ref()
+            .map(tls::build_acceptor)
+            .transpose()?;
+        let listener = BrokerListener::bind(&listener_config).await?;
+        tracing::info!(
+            "Broker listener {} listening on {}{}",
+            listener_config.name,
+            listener.local_addr()?,
+            if tls.is_some() { " (TLS)" } else { "" }
+        );
+        socket_paths.extend(listener_config.unix_socket_path.clone());
+        let context = ListenerContext {
+            config: listener_config,
+            tls,
+            broker: Arc::clone(&broker),
+            authenticator: authenticator.clone(),
+            authorizer: authorizer.clone(),
+            quotas: quotas.clone(),
+            connections: Arc::clone(&connections),
+            shutdown: shutdown.clone(),
+            connection_tasks: connection_tasks.clone(),
+        };
+        listeners.push(accept_connections(listener, Arc::new(context)));
+    }
+
+    readiness.set_ready(true);
+    tokio::select! {
+        _ = futures::future::join_all(listeners) => {}
+        _ = shutdown_signal.notified() => {
+            tracing::info!("Shutting down broker requested");
+        }
+    }
+
+    readiness.set_ready(false);
+    // The listeners are gone at this point, so no new connections come in while the open ones
+    // finish their in-flight requests, say goodbye and flush what is left to send.
+    tracing::info!(
+        "Draining {} open connections",
+        connections.active_connections()
+    );
+    shutdown.cancel();
+    connection_tasks.close();
+    if tokio::time::timeout(config.shutdown_timeout, connection_tasks.wait())
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "Shutdown timed out with {} connections still open",
+            connection_tasks.len()
+        );
+    }
+
+    for path in socket_paths {
+        let _ = std::fs::remove_file(path);
+    }
+
+    http_shutdown.cancel();
+    if let Some(http_server) = http_server
+        && let Ok(Err(e)) = http_server.await
+    {
+        tracing::error!("HTTP endpoints failed: {e}");
+    }
+
+    Ok(())
+}
+
+/// What every connection accepted by a listener is served with.
+struct ListenerContext {
+    config: ListenerConfig,
+    tls: Option<TlsAcceptor>,
+    broker: Arc<Broker>,
+    authenticator: Option<Arc<Authenticator>>,
+    authorizer: Option<Arc<Authorizer>>,
+    quotas: Option<Arc<QuotaManager>>,
+    connections: Arc<ConnectionTracker>,
+    /// Cancelled once the broker starts shutting down.
+    shutdown: CancellationToken,
+    connection_tasks: TaskTracker,
+}
+
+enum BrokerListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl BrokerListener {
+    async fn bind(config: &ListenerConfig) -> io::Result<Self> {
+        match &config.unix_socket_path {
+            #[cfg(unix)]
+            Some(path) => {
+                remove_stale_socket(path)?;
+                Ok(BrokerListener::Unix(
+                    UnixListener::bind(path)?,
+                    path.clone(),
+                ))
+            }
+            #[cfg(not(unix))]
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix domain socket listeners are not supported on this platform",
+            )),
+            None => Ok(BrokerListener::Tcp(
+                TcpListener::bind((config.bind_address, config.port)).await?,
+            )),
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<String> {
+        match self {
+            BrokerListener::Tcp(listener) => Ok(listener.local_addr()?.to_string()),
+            #[cfg(unix)]
+            BrokerListener::Unix(_, path) => Ok(format!("unix:{}", path.display())),
+        }
+    }
+}
+
+/// Removes a socket file left behind by a previous run, so that binding does not fail with
+/// `AddrInUse`. Anything other than a socket is left alone.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// How often open transactions are checked for having timed out.
+const TRANSACTION_EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Aborts transactions that outlived the transaction timeout until the broker shuts down, so
+/// that transactions of clients that never come back to them don't stay open.
+async fn expire_transactions(broker: Arc<Broker>, shutdown: CancellationToken) {
+    let mut interval = tokio::time::interval(TRANSACTION_EXPIRY_INTERVAL);
     loop {
         tokio::select! {
-            accepted_socket = listener.accept() => {
-                match accepted_socket {
-                    Ok((socket, client_addr)) => {
-                        tracing::debug!("Accepted connection from {client_addr}");
-                        tokio::spawn({
-                            let config = Arc::clone(&config);
-                            let broker = Arc::clone(&broker);
-                            async move {
-                                if let Err(e) = handle_connection(socket, &broker, &config).await {
-                                    tracing::error!("Failed to handle connection with {client_addr} due to: {}", e);
-                                }
-                            }
-                        });
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to accept incoming connection {}", e);
-                        break;
+            _ = shutdown.cancelled() => break,
+            _ = interval.tick() => broker.abort_expired_transactions().await,
+        }
+    }
+}
+
+async fn accept_connections(listener: BrokerListener, context: Arc<ListenerContext>) {
+    loop {
+        let accepted = match &listener {
+            BrokerListener::Tcp(listener) => {
+                listener.accept().await.map(|(socket, client_addr)| {
+                    let local_addr = socket.local_addr().ok();
+                    spawn_connection(
+                        socket,
+                        client_addr.to_string(),
+                        Some(client_addr.ip()),
+                        local_addr,
+                        &context,
+                    )
+                })
+            }
+            #[cfg(unix)]
+            BrokerListener::Unix(listener, path) => listener.accept().await.map(|(socket, _)| {
+                spawn_connection(
+                    socket,
+                    format!("unix:{}", path.display()),
+                    None,
+                    None,
+                    &context,
+                )
+            }),
+        };
+        if let Err(e) = accepted {
+            tracing::error!(
+                "Failed to accept incoming connection on {}: {}",
+                context.config.name,
+                e
+            );
+            break;
+        }
+    }
+}
+
+/// Serves an accepted socket on its own task, completing the TLS handshake first when the
+/// listener has TLS enabled. Connections over the configured limits are refused once the
+/// handshake is done, so that the client can still read why.
+fn spawn_connection<S>(
+    socket: S,
+    client_addr: String,
+    peer_ip: Option<IpAddr>,
+    local_addr: Option<SocketAddr>,
+    context: &Arc<ListenerContext>,
+) where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    // The client id is only known once the client binds one.
+    let span = tracing::info_span!(
+        "connection",
+        listener = %context.config.name,
+        client_addr = %client_addr,
+        client_id = tracing::field::Empty,
+    );
+    let permit = context
+        .connections
+        .try_acquire(peer_ip, &context.config.name, &client_addr);
+    span.in_scope(|| {
+        tracing::debug!(
+            "Accepted connection from {client_addr} on {} ({} active, {} from this address)",
+            context.config.name,
+            context.connections.active_connections(),
+            peer_ip.map_or(0, |ip| context.connections.active_connections_from(ip))
+        )
+    });
+    context.connection_tasks.spawn({
+        let context = Arc::clone(context);
+        async move {
+            let config = &context.config;
+            let result = match &context.tls {
+                Some(acceptor) => {
+                    let handshake = acceptor.accept(socket);
+                    match tokio::time::timeout(config.connection_timeout, handshake).await {
+                        Ok(Ok(stream)) => {
+                            serve_connection(
+                                stream,
+                                &client_addr,
+                                peer_ip,
+                                local_addr,
+                                permit,
+                                &context,
+                            )
+                            .await
+                        }
+                        Ok(Err(e)) => Err(format!("TLS handshake failed: {e}").into()),
+                        Err(_) => Err("TLS handshake timed out".into()),
                     }
                 }
+                None => {
+                    serve_connection(socket, &client_addr, peer_ip, local_addr, permit, &context)
+                        .await
+                }
+            };
+            if let Err(e) = result {
+                tracing::error!(
+                    "Failed to handle connection with {client_addr} due to: {}",
+                    e
+                );
             }
-            _ = shutdown_signal.notified() => {
-                tracing::info!("Shutting down broker requested");
-                break;
-            }
+        }
+        .instrument(span)
+    });
+}
+
+async fn serve_connection<S>(
+    socket: S,
+    client_addr: &str,
+    peer_ip: Option<IpAddr>,
+    local_addr: Option<SocketAddr>,
+    permit: Result<ConnectionPermit, ConnectionLimitError>,
+    context: &ListenerContext,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let broker = context.broker.as_ref();
+    let config = &context.config;
+    let permit = match permit {
+        Ok(permit) => permit,
+        // Kafka and MQTT clients have no way of reading why, so they are just disconnected.
+        Err(e) if config.protocol != ListenerProtocol::Kafkalite => {
+            tracing::warn!("Refused connection from {client_addr}: {e}");
+            return Ok(());
+        }
+        Err(e) => {
+            tracing::warn!("Refused connection from {client_addr}: {e}");
+            let mut writer = FramedWrite::new(socket, ResponseCodec);
+            writer
+                .send(Response::Error {
+                    message: e.to_string(),
+                })
+                .await?;
+            return Ok(writer.close().await?);
+        }
+    };
+    let _active = broker.metrics().connection_opened(&config.name);
+    match (config.protocol, local_addr) {
+        (ListenerProtocol::Kafkalite, _) => {
+            let session = Session {
+                peer_ip,
+                ..Session::new(
+                    context.authenticator.clone(),
+                    context.authorizer.clone(),
+                    context.quotas.clone(),
+                )
+            };
+            handle_connection(socket, client_addr, session, &permit, context).await
+        }
+        // Kafka clients authenticate over SASL frames of their own, so ACLs apply to `ANONYMOUS`
+        // and quotas to the client's address.
+        (ListenerProtocol::Kafka, Some(advertised)) => {
+            let session = Session {
+                peer_ip,
+                ..Session::new(None, context.authorizer.clone(), context.quotas.clone())
+            };
+            kafka::handle_connection(
+                socket,
+                client_addr,
+                advertised,
+                &session,
+                broker,
+                broker.metrics(),
+                broker.max_payload_size(),
+                config.connection_timeout,
+                &context.shutdown,
+            )
+            .await
+        }
+        (ListenerProtocol::Kafka, None) => Err("Kafka listeners must listen on TCP".into()),
+        // MQTT clients authenticate with the credentials of their CONNECT.
+        (ListenerProtocol::Mqtt, _) => {
+            let session = Session {
+                peer_ip,
+                ..Session::new(
+                    context.authenticator.clone(),
+                    context.authorizer.clone(),
+                    context.quotas.clone(),
+                )
+            };
+            mqtt::handle_connection(
+                socket,
+                client_addr,
+                session,
+                &permit,
+                &context.broker,
+                &context.connection_tasks,
+                broker.metrics(),
+                broker.max_payload_size(),
+                config.connection_timeout,
+                &context.shutdown,
+            )
+            .await
         }
     }
+}
 
-    Ok(())
+async fn handle_connection<S>(
+    socket: S,
+    client_addr: &str,
+    session: Session,
+    permit: &ConnectionPermit,
+    context: &ListenerContext,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let broker = context.broker.as_ref();
+    let (read_half, write_half) = tokio::io::split(socket);
+    let mut reader = FramedRead::new(read_half, RequestCodec::new(broker.max_payload_size()));
+    let mut sender = BrokerSender::init(write_half);
+    let mut session = SessionGuard {
+        session,
+        client_addr: client_addr.to_string(),
+        broker: Arc::clone(&context.broker),
+        cleanup_tasks: context.connection_tasks.clone(),
+    };
+
+    // Errors are turned into strings so that the result can be held across the cleanup below.
+    let result = serve_requests(
+        &mut reader,
+        &mut sender,
+        client_addr,
+        &mut session.session,
+        permit,
+        context,
+    )
+    .await
+    .map_err(|e| e.to_string());
+
+    session.clean_up().await;
+    // Clients get less time to read what is left when the broker shuts down.
+    let close_timeout = if context.shutdown.is_cancelled() {
+        SHUTDOWN_NOTICE_TIMEOUT
+    } else {
+        context.config.connection_timeout
+    };
+    sender.close(close_timeout).await;
+    Ok(result?)
+}
+
+/// Releases what a connection's session holds in the broker: its open transaction is aborted and
+/// its client ID unsubscribed and deregistered. Connections clean up once they are done serving,
+/// but if their task panics or is cancelled first, the cleanup is spawned when the guard drops.
+struct SessionGuard {
+    session: Session,
+    client_addr: String,
+    broker: Arc<Broker>,
+    cleanup_tasks: TaskTracker,
 }
 
-async fn handle_connection(
-    socket: TcpStream,
+impl SessionGuard {
+    async fn clean_up(&mut self) {
+        let transaction_id = self.session.transaction_id.take();
+        let client_id = self.session.client_id.take();
+        let topics = std::mem::take(&mut self.session.subscribed_topics);
+        release_session(
+            &self.broker,
+            &self.client_addr,
+            transaction_id,
+            client_id,
+            topics,
+        )
+        .await;
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        if self.session.transaction_id.is_none() && self.session.client_id.is_none() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let broker = Arc::clone(&self.broker);
+        let client_addr = std::mem::take(&mut self.client_addr);
+        let transaction_id = self.session.transaction_id.take();
+        let client_id = self.session.client_id.take();
+        let topics = std::mem::take(&mut self.session.subscribed_topics);
+        self.cleanup_tasks.spawn_on(
+            async move {
+                release_session(&broker, &client_addr, transaction_id, client_id, topics).await;
+            },
+            &runtime,
+        );
+    }
+}
+
+async fn release_session(
     broker: &Broker,
-    config: &BrokerConfig,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let client_addr = socket.peer_addr()?;
-    let (read_half, write_half) = tokio::io::split(socket);
-    let mut reader = FramedRead::new(read_half, RequestCodec);
+    client_addr: &str,
+    transaction_id: Option<TransactionId>,
+    client_id: Option<ClientId>,
+    topics: HashSet<TopicName>,
+) {
+    if let Some(transaction_id) = transaction_id {
+        tracing::debug!("Aborting transaction {transaction_id} left open by {client_addr}");
+        let _ = broker.abort_transaction(transaction_id).await;
+    }
 
-    let mut sender = BrokerSender::init(write_half);
+    if let Some(client_id) = client_id {
+        tracing::debug!("Unsubscribing client {client_id} of closed connection with {client_addr}");
+        for topic in topics {
+            let _ = broker.unsubscribe(&topic, client_id).await;
+        }
+        broker.deregister_client(client_id).await;
+    }
+}
 
+async fn serve_requests<R>(
+    reader: &mut FramedRead<R, RequestCodec>,
+    sender: &mut BrokerSender,
+    client_addr: &str,
+    session: &mut Session,
+    permit: &ConnectionPermit,
+    context: &ListenerContext,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    R: AsyncRead + Unpin,
+{
+    let broker = context.broker.as_ref();
+    let config = &context.config;
+    // Any traffic keeps the connection from idling, but only requests prove that the client is
+    // still there.
+    let mut idle_deadline = Instant::now() + config.connection_timeout;
+    let mut session_deadline = config
+        .session_timeout
+        .map(|timeout| Instant::now() + timeout);
+    let mut recorded_client_id = None;
+    let mut recorded_principal = None;
+    let mut shutting_down = false;
     loop {
+        let deadline = session_deadline.map_or(idle_deadline, |session_deadline| {
+            session_deadline.min(idle_deadline)
+        });
         tokio::select! {
             accepted_request = reader.next() => {
                 match accepted_request {
                     Some(request) => {
-                        let response = router::route_broker_request(request?, broker).await;
-                        sender.send(response)?;
-
+                        idle_deadline = Instant::now() + config.connection_timeout;
+                        session_deadline = config.session_timeout.map(|timeout| Instant::now() + timeout);
+                        let request = request?;
+                        let span = tracing::info_span!("request", request = request.name());
+                        let response = router::route_broker_request(request, session, broker, broker.metrics())
+                            .instrument(span)
+                            .await;
+                        if session.client_id != recorded_client_id || session.principal != recorded_principal {
+                            if let Some(client_id) = session.client_id && recorded_client_id.is_none() {
+                                Span::current().record("client_id", tracing::field::display(client_id));
+                            }
+                            recorded_client_id = session.client_id;
+                            recorded_principal = session.principal.clone();
+                            permit.identify(recorded_client_id, recorded_principal.as_ref());
+                        }
+                        // A client that stops reading fills up the response queue; it must not keep
+                        // the connection from shutting down or timing out.
+                        tokio::select! {
+                            sent = sender.send(response, session) => sent?,
+                            _ = context.shutdown.cancelled() => {
+                                shutting_down = true;
+                                break;
+                            }
+                            _ = tokio::time::sleep_until(idle_deadline) => {
+                                tracing::warn!("Connection with {client_addr} timed out without reading responses");
+                                break;
+                            }
+                        }
                     }
                     None => {
                         tracing::debug!("Connection with {client_addr} closed");
@@ -79,79 +625,194 @@ async fn handle_connection(
                     }
                 }
             }
-            _ = tokio::time::sleep(config.connection_timeout) => {
-                tracing::warn!("Connection with {client_addr} timed out");
+            _ = context.shutdown.cancelled() => {
+                shutting_down = true;
+                break;
+            }
+            _ = sender.written() => {
+                idle_deadline = Instant::now() + config.connection_timeout;
+            }
+            _ = tokio::time::sleep_until(deadline) => {
+                if idle_deadline <= Instant::now() {
+                    tracing::warn!("Connection with {client_addr} timed out after being idle");
+                } else {
+                    tracing::warn!("Session of {client_addr} timed out without any request");
+                }
                 break;
             }
         }
     }
 
-    // todo: unsubscribe client from all topics it is subscribed to
+    if shutting_down {
+        tracing::debug!("Closing connection with {client_addr} for shutdown");
+        sender.stop_streams().await;
+        sender.notify_shutdown(client_addr).await;
+    }
     Ok(())
 }
 
 pub enum BrokerResponse {
     BasicResponse(Response),
     StreamedResponse(Subscription),
+    /// The request is not answered.
+    Empty,
 }
 
+/// Responses queued for the writer of a connection. Once a client stops reading, the queue fills
+/// up and its subscriptions stop pulling from their topic logs until it catches up.
+const RESPONSE_QUEUE_LEN: usize = 64;
+
+/// How long a client that stopped reading gets to make room for the shutdown notice, and then to
+/// read it.
+const SHUTDOWN_NOTICE_TIMEOUT: Duration = Duration::from_secs(1);
+
 struct BrokerSender {
-    sender: UnboundedSender<Response>,
+    sender: Sender<Response>,
+    /// Notified whenever a frame has been written to the client.
+    written: Arc<Notify>,
+    writer: JoinHandle<()>,
+    /// Tasks forwarding the messages of subscriptions.
+    streams: JoinSet<()>,
 }
 
 impl BrokerSender {
-    fn init(write: WriteHalf<TcpStream>) -> Self {
-        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<Response>();
+    fn init<W>(write: W) -> Self
+    where
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel::<Response>(RESPONSE_QUEUE_LEN);
+        let written = Arc::new(Notify::new());
 
-        tokio::spawn({
+        let writer = tokio::spawn({
             let mut framed_write = FramedWrite::new(write, ResponseCodec);
+            let written = Arc::clone(&written);
             async move {
                 while let Some(response) = receiver.recv().await {
                     if let Err(e) = framed_write.send(response).await {
                         tracing::error!("Error sending response: {e}");
-                        break;
+                        return;
                     }
+                    written.notify_one();
                 }
+                let _ = framed_write.close().await;
             }
+            .in_current_span()
         });
 
-        Self { sender }
+        Self {
+            sender,
+            written,
+            writer,
+            streams: JoinSet::new(),
+        }
+    }
+
+    /// Stops forwarding messages of subscriptions; messages already queued are still sent.
+    async fn stop_streams(&mut self) {
+        self.streams.shutdown().await;
     }
 
-    fn send(&mut self, response: BrokerResponse) -> Result<(), Box<dyn std::error::Error>> {
+    /// Writes out everything queued so far and closes the write side of the connection, giving up
+    /// on clients that don't read it within `timeout`.
+    async fn close(mut self, timeout: Duration) {
+        self.stop_streams().await;
+        drop(self.sender);
+        if tokio::time::timeout(timeout, &mut self.writer)
+            .await
+            .is_err()
+        {
+            self.writer.abort();
+        }
+    }
+
+    /// Completes once a frame has been written since the last call.
+    async fn written(&self) {
+        self.written.notified().await
+    }
+
+    async fn send(
+        &mut self,
+        response: BrokerResponse,
+        session: &Session,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         match response {
-            BrokerResponse::BasicResponse(response) => self.send_basic_response(response),
+            BrokerResponse::BasicResponse(response) => self.send_basic_response(response).await,
+            BrokerResponse::Empty => Ok(()),
             BrokerResponse::StreamedResponse(subscription) => {
-                self.send_streamed_response(subscription)
+                let consume_quota = session
+                    .quotas
+                    .clone()
+                    .map(|quotas| (quotas, session.client_identity()));
+                self.send_streamed_response(subscription, consume_quota)
+                    .await
             }
         }
     }
 
-    fn send_basic_response(
+    async fn send_basic_response(
         &mut self,
         response: Response,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.sender.send(response)?;
+        self.sender.send(response).await?;
         Ok(())
     }
 
-    fn send_streamed_response(
+    /// Queues a [`Response::ShuttingDown`], unless the client doesn't read its responses.
+    async fn notify_shutdown(&self, client_addr: &str) {
+        let notice = self
+            .sender
+            .send_timeout(Response::ShuttingDown, SHUTDOWN_NOTICE_TIMEOUT)
+            .await;
+        if notice.is_err() {
+            tracing::debug!("Could not tell {client_addr} about the shutdown");
+        }
+    }
+
+    async fn send_streamed_response(
         &mut self,
         mut subscription: Subscription,
+        consume_quota: Option<(Arc<QuotaManager>, String)>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.sender.send(Response::Ack)?;
-        tokio::spawn({
+        self.sender.send(Response::Ack).await?;
+        // Streams end on their own when unsubscribed, so a client subscribing over and over
+        // doesn't leave their handles behind.
+        while self.streams.try_join_next().is_some() {}
+        self.streams.spawn({
             let sender = self.sender.clone();
             async move {
-                while let Some(message) = subscription.receiver.recv().await {
+                while let Some(message) = subscription.recv().await {
+                    let payload_size = message.payload.len();
+                    let span = tracing::info_span!(
+                        target: telemetry::TARGET,
+                        "deliver",
+                        topic = %subscription.topic_name,
+                        offset = message.offset,
+                    );
+                    let traceparent =
+                        telemetry::continue_trace(&span, message.traceparent.as_deref());
                     let response = Response::Message {
                         topic: subscription.topic_name.to_string(),
                         payload: message.payload,
                         offset: message.offset,
+                        compression: message.compression,
+                        traceparent,
                     };
-                    let _ = sender.send(response);
+                    if sender.send(response).await.is_err() {
+                        break;
+                    }
+                    subscription.record_delivered(payload_size);
+                    drop(span);
+                    // Consumers can't be told to back off, so delivery is paced instead.
+                    if let Some((quotas, client)) = &consume_quota {
+                        let delay =
+                            quotas.record_consumed(client, &subscription.topic_name, payload_size);
+                        if !delay.is_zero() {
+                            tokio::time::sleep(delay).await;
+                        }
+                    }
                 }
             }
+            .in_current_span()
         });
         Ok(())
     }
@@ -0,0 +1,94 @@
+use crate::auth::{ANONYMOUS, AclOperation, Authenticator, Authorizer, Principal, SaslState};
+use crate::quota::QuotaManager;
+use crate::topic::{ClientId, TopicName, TransactionId};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Keeps track of the client ids bound to open connections, so that no two connections can act
+/// as the same client.
+pub trait ClientRegistry {
+    /// Claims `client_id` for a connection; returns `false` if another connection holds it.
+    async fn register_client(&self, client_id: ClientId) -> bool;
+    async fn deregister_client(&self, client_id: ClientId);
+
+    /// Claims a fresh client id for a subscription the broker holds on behalf of a client of
+    /// another protocol, so that no connection can register it and take the subscription over.
+    async fn register_new_client(&self) -> ClientId {
+        loop {
+            let client_id = ClientId::new_v4();
+            if self.register_client(client_id).await {
+                return client_id;
+            }
+        }
+    }
+}
+
+/// State bound to a single client connection, shared by all requests sent over it.
+#[derive(Default)]
+pub struct Session {
+    pub transaction_id: Option<TransactionId>,
+    /// Present when clients have to authenticate before sending any other request.
+    pub authenticator: Option<Arc<Authenticator>>,
+    /// Present when requests are checked against ACLs.
+    pub authorizer: Option<Arc<Authorizer>>,
+    /// Present when quotas are configured.
+    pub quotas: Option<Arc<QuotaManager>>,
+    pub sasl_state: SaslState,
+    pub principal: Option<Principal>,
+    /// Identity the connection registered as; subscriptions are only made and removed under it.
+    pub client_id: Option<ClientId>,
+    pub subscribed_topics: HashSet<TopicName>,
+    /// Address the client connected from, if it connected over TCP.
+    pub peer_ip: Option<IpAddr>,
+}
+
+impl Session {
+    pub fn new(
+        authenticator: Option<Arc<Authenticator>>,
+        authorizer: Option<Arc<Authorizer>>,
+        quotas: Option<Arc<QuotaManager>>,
+    ) -> Self {
+        Session {
+            authenticator,
+            authorizer,
+            quotas,
+            ..Session::default()
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticator.is_none() || self.principal.is_some()
+    }
+
+    pub fn principal_name(&self) -> &str {
+        self.principal.as_deref().unwrap_or(ANONYMOUS)
+    }
+
+    /// Who client quotas are accounted to: the authenticated user, or else `ANONYMOUS` at the
+    /// address the client connected from, so that unauthenticated clients neither share a quota
+    /// nor get a fresh one by reconnecting. Client ids are picked by clients themselves and are
+    /// never used. Unauthenticated clients of Unix domain sockets all share the quota of
+    /// `ANONYMOUS`.
+    pub fn client_identity(&self) -> String {
+        match (&self.principal, self.peer_ip) {
+            (Some(principal), _) => principal.clone(),
+            (None, Some(peer_ip)) => format!("{ANONYMOUS}@{peer_ip}"),
+            (None, None) => ANONYMOUS.to_string(),
+        }
+    }
+
+    /// Whether the connection may see what concerns the broker as a whole rather than a topic,
+    /// such as its connections: without ACLs anyone may, otherwise only super users.
+    pub fn is_super_user(&self) -> bool {
+        self.authorizer
+            .as_ref()
+            .is_none_or(|authorizer| authorizer.is_super_user(self.principal_name()))
+    }
+
+    pub fn is_authorized(&self, operation: AclOperation, topic: &str) -> bool {
+        self.authorizer
+            .as_ref()
+            .is_none_or(|authorizer| authorizer.is_allowed(self.principal_name(), operation, topic))
+    }
+}
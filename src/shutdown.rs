@@ -1,14 +1,37 @@
 use std::sync::Arc;
 use tokio::sync::Notify;
 
-pub fn ctrl_c_shutdown_signal() -> Arc<Notify> {
+/// Notifies once the process is asked to terminate, by Ctrl-C or, on Unix, by SIGTERM.
+pub fn termination_signal() -> Arc<Notify> {
     let notify = Arc::new(Notify::new());
     tokio::spawn({
         let notify = Arc::clone(&notify);
         async move {
-            let _ = tokio::signal::ctrl_c().await;
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = sigterm() => {}
+            }
             notify.notify_waiters();
         }
     });
     notify
 }
+
+#[cfg(unix)]
+async fn sigterm() {
+    use tokio::signal::unix::{SignalKind, signal};
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            sigterm.recv().await;
+        }
+        Err(e) => {
+            tracing::error!("Failed to listen for SIGTERM: {e}");
+            std::future::pending::<()>().await
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn sigterm() {
+    std::future::pending::<()>().await
+}
@@ -1,12 +1,14 @@
 use crate::broker::Broker;
 use crate::config::BrokerConfig;
+use crate::readiness::Readiness;
 use crate::server::start_broker_server;
 use std::sync::Arc;
 
 pub async fn run_broker(
     config: BrokerConfig,
     shutdown_signal: Arc<tokio::sync::Notify>,
+    readiness: Arc<Readiness>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let broker = Broker::default();
-    start_broker_server(broker, config, shutdown_signal).await
+    let broker = Broker::new(&config);
+    start_broker_server(broker, config, shutdown_signal, readiness).await
 }
@@ -0,0 +1,86 @@
+use opentelemetry::Context;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{TraceContextExt, TracerProvider};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::collections::HashMap;
+use tracing::{Level, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Target of the spans exported over OTLP. Spans of any other target, such as the connection and
+/// request spans, only annotate logs.
+pub const TARGET: &str = "kafkalite::telemetry";
+
+const TRACEPARENT: &str = "traceparent";
+
+/// Exports the broker's spans to an OTLP/HTTP collector at `endpoint`, e.g.
+/// `http://localhost:4318/v1/traces`. Spans are sent in batches from a background thread.
+pub fn tracer_provider(endpoint: &str) -> Result<SdkTracerProvider, Box<dyn std::error::Error>> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("kafkalite").build())
+        .build())
+}
+
+/// A layer turning the spans of [`TARGET`] into OpenTelemetry spans of `provider`.
+pub fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("kafkalite"))
+        .with_filter(Targets::new().with_target(TARGET, Level::INFO))
+}
+
+/// Makes `span` continue the trace of `traceparent` and returns the trace context to hand on to
+/// whatever comes next: the one of `span` if it is exported, otherwise `traceparent` itself as
+/// long as it is valid, so that traces pass through a broker that doesn't export spans.
+pub fn continue_trace(span: &Span, traceparent: Option<&str>) -> Option<String> {
+    let parent = traceparent.and_then(extract);
+    if let Some(parent) = &parent {
+        let _ = span.set_parent(parent.clone());
+    }
+    inject(&span.context()).or_else(|| parent.as_ref().and_then(inject))
+}
+
+fn extract(traceparent: &str) -> Option<Context> {
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    let context = TraceContextPropagator::new().extract(&carrier);
+    context.span().span_context().is_valid().then_some(context)
+}
+
+fn inject(context: &Context) -> Option<String> {
+    if !context.span().span_context().is_valid() {
+        return None;
+    }
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(context, &mut carrier);
+    carrier.remove(TRACEPARENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACEPARENT_VALUE: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    #[test]
+    fn passes_valid_trace_context_through_without_exporter() {
+        let span = tracing::info_span!(target: TARGET, "append");
+        assert_eq!(
+            continue_trace(&span, Some(TRACEPARENT_VALUE)).as_deref(),
+            Some(TRACEPARENT_VALUE)
+        );
+        assert_eq!(continue_trace(&span, Some("00-invalid")), None);
+        assert_eq!(continue_trace(&span, None), None);
+    }
+}
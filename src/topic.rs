@@ -1,23 +1,76 @@
-use std::collections::{HashMap, VecDeque};
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use crate::compression::Compression;
+use crate::metrics::{SubscriptionMetrics, TopicMetrics};
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::sync::watch;
 use uuid::Uuid;
 
 pub trait TopicManager {
-    async fn add_topic(&self, topic_name: &TopicName, retention: u64) -> bool;
+    async fn add_topic(
+        &self,
+        topic_name: &TopicName,
+        retention: Option<u64>,
+        compression: Option<Compression>,
+    ) -> Result<(), TopicAddError>;
     async fn delete_topic(&self, topic_name: &TopicName) -> bool;
     async fn list_topics(&self) -> Vec<TopicName>;
+    async fn describe_topic(&self, topic_name: &TopicName) -> Option<TopicDescription>;
+    async fn topic_exists(&self, topic_name: &TopicName) -> bool;
+    /// A receiver that is notified whenever a topic is added.
+    fn topics_added(&self) -> watch::Receiver<()>;
+}
+
+pub enum TopicAddError {
+    EmptyName,
+    AlreadyExists(TopicName),
+    InvalidRetention { retention: u64, limit: u64 },
+}
+
+/// A snapshot of a topic's configuration and state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicDescription {
+    pub name: TopicName,
+    pub retention: u64,
+    pub compression: Option<Compression>,
+    /// Offset of the oldest retained record.
+    pub start_offset: u64,
+    /// Offset the next record gets appended at.
+    pub end_offset: u64,
+    /// Offset below which no record belongs to an open transaction.
+    pub last_stable_offset: u64,
+    pub subscribers: Vec<ClientId>,
+    pub retained_records: usize,
+    pub retained_bytes: u64,
 }
 
 pub trait TopicPublisher {
+    /// Appends a message and returns the offset it was appended at.
     async fn publish(
         &self,
         topic_name: &TopicName,
-        message_payload: Vec<u8>,
-    ) -> Result<(), TopicPublishError>;
+        message_payload: Bytes,
+        compression: Compression,
+        traceparent: Option<String>,
+    ) -> Result<u64, TopicPublishError>;
+
+    /// Appends all messages one after another, or none of them if any is rejected, and returns
+    /// the offset of the first one.
+    async fn publish_batch(
+        &self,
+        topic_name: &TopicName,
+        message_payloads: Vec<Bytes>,
+        compression: Compression,
+        traceparent: Option<String>,
+    ) -> Result<u64, TopicPublishError>;
 }
 
 pub enum TopicPublishError {
     TopicNotFound(TopicName),
+    TransactionNotFound(TransactionId),
+    InvalidPayload(TopicName, std::io::Error),
+    PayloadTooLarge { size: usize, limit: usize },
+    TopicRecreated(TopicName),
 }
 
 pub trait TopicSubscriber {
@@ -25,6 +78,7 @@ pub trait TopicSubscriber {
         &self,
         topic_name: &TopicName,
         from_offset: Option<u64>,
+        isolation_level: IsolationLevel,
         client_id: ClientId,
     ) -> Result<Subscription, TopicSubscribeError>;
 
@@ -33,89 +87,438 @@ pub trait TopicSubscriber {
         topic_name: &TopicName,
         client_id: ClientId,
     ) -> Result<(), TopicSubscribeError>;
+
+    /// Reads up to `max` records starting at `from_offset`, or at the oldest retained one, without
+    /// subscribing to the topic.
+    async fn fetch(
+        &self,
+        topic_name: &TopicName,
+        from_offset: u64,
+        isolation_level: IsolationLevel,
+        max: usize,
+    ) -> Result<Vec<MessageRecord>, TopicSubscribeError>;
+
+    /// Notifies of records appended to the topic, so that point reads can wait for new ones
+    /// without subscribing. Also changes when the topic is deleted.
+    async fn appended(
+        &self,
+        topic_name: &TopicName,
+    ) -> Result<watch::Receiver<()>, TopicSubscribeError>;
 }
 
 pub enum TopicSubscribeError {
     TopicNotFound(TopicName),
 }
 
+pub trait TopicTransactionCoordinator {
+    async fn begin_transaction(&self) -> TransactionId;
+
+    async fn publish_in_transaction(
+        &self,
+        transaction_id: TransactionId,
+        topic_name: &TopicName,
+        message_payload: Bytes,
+        compression: Compression,
+        traceparent: Option<String>,
+    ) -> Result<(), TopicPublishError>;
+
+    async fn commit_transaction(
+        &self,
+        transaction_id: TransactionId,
+    ) -> Result<(), TopicTransactionError>;
+
+    async fn abort_transaction(
+        &self,
+        transaction_id: TransactionId,
+    ) -> Result<(), TopicTransactionError>;
+}
+
+pub enum TopicTransactionError {
+    TransactionNotFound(TransactionId),
+}
+
+/// A topic is split into an append-only log and its subscriptions. Appending only takes the log
+/// lock for as long as it takes to push a record; every subscription then pulls new records at its
+/// own pace after being woken up through the `appended` channel, so slow subscribers never hold
+/// back producers.
 pub struct Topic {
     pub topic_name: TopicName,
-    subscribers: HashMap<ClientId, SubscriberHandle>,
-    log: VecDeque<MessageRecord>,
-    retention: u64,
-    next_offset: u64,
+    compression: Option<Compression>,
+    log: Arc<RwLock<TopicLog>>,
+    appended: watch::Sender<()>,
+    subscribers: Mutex<HashMap<ClientId, SubscriberHandle>>,
+    metrics: TopicMetrics,
 }
 
 impl Topic {
-    pub fn new(topic_name: &str, retention: u64) -> Self {
+    pub fn new(topic_name: &str, retention: u64, compression: Option<Compression>) -> Self {
+        let (appended, _) = watch::channel(());
         Self {
             topic_name: topic_name.to_string(),
-            subscribers: HashMap::new(),
-            log: VecDeque::with_capacity(retention as usize),
-            retention,
-            next_offset: 0,
+            compression,
+            log: Arc::new(RwLock::new(TopicLog::new(retention))),
+            appended,
+            subscribers: Mutex::new(HashMap::new()),
+            metrics: TopicMetrics::detached(),
         }
     }
+
+    pub fn with_metrics(self, metrics: TopicMetrics) -> Self {
+        Topic { metrics, ..self }
+    }
 }
 
 pub type ClientId = Uuid;
 
 pub type TopicName = String;
 
-impl Topic {
-    pub fn subscribe(&mut self, client_id: ClientId, from_offset: Option<u64>) -> Subscription {
-        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel::<MessageRecord>();
+pub type TransactionId = Uuid;
+
+/// How much of the not yet committed transactional data a subscriber gets to see.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum IsolationLevel {
+    /// Every appended message, including ones from open or aborted transactions.
+    ReadUncommitted,
+    /// Only messages below the last stable offset, never ones from aborted transactions.
+    ReadCommitted,
+}
 
+impl Topic {
+    pub fn subscribe(
+        &self,
+        client_id: ClientId,
+        from_offset: Option<u64>,
+        isolation_level: IsolationLevel,
+    ) -> Subscription {
+        // Replays start at the oldest retained record, so that only records evicted while the
+        // subscription lags behind count as skipped.
+        let log = self.read_log();
         let start_offset = match from_offset {
-            Some(offset) => offset,
-            None => self.next_offset,
+            Some(offset) => offset.max(log.start_offset()),
+            None => log.next_offset,
+        };
+        drop(log);
+
+        let (closed, closed_receiver) = watch::channel(());
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        subscribers.retain(|_, subscriber_handle| !subscriber_handle.closed.is_closed());
+        subscribers.insert(client_id, SubscriberHandle::new(closed));
+        self.metrics.subscribers.set(subscribers.len() as i64);
+
+        Subscription::new(
+            self.topic_name.to_string(),
+            Arc::clone(&self.log),
+            self.appended.subscribe(),
+            closed_receiver,
+            start_offset,
+            isolation_level,
+            self.metrics.subscription(),
+        )
+    }
+
+    /// Reads up to `max` records visible at the given isolation level starting at `from_offset`,
+    /// and counts them as delivered.
+    pub fn fetch(
+        &self,
+        from_offset: u64,
+        isolation_level: IsolationLevel,
+        max: usize,
+    ) -> Vec<MessageRecord> {
+        let mut records = VecDeque::with_capacity(max.min(READ_BATCH_SIZE));
+        self.read_log()
+            .read_from(from_offset, isolation_level, max, &mut records);
+        for record in &records {
+            self.metrics.messages_out.inc();
+            self.metrics.bytes_out.inc_by(record.payload.len() as u64);
+        }
+        records.into()
+    }
+
+    pub fn unsubscribe(&self, client_id: ClientId) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        subscribers.remove(&client_id);
+        self.metrics.subscribers.set(subscribers.len() as i64);
+    }
+
+    pub fn describe(&self) -> TopicDescription {
+        let mut subscribers: Vec<ClientId> = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|(_, subscriber_handle)| !subscriber_handle.closed.is_closed())
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        subscribers.sort();
+        let log = self.read_log();
+        TopicDescription {
+            name: self.topic_name.clone(),
+            retention: log.retention,
+            compression: self.compression,
+            start_offset: log.start_offset(),
+            end_offset: log.next_offset,
+            last_stable_offset: log.last_stable_offset(),
+            subscribers,
+            retained_records: log.records.len(),
+            retained_bytes: log.retained_bytes,
+        }
+    }
+
+    /// Ends every subscription to this topic, e.g. once the topic gets deleted.
+    pub fn close(&self) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        subscribers.clear();
+        self.metrics.subscribers.set(0);
+        drop(subscribers);
+        // Wakes up fetches waiting for appends, which then find the topic gone.
+        self.appended.send_replace(());
+    }
+
+    /// Changes whenever records are appended to the topic or their visibility changes.
+    pub fn appended(&self) -> watch::Receiver<()> {
+        self.appended.subscribe()
+    }
+
+    /// Appends a message and returns its offset; `traceparent` is the trace context its
+    /// deliveries continue.
+    pub fn publish(
+        &self,
+        payload: Bytes,
+        compression: Compression,
+        traceparent: Option<String>,
+    ) -> u64 {
+        let offset = {
+            let mut log = self.write_log();
+            self.record_appended(&payload);
+            let offset = log.append(payload, compression, traceparent);
+            self.record_retained(&log);
+            offset
         };
+        self.appended.send_replace(());
+        offset
+    }
 
-        for message in self.log.iter() {
-            if message.offset >= start_offset {
-                let _ = sender.send(message.clone());
+    /// Appends messages at consecutive offsets under a single log lock, so subscribers never see
+    /// only part of them, and returns the offset of the first one.
+    pub fn publish_batch(
+        &self,
+        payloads: Vec<(Bytes, Compression)>,
+        traceparent: Option<String>,
+    ) -> u64 {
+        let offset = {
+            let mut log = self.write_log();
+            let offset = log.next_offset;
+            for (payload, compression) in payloads {
+                self.record_appended(&payload);
+                log.append(payload, compression, traceparent.clone());
             }
+            self.record_retained(&log);
+            offset
+        };
+        self.appended.send_replace(());
+        offset
+    }
+
+    /// Appends a message that stays invisible to read-committed subscribers until its
+    /// transaction is committed.
+    pub fn publish_in_transaction(
+        &self,
+        transaction_id: TransactionId,
+        payload: Bytes,
+        compression: Compression,
+        traceparent: Option<String>,
+    ) {
+        {
+            let mut log = self.write_log();
+            self.record_appended(&payload);
+            let offset = log.append(payload, compression, traceparent);
+            log.pending_transactions
+                .entry(transaction_id)
+                .or_default()
+                .push(offset);
+            self.record_retained(&log);
         }
+        self.appended.send_replace(());
+    }
 
-        self.subscribers
-            .entry(client_id)
-            .or_insert(SubscriberHandle::new(sender));
+    /// Commits a transaction on all given topics while holding all of their logs locked, so no
+    /// subscriber ever observes it applied to only part of them. Topics have to be passed in a
+    /// stable order to avoid deadlocks.
+    pub fn commit_transaction<'a>(
+        topics: impl IntoIterator<Item = &'a Topic>,
+        transaction_id: TransactionId,
+    ) {
+        let topics: Vec<&Topic> = topics.into_iter().collect();
+        {
+            let mut logs: Vec<_> = topics.iter().map(|topic| topic.write_log()).collect();
+            for log in logs.iter_mut() {
+                log.pending_transactions.remove(&transaction_id);
+            }
+        }
+        for topic in topics {
+            topic.appended.send_replace(());
+        }
+    }
 
-        Subscription::new(self.topic_name.to_string(), receiver)
+    pub fn abort_transaction(&self, transaction_id: TransactionId) {
+        {
+            let mut log = self.write_log();
+            if let Some(offsets) = log.pending_transactions.remove(&transaction_id) {
+                // Offsets retention already evicted are never read again.
+                let start_offset = log.start_offset();
+                log.aborted_offsets
+                    .extend(offsets.into_iter().filter(|offset| *offset >= start_offset));
+            }
+        }
+        self.appended.send_replace(());
+    }
+
+    /// Recompresses a payload to the topic-level codec; payloads are stored as sent if there is none.
+    /// Payloads decompressing to more than `max_payload_size` bytes are rejected.
+    pub fn encode_payload(
+        &self,
+        payload: Bytes,
+        compression: Compression,
+        max_payload_size: usize,
+    ) -> std::io::Result<(Bytes, Compression)> {
+        match self.compression {
+            Some(target) => Ok((
+                compression.recompress(payload, target, max_payload_size)?,
+                target,
+            )),
+            None => Ok((payload, compression)),
+        }
     }
 
-    pub fn unsubscribe(&mut self, client_id: ClientId) {
-        self.subscribers.remove(&client_id);
+    fn record_appended(&self, payload: &Bytes) {
+        self.metrics.messages_in.inc();
+        self.metrics.bytes_in.inc_by(payload.len() as u64);
     }
 
-    pub fn publish(&mut self, payload: Vec<u8>) {
+    fn record_retained(&self, log: &TopicLog) {
+        self.metrics.retained_records.set(log.records.len() as i64);
+        self.metrics.retained_bytes.set(log.retained_bytes as i64);
+    }
+
+    fn read_log(&self) -> RwLockReadGuard<'_, TopicLog> {
+        self.log.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_log(&self) -> RwLockWriteGuard<'_, TopicLog> {
+        self.log.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+struct TopicLog {
+    records: VecDeque<MessageRecord>,
+    retention: u64,
+    /// Sum of the payload sizes of `records`.
+    retained_bytes: u64,
+    next_offset: u64,
+    pending_transactions: HashMap<TransactionId, Vec<u64>>,
+    aborted_offsets: HashSet<u64>,
+}
+
+impl TopicLog {
+    fn new(retention: u64) -> Self {
+        Self {
+            records: VecDeque::new(),
+            retention,
+            retained_bytes: 0,
+            next_offset: 0,
+            pending_transactions: HashMap::new(),
+            aborted_offsets: HashSet::new(),
+        }
+    }
+
+    /// Offset of the oldest retained record, or of the next one if none is retained.
+    fn start_offset(&self) -> u64 {
+        self.records
+            .front()
+            .map_or(self.next_offset, |record| record.offset)
+    }
+
+    /// Offset below which every retained message belongs to a finished transaction (or none).
+    fn last_stable_offset(&self) -> u64 {
+        self.pending_transactions
+            .values()
+            .filter_map(|offsets| offsets.first().copied())
+            .min()
+            .unwrap_or(self.next_offset)
+    }
+
+    fn append(
+        &mut self,
+        payload: Bytes,
+        compression: Compression,
+        traceparent: Option<String>,
+    ) -> u64 {
         let offset = self.get_next_offset();
-        let message_record = MessageRecord::new(offset, payload);
+        let message_record = MessageRecord {
+            traceparent: traceparent.map(Arc::from),
+            ..MessageRecord::new(offset, payload, compression)
+        };
+        self.persist_message(message_record);
+        offset
+    }
 
-        self.persist_message(message_record.clone());
+    /// Copies (cheaply, payloads are shared) up to `limit` records visible at the given isolation
+    /// level starting from `next_offset`, and returns the offset to continue reading from.
+    fn read_from(
+        &self,
+        next_offset: u64,
+        isolation_level: IsolationLevel,
+        limit: usize,
+        into: &mut VecDeque<MessageRecord>,
+    ) -> u64 {
+        let read_committed = isolation_level == IsolationLevel::ReadCommitted;
+        let end_offset = if read_committed {
+            self.last_stable_offset()
+        } else {
+            self.next_offset
+        };
+        let first_offset = match self.records.front() {
+            Some(message) => message.offset,
+            None => return next_offset.max(end_offset),
+        };
 
-        let mut dead_subscribers = vec![];
+        let start_offset = next_offset.max(first_offset);
+        let skip = start_offset.saturating_sub(first_offset) as usize;
 
-        for (&client_id, subscriber_handle) in self.subscribers.iter() {
-            if subscriber_handle
-                .sender
-                .send(message_record.clone())
-                .is_err()
-            {
-                dead_subscribers.push(client_id);
+        let mut read = 0;
+        for message in self.records.iter().skip(skip) {
+            if message.offset >= end_offset {
+                break;
+            }
+            if read == limit {
+                return message.offset;
+            }
+            if !read_committed || !self.aborted_offsets.contains(&message.offset) {
+                into.push_back(message.clone());
+                read += 1;
             }
         }
 
-        for client_id in dead_subscribers {
-            self.subscribers.remove(&client_id);
-        }
+        next_offset.max(end_offset)
     }
 
     fn persist_message(&mut self, message: MessageRecord) {
-        self.log.push_back(message);
-        if self.log.len() > self.retention as usize {
-            self.log.pop_front();
+        self.retained_bytes += message.payload.len() as u64;
+        self.records.push_back(message);
+        if self.records.len() > self.retention as usize
+            && let Some(evicted) = self.records.pop_front()
+        {
+            self.retained_bytes -= evicted.payload.len() as u64;
+            self.aborted_offsets.remove(&evicted.offset);
         }
     }
 
@@ -128,37 +531,124 @@ impl Topic {
 
 pub struct Subscription {
     pub topic_name: TopicName,
-    pub receiver: UnboundedReceiver<MessageRecord>,
+    log: Arc<RwLock<TopicLog>>,
+    appended: watch::Receiver<()>,
+    closed: watch::Receiver<()>,
+    next_offset: u64,
+    isolation_level: IsolationLevel,
+    buffered: VecDeque<MessageRecord>,
+    metrics: SubscriptionMetrics,
 }
 
+/// Most records a subscription copies out of the log at once, so that a subscriber catching up
+/// on a long log doesn't hold the log lock, or buffer the records, all at once.
+const READ_BATCH_SIZE: usize = 256;
+
 impl Subscription {
-    pub fn new(topic_name: TopicName, receiver: UnboundedReceiver<MessageRecord>) -> Self {
+    fn new(
+        topic_name: TopicName,
+        log: Arc<RwLock<TopicLog>>,
+        appended: watch::Receiver<()>,
+        closed: watch::Receiver<()>,
+        next_offset: u64,
+        isolation_level: IsolationLevel,
+        metrics: SubscriptionMetrics,
+    ) -> Self {
         Subscription {
             topic_name,
-            receiver,
+            log,
+            appended,
+            closed,
+            next_offset,
+            isolation_level,
+            buffered: VecDeque::new(),
+            metrics,
+        }
+    }
+
+    /// Waits for the next message, returning `None` once the subscription has been unsubscribed
+    /// or its topic deleted.
+    pub async fn recv(&mut self) -> Option<MessageRecord> {
+        loop {
+            if let Some(message) = self.try_recv() {
+                return Some(message);
+            }
+            if self.is_closed() {
+                return None;
+            }
+            tokio::select! {
+                appended = self.appended.changed() => appended.ok()?,
+                _ = self.closed.changed() => return None,
+            }
         }
     }
+
+    /// Returns the next message if one is available right away.
+    pub fn try_recv(&mut self) -> Option<MessageRecord> {
+        if self.is_closed() {
+            return None;
+        }
+        if self.buffered.is_empty() {
+            self.appended.mark_unchanged();
+            let log = self.log.read().unwrap_or_else(PoisonError::into_inner);
+            let skipped = log.start_offset().saturating_sub(self.next_offset);
+            if skipped > 0 {
+                tracing::warn!(
+                    "Subscription to {} skipped {skipped} records evicted by retention before \
+                     they were read",
+                    self.topic_name
+                );
+                self.metrics.messages_skipped.inc_by(skipped);
+            }
+            self.next_offset = log.read_from(
+                self.next_offset,
+                self.isolation_level,
+                READ_BATCH_SIZE,
+                &mut self.buffered,
+            );
+        }
+        self.buffered.pop_front()
+    }
+
+    /// Counts a record of `bytes` bytes as delivered to the subscriber.
+    pub fn record_delivered(&self, bytes: usize) {
+        self.metrics.messages_out.inc();
+        self.metrics.bytes_out.inc_by(bytes as u64);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.has_changed().is_err()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct MessageRecord {
     pub offset: u64,
-    pub payload: Vec<u8>,
+    pub payload: Bytes,
+    pub compression: Compression,
+    /// Trace context of the append, shared by every delivery of the record.
+    pub traceparent: Option<Arc<str>>,
 }
 
 impl MessageRecord {
-    pub fn new(offset: u64, payload: Vec<u8>) -> Self {
-        MessageRecord { offset, payload }
+    pub fn new(offset: u64, payload: Bytes, compression: Compression) -> Self {
+        MessageRecord {
+            offset,
+            payload,
+            compression,
+            traceparent: None,
+        }
     }
 }
 
-pub struct SubscriberHandle {
-    sender: UnboundedSender<MessageRecord>,
+/// Keeps a subscription alive; dropping it ends the subscription.
+struct SubscriberHandle {
+    closed: watch::Sender<()>,
 }
 
 impl SubscriberHandle {
-    fn new(sender: UnboundedSender<MessageRecord>) -> Self {
-        Self { sender }
+    fn new(closed: watch::Sender<()>) -> Self {
+        Self { closed }
     }
 }
 
@@ -168,82 +658,396 @@ mod tests {
 
     #[test]
     fn publishing_new_messages_into_topic_increases_its_offset() {
-        let mut topic = Topic::new("topic-1", 5);
-        assert_eq!(topic.next_offset, 0);
+        let topic = Topic::new("topic-1", 5, None);
+        assert_eq!(topic.read_log().next_offset, 0);
 
-        topic.publish(b"test-message-1".to_vec());
-        assert_eq!(topic.next_offset, 1);
-        topic.publish(b"test-message-2".to_vec());
-        assert_eq!(topic.next_offset, 2);
-        topic.publish(b"test-message-3".to_vec());
-        assert_eq!(topic.next_offset, 3);
+        topic.publish(
+            Bytes::from_static(b"test-message-1"),
+            Compression::None,
+            None,
+        );
+        assert_eq!(topic.read_log().next_offset, 1);
+        topic.publish(
+            Bytes::from_static(b"test-message-2"),
+            Compression::None,
+            None,
+        );
+        assert_eq!(topic.read_log().next_offset, 2);
+        topic.publish(
+            Bytes::from_static(b"test-message-3"),
+            Compression::None,
+            None,
+        );
+        assert_eq!(topic.read_log().next_offset, 3);
 
-        let offsets = topic.log.iter().map(|m| m.offset).collect::<Vec<_>>();
+        let offsets = topic
+            .read_log()
+            .records
+            .iter()
+            .map(|m| m.offset)
+            .collect::<Vec<_>>();
         assert_eq!(offsets, vec![0, 1, 2]);
     }
 
     #[test]
     fn publishing_drops_old_messages_based_on_retention() {
-        let mut topic = Topic::new("topic-1", 3);
+        let topic = Topic::new("topic-1", 3, None);
 
-        topic.publish(vec![1]);
-        topic.publish(vec![2]);
-        topic.publish(vec![3]);
-        topic.publish(vec![4]);
-        topic.publish(vec![5]);
+        topic.publish(Bytes::from(vec![1]), Compression::None, None);
+        topic.publish(Bytes::from(vec![2]), Compression::None, None);
+        topic.publish(Bytes::from(vec![3]), Compression::None, None);
+        topic.publish(Bytes::from(vec![4]), Compression::None, None);
+        topic.publish(Bytes::from(vec![5]), Compression::None, None);
 
-        let messages: Vec<u8> = topic.log.iter().map(|m| m.payload[0]).collect();
+        let log = topic.read_log();
+        let messages: Vec<u8> = log.records.iter().map(|m| m.payload[0]).collect();
         assert_eq!(messages, vec![3, 4, 5]);
-        assert_eq!(topic.log.len(), 3);
-        assert_eq!(topic.next_offset, 5);
+        assert_eq!(log.records.len(), 3);
+        assert_eq!(log.retained_bytes, 3);
+        assert_eq!(log.next_offset, 5);
+    }
+
+    #[test]
+    fn publishing_a_batch_appends_its_messages_at_consecutive_offsets() {
+        let topic = Topic::new("topic-1", 5, None);
+        topic.publish(Bytes::from(vec![1]), Compression::None, None);
+
+        let payloads = vec![
+            (Bytes::from(vec![2]), Compression::None),
+            (Bytes::from(vec![3]), Compression::None),
+        ];
+        assert_eq!(topic.publish_batch(payloads, None), 1);
+
+        let log = topic.read_log();
+        let messages: Vec<(u64, u8)> = log
+            .records
+            .iter()
+            .map(|m| (m.offset, m.payload[0]))
+            .collect();
+        assert_eq!(messages, vec![(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(log.next_offset, 3);
+    }
+
+    #[test]
+    fn describes_retained_range_and_open_subscriptions() {
+        let topic = Topic::new("topic-1", 2, Some(Compression::Lz4));
+        for payload in [vec![1], vec![2, 2], vec![3, 3, 3]] {
+            topic.publish(Bytes::from(payload), Compression::Lz4, None);
+        }
+        let transaction_id = TransactionId::new_v4();
+        topic.publish_in_transaction(transaction_id, Bytes::from(vec![4]), Compression::Lz4, None);
+        let client_id = ClientId::new_v4();
+        let _subscription = topic.subscribe(client_id, None, IsolationLevel::ReadCommitted);
+        drop(topic.subscribe(ClientId::new_v4(), None, IsolationLevel::ReadCommitted));
+
+        let description = topic.describe();
+        assert_eq!(description.retention, 2);
+        assert_eq!(description.compression, Some(Compression::Lz4));
+        assert_eq!(description.start_offset, 2);
+        assert_eq!(description.end_offset, 4);
+        assert_eq!(description.last_stable_offset, 3);
+        assert_eq!(description.subscribers, vec![client_id]);
+        assert_eq!(description.retained_records, 2);
+        assert_eq!(description.retained_bytes, 4);
     }
 
     #[test]
     fn replies_all_retained_messages_when_new_client_subscribe_to_topic() {
-        let mut topic = Topic::new("topic-1", 3);
+        let topic = Topic::new("topic-1", 3, None);
 
-        topic.publish(vec![1]);
-        topic.publish(vec![2]);
+        topic.publish(Bytes::from(vec![1]), Compression::None, None);
+        topic.publish(Bytes::from(vec![2]), Compression::None, None);
 
         let from_offset = Some(0);
-        let mut subscription = topic.subscribe(ClientId::new_v4(), from_offset);
+        let mut subscription = topic.subscribe(
+            ClientId::new_v4(),
+            from_offset,
+            IsolationLevel::ReadCommitted,
+        );
 
-        let mut messages = vec![];
-        subscription.receiver.blocking_recv_many(&mut messages, 2);
-
-        let payloads: Vec<u8> = messages.iter().map(|m| m.payload[0]).collect();
-        assert_eq!(payloads, vec![1, 2]);
+        assert_eq!(received_payloads(&mut subscription), vec![1, 2]);
     }
 
     #[test]
     fn replies_retained_messages_starting_from_given_offset_when_new_client_subscribe_to_topic() {
-        let mut topic = Topic::new("topic-1", 5);
+        let topic = Topic::new("topic-1", 5, None);
 
-        topic.publish(vec![1]);
-        topic.publish(vec![2]);
-        topic.publish(vec![3]);
-        topic.publish(vec![4]);
+        topic.publish(Bytes::from(vec![1]), Compression::None, None);
+        topic.publish(Bytes::from(vec![2]), Compression::None, None);
+        topic.publish(Bytes::from(vec![3]), Compression::None, None);
+        topic.publish(Bytes::from(vec![4]), Compression::None, None);
 
         let from_offset = Some(2);
-        let mut subscription = topic.subscribe(ClientId::new_v4(), from_offset);
-
-        let mut messages = vec![];
-        subscription.receiver.blocking_recv_many(&mut messages, 2);
+        let mut subscription = topic.subscribe(
+            ClientId::new_v4(),
+            from_offset,
+            IsolationLevel::ReadCommitted,
+        );
 
-        let payloads: Vec<u8> = messages.iter().map(|m| m.payload[0]).collect();
-        assert_eq!(payloads, vec![3, 4]);
+        assert_eq!(received_payloads(&mut subscription), vec![3, 4]);
     }
 
     #[test]
     fn replies_no_messages_when_new_client_subscribe_to_topic_without_from_offset() {
-        let mut topic = Topic::new("topic-1", 3);
+        let topic = Topic::new("topic-1", 3, None);
 
-        topic.publish(vec![1]);
-        topic.publish(vec![2]);
+        topic.publish(Bytes::from(vec![1]), Compression::None, None);
+        topic.publish(Bytes::from(vec![2]), Compression::None, None);
 
         let from_offset = None;
-        let subscription = topic.subscribe(ClientId::new_v4(), from_offset);
+        let mut subscription = topic.subscribe(
+            ClientId::new_v4(),
+            from_offset,
+            IsolationLevel::ReadCommitted,
+        );
+
+        assert!(subscription.try_recv().is_none());
+    }
+
+    #[test]
+    fn holds_back_transactional_messages_until_transaction_is_committed() {
+        let topic = Topic::new("topic-1", 5, None);
+        let mut subscription =
+            topic.subscribe(ClientId::new_v4(), None, IsolationLevel::ReadCommitted);
+        let transaction_id = TransactionId::new_v4();
+
+        topic.publish_in_transaction(
+            transaction_id,
+            Bytes::from(vec![1]),
+            Compression::None,
+            None,
+        );
+        topic.publish_in_transaction(
+            transaction_id,
+            Bytes::from(vec![2]),
+            Compression::None,
+            None,
+        );
+        assert!(subscription.try_recv().is_none());
+
+        Topic::commit_transaction([&topic], transaction_id);
+
+        assert_eq!(received_payloads(&mut subscription), vec![1, 2]);
+    }
+
+    #[test]
+    fn never_delivers_messages_from_aborted_transaction() {
+        let topic = Topic::new("topic-1", 5, None);
+        let mut subscription =
+            topic.subscribe(ClientId::new_v4(), None, IsolationLevel::ReadCommitted);
+        let transaction_id = TransactionId::new_v4();
+
+        topic.publish_in_transaction(
+            transaction_id,
+            Bytes::from(vec![1]),
+            Compression::None,
+            None,
+        );
+        topic.publish(Bytes::from(vec![2]), Compression::None, None);
+        assert!(subscription.try_recv().is_none());
+
+        topic.abort_transaction(transaction_id);
+
+        let message = subscription.try_recv().expect("Expected a message");
+        assert_eq!(message.offset, 1);
+        assert!(subscription.try_recv().is_none());
+
+        let mut replayed =
+            topic.subscribe(ClientId::new_v4(), Some(0), IsolationLevel::ReadCommitted);
+        assert_eq!(received_payloads(&mut replayed), vec![2]);
+    }
+
+    #[test]
+    fn forgets_aborted_offsets_already_dropped_by_retention() {
+        let topic = Topic::new("topic-1", 2, None);
+        let transaction_id = TransactionId::new_v4();
+
+        topic.publish_in_transaction(
+            transaction_id,
+            Bytes::from(vec![1]),
+            Compression::None,
+            None,
+        );
+        topic.publish_in_transaction(
+            transaction_id,
+            Bytes::from(vec![2]),
+            Compression::None,
+            None,
+        );
+        topic.publish(Bytes::from(vec![3]), Compression::None, None);
+        topic.abort_transaction(transaction_id);
+
+        let log = topic.read_log();
+        assert_eq!(log.aborted_offsets, HashSet::from([1]));
+    }
 
-        assert!(subscription.receiver.is_empty());
+    #[test]
+    fn delivers_transactional_messages_immediately_to_read_uncommitted_subscribers() {
+        let topic = Topic::new("topic-1", 5, None);
+        let mut uncommitted =
+            topic.subscribe(ClientId::new_v4(), None, IsolationLevel::ReadUncommitted);
+        let mut committed =
+            topic.subscribe(ClientId::new_v4(), None, IsolationLevel::ReadCommitted);
+        let transaction_id = TransactionId::new_v4();
+
+        topic.publish_in_transaction(
+            transaction_id,
+            Bytes::from(vec![1]),
+            Compression::None,
+            None,
+        );
+        topic.publish(Bytes::from(vec![2]), Compression::None, None);
+
+        assert_eq!(received_payloads(&mut uncommitted), vec![1, 2]);
+        assert!(committed.try_recv().is_none());
+
+        topic.abort_transaction(transaction_id);
+
+        assert_eq!(received_payloads(&mut committed), vec![2]);
+        assert!(uncommitted.try_recv().is_none());
+    }
+
+    #[test]
+    fn read_committed_subscriber_stops_at_last_stable_offset_on_replay() {
+        let topic = Topic::new("topic-1", 5, None);
+        let transaction_id = TransactionId::new_v4();
+
+        topic.publish(Bytes::from(vec![1]), Compression::None, None);
+        topic.publish_in_transaction(
+            transaction_id,
+            Bytes::from(vec![2]),
+            Compression::None,
+            None,
+        );
+        topic.publish(Bytes::from(vec![3]), Compression::None, None);
+        assert_eq!(topic.read_log().last_stable_offset(), 1);
+
+        let mut subscription =
+            topic.subscribe(ClientId::new_v4(), Some(0), IsolationLevel::ReadCommitted);
+        assert_eq!(received_payloads(&mut subscription), vec![1]);
+
+        Topic::commit_transaction([&topic], transaction_id);
+        assert_eq!(topic.read_log().last_stable_offset(), 3);
+
+        assert_eq!(received_payloads(&mut subscription), vec![2, 3]);
+    }
+
+    #[test]
+    fn stores_payloads_as_sent_when_topic_has_no_compression() {
+        let topic = Topic::new("topic-1", 5, None);
+        let payload = Bytes::from(Compression::Lz4.compress(b"test-payload").unwrap());
+
+        let (encoded, compression) = topic
+            .encode_payload(payload.clone(), Compression::Lz4, 1024)
+            .expect("Failed to encode payload");
+
+        assert_eq!(encoded, payload);
+        assert_eq!(compression, Compression::Lz4);
+    }
+
+    #[test]
+    fn recompresses_payloads_to_topic_level_compression() {
+        let topic = Topic::new("topic-1", 5, Some(Compression::Zstd));
+        let payload = Bytes::from(Compression::Gzip.compress(b"test-payload").unwrap());
+
+        let (encoded, compression) = topic
+            .encode_payload(payload, Compression::Gzip, 1024)
+            .expect("Failed to encode payload");
+
+        assert_eq!(compression, Compression::Zstd);
+        assert_eq!(
+            Compression::Zstd.decompress(&encoded, 1024).unwrap(),
+            b"test-payload"
+        );
+    }
+
+    #[test]
+    fn fans_out_one_shared_payload_buffer_to_all_subscribers() {
+        let topic = Topic::new("topic-1", 5, None);
+        let mut subscription_1 =
+            topic.subscribe(ClientId::new_v4(), None, IsolationLevel::ReadCommitted);
+        let mut subscription_2 =
+            topic.subscribe(ClientId::new_v4(), None, IsolationLevel::ReadCommitted);
+
+        let payload = Bytes::from_static(b"test-payload");
+        topic.publish(payload.clone(), Compression::None, None);
+
+        let message_1 = subscription_1.try_recv().unwrap();
+        let message_2 = subscription_2.try_recv().unwrap();
+        assert_eq!(message_1.payload.as_ptr(), payload.as_ptr());
+        assert_eq!(message_2.payload.as_ptr(), payload.as_ptr());
+    }
+
+    #[test]
+    fn ends_subscription_on_unsubscribe() {
+        let topic = Topic::new("topic-1", 5, None);
+        let client_id = ClientId::new_v4();
+        let mut subscription = topic.subscribe(client_id, None, IsolationLevel::ReadCommitted);
+
+        topic.unsubscribe(client_id);
+        topic.publish(Bytes::from(vec![1]), Compression::None, None);
+
+        assert!(subscription.try_recv().is_none());
+    }
+
+    #[tokio::test]
+    async fn wakes_up_waiting_subscription_on_publish() {
+        let topic = Topic::new("topic-1", 5, None);
+        let mut subscription =
+            topic.subscribe(ClientId::new_v4(), None, IsolationLevel::ReadCommitted);
+
+        let received = tokio::spawn(async move { subscription.recv().await });
+        tokio::task::yield_now().await;
+        topic.publish(Bytes::from(vec![1]), Compression::None, None);
+
+        let message = received.await.unwrap().expect("Expected a message");
+        assert_eq!(message.payload[0], 1);
+    }
+
+    #[test]
+    fn counts_records_evicted_before_a_lagging_subscription_reads_them() {
+        let topic = Topic::new("topic-1", 2, None);
+        topic.publish(Bytes::from(vec![1]), Compression::None, None);
+        topic.publish(Bytes::from(vec![2]), Compression::None, None);
+        topic.publish(Bytes::from(vec![3]), Compression::None, None);
+        let mut subscription =
+            topic.subscribe(ClientId::new_v4(), Some(0), IsolationLevel::ReadCommitted);
+        assert_eq!(subscription.try_recv().unwrap().payload[0], 2);
+        assert_eq!(topic.metrics.messages_skipped.get(), 0);
+
+        for payload in 4..=7 {
+            topic.publish(Bytes::from(vec![payload]), Compression::None, None);
+        }
+
+        assert_eq!(received_payloads(&mut subscription), vec![3, 6, 7]);
+        assert_eq!(topic.metrics.messages_skipped.get(), 2);
+    }
+
+    #[test]
+    fn reads_long_logs_in_bounded_batches() {
+        let topic = Topic::new("topic-1", 1000, None);
+        for _ in 0..600 {
+            topic.publish(Bytes::from(vec![1]), Compression::None, None);
+        }
+        let mut subscription =
+            topic.subscribe(ClientId::new_v4(), Some(0), IsolationLevel::ReadCommitted);
+
+        let message = subscription.try_recv().expect("Expected a message");
+        assert_eq!(message.offset, 0);
+        assert_eq!(subscription.buffered.len(), READ_BATCH_SIZE - 1);
+
+        let mut offsets = vec![message.offset];
+        while let Some(message) = subscription.try_recv() {
+            offsets.push(message.offset);
+        }
+        assert_eq!(offsets, (0..600).collect::<Vec<_>>());
+    }
+
+    fn received_payloads(subscription: &mut Subscription) -> Vec<u8> {
+        let mut payloads = vec![];
+        while let Some(message) = subscription.try_recv() {
+            payloads.push(message.payload[0]);
+        }
+        payloads
     }
 }
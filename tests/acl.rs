@@ -0,0 +1,113 @@
+pub mod helpers;
+
+use crate::helpers::sasl::{authenticate_plain, write_credentials_file};
+use crate::helpers::{test_broker, test_client};
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::config::BrokerConfig;
+use kafkalite::protocol::request::Request;
+use kafkalite::protocol::response::Response;
+use std::time::Duration;
+
+const ACLS: &str = r#"
+super_users = ["admin"]
+
+[[acls]]
+principal = "alice"
+operations = ["Publish", "Subscribe"]
+topic = "orders"
+
+[[acls]]
+principal = "*"
+operations = ["ListTopics"]
+topic_prefix = "public."
+"#;
+
+async fn start_broker_with_acls() -> test_broker::TestBroker {
+    let credentials_file =
+        write_credentials_file(&[("alice", "alice-secret"), ("admin", "admin-secret")]);
+    let acl_file =
+        std::env::temp_dir().join(format!("kafkalite-acls-{}.toml", uuid::Uuid::new_v4()));
+    std::fs::write(&acl_file, ACLS).expect("Failed to write ACL file");
+    let config = BrokerConfig {
+        credentials_file: Some(credentials_file),
+        acl_file: Some(acl_file),
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    };
+    test_broker::TestBroker::start_with_config(config).await
+}
+
+async fn connect_as(
+    test_broker: &test_broker::TestBroker,
+    username: &str,
+    password: &str,
+) -> test_client::TestClient {
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let response = authenticate_plain(&mut test_client, username, password).await;
+    assert!(matches!(response, Response::SaslAuthenticate { .. }));
+    test_client
+}
+
+#[tokio::test]
+async fn requests_are_checked_against_acls_test() {
+    let test_broker = start_broker_with_acls().await;
+    let mut admin = connect_as(&test_broker, "admin", "admin-secret").await;
+    assert_eq!(admin.add_topic("orders", None).await, Response::Ack);
+
+    let mut alice = connect_as(&test_broker, "alice", "alice-secret").await;
+    let publish = Request::Publish {
+        topic: "orders".to_string(),
+        payload: Bytes::from_static(b"order-1"),
+        compression: Compression::None,
+        traceparent: None,
+    };
+    assert_eq!(alice.send_and_receive(publish).await, Response::Ack);
+
+    let response = alice
+        .send_and_receive(Request::DeleteTopic {
+            topic: "orders".to_string(),
+        })
+        .await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: "Not authorized to perform DeleteTopic on topic orders".to_string()
+        }
+    );
+    let response = alice.add_topic("payments", None).await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: "Not authorized to perform AddTopic on topic payments".to_string()
+        }
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn list_topics_only_shows_describable_topics_test() {
+    let test_broker = start_broker_with_acls().await;
+    let mut admin = connect_as(&test_broker, "admin", "admin-secret").await;
+    for topic in ["orders", "payments", "public.news"] {
+        assert_eq!(admin.add_topic(topic, None).await, Response::Ack);
+    }
+
+    let mut alice = connect_as(&test_broker, "alice", "alice-secret").await;
+    let Response::TopicsList { mut topics } = alice.send_and_receive(Request::ListTopics).await
+    else {
+        panic!("Expected topics list");
+    };
+    topics.sort();
+    assert_eq!(
+        topics,
+        vec!["orders".to_string(), "public.news".to_string()]
+    );
+
+    let Response::TopicsList { topics } = admin.send_and_receive(Request::ListTopics).await else {
+        panic!("Expected topics list");
+    };
+    assert_eq!(topics.len(), 3);
+
+    test_broker.stop().await;
+}
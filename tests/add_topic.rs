@@ -1,8 +1,12 @@
 pub mod helpers;
 
 use crate::helpers::{test_broker, test_client};
-use kafkalite::protocol::request::Request;
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::config::BrokerConfig;
+use kafkalite::protocol::request::{IsolationLevel, Request};
 use kafkalite::protocol::response::Response;
+use std::time::Duration;
 
 #[tokio::test]
 async fn broker_returns_ack_on_adding_new_topic_test() {
@@ -11,7 +15,8 @@ async fn broker_returns_ack_on_adding_new_topic_test() {
 
     let add_topic = Request::AddTopic {
         topic: "test-topic".to_string(),
-        retention: 1,
+        retention: Some(1),
+        compression: None,
     };
     let ack = test_client.send_and_receive(add_topic).await;
     assert_eq!(ack, Response::Ack);
@@ -26,14 +31,16 @@ async fn broker_returns_nack_on_adding_two_times_the_same_topic_test() {
 
     let add_topic = Request::AddTopic {
         topic: "test-topic".to_string(),
-        retention: 1,
+        retention: Some(1),
+        compression: None,
     };
     let ack = test_client.send_and_receive(add_topic).await;
     assert_eq!(ack, Response::Ack);
 
     let add_topic = Request::AddTopic {
         topic: "test-topic".to_string(),
-        retention: 1,
+        retention: Some(1),
+        compression: None,
     };
     let nack = test_client.send_and_receive(add_topic).await;
     assert_eq!(
@@ -45,3 +52,107 @@ async fn broker_returns_nack_on_adding_two_times_the_same_topic_test() {
 
     test_broker.stop().await;
 }
+
+#[tokio::test]
+async fn broker_rejects_topics_with_an_empty_name_test() {
+    let test_broker = test_broker::TestBroker::start().await;
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    let add_topic = Request::AddTopic {
+        topic: String::new(),
+        retention: None,
+        compression: None,
+    };
+    let nack = test_client.send_and_receive(add_topic).await;
+    assert_eq!(
+        nack,
+        Response::Error {
+            message: "Topic name must not be empty".to_string()
+        }
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_rejects_topics_with_retention_out_of_bounds_test() {
+    let config = BrokerConfig {
+        max_retention: 100,
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    for retention in [0, 101, u64::MAX] {
+        let add_topic = Request::AddTopic {
+            topic: "test-topic".to_string(),
+            retention: Some(retention),
+            compression: None,
+        };
+        let nack = test_client.send_and_receive(add_topic).await;
+        assert_eq!(
+            nack,
+            Response::Error {
+                message: format!("Retention {retention} is not between 1 and 100")
+            }
+        );
+    }
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_applies_default_retention_to_topics_added_without_one_test() {
+    let config = BrokerConfig {
+        default_retention: 2,
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    let add_topic = Request::AddTopic {
+        topic: "test-topic".to_string(),
+        retention: None,
+        compression: None,
+    };
+    let ack = test_client.send_and_receive(add_topic).await;
+    assert_eq!(ack, Response::Ack);
+
+    for n in 0..3 {
+        let publish = Request::Publish {
+            topic: "test-topic".to_string(),
+            payload: Bytes::from(vec![n]),
+            compression: Compression::None,
+            traceparent: None,
+        };
+        let ack = test_client.send_and_receive(publish).await;
+        assert_eq!(ack, Response::Ack);
+    }
+
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: test_client.client_id,
+        from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadCommitted,
+    };
+    let ack = test_client.send_and_receive(subscribe).await;
+    assert_eq!(ack, Response::Ack);
+
+    let offsets: Vec<u64> = test_client
+        .receive(2)
+        .await
+        .into_iter()
+        .map(|response| match response {
+            Response::Message { offset, .. } => offset,
+            response => panic!("Received non Message response: {:?}", response),
+        })
+        .collect();
+    assert_eq!(offsets, vec![1, 2]);
+    assert!(
+        test_client
+            .receive_no_messages(Duration::from_millis(100))
+            .await
+    );
+
+    test_broker.stop().await;
+}
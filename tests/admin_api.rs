@@ -0,0 +1,268 @@
+pub mod helpers;
+
+use crate::helpers::sasl::{write_acl_file, write_credentials_file};
+use crate::helpers::{http, test_broker, test_client};
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::config::{BrokerConfig, QuotaConfig, QuotaLimits};
+use kafkalite::protocol::request::{IsolationLevel, Request};
+use kafkalite::protocol::response::Response;
+use serde_json::json;
+use std::time::Duration;
+
+fn admin_config() -> BrokerConfig {
+    BrokerConfig {
+        http_address: Some(([127, 0, 0, 1], 0).into()),
+        admin_api: true,
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    }
+}
+
+#[tokio::test]
+async fn admin_api_manages_topics_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(admin_config()).await;
+    let http_addr = test_broker.http_addr.unwrap();
+
+    let created = http::request(
+        http_addr,
+        "POST",
+        "/topics",
+        Some(r#"{"name": "orders", "retention": 10, "compression": "lz4"}"#),
+    )
+    .await;
+    assert_eq!(created.status, 201);
+    assert_eq!(http::json_body(&created)["compression"], "lz4");
+
+    let duplicate =
+        http::request(http_addr, "POST", "/topics", Some(r#"{"name": "orders"}"#)).await;
+    assert_eq!(duplicate.status, 409);
+    assert_eq!(
+        http::json_body(&duplicate),
+        json!({"error": "Topic orders already exists"})
+    );
+
+    let huge_retention = http::request(
+        http_addr,
+        "POST",
+        "/topics",
+        Some(r#"{"name": "huge", "retention": 18446744073709551615}"#),
+    )
+    .await;
+    assert_eq!(huge_retention.status, 400);
+    assert_eq!(
+        http::json_body(&huge_retention),
+        json!({"error": "Retention 18446744073709551615 is not between 1 and 1000000"})
+    );
+
+    // Topics created over HTTP are the ones clients of the binary protocol see.
+    let mut client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(
+        client.send_and_receive(Request::ListTopics).await,
+        Response::TopicsList {
+            topics: vec!["orders".to_string()]
+        }
+    );
+    let publish = Request::Publish {
+        topic: "orders".to_string(),
+        payload: Bytes::from_static(b"order"),
+        compression: Compression::None,
+        traceparent: None,
+    };
+    assert_eq!(client.send_and_receive(publish).await, Response::Ack);
+
+    let topics = http::get(http_addr, "/topics").await;
+    assert_eq!(topics.status, 200);
+    assert_eq!(http::json_body(&topics), json!(["orders"]));
+
+    let described = http::get(http_addr, "/topics/orders").await;
+    assert_eq!(described.status, 200);
+    let described = http::json_body(&described);
+    assert_eq!(described["retention"], 10);
+    assert_eq!(described["start_offset"], 0);
+    assert_eq!(described["end_offset"], 1);
+    assert_eq!(described["retained_records"], 1);
+
+    let deleted = http::request(http_addr, "DELETE", "/topics/orders", None).await;
+    assert_eq!(deleted.status, 204);
+    let missing = http::get(http_addr, "/topics/orders").await;
+    assert_eq!(missing.status, 404);
+    assert_eq!(
+        http::json_body(&missing),
+        json!({"error": "Topic orders not found"})
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn admin_api_requires_credentials_and_permissions_test() {
+    let acls = r#"
+        super_users = ["admin"]
+
+        [[acls]]
+        principal = "alice"
+        operations = ["AddTopic"]
+        topic_prefix = "alice."
+    "#;
+    let config = BrokerConfig {
+        credentials_file: Some(write_credentials_file(&[
+            ("alice", "alice-secret"),
+            ("admin", "admin-secret"),
+        ])),
+        acl_file: Some(write_acl_file(acls)),
+        ..admin_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let alice = ("alice", "alice-secret");
+    let admin = ("admin", "admin-secret");
+
+    let anonymous =
+        http::request(http_addr, "POST", "/topics", Some(r#"{"name": "alice.a"}"#)).await;
+    assert_eq!(anonymous.status, 401);
+    let wrong_password = http::request_as(
+        http_addr,
+        "POST",
+        "/topics",
+        Some(r#"{"name": "alice.a"}"#),
+        ("alice", "wrong"),
+    )
+    .await;
+    assert_eq!(wrong_password.status, 401);
+
+    let created = http::request_as(
+        http_addr,
+        "POST",
+        "/topics",
+        Some(r#"{"name": "alice.a"}"#),
+        alice,
+    )
+    .await;
+    assert_eq!(created.status, 201);
+    let denied = http::request_as(
+        http_addr,
+        "POST",
+        "/topics",
+        Some(r#"{"name": "orders"}"#),
+        alice,
+    )
+    .await;
+    assert_eq!(denied.status, 403);
+    assert_eq!(
+        http::json_body(&denied),
+        json!({"error": "Not authorized to perform AddTopic on topic orders"})
+    );
+    let created = http::request_as(
+        http_addr,
+        "POST",
+        "/topics",
+        Some(r#"{"name": "orders"}"#),
+        admin,
+    )
+    .await;
+    assert_eq!(created.status, 201);
+
+    let topics = http::request_as(http_addr, "GET", "/topics", None, alice).await;
+    assert_eq!(http::json_body(&topics), json!(["alice.a"]));
+    let described = http::request_as(http_addr, "GET", "/topics/orders", None, alice).await;
+    assert_eq!(described.status, 403);
+    let described = http::request_as(http_addr, "GET", "/topics/alice.a", None, alice).await;
+    assert_eq!(described.status, 200);
+    assert_eq!(http::json_body(&described).get("subscribers"), None);
+    let described = http::request_as(http_addr, "GET", "/topics/alice.a", None, admin).await;
+    assert_eq!(http::json_body(&described)["subscribers"], json!([]));
+    let deleted = http::request_as(http_addr, "DELETE", "/topics/alice.a", None, alice).await;
+    assert_eq!(deleted.status, 403);
+    let connections = http::request_as(http_addr, "GET", "/connections", None, alice).await;
+    assert_eq!(connections.status, 403);
+
+    let connections = http::request_as(http_addr, "GET", "/connections", None, admin).await;
+    assert_eq!(connections.status, 200);
+    let deleted = http::request_as(http_addr, "DELETE", "/topics/alice.a", None, admin).await;
+    assert_eq!(deleted.status, 204);
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn admin_api_lists_connections_and_subscribers_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(admin_config()).await;
+    let http_addr = test_broker.http_addr.unwrap();
+
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let add_topic = Request::AddTopic {
+        topic: "test-topic".to_string(),
+        retention: None,
+        compression: None,
+    };
+    assert_eq!(subscriber.send_and_receive(add_topic).await, Response::Ack);
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: subscriber.client_id,
+        from_offset: None,
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    assert_eq!(subscriber.send_and_receive(subscribe).await, Response::Ack);
+
+    let described = http::json_body(&http::get(http_addr, "/topics/test-topic").await);
+    assert_eq!(
+        described["subscribers"],
+        json!([subscriber.client_id.to_string()])
+    );
+
+    let connections = http::json_body(&http::get(http_addr, "/connections").await);
+    let connections = connections.as_array().unwrap();
+    assert_eq!(connections.len(), 1);
+    assert_eq!(connections[0]["listener"], "default");
+    assert_eq!(
+        connections[0]["client_id"],
+        subscriber.client_id.to_string()
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn admin_api_throttles_clients_over_their_request_rate_test() {
+    let config = BrokerConfig {
+        quotas: QuotaConfig {
+            client: QuotaLimits {
+                request_rate: Some(2),
+                ..QuotaLimits::default()
+            },
+            ..QuotaConfig::default()
+        },
+        ..admin_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let http_addr = test_broker.http_addr.unwrap();
+
+    let mut statuses = Vec::new();
+    for i in 0..5 {
+        let body = json!({"name": format!("topic-{i}")}).to_string();
+        let created = http::request(http_addr, "POST", "/topics", Some(&body)).await;
+        statuses.push(created.status);
+    }
+    assert_eq!(statuses[0], 201);
+    assert!(
+        statuses.contains(&429),
+        "Expected client to be throttled, got {statuses:?}"
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn admin_api_is_disabled_by_default_test() {
+    let config = BrokerConfig {
+        admin_api: false,
+        ..admin_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let http_addr = test_broker.http_addr.unwrap();
+
+    assert_eq!(http::get(http_addr, "/topics").await.status, 404);
+    assert_eq!(http::get(http_addr, "/metrics").await.status, 200);
+
+    test_broker.stop().await;
+}
@@ -0,0 +1,134 @@
+pub mod helpers;
+
+use crate::helpers::sasl::{authenticate_plain, authenticate_scram, write_credentials_file};
+use crate::helpers::{test_broker, test_client};
+use bytes::Bytes;
+use kafkalite::config::BrokerConfig;
+use kafkalite::protocol::request::Request;
+use kafkalite::protocol::response::Response;
+use std::path::PathBuf;
+use std::time::Duration;
+
+async fn start_authenticating_broker() -> (test_broker::TestBroker, PathBuf) {
+    let credentials_file = write_credentials_file(&[("alice", "alice-secret")]);
+    let config = BrokerConfig {
+        credentials_file: Some(credentials_file.clone()),
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    (test_broker, credentials_file)
+}
+
+#[tokio::test]
+async fn unauthenticated_requests_are_rejected_test() {
+    let (test_broker, credentials_file) = start_authenticating_broker().await;
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    let response = test_client
+        .send_and_receive(Request::DeleteTopic {
+            topic: "topic".to_string(),
+        })
+        .await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: "Authentication required".to_string()
+        }
+    );
+    let response = test_client.send_and_receive(Request::Ping).await;
+    assert!(matches!(response, Response::Error { .. }));
+
+    test_broker.stop().await;
+    let _ = std::fs::remove_file(credentials_file);
+}
+
+#[tokio::test]
+async fn plain_authentication_test() {
+    let (test_broker, credentials_file) = start_authenticating_broker().await;
+
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let response = authenticate_plain(&mut test_client, "alice", "wrong-password").await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: "Authentication failed".to_string()
+        }
+    );
+    assert!(matches!(
+        test_client.send_and_receive(Request::Ping).await,
+        Response::Error { .. }
+    ));
+
+    let response = authenticate_plain(&mut test_client, "alice", "alice-secret").await;
+    assert_eq!(
+        response,
+        Response::SaslAuthenticate {
+            auth_bytes: Bytes::new()
+        }
+    );
+    assert_eq!(
+        test_client.send_and_receive(Request::Ping).await,
+        Response::Pong
+    );
+
+    test_broker.stop().await;
+    let _ = std::fs::remove_file(credentials_file);
+}
+
+#[tokio::test]
+async fn scram_sha_256_authentication_test() {
+    let (test_broker, credentials_file) = start_authenticating_broker().await;
+
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let response = authenticate_scram(&mut test_client, "alice", "wrong-password").await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: "Authentication failed".to_string()
+        }
+    );
+
+    let response = authenticate_scram(&mut test_client, "alice", "alice-secret").await;
+    assert!(matches!(response, Response::SaslAuthenticate { .. }));
+    assert_eq!(
+        test_client.send_and_receive(Request::Ping).await,
+        Response::Pong
+    );
+
+    let response = test_client
+        .send_and_receive(Request::SaslHandshake {
+            mechanism: "PLAIN".to_string(),
+        })
+        .await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: "Already authenticated as alice".to_string()
+        }
+    );
+
+    test_broker.stop().await;
+    let _ = std::fs::remove_file(credentials_file);
+}
+
+#[tokio::test]
+async fn unsupported_mechanism_is_rejected_test() {
+    let (test_broker, credentials_file) = start_authenticating_broker().await;
+
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let response = test_client
+        .send_and_receive(Request::SaslHandshake {
+            mechanism: "GSSAPI".to_string(),
+        })
+        .await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: "Unsupported SASL mechanism GSSAPI, expected one of PLAIN, SCRAM-SHA-256"
+                .to_string()
+        }
+    );
+
+    test_broker.stop().await;
+    let _ = std::fs::remove_file(credentials_file);
+}
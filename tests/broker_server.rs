@@ -1,9 +1,16 @@
 pub mod helpers;
 
 use crate::helpers::{test_broker, test_client};
-use kafkalite::protocol::request::Request;
+use bytes::Bytes;
+use futures::SinkExt;
+use kafkalite::compression::Compression;
+use kafkalite::config::{BrokerConfig, ListenerConfig};
+use kafkalite::protocol::request::{IsolationLevel, Request, RequestCodec};
 use kafkalite::protocol::response::Response;
 use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_util::codec::FramedWrite;
+use uuid::Uuid;
 
 #[tokio::test]
 async fn ping_pong_test() {
@@ -31,3 +38,341 @@ async fn broker_disconnects_inactive_client_after_timeout_test() {
 
     test_broker.stop().await;
 }
+
+#[tokio::test]
+async fn broker_keeps_subscriber_receiving_messages_connected_test() {
+    let config = BrokerConfig::new(0, Duration::from_millis(300));
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let add_topic = Request::AddTopic {
+        topic: "test-topic".to_string(),
+        retention: None,
+        compression: None,
+    };
+    assert_eq!(publisher.send_and_receive(add_topic).await, Response::Ack);
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: subscriber.client_id,
+        from_offset: None,
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    assert_eq!(subscriber.send_and_receive(subscribe).await, Response::Ack);
+
+    // The subscriber sends nothing for twice the timeout, but keeps receiving messages.
+    for _ in 0..6 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let publish = Request::Publish {
+            topic: "test-topic".to_string(),
+            payload: Bytes::from_static(b"test"),
+            compression: Compression::None,
+            traceparent: None,
+        };
+        assert_eq!(publisher.send_and_receive(publish).await, Response::Ack);
+        assert_eq!(subscriber.receive(1).await.len(), 1);
+    }
+
+    assert_eq!(
+        subscriber.send_and_receive(Request::Ping).await,
+        Response::Pong
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn heartbeats_keep_connection_alive_without_responses_test() {
+    let config = BrokerConfig::new(0, Duration::from_millis(200));
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    for _ in 0..4 {
+        test_client.send(Request::Heartbeat).await;
+        assert!(
+            test_client
+                .receive_no_messages(Duration::from_millis(100))
+                .await
+        );
+    }
+
+    assert_eq!(
+        test_client.send_and_receive(Request::Ping).await,
+        Response::Pong
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_closes_session_without_requests_despite_outbound_traffic_test() {
+    let config = BrokerConfig {
+        listeners: vec![ListenerConfig {
+            session_timeout: Some(Duration::from_millis(300)),
+            ..ListenerConfig::new("default", [127, 0, 0, 1].into(), 0, Duration::from_secs(5))
+        }],
+        ..BrokerConfig::default()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let add_topic = Request::AddTopic {
+        topic: "test-topic".to_string(),
+        retention: None,
+        compression: None,
+    };
+    assert_eq!(publisher.send_and_receive(add_topic).await, Response::Ack);
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: subscriber.client_id,
+        from_offset: None,
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    assert_eq!(subscriber.send_and_receive(subscribe).await, Response::Ack);
+
+    for _ in 0..5 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let publish = Request::Publish {
+            topic: "test-topic".to_string(),
+            payload: Bytes::from_static(b"test"),
+            compression: Compression::None,
+            traceparent: None,
+        };
+        publisher.send_and_receive(publish).await;
+    }
+
+    // Messages delivered before the session timed out are followed by the end of the stream.
+    let mut closed = false;
+    for _ in 0..5 {
+        if subscriber.check_is_connection_closed().await {
+            closed = true;
+            break;
+        }
+    }
+    assert!(closed, "Expected session without requests to time out");
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_accepts_connections_on_every_listener_test() {
+    let config = kafkalite::config::BrokerConfig {
+        listeners: vec![
+            ListenerConfig::new("internal", [127, 0, 0, 1].into(), 0, Duration::from_secs(1)),
+            ListenerConfig::new(
+                "external",
+                [127, 0, 0, 1].into(),
+                0,
+                Duration::from_millis(10),
+            ),
+        ],
+        ..Default::default()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+
+    let mut internal_client =
+        test_client::TestClient::connect(test_broker.listener_addr("internal")).await;
+    let mut external_client =
+        test_client::TestClient::connect(test_broker.listener_addr("external")).await;
+    assert_eq!(
+        internal_client.send_and_receive(Request::Ping).await,
+        Response::Pong
+    );
+    assert_eq!(
+        external_client.send_and_receive(Request::Ping).await,
+        Response::Pong
+    );
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(
+        external_client.check_is_connection_closed().await,
+        "Expected external connection to be closed due to its listener timeout"
+    );
+    assert_eq!(
+        internal_client.send_and_receive(Request::Ping).await,
+        Response::Pong
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_accepts_connections_on_unix_socket_test() {
+    let socket_path = std::env::temp_dir().join(format!("kafkalite-{}.sock", uuid::Uuid::new_v4()));
+    let config = kafkalite::config::BrokerConfig {
+        listeners: vec![
+            ListenerConfig::new("tcp", [127, 0, 0, 1].into(), 0, Duration::from_secs(1)),
+            ListenerConfig::unix("local", &socket_path, Duration::from_secs(1)),
+        ],
+        ..Default::default()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+
+    let mut unix_client = test_client::TestClient::connect_unix(&socket_path).await;
+    let mut tcp_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let create_topic = Request::AddTopic {
+        topic: "local".to_string(),
+        retention: None,
+        compression: None,
+    };
+    assert_eq!(
+        unix_client.send_and_receive(create_topic).await,
+        Response::Ack
+    );
+    assert_eq!(
+        tcp_client.send_and_receive(Request::ListTopics).await,
+        Response::TopicsList {
+            topics: vec!["local".to_string()]
+        }
+    );
+
+    test_broker.stop().await;
+    assert!(
+        !socket_path.exists(),
+        "Expected socket file to be removed on shutdown"
+    );
+}
+
+#[tokio::test]
+async fn broker_refuses_connections_over_the_limit_test() {
+    let config = BrokerConfig {
+        max_connections: Some(1),
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+
+    let mut first_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let response = first_client.send_and_receive(Request::Ping).await;
+    assert_eq!(response, Response::Pong);
+
+    let mut refused_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(
+        refused_client.receive(1).await,
+        vec![Response::Error {
+            message: "Too many connections".to_string()
+        }]
+    );
+    assert!(refused_client.check_is_connection_closed().await);
+
+    // The first client still gets served.
+    let response = first_client.send_and_receive(Request::Ping).await;
+    assert_eq!(response, Response::Pong);
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_refuses_connections_over_the_per_ip_limit_test() {
+    let config = BrokerConfig {
+        max_connections_per_ip: Some(1),
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+
+    let first_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    let mut refused_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(
+        refused_client.receive(1).await,
+        vec![Response::Error {
+            message: "Too many connections from 127.0.0.1".to_string()
+        }]
+    );
+
+    // Closing a connection makes room for a new one.
+    drop(first_client);
+    let mut response = Response::Nack;
+    for _ in 0..20 {
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+        response = test_client.send_and_receive(Request::Ping).await;
+        if response == Response::Pong {
+            break;
+        }
+    }
+    assert_eq!(response, Response::Pong);
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_tells_clients_it_is_shutting_down_test() {
+    let test_broker = test_broker::TestBroker::start().await;
+
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let add_topic = Request::AddTopic {
+        topic: "test-topic".to_string(),
+        retention: None,
+        compression: None,
+    };
+    assert_eq!(publisher.send_and_receive(add_topic).await, Response::Ack);
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: subscriber.client_id,
+        from_offset: None,
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    assert_eq!(subscriber.send_and_receive(subscribe).await, Response::Ack);
+    let publish = Request::Publish {
+        topic: "test-topic".to_string(),
+        payload: Bytes::from_static(b"test"),
+        compression: Compression::None,
+        traceparent: None,
+    };
+    assert_eq!(publisher.send_and_receive(publish).await, Response::Ack);
+    assert_eq!(subscriber.receive(1).await.len(), 1);
+
+    test_broker.stop().await;
+
+    assert_eq!(subscriber.receive(1).await, vec![Response::ShuttingDown]);
+    assert!(subscriber.check_is_connection_closed().await);
+    assert_eq!(publisher.receive(1).await, vec![Response::ShuttingDown]);
+    assert!(publisher.check_is_connection_closed().await);
+}
+
+#[tokio::test]
+async fn broker_shuts_down_connections_of_clients_not_reading_responses_test() {
+    let config = BrokerConfig {
+        shutdown_timeout: Duration::from_secs(10),
+        ..BrokerConfig::new(0, Duration::from_secs(10))
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let add_topic = Request::AddTopic {
+        topic: "test-topic".to_string(),
+        retention: None,
+        compression: None,
+    };
+    assert_eq!(publisher.send_and_receive(add_topic).await, Response::Ack);
+
+    // The subscriber never reads, so its responses pile up until the broker can't queue more.
+    let socket = TcpStream::connect(test_broker.socket_addr).await.unwrap();
+    let mut subscriber = FramedWrite::new(socket, RequestCodec::default());
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: Uuid::new_v4(),
+        from_offset: None,
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    subscriber.send(subscribe).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    for _ in 0..128 {
+        let publish = Request::Publish {
+            topic: "test-topic".to_string(),
+            payload: Bytes::from(vec![0; 256 * 1024]),
+            compression: Compression::None,
+            traceparent: None,
+        };
+        assert_eq!(publisher.send_and_receive(publish).await, Response::Ack);
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    subscriber.send(Request::Ping).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    tokio::time::timeout(Duration::from_secs(4), test_broker.stop())
+        .await
+        .expect("Expected the broker to shut down despite the blocked connection");
+}
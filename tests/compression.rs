@@ -0,0 +1,157 @@
+pub mod helpers;
+
+use crate::helpers::test_client::TestClient;
+use crate::helpers::{test_broker, test_client};
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::protocol::request::{IsolationLevel, Request};
+use kafkalite::protocol::response::Response;
+
+#[tokio::test]
+async fn broker_stores_and_delivers_compressed_payload_as_sent() {
+    let test_broker = test_broker::TestBroker::start().await;
+
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    assert_eq!(publisher.add_topic("test-topic", None).await, Response::Ack);
+    subscribe(&mut subscriber).await;
+
+    let payload = Bytes::from(Compression::Gzip.compress(b"test-payload").unwrap());
+    let publish = Request::Publish {
+        topic: "test-topic".to_string(),
+        payload: payload.clone(),
+        compression: Compression::Gzip,
+        traceparent: None,
+    };
+    let ack = publisher.send_and_receive(publish).await;
+    assert_eq!(ack, Response::Ack);
+
+    let messages = subscriber.receive(1).await;
+    assert_eq!(
+        messages,
+        vec![Response::Message {
+            topic: "test-topic".to_string(),
+            payload,
+            offset: 0,
+            compression: Compression::Gzip,
+            traceparent: None,
+        }]
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_recompresses_payload_to_topic_level_compression() {
+    let test_broker = test_broker::TestBroker::start().await;
+
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    assert_eq!(
+        publisher
+            .add_topic("test-topic", Some(Compression::Zstd))
+            .await,
+        Response::Ack
+    );
+    subscribe(&mut subscriber).await;
+
+    let publish = Request::Publish {
+        topic: "test-topic".to_string(),
+        payload: Bytes::from(Compression::Snappy.compress(b"test-payload").unwrap()),
+        compression: Compression::Snappy,
+        traceparent: None,
+    };
+    let ack = publisher.send_and_receive(publish).await;
+    assert_eq!(ack, Response::Ack);
+
+    let message = subscriber.receive(1).await.remove(0);
+    if let Response::Message {
+        payload,
+        compression,
+        ..
+    } = message
+    {
+        assert_eq!(compression, Compression::Zstd);
+        assert_eq!(
+            Compression::Zstd.decompress(&payload, 1024).unwrap(),
+            b"test-payload"
+        );
+    } else {
+        panic!("Received non Message response: {:?}", message);
+    }
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_returns_error_when_payload_cannot_be_recompressed() {
+    let test_broker = test_broker::TestBroker::start().await;
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    assert_eq!(
+        publisher
+            .add_topic("test-topic", Some(Compression::None))
+            .await,
+        Response::Ack
+    );
+
+    let publish = Request::Publish {
+        topic: "test-topic".to_string(),
+        payload: Bytes::from_static(b"not gzip"),
+        compression: Compression::Gzip,
+        traceparent: None,
+    };
+    let response = publisher.send_and_receive(publish).await;
+    assert!(
+        matches!(response, Response::Error { ref message } if message.starts_with("Failed to recompress payload for topic test-topic")),
+        "Unexpected response: {:?}",
+        response
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_rejects_payload_decompressing_beyond_max_payload_size() {
+    let test_broker = test_broker::TestBroker::start().await;
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    assert_eq!(
+        publisher
+            .add_topic("test-topic", Some(Compression::Zstd))
+            .await,
+        Response::Ack
+    );
+
+    // A few KiB of gzip expanding to 16 MiB, far beyond the default limit of 1 MiB.
+    let bomb = Compression::Gzip
+        .compress(&vec![0; 16 * 1024 * 1024])
+        .unwrap();
+    let publish = Request::Publish {
+        topic: "test-topic".to_string(),
+        payload: Bytes::from(bomb),
+        compression: Compression::Gzip,
+        traceparent: None,
+    };
+    let response = publisher.send_and_receive(publish).await;
+    assert!(
+        matches!(response, Response::Error { ref message } if message.ends_with("Decompressed payload exceeds the limit of 1048576 bytes")),
+        "Unexpected response: {:?}",
+        response
+    );
+
+    test_broker.stop().await;
+}
+
+async fn subscribe(client: &mut TestClient) {
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: client.client_id,
+        from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadCommitted,
+    };
+    let ack = client.send_and_receive(subscribe).await;
+    assert_eq!(ack, Response::Ack);
+}
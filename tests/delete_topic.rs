@@ -32,7 +32,8 @@ async fn delete_topic() {
 
     let add_topic = Request::AddTopic {
         topic: "test-topic".to_string(),
-        retention: 1,
+        retention: Some(1),
+        compression: None,
     };
     let ack = test_client_1.send_and_receive(add_topic).await;
     assert_eq!(Response::Ack, ack);
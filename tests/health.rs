@@ -0,0 +1,23 @@
+pub mod helpers;
+
+use crate::helpers::{http, test_broker};
+use kafkalite::config::BrokerConfig;
+use std::time::Duration;
+
+#[tokio::test]
+async fn broker_reports_health_and_readiness_over_http_test() {
+    let config = BrokerConfig {
+        http_address: Some(([127, 0, 0, 1], 0).into()),
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let http_addr = test_broker.http_addr.unwrap();
+
+    let healthz = http::get(http_addr, "/healthz").await;
+    assert_eq!(healthz.status, 200);
+    let readyz = http::get(http_addr, "/readyz").await;
+    assert_eq!(readyz.status, 200);
+    assert_eq!(readyz.body, "ready");
+
+    test_broker.stop().await;
+}
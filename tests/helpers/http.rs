@@ -0,0 +1,228 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+pub fn json_body(response: &HttpResponse) -> Value {
+    serde_json::from_str(&response.body).expect("Expected a JSON body")
+}
+
+/// Sends a `GET` request over a fresh connection and reads the whole response.
+pub async fn get(addr: SocketAddr, path: &str) -> HttpResponse {
+    request(addr, "GET", path, None).await
+}
+
+/// Sends a request, with a JSON `body` if given, over a fresh connection and reads the whole
+/// response.
+pub async fn request(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    body: Option<&str>,
+) -> HttpResponse {
+    match body {
+        Some(body) => {
+            send(
+                addr,
+                method,
+                path,
+                Some("application/json"),
+                body.as_bytes(),
+            )
+            .await
+        }
+        None => send(addr, method, path, None, b"").await,
+    }
+}
+
+/// Like [`request`], authenticating as `username` with HTTP Basic credentials.
+pub async fn request_as(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    body: Option<&str>,
+    (username, password): (&str, &str),
+) -> HttpResponse {
+    let mut headers = vec![("Authorization", basic_authorization(username, password))];
+    if body.is_some() {
+        headers.push(("Content-Type", "application/json".to_string()));
+    }
+    send_with_headers(
+        addr,
+        method,
+        path,
+        &headers,
+        body.unwrap_or_default().as_bytes(),
+    )
+    .await
+}
+
+/// The value of an `Authorization` header carrying HTTP Basic credentials.
+pub fn basic_authorization(username: &str, password: &str) -> String {
+    format!("Basic {}", BASE64.encode(format!("{username}:{password}")))
+}
+
+/// Sends a request with `body` of the given content type over a fresh connection and reads the
+/// whole response.
+pub async fn send(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> HttpResponse {
+    let headers: Vec<_> = content_type
+        .map(|content_type| ("Content-Type", content_type.to_string()))
+        .into_iter()
+        .collect();
+    send_with_headers(addr, method, path, &headers, body).await
+}
+
+async fn send_with_headers(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> HttpResponse {
+    let mut socket = TcpStream::connect(addr)
+        .await
+        .expect("Failed to connect to HTTP endpoint");
+    let mut request = format!("{method} {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n");
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+    let mut request = request.into_bytes();
+    request.extend_from_slice(body);
+    socket
+        .write_all(&request)
+        .await
+        .expect("Failed to send HTTP request");
+    let mut response = String::new();
+    socket
+        .read_to_string(&mut response)
+        .await
+        .expect("Failed to read HTTP response");
+
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .expect("Malformed HTTP response");
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .expect("Malformed HTTP status line");
+    HttpResponse {
+        status,
+        body: body.to_string(),
+    }
+}
+
+/// An event received over a Server-Sent Events stream.
+#[derive(Debug, PartialEq)]
+pub struct ServerSentEvent {
+    pub id: Option<String>,
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// A Server-Sent Events stream, requested over HTTP/1.0 so that the body isn't chunked.
+pub struct EventStream {
+    pub status: u16,
+    reader: BufReader<TcpStream>,
+}
+
+impl EventStream {
+    pub async fn open(addr: SocketAddr, path: &str, last_event_id: Option<u64>) -> Self {
+        let headers: Vec<_> = last_event_id
+            .map(|last_event_id| ("Last-Event-ID", last_event_id.to_string()))
+            .into_iter()
+            .collect();
+        Self::open_with_headers(addr, path, &headers).await
+    }
+
+    /// Opens a stream authenticating as `username` with HTTP Basic credentials.
+    pub async fn open_as(addr: SocketAddr, path: &str, (username, password): (&str, &str)) -> Self {
+        let headers = [("Authorization", basic_authorization(username, password))];
+        Self::open_with_headers(addr, path, &headers).await
+    }
+
+    async fn open_with_headers(addr: SocketAddr, path: &str, headers: &[(&str, String)]) -> Self {
+        let mut socket = TcpStream::connect(addr)
+            .await
+            .expect("Failed to connect to HTTP endpoint");
+        let mut request = format!("GET {path} HTTP/1.0\r\nHost: {addr}\r\n");
+        for (name, value) in headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+        socket
+            .write_all(request.as_bytes())
+            .await
+            .expect("Failed to send HTTP request");
+
+        let mut reader = BufReader::new(socket);
+        let mut status_line = String::new();
+        reader
+            .read_line(&mut status_line)
+            .await
+            .expect("Failed to read HTTP status line");
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .expect("Malformed HTTP status line");
+        let mut stream = EventStream { status, reader };
+        while !stream.read_line().await.unwrap_or_default().is_empty() {}
+        stream
+    }
+
+    /// Waits for the next event, skipping keep-alive comments; `None` once the stream ended.
+    pub async fn next_event(&mut self) -> Option<ServerSentEvent> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            let mut event = ServerSentEvent {
+                id: None,
+                event: None,
+                data: String::new(),
+            };
+            let mut has_fields = false;
+            loop {
+                let line = self.read_line().await?;
+                if line.is_empty() {
+                    if has_fields {
+                        return Some(event);
+                    }
+                    continue;
+                }
+                let (field, value) = line.split_once(':').unwrap_or((line.as_str(), ""));
+                let value = value.strip_prefix(' ').unwrap_or(value).to_string();
+                match field {
+                    "id" => event.id = Some(value),
+                    "event" => event.event = Some(value),
+                    "data" => event.data.push_str(&value),
+                    _ => continue,
+                }
+                has_fields = true;
+            }
+        })
+        .await
+        .expect("Timed out waiting for an event")
+    }
+
+    async fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line).await {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! A bare-bones Kafka client, encoding just the requests and record batches the tests send.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const PRODUCE: i16 = 0;
+pub const FETCH: i16 = 1;
+pub const LIST_OFFSETS: i16 = 2;
+pub const METADATA: i16 = 3;
+pub const API_VERSIONS: i16 = 18;
+pub const CREATE_TOPICS: i16 = 19;
+pub const DELETE_TOPICS: i16 = 20;
+
+pub struct KafkaClient {
+    socket: TcpStream,
+    correlation_id: i32,
+}
+
+impl KafkaClient {
+    pub async fn connect(addr: SocketAddr) -> Self {
+        let socket = TcpStream::connect(addr)
+            .await
+            .expect("Failed to connect to broker");
+        KafkaClient {
+            socket,
+            correlation_id: 0,
+        }
+    }
+
+    /// Sends a request and returns the body of its response, checking the correlation id.
+    pub async fn request(&mut self, api_key: i16, api_version: i16, body: &[u8]) -> Bytes {
+        self.send(api_key, api_version, body).await;
+        let mut response = self.receive().await.expect("Connection closed");
+        assert_eq!(response.get_i32(), self.correlation_id);
+        response
+    }
+
+    pub async fn send(&mut self, api_key: i16, api_version: i16, body: &[u8]) {
+        self.correlation_id += 1;
+        let mut frame = BytesMut::new();
+        frame.put_i16(api_key);
+        frame.put_i16(api_version);
+        frame.put_i32(self.correlation_id);
+        put_string(&mut frame, "kafkalite-tests");
+        frame.put_slice(body);
+        self.socket
+            .write_all(&(frame.len() as u32).to_be_bytes())
+            .await
+            .expect("Failed to send request");
+        self.socket
+            .write_all(&frame)
+            .await
+            .expect("Failed to send request");
+    }
+
+    /// Reads the next response frame, or `None` once the broker closed the connection.
+    pub async fn receive(&mut self) -> Option<Bytes> {
+        let read = async {
+            let frame_len = self.socket.read_u32().await.ok()?;
+            let mut frame = vec![0; frame_len as usize];
+            self.socket.read_exact(&mut frame).await.ok()?;
+            Some(Bytes::from(frame))
+        };
+        tokio::time::timeout(Duration::from_secs(5), read)
+            .await
+            .expect("Timed out waiting for a response")
+    }
+}
+
+pub fn put_string(dst: &mut BytesMut, value: &str) {
+    dst.put_i16(value.len() as i16);
+    dst.put_slice(value.as_bytes());
+}
+
+pub fn get_string(src: &mut Bytes) -> String {
+    let len = src.get_i16();
+    String::from_utf8(src.split_to(len as usize).to_vec()).expect("Invalid string")
+}
+
+pub fn get_nullable_string(src: &mut Bytes) -> Option<String> {
+    let len = src.get_i16();
+    (len >= 0).then(|| String::from_utf8(src.split_to(len as usize).to_vec()).unwrap())
+}
+
+pub fn get_bytes(src: &mut Bytes) -> Bytes {
+    let len = src.get_i32();
+    src.split_to(len.max(0) as usize)
+}
+
+/// Encodes `values` as a record batch, gzip-compressed if asked to.
+pub fn record_batch(values: &[&[u8]], headers: &[(&str, &[u8])], gzip: bool) -> Bytes {
+    let mut records = BytesMut::new();
+    for (offset_delta, value) in values.iter().enumerate() {
+        let mut record = BytesMut::new();
+        record.put_i8(0);
+        put_varint(&mut record, 0);
+        put_varint(&mut record, offset_delta as i64);
+        put_varint(&mut record, -1);
+        put_varint(&mut record, value.len() as i64);
+        record.put_slice(value);
+        put_varint(&mut record, headers.len() as i64);
+        for (key, value) in headers {
+            put_varint(&mut record, key.len() as i64);
+            record.put_slice(key.as_bytes());
+            put_varint(&mut record, value.len() as i64);
+            record.put_slice(value);
+        }
+        put_varint(&mut records, record.len() as i64);
+        records.put_slice(&record);
+    }
+    let records = if gzip {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&records).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    } else {
+        records.freeze()
+    };
+
+    let mut checked = BytesMut::new();
+    checked.put_i16(if gzip { 1 } else { 0 });
+    checked.put_i32(values.len() as i32 - 1);
+    checked.put_i64(0);
+    checked.put_i64(0);
+    checked.put_i64(-1);
+    checked.put_i16(-1);
+    checked.put_i32(-1);
+    checked.put_i32(values.len() as i32);
+    checked.put_slice(&records);
+
+    let mut batch = BytesMut::new();
+    batch.put_i64(0);
+    batch.put_i32((4 + 1 + 4 + checked.len()) as i32);
+    batch.put_i32(0);
+    batch.put_i8(2);
+    batch.put_u32(crc32c::crc32c(&checked));
+    batch.put_slice(&checked);
+    batch.freeze()
+}
+
+/// A record of a fetched batch: its offset, value and headers.
+pub type FetchedRecord = (i64, Bytes, Vec<(String, Bytes)>);
+
+/// Decodes the uncompressed batches a broker returns from a fetch.
+pub fn decode_batches(mut src: Bytes) -> Vec<FetchedRecord> {
+    let mut records = Vec::new();
+    while src.has_remaining() {
+        let base_offset = src.get_i64();
+        let batch_len = src.get_i32() as usize;
+        let mut batch = src.split_to(batch_len);
+        batch.advance(4 + 1);
+        let crc = batch.get_u32();
+        assert_eq!(crc32c::crc32c(&batch), crc, "Batch CRC mismatch");
+        batch.advance(2 + 4 + 8 + 8 + 8 + 2 + 4);
+        let count = batch.get_i32();
+        for _ in 0..count {
+            let _len = get_varint(&mut batch);
+            batch.advance(1);
+            let _timestamp_delta = get_varint(&mut batch);
+            let offset_delta = get_varint(&mut batch);
+            let key_len = get_varint(&mut batch);
+            batch.advance(key_len.max(0) as usize);
+            let value_len = get_varint(&mut batch);
+            let value = batch.split_to(value_len as usize);
+            let mut headers = Vec::new();
+            for _ in 0..get_varint(&mut batch) {
+                let key_len = get_varint(&mut batch) as usize;
+                let key = String::from_utf8(batch.split_to(key_len).to_vec()).unwrap();
+                let value_len = get_varint(&mut batch) as usize;
+                headers.push((key, batch.split_to(value_len)));
+            }
+            records.push((base_offset + offset_delta, value, headers));
+        }
+    }
+    records
+}
+
+fn put_varint(dst: &mut BytesMut, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x80 {
+        dst.put_u8((value as u8) | 0x80);
+        value >>= 7;
+    }
+    dst.put_u8(value as u8);
+}
+
+fn get_varint(src: &mut Bytes) -> i64 {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = src.get_u8();
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return (value >> 1) as i64 ^ -((value & 1) as i64);
+        }
+        shift += 7;
+    }
+}
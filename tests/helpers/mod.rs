@@ -1,2 +1,6 @@
+pub mod http;
+pub mod kafka;
+pub mod sasl;
 pub mod test_broker;
 pub mod test_client;
+pub mod tls;
@@ -0,0 +1,125 @@
+use crate::helpers::test_client::TestClient;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::Bytes;
+use kafkalite::protocol::request::Request;
+use kafkalite::protocol::response::Response;
+use ring::{digest, hmac, pbkdf2};
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+
+/// Writes a credentials file with the given users to a temporary location and returns its path.
+pub fn write_credentials_file(users: &[(&str, &str)]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("kafkalite-users-{}.toml", uuid::Uuid::new_v4()));
+    let content: String = users
+        .iter()
+        .map(|(username, password)| {
+            format!("[[users]]\nusername = \"{username}\"\npassword = \"{password}\"\n\n")
+        })
+        .collect();
+    std::fs::write(&path, content).expect("Failed to write credentials file");
+    path
+}
+
+/// Writes an ACL file with the given content to a temporary location and returns its path.
+pub fn write_acl_file(acls: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("kafkalite-acls-{}.toml", uuid::Uuid::new_v4()));
+    std::fs::write(&path, acls).expect("Failed to write ACL file");
+    path
+}
+
+pub async fn authenticate_plain(
+    client: &mut TestClient,
+    username: &str,
+    password: &str,
+) -> Response {
+    let handshake = Request::SaslHandshake {
+        mechanism: "PLAIN".to_string(),
+    };
+    assert_eq!(client.send_and_receive(handshake).await, Response::Ack);
+    let auth_bytes = Bytes::from(format!("\0{username}\0{password}"));
+    client
+        .send_and_receive(Request::SaslAuthenticate { auth_bytes })
+        .await
+}
+
+/// Runs a full SCRAM-SHA-256 exchange and returns the broker's last response, after checking the
+/// server signature when the exchange succeeded.
+pub async fn authenticate_scram(
+    client: &mut TestClient,
+    username: &str,
+    password: &str,
+) -> Response {
+    let handshake = Request::SaslHandshake {
+        mechanism: "SCRAM-SHA-256".to_string(),
+    };
+    assert_eq!(client.send_and_receive(handshake).await, Response::Ack);
+
+    let client_nonce = BASE64.encode(uuid::Uuid::new_v4().as_bytes());
+    let client_first_bare = format!("n={username},r={client_nonce}");
+    let client_first = Bytes::from(format!("n,,{client_first_bare}"));
+    let server_first = match client
+        .send_and_receive(Request::SaslAuthenticate {
+            auth_bytes: client_first,
+        })
+        .await
+    {
+        Response::SaslAuthenticate { auth_bytes } => {
+            String::from_utf8(auth_bytes.to_vec()).expect("Server-first message is not UTF-8")
+        }
+        other => return other,
+    };
+
+    let mut attributes = server_first.split(',');
+    let nonce = attributes.next().unwrap().strip_prefix("r=").unwrap();
+    let salt = BASE64
+        .decode(attributes.next().unwrap().strip_prefix("s=").unwrap())
+        .unwrap();
+    let iterations: u32 = attributes
+        .next()
+        .unwrap()
+        .strip_prefix("i=")
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(nonce.starts_with(&client_nonce));
+
+    let mut salted_password = [0; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(iterations).unwrap(),
+        &salt,
+        password.as_bytes(),
+        &mut salted_password,
+    );
+    let client_key = hmac_sha256(&salted_password, b"Client Key");
+    let stored_key = digest::digest(&digest::SHA256, &client_key);
+    let without_proof = format!("c=biws,r={nonce}");
+    let auth_message = format!("{client_first_bare},{server_first},{without_proof}");
+    let client_signature = hmac_sha256(stored_key.as_ref(), auth_message.as_bytes());
+    let proof: Vec<u8> = client_key
+        .iter()
+        .zip(client_signature.iter())
+        .map(|(key, signature)| key ^ signature)
+        .collect();
+    let client_final = Bytes::from(format!("{without_proof},p={}", BASE64.encode(proof)));
+
+    let response = client
+        .send_and_receive(Request::SaslAuthenticate {
+            auth_bytes: client_final,
+        })
+        .await;
+    if let Response::SaslAuthenticate { auth_bytes } = &response {
+        let server_key = hmac_sha256(&salted_password, b"Server Key");
+        let server_signature = hmac_sha256(&server_key, auth_message.as_bytes());
+        let expected = format!("v={}", BASE64.encode(server_signature));
+        assert_eq!(auth_bytes, expected.as_bytes(), "Invalid server signature");
+    }
+    response
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), message)
+        .as_ref()
+        .to_vec()
+}
@@ -1,4 +1,6 @@
 use kafkalite::config::BrokerConfig;
+use kafkalite::readiness::Readiness;
+use std::collections::HashMap;
 use std::net::{SocketAddr, TcpListener};
 use std::sync::Arc;
 use std::time::Duration;
@@ -9,6 +11,8 @@ pub struct TestBroker {
     join: JoinHandle<()>,
     shutdown_signal: Arc<Notify>,
     pub socket_addr: SocketAddr,
+    listener_addrs: HashMap<String, SocketAddr>,
+    pub http_addr: Option<SocketAddr>,
 }
 
 impl TestBroker {
@@ -20,32 +24,64 @@ impl TestBroker {
 
     pub async fn start_with_config(config: BrokerConfig) -> Self {
         let mut config = config;
-        if config.port == 0 {
-            let socket_addr = get_socket_addr();
-            config.port = socket_addr.port();
+        for listener in config.listeners.iter_mut() {
+            if listener.unix_socket_path.is_none() && listener.port == 0 {
+                listener.port = get_socket_addr().port();
+            }
+        }
+        if let Some(http_address) = config.http_address.as_mut()
+            && http_address.port() == 0
+        {
+            http_address.set_port(get_socket_addr().port());
         }
+        let http_addr = config.http_address;
+        let listener_addrs: HashMap<String, SocketAddr> = config
+            .listeners
+            .iter()
+            .filter(|listener| listener.unix_socket_path.is_none())
+            .map(|listener| {
+                let socket_addr = ([127, 0, 0, 1], listener.port).into();
+                (listener.name.clone(), socket_addr)
+            })
+            .collect();
+        let socket_addr = listener_addrs
+            .get(&config.listeners[0].name)
+            .copied()
+            .unwrap_or_else(|| ([127, 0, 0, 1], 0).into());
 
         let shutdown_signal = Arc::new(Notify::new());
-        let broker_port = config.port;
-        let join = tokio::spawn({
+        let readiness = Arc::new(Readiness::new());
+        let mut join = tokio::spawn({
             let shutdown_signal = Arc::clone(&shutdown_signal);
+            let readiness = Arc::clone(&readiness);
             async move {
-                kafkalite::startup::run_broker(config, shutdown_signal)
+                kafkalite::startup::run_broker(config, shutdown_signal, readiness)
                     .await
                     .expect("Failed to start broker");
             }
         });
 
-        tokio::time::sleep(Duration::from_millis(50)).await;
+        tokio::select! {
+            _ = readiness.wait() => {}
+            result = &mut join => panic!("Test broker stopped before becoming ready: {result:?}"),
+            _ = tokio::time::sleep(Duration::from_secs(5)) => {
+                panic!("Timed out waiting for test broker to become ready")
+            }
+        }
 
-        let socket_addr = ([127, 0, 0, 1], broker_port).into();
         Self {
             join,
             shutdown_signal,
             socket_addr,
+            listener_addrs,
+            http_addr,
         }
     }
 
+    pub fn listener_addr(&self, name: &str) -> SocketAddr {
+        self.listener_addrs[name]
+    }
+
     pub async fn stop(self) {
         self.shutdown_signal.notify_waiters();
         tokio::time::timeout(Duration::from_secs(5), self.join)
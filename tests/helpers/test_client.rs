@@ -1,11 +1,16 @@
 use futures::{SinkExt, StreamExt};
+use kafkalite::compression::Compression;
 use kafkalite::protocol::request::{Request, RequestCodec};
 use kafkalite::protocol::response::{Response, ResponseCodec};
+use rustls::pki_types::ServerName;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{ReadHalf, WriteHalf};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::net::{TcpStream, UnixStream};
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio_rustls::TlsConnector;
 use tokio_util::codec::{FramedRead, FramedWrite};
 use uuid::Uuid;
 
@@ -20,6 +25,32 @@ impl TestClient {
         let socket = TcpStream::connect(addr)
             .await
             .expect("Failed to connect to broker");
+        Self::from_stream(socket)
+    }
+
+    pub async fn connect_tls(addr: SocketAddr, config: rustls::ClientConfig) -> Self {
+        let socket = TcpStream::connect(addr)
+            .await
+            .expect("Failed to connect to broker");
+        let server_name = ServerName::try_from("localhost").expect("Invalid server name");
+        let socket = TlsConnector::from(Arc::new(config))
+            .connect(server_name, socket)
+            .await
+            .expect("Failed to complete TLS handshake");
+        Self::from_stream(socket)
+    }
+
+    pub async fn connect_unix(path: &Path) -> Self {
+        let socket = UnixStream::connect(path)
+            .await
+            .expect("Failed to connect to broker");
+        Self::from_stream(socket)
+    }
+
+    fn from_stream<S>(socket: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
         let (read_half, write_half) = tokio::io::split(socket);
         let receiver = Self::register_response_receiver(read_half);
         let sender = Self::register_request_sender(write_half);
@@ -33,18 +64,17 @@ impl TestClient {
         }
     }
 
-    fn register_response_receiver(read_half: ReadHalf<TcpStream>) -> Receiver<Response> {
+    fn register_response_receiver<S>(read_half: ReadHalf<S>) -> Receiver<Response>
+    where
+        S: AsyncRead + Send + 'static,
+    {
         let mut reader = FramedRead::new(read_half, ResponseCodec);
         let (sender, receiver) = tokio::sync::mpsc::channel(1024);
         tokio::spawn(async move {
-            loop {
-                if let Some(response) = reader.next().await {
-                    let response = response.expect("Failed to receive response");
-                    sender
-                        .send(response)
-                        .await
-                        .expect("Failed to send response");
-                } else {
+            while let Some(response) = reader.next().await {
+                let response = response.expect("Failed to receive response");
+                // Responses still arriving after the client was dropped are not read anymore.
+                if sender.send(response).await.is_err() {
                     break;
                 }
             }
@@ -52,17 +82,40 @@ impl TestClient {
         receiver
     }
 
-    fn register_request_sender(write_half: WriteHalf<TcpStream>) -> Sender<Request> {
-        let mut writer = FramedWrite::new(write_half, RequestCodec);
+    fn register_request_sender<S>(write_half: WriteHalf<S>) -> Sender<Request>
+    where
+        S: AsyncWrite + Send + 'static,
+    {
+        let mut writer = FramedWrite::new(write_half, RequestCodec::default());
         let (sender, mut receiver) = tokio::sync::mpsc::channel(1024);
         tokio::spawn(async move {
             while let Some(request) = receiver.recv().await {
                 writer.send(request).await.expect("Failed to send request");
             }
+            // Dropping the client closes its side of the connection.
+            let _ = writer.close().await;
         });
         sender
     }
 
+    pub async fn send(&mut self, request: Request) {
+        self.sender
+            .send(request)
+            .await
+            .expect("Failed to send data to broker");
+    }
+
+    /// Adds a topic with the default retention, compressing its records with `compression` if
+    /// given.
+    pub async fn add_topic(&mut self, topic: &str, compression: Option<Compression>) -> Response {
+        let add_topic = Request::AddTopic {
+            topic: topic.to_string(),
+            retention: None,
+            compression,
+        };
+        self.send_and_receive(add_topic).await
+    }
+
     pub async fn send_and_receive(&mut self, request: Request) -> Response {
         self.sender
             .send(request)
@@ -0,0 +1,92 @@
+use kafkalite::config::TlsConfig;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+};
+use rustls::RootCertStore;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use std::path::PathBuf;
+
+/// A throwaway CA with a server certificate for `localhost`/`127.0.0.1` and a client certificate,
+/// all signed by it. The server files are written to a temporary directory so that a broker can
+/// be configured with their paths.
+pub struct TestCertificates {
+    dir: PathBuf,
+    ca_cert: Certificate,
+    client_cert: Certificate,
+    client_key: KeyPair,
+}
+
+impl TestCertificates {
+    pub fn generate() -> Self {
+        let ca_key = KeyPair::generate().expect("Failed to generate CA key");
+        let mut ca_params = CertificateParams::new(Vec::new()).expect("Invalid CA params");
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_cert = ca_params
+            .self_signed(&ca_key)
+            .expect("Failed to sign CA certificate");
+
+        let server_key = KeyPair::generate().expect("Failed to generate server key");
+        let mut server_params =
+            CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()])
+                .expect("Invalid server params");
+        server_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        let server_cert = server_params
+            .signed_by(&server_key, &ca_cert, &ca_key)
+            .expect("Failed to sign server certificate");
+
+        let client_key = KeyPair::generate().expect("Failed to generate client key");
+        let mut client_params =
+            CertificateParams::new(vec!["test-client".to_string()]).expect("Invalid client params");
+        client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let client_cert = client_params
+            .signed_by(&client_key, &ca_cert, &ca_key)
+            .expect("Failed to sign client certificate");
+
+        let dir = std::env::temp_dir().join(format!("kafkalite-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("Failed to create certificate directory");
+        std::fs::write(dir.join("ca.pem"), ca_cert.pem()).expect("Failed to write CA");
+        std::fs::write(dir.join("server.pem"), server_cert.pem())
+            .expect("Failed to write server certificate");
+        std::fs::write(dir.join("server.key"), server_key.serialize_pem())
+            .expect("Failed to write server key");
+
+        Self {
+            dir,
+            ca_cert,
+            client_cert,
+            client_key,
+        }
+    }
+
+    pub fn server_tls_config(&self, require_client_cert: bool) -> TlsConfig {
+        TlsConfig {
+            cert_path: self.dir.join("server.pem"),
+            key_path: self.dir.join("server.key"),
+            client_ca_path: require_client_cert.then(|| self.dir.join("ca.pem")),
+        }
+    }
+
+    pub fn client_config(&self, with_client_cert: bool) -> rustls::ClientConfig {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(self.ca_cert.der().clone())
+            .expect("Failed to add CA certificate");
+        let builder = rustls::ClientConfig::builder().with_root_certificates(roots);
+        if with_client_cert {
+            let cert: CertificateDer<'static> = self.client_cert.der().clone();
+            let key =
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(self.client_key.serialize_der()));
+            builder
+                .with_client_auth_cert(vec![cert], key)
+                .expect("Invalid client certificate")
+        } else {
+            builder.with_no_client_auth()
+        }
+    }
+}
+
+impl Drop for TestCertificates {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,346 @@
+pub mod helpers;
+
+use crate::helpers::kafka::{
+    self, API_VERSIONS, CREATE_TOPICS, DELETE_TOPICS, FETCH, KafkaClient, LIST_OFFSETS, METADATA,
+    PRODUCE, get_bytes, get_nullable_string, get_string, put_string,
+};
+use crate::helpers::{http, test_broker, test_client};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use kafkalite::config::{BrokerConfig, ListenerConfig, ListenerProtocol, QuotaConfig, QuotaLimits};
+use kafkalite::protocol::request::Request;
+use kafkalite::protocol::response::Response;
+use std::time::Duration;
+
+const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+fn kafka_config() -> BrokerConfig {
+    BrokerConfig {
+        listeners: vec![
+            ListenerConfig::new("default", [127, 0, 0, 1].into(), 0, Duration::from_secs(5)),
+            ListenerConfig {
+                protocol: ListenerProtocol::Kafka,
+                ..ListenerConfig::new("kafka", [127, 0, 0, 1].into(), 0, Duration::from_secs(5))
+            },
+        ],
+        ..Default::default()
+    }
+}
+
+fn create_topics_request(name: &str, num_partitions: i32) -> BytesMut {
+    let mut body = BytesMut::new();
+    body.put_i32(1);
+    put_string(&mut body, name);
+    body.put_i32(num_partitions);
+    body.put_i16(-1);
+    body.put_i32(0);
+    body.put_i32(1);
+    put_string(&mut body, "compression.type");
+    put_string(&mut body, "gzip");
+    body.put_i32(1000);
+    body.put_i8(0);
+    body
+}
+
+/// Error code and message of the only topic of a CreateTopics v2 response.
+fn create_topics_result(mut response: Bytes) -> (i16, Option<String>) {
+    assert_eq!(response.get_i32(), 0);
+    assert_eq!(response.get_i32(), 1);
+    get_string(&mut response);
+    (response.get_i16(), get_nullable_string(&mut response))
+}
+
+fn produce_request(topic: &str, records: &[u8]) -> BytesMut {
+    let mut body = BytesMut::new();
+    body.put_i16(-1);
+    body.put_i16(1);
+    body.put_i32(1000);
+    body.put_i32(1);
+    put_string(&mut body, topic);
+    body.put_i32(1);
+    body.put_i32(0);
+    body.put_i32(records.len() as i32);
+    body.put_slice(records);
+    body
+}
+
+/// Error code and base offset of the only partition of a Produce v3 response.
+fn produce_result(mut response: Bytes) -> (i16, i64) {
+    assert_eq!(response.get_i32(), 1);
+    get_string(&mut response);
+    assert_eq!(response.get_i32(), 1);
+    assert_eq!(response.get_i32(), 0);
+    (response.get_i16(), response.get_i64())
+}
+
+fn fetch_request(topic: &str, offset: i64, max_wait_ms: i32) -> BytesMut {
+    let mut body = BytesMut::new();
+    body.put_i32(-1);
+    body.put_i32(max_wait_ms);
+    body.put_i32(1);
+    body.put_i32(1024 * 1024);
+    body.put_i8(0);
+    body.put_i32(1);
+    put_string(&mut body, topic);
+    body.put_i32(1);
+    body.put_i32(0);
+    body.put_i64(offset);
+    body.put_i32(1024 * 1024);
+    body
+}
+
+/// Error code, high watermark and records of the only partition of a Fetch v4 response.
+fn fetch_result(mut response: Bytes) -> (i16, i64, Vec<kafka::FetchedRecord>) {
+    assert_eq!(response.get_i32(), 0);
+    assert_eq!(response.get_i32(), 1);
+    get_string(&mut response);
+    assert_eq!(response.get_i32(), 1);
+    assert_eq!(response.get_i32(), 0);
+    let error_code = response.get_i16();
+    let high_watermark = response.get_i64();
+    let _last_stable_offset = response.get_i64();
+    assert_eq!(response.get_i32(), 0);
+    let records = kafka::decode_batches(get_bytes(&mut response));
+    (error_code, high_watermark, records)
+}
+
+fn list_offsets_request(topic: &str, timestamp: i64) -> BytesMut {
+    let mut body = BytesMut::new();
+    body.put_i32(-1);
+    body.put_i32(1);
+    put_string(&mut body, topic);
+    body.put_i32(1);
+    body.put_i32(0);
+    body.put_i64(timestamp);
+    body
+}
+
+/// Offset of the only partition of a ListOffsets v1 response.
+fn list_offsets_result(mut response: Bytes) -> i64 {
+    assert_eq!(response.get_i32(), 1);
+    get_string(&mut response);
+    assert_eq!(response.get_i32(), 1);
+    assert_eq!(response.get_i32(), 0);
+    assert_eq!(response.get_i16(), 0);
+    let _timestamp = response.get_i64();
+    response.get_i64()
+}
+
+#[tokio::test]
+async fn kafka_listener_describes_cluster_and_manages_topics_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(kafka_config()).await;
+    let kafka_addr = test_broker.listener_addr("kafka");
+    let mut client = KafkaClient::connect(kafka_addr).await;
+
+    let mut versions = client.request(API_VERSIONS, 2, &[]).await;
+    assert_eq!(versions.get_i16(), 0);
+    let apis: Vec<(i16, i16, i16)> = (0..versions.get_i32())
+        .map(|_| (versions.get_i16(), versions.get_i16(), versions.get_i16()))
+        .collect();
+    assert!(apis.contains(&(PRODUCE, 3, 8)));
+    assert!(apis.contains(&(FETCH, 4, 11)));
+    // Newer clients are told to fall back to a supported version.
+    let mut versions = client.request(API_VERSIONS, 3, &[]).await;
+    assert_eq!(versions.get_i16(), 35);
+
+    let created = client
+        .request(CREATE_TOPICS, 2, &create_topics_request("orders", -1))
+        .await;
+    assert_eq!(create_topics_result(created), (0, None));
+    let created = client
+        .request(CREATE_TOPICS, 2, &create_topics_request("orders", 1))
+        .await;
+    assert_eq!(
+        create_topics_result(created),
+        (36, Some("Topic orders already exists".to_string()))
+    );
+    let created = client
+        .request(CREATE_TOPICS, 2, &create_topics_request("wide", 3))
+        .await;
+    assert_eq!(create_topics_result(created).0, 37);
+
+    let mut metadata = client.request(METADATA, 1, &(-1i32).to_be_bytes()).await;
+    assert_eq!(metadata.get_i32(), 1);
+    assert_eq!(metadata.get_i32(), 0);
+    assert_eq!(get_string(&mut metadata), "127.0.0.1");
+    assert_eq!(metadata.get_i32(), i32::from(kafka_addr.port()));
+    assert_eq!(get_nullable_string(&mut metadata), None);
+    assert_eq!(metadata.get_i32(), 0);
+    assert_eq!(metadata.get_i32(), 1);
+    assert_eq!(metadata.get_i16(), 0);
+    assert_eq!(get_string(&mut metadata), "orders");
+    assert_eq!(metadata.get_i8(), 0);
+    assert_eq!(metadata.get_i32(), 1);
+    assert_eq!(metadata.get_i16(), 0);
+    assert_eq!(metadata.get_i32(), 0);
+    assert_eq!(metadata.get_i32(), 0);
+
+    let mut delete = BytesMut::new();
+    delete.put_i32(2);
+    put_string(&mut delete, "orders");
+    put_string(&mut delete, "missing");
+    delete.put_i32(1000);
+    let mut deleted = client.request(DELETE_TOPICS, 1, &delete).await;
+    assert_eq!(deleted.get_i32(), 0);
+    assert_eq!(deleted.get_i32(), 2);
+    assert_eq!(get_string(&mut deleted), "orders");
+    assert_eq!(deleted.get_i16(), 0);
+    assert_eq!(get_string(&mut deleted), "missing");
+    assert_eq!(deleted.get_i16(), 3);
+
+    // Versions the listener doesn't know close the connection.
+    client.send(METADATA, 9, &[]).await;
+    assert_eq!(client.receive().await, None);
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn kafka_listener_produces_and_fetches_records_test() {
+    let config = BrokerConfig {
+        http_address: Some(([127, 0, 0, 1], 0).into()),
+        admin_api: true,
+        ..kafka_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let mut client = KafkaClient::connect(test_broker.listener_addr("kafka")).await;
+    let created = client
+        .request(CREATE_TOPICS, 2, &create_topics_request("orders", 1))
+        .await;
+    assert_eq!(create_topics_result(created).0, 0);
+
+    let mut batches = BytesMut::new();
+    batches.put_slice(&kafka::record_batch(&[b"first", b"second"], &[], false));
+    batches.put_slice(&kafka::record_batch(
+        &[b"third"],
+        &[("traceparent", TRACEPARENT.as_bytes())],
+        true,
+    ));
+    let produced = client
+        .request(PRODUCE, 3, &produce_request("orders", &batches))
+        .await;
+    assert_eq!(produce_result(produced), (0, 0));
+
+    let fetched = client
+        .request(FETCH, 4, &fetch_request("orders", 1, 0))
+        .await;
+    let (error_code, high_watermark, records) = fetch_result(fetched);
+    assert_eq!((error_code, high_watermark), (0, 3));
+    assert_eq!(
+        records,
+        vec![
+            (1, Bytes::from_static(b"second"), vec![]),
+            (
+                2,
+                Bytes::from_static(b"third"),
+                vec![("traceparent".to_string(), Bytes::from(TRACEPARENT))]
+            ),
+        ]
+    );
+
+    let latest = client
+        .request(LIST_OFFSETS, 1, &list_offsets_request("orders", -1))
+        .await;
+    assert_eq!(list_offsets_result(latest), 3);
+    let earliest = client
+        .request(LIST_OFFSETS, 1, &list_offsets_request("orders", -2))
+        .await;
+    assert_eq!(list_offsets_result(earliest), 0);
+
+    // A fetch at the end of the log waits for the next record, here published by a client of
+    // the broker's own protocol.
+    client
+        .send(FETCH, 4, &fetch_request("orders", 3, 5000))
+        .await;
+    // Waiting fetches read the log rather than subscribing to the topic.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let described = http::get(test_broker.http_addr.unwrap(), "/topics/orders").await;
+    assert_eq!(
+        http::json_body(&described)["subscribers"],
+        serde_json::json!([])
+    );
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let publish = Request::Publish {
+        topic: "orders".to_string(),
+        payload: Bytes::from_static(b"fourth"),
+        compression: kafkalite::compression::Compression::None,
+        traceparent: None,
+    };
+    assert_eq!(publisher.send_and_receive(publish).await, Response::Ack);
+    let mut fetched = client.receive().await.expect("Expected a fetch response");
+    fetched.advance(4);
+    let (_, _, records) = fetch_result(fetched);
+    assert_eq!(records, vec![(3, Bytes::from_static(b"fourth"), vec![])]);
+
+    let out_of_range = client
+        .request(FETCH, 4, &fetch_request("orders", 10, 0))
+        .await;
+    assert_eq!(fetch_result(out_of_range).0, 1);
+    let batch = kafka::record_batch(&[b"lost"], &[], false);
+    let missing = client
+        .request(PRODUCE, 3, &produce_request("missing", &batch))
+        .await;
+    assert_eq!(produce_result(missing).0, 3);
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn kafka_listener_throttles_clients_over_their_quotas_test() {
+    let config = BrokerConfig {
+        quotas: QuotaConfig {
+            client: QuotaLimits {
+                produce_byte_rate: Some(10),
+                consume_byte_rate: Some(10),
+                ..QuotaLimits::default()
+            },
+            ..QuotaConfig::default()
+        },
+        ..kafka_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let mut client = KafkaClient::connect(test_broker.listener_addr("kafka")).await;
+    let created = client
+        .request(CREATE_TOPICS, 2, &create_topics_request("orders", 1))
+        .await;
+    assert_eq!(create_topics_result(created).0, 0);
+
+    // The first batch is admitted on credit, leaving the client in debt for a while, and
+    // producing again before it is paid back fails.
+    let batch = kafka::record_batch(&[b"first record over the produce quota"], &[], false);
+    let produced = client
+        .request(PRODUCE, 3, &produce_request("orders", &batch))
+        .await;
+    assert_eq!(produce_result(produced.clone()), (0, 0));
+    let throttle_time_ms = (&produced[produced.len() - 4..]).get_i32();
+    assert!(throttle_time_ms > 0, "throttle time {throttle_time_ms}");
+
+    let throttled = client
+        .request(PRODUCE, 3, &produce_request("orders", &batch))
+        .await;
+    assert_eq!(produce_result(throttled.clone()).0, 89);
+    let throttle_time_ms = (&throttled[throttled.len() - 4..]).get_i32();
+    assert!(throttle_time_ms > 0, "throttle time {throttle_time_ms}");
+
+    // Fetching the record puts the client over its consume quota, so the next fetch returns
+    // nothing until it has backed off.
+    let mut fetched = client
+        .request(FETCH, 4, &fetch_request("orders", 0, 0))
+        .await;
+    assert!(fetched.get_i32() > 0);
+    assert_eq!(fetched.get_i32(), 1);
+    let mut throttled = client
+        .request(FETCH, 4, &fetch_request("orders", 0, 0))
+        .await;
+    assert!(throttled.get_i32() > 0);
+    assert_eq!(throttled.get_i32(), 1);
+    get_string(&mut throttled);
+    assert_eq!(throttled.get_i32(), 1);
+    assert_eq!(throttled.get_i32(), 0);
+    assert_eq!(throttled.get_i16(), 0);
+    let _high_watermark = throttled.get_i64();
+    let _last_stable_offset = throttled.get_i64();
+    assert_eq!(throttled.get_i32(), 0);
+    assert!(kafka::decode_batches(get_bytes(&mut throttled)).is_empty());
+
+    test_broker.stop().await;
+}
@@ -0,0 +1,118 @@
+pub mod helpers;
+
+use crate::helpers::{http, test_broker, test_client};
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::config::BrokerConfig;
+use kafkalite::protocol::request::{IsolationLevel, Request};
+use kafkalite::protocol::response::Response;
+use std::time::Duration;
+
+async fn start_broker_with_metrics() -> test_broker::TestBroker {
+    let config = BrokerConfig {
+        http_address: Some(([127, 0, 0, 1], 0).into()),
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    };
+    test_broker::TestBroker::start_with_config(config).await
+}
+
+#[tokio::test]
+async fn broker_exposes_topic_and_connection_metrics_test() {
+    let test_broker = start_broker_with_metrics().await;
+    let http_addr = test_broker.http_addr.unwrap();
+
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut consumer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let add_topic = Request::AddTopic {
+        topic: "test-topic".to_string(),
+        retention: None,
+        compression: None,
+    };
+    assert_eq!(producer.send_and_receive(add_topic).await, Response::Ack);
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: consumer.client_id,
+        from_offset: None,
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    assert_eq!(consumer.send_and_receive(subscribe).await, Response::Ack);
+    let publish = Request::Publish {
+        topic: "test-topic".to_string(),
+        payload: Bytes::from_static(b"test message"),
+        compression: Compression::None,
+        traceparent: None,
+    };
+    assert_eq!(producer.send_and_receive(publish).await, Response::Ack);
+    assert_eq!(consumer.receive(1).await.len(), 1);
+
+    let response = http::get(http_addr, "/metrics").await;
+    assert_eq!(response.status, 200);
+    for series in [
+        "kafkalite_messages_in_total{topic=\"test-topic\"} 1",
+        "kafkalite_bytes_in_total{topic=\"test-topic\"} 12",
+        "kafkalite_messages_out_total{topic=\"test-topic\"} 1",
+        "kafkalite_bytes_out_total{topic=\"test-topic\"} 12",
+        "kafkalite_publish_latency_seconds_count{topic=\"test-topic\"} 1",
+        "kafkalite_subscribers{topic=\"test-topic\"} 1",
+        "kafkalite_retained_records{topic=\"test-topic\"} 1",
+        "kafkalite_retained_bytes{topic=\"test-topic\"} 12",
+        "kafkalite_active_connections{listener=\"default\"} 2",
+    ] {
+        assert!(
+            response.body.contains(series),
+            "Missing {series} in:\n{}",
+            response.body
+        );
+    }
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_counts_errors_by_request_type_test() {
+    let test_broker = start_broker_with_metrics().await;
+    let http_addr = test_broker.http_addr.unwrap();
+
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let delete_topic = Request::DeleteTopic {
+        topic: "unknown-topic".to_string(),
+    };
+    let response = test_client.send_and_receive(delete_topic).await;
+    assert!(matches!(response, Response::Error { .. }));
+
+    let response = http::get(http_addr, "/metrics").await;
+    assert!(
+        response
+            .body
+            .contains("kafkalite_request_errors_total{request=\"DeleteTopic\"} 1")
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_adds_no_series_for_publishes_to_unknown_topics_test() {
+    let test_broker = start_broker_with_metrics().await;
+    let http_addr = test_broker.http_addr.unwrap();
+
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    for i in 0..3 {
+        let publish = Request::Publish {
+            topic: format!("unknown-topic-{i}"),
+            payload: Bytes::from_static(b"test message"),
+            compression: Compression::None,
+            traceparent: None,
+        };
+        let response = test_client.send_and_receive(publish).await;
+        assert!(matches!(response, Response::Error { .. }));
+    }
+
+    let response = http::get(http_addr, "/metrics").await;
+    assert!(
+        !response.body.contains("unknown-topic"),
+        "Unexpected series in:\n{}",
+        response.body
+    );
+
+    test_broker.stop().await;
+}
@@ -0,0 +1,376 @@
+pub mod helpers;
+
+use crate::helpers::{http, sasl, test_broker, test_client};
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::config::{BrokerConfig, ListenerConfig, ListenerProtocol, QuotaConfig, QuotaLimits};
+use kafkalite::protocol::request::{IsolationLevel, Request};
+use kafkalite::protocol::response::Response;
+use rumqttc::{
+    AsyncClient, ConnectReturnCode, ConnectionError, Event, EventLoop, MqttOptions, Packet, QoS,
+    SubscribeReasonCode,
+};
+use std::net::SocketAddr;
+use std::time::Duration;
+use uuid::Uuid;
+
+fn mqtt_config() -> BrokerConfig {
+    BrokerConfig {
+        listeners: vec![
+            ListenerConfig::new("default", [127, 0, 0, 1].into(), 0, Duration::from_secs(5)),
+            ListenerConfig {
+                protocol: ListenerProtocol::Mqtt,
+                ..ListenerConfig::new("mqtt", [127, 0, 0, 1].into(), 0, Duration::from_secs(5))
+            },
+        ],
+        ..Default::default()
+    }
+}
+
+fn mqtt_client(addr: SocketAddr, client_id: &str) -> (AsyncClient, EventLoop) {
+    let mut options = MqttOptions::new(client_id, addr.ip().to_string(), addr.port());
+    options.set_keep_alive(Duration::from_secs(5));
+    AsyncClient::new(options, 10)
+}
+
+/// Polls the event loop until the broker sends a packet, skipping what the client sends.
+async fn next_packet(eventloop: &mut EventLoop) -> Result<Packet, ConnectionError> {
+    let poll = async {
+        loop {
+            if let Event::Incoming(packet) = eventloop.poll().await? {
+                return Ok(packet);
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), poll)
+        .await
+        .expect("Timed out waiting for a packet")
+}
+
+#[tokio::test]
+async fn mqtt_listener_delivers_retained_and_new_messages_to_wildcard_subscriptions_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(mqtt_config()).await;
+    let mqtt_addr = test_broker.listener_addr("mqtt");
+    let mut client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(
+        client
+            .add_topic("sensors/kitchen/temperature", Some(Compression::Gzip))
+            .await,
+        Response::Ack
+    );
+    assert_eq!(
+        client.add_topic("sensors/hall/temperature", None).await,
+        Response::Ack
+    );
+    assert_eq!(
+        client.add_topic("sensors/hall/humidity", None).await,
+        Response::Ack
+    );
+    for payload in [&b"20"[..], b"21"] {
+        let publish = Request::Publish {
+            topic: "sensors/kitchen/temperature".to_string(),
+            payload: Bytes::from_static(payload),
+            compression: Compression::None,
+            traceparent: None,
+        };
+        assert_eq!(client.send_and_receive(publish).await, Response::Ack);
+    }
+
+    let (subscriber, mut subscriber_events) = mqtt_client(mqtt_addr, "subscriber");
+    assert!(matches!(
+        next_packet(&mut subscriber_events).await,
+        Ok(Packet::ConnAck(_))
+    ));
+    subscriber
+        .subscribe("sensors/+/temperature", QoS::ExactlyOnce)
+        .await
+        .unwrap();
+    let Ok(Packet::SubAck(suback)) = next_packet(&mut subscriber_events).await else {
+        panic!("Expected a SUBACK");
+    };
+    assert_eq!(
+        suback.return_codes,
+        vec![SubscribeReasonCode::Success(QoS::AtLeastOnce)]
+    );
+    // Only the last record of the log is sent as the retained message.
+    let Ok(Packet::Publish(retained)) = next_packet(&mut subscriber_events).await else {
+        panic!("Expected a PUBLISH");
+    };
+    assert_eq!(retained.topic, "sensors/kitchen/temperature");
+    assert_eq!(retained.payload, Bytes::from_static(b"21"));
+    assert!(retained.retain);
+    subscriber
+        .subscribe("missing", QoS::AtMostOnce)
+        .await
+        .unwrap();
+    let Ok(Packet::SubAck(suback)) = next_packet(&mut subscriber_events).await else {
+        panic!("Expected a SUBACK");
+    };
+    assert_eq!(suback.return_codes, vec![SubscribeReasonCode::Failure]);
+
+    let (publisher, mut publisher_events) = mqtt_client(mqtt_addr, "publisher");
+    assert!(matches!(
+        next_packet(&mut publisher_events).await,
+        Ok(Packet::ConnAck(_))
+    ));
+    publisher
+        .publish("sensors/hall/humidity", QoS::AtMostOnce, false, "40")
+        .await
+        .unwrap();
+    publisher
+        .publish("sensors/hall/temperature", QoS::AtLeastOnce, false, "19")
+        .await
+        .unwrap();
+    assert!(matches!(
+        next_packet(&mut publisher_events).await,
+        Ok(Packet::PubAck(_))
+    ));
+
+    let Ok(Packet::Publish(message)) = next_packet(&mut subscriber_events).await else {
+        panic!("Expected a PUBLISH");
+    };
+    assert_eq!(message.topic, "sensors/hall/temperature");
+    assert_eq!(message.payload, Bytes::from_static(b"19"));
+    assert_eq!(message.qos, QoS::AtLeastOnce);
+    assert!(!message.retain);
+
+    // Records published over MQTT land in the topic log like any other.
+    let subscribe = Request::Subscribe {
+        topic: "sensors/hall/humidity".to_string(),
+        client_id: Uuid::new_v4(),
+        from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadCommitted,
+    };
+    assert_eq!(client.send_and_receive(subscribe).await, Response::Ack);
+    assert!(matches!(
+        &client.receive(1).await[..],
+        [Response::Message { payload, .. }] if payload.as_ref() == b"40"
+    ));
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn mqtt_listener_subscribes_wildcard_filters_to_topics_added_later_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(mqtt_config()).await;
+    let mqtt_addr = test_broker.listener_addr("mqtt");
+    let mut client = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    let (subscriber, mut subscriber_events) = mqtt_client(mqtt_addr, "subscriber");
+    assert!(matches!(
+        next_packet(&mut subscriber_events).await,
+        Ok(Packet::ConnAck(_))
+    ));
+    subscriber
+        .subscribe("sensors/#", QoS::AtLeastOnce)
+        .await
+        .unwrap();
+    let Ok(Packet::SubAck(suback)) = next_packet(&mut subscriber_events).await else {
+        panic!("Expected a SUBACK");
+    };
+    assert_eq!(
+        suback.return_codes,
+        vec![SubscribeReasonCode::Success(QoS::AtLeastOnce)]
+    );
+
+    assert_eq!(client.add_topic("lights/garage", None).await, Response::Ack);
+    assert_eq!(
+        client.add_topic("sensors/garage/temperature", None).await,
+        Response::Ack
+    );
+    for topic in ["lights/garage", "sensors/garage/temperature"] {
+        let publish = Request::Publish {
+            topic: topic.to_string(),
+            payload: Bytes::from_static(b"7"),
+            compression: Compression::None,
+            traceparent: None,
+        };
+        assert_eq!(client.send_and_receive(publish).await, Response::Ack);
+    }
+
+    // Records of a topic added after subscribing are all new, so none is sent as retained.
+    let Ok(Packet::Publish(message)) = next_packet(&mut subscriber_events).await else {
+        panic!("Expected a PUBLISH");
+    };
+    assert_eq!(message.topic, "sensors/garage/temperature");
+    assert_eq!(message.payload, Bytes::from_static(b"7"));
+    assert!(!message.retain);
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn mqtt_listener_keeps_delivery_client_ids_from_being_taken_over_test() {
+    let config = BrokerConfig {
+        http_address: Some(([127, 0, 0, 1], 0).into()),
+        admin_api: true,
+        ..mqtt_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let mqtt_addr = test_broker.listener_addr("mqtt");
+    let mut client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(
+        client.add_topic("sensors/temperature", None).await,
+        Response::Ack
+    );
+
+    let (subscriber, mut subscriber_events) = mqtt_client(mqtt_addr, "subscriber");
+    assert!(matches!(
+        next_packet(&mut subscriber_events).await,
+        Ok(Packet::ConnAck(_))
+    ));
+    subscriber
+        .subscribe("sensors/temperature", QoS::AtMostOnce)
+        .await
+        .unwrap();
+    assert!(matches!(
+        next_packet(&mut subscriber_events).await,
+        Ok(Packet::SubAck(_))
+    ));
+
+    let described = http::get(
+        test_broker.http_addr.unwrap(),
+        "/topics/sensors%2Ftemperature",
+    )
+    .await;
+    let delivery = http::json_body(&described)["subscribers"][0]
+        .as_str()
+        .expect("Expected the MQTT client to be subscribed")
+        .parse()
+        .unwrap();
+    let register = Request::RegisterClient {
+        client_id: delivery,
+    };
+    assert!(matches!(
+        client.send_and_receive(register).await,
+        Response::Error { .. }
+    ));
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn mqtt_listener_disconnects_clients_publishing_to_unknown_topics_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(mqtt_config()).await;
+    let (publisher, mut events) = mqtt_client(test_broker.listener_addr("mqtt"), "publisher");
+    assert!(matches!(
+        next_packet(&mut events).await,
+        Ok(Packet::ConnAck(_))
+    ));
+
+    publisher
+        .publish("missing", QoS::AtLeastOnce, false, "lost")
+        .await
+        .unwrap();
+    assert!(next_packet(&mut events).await.is_err());
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn mqtt_listener_authenticates_connect_credentials_test() {
+    let credentials_file = sasl::write_credentials_file(&[("device", "secret")]);
+    let config = BrokerConfig {
+        credentials_file: Some(credentials_file),
+        ..mqtt_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let mqtt_addr = test_broker.listener_addr("mqtt");
+
+    let (_client, mut events) = mqtt_client(mqtt_addr, "anonymous");
+    assert!(matches!(
+        next_packet(&mut events).await,
+        Err(ConnectionError::ConnectionRefused(
+            ConnectReturnCode::NotAuthorized
+        ))
+    ));
+
+    let mut options = MqttOptions::new("device", "127.0.0.1", mqtt_addr.port());
+    options.set_credentials("device", "wrong");
+    let (_client, mut events) = AsyncClient::new(options, 10);
+    assert!(matches!(
+        next_packet(&mut events).await,
+        Err(ConnectionError::ConnectionRefused(
+            ConnectReturnCode::BadUserNamePassword
+        ))
+    ));
+
+    let mut options = MqttOptions::new("device", "127.0.0.1", mqtt_addr.port());
+    options.set_credentials("device", "secret");
+    let (_client, mut events) = AsyncClient::new(options, 10);
+    assert!(matches!(
+        next_packet(&mut events).await,
+        Ok(Packet::ConnAck(_))
+    ));
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn mqtt_listener_keeps_throttled_users_throttled_test() {
+    let credentials_file = sasl::write_credentials_file(&[("device", "secret")]);
+    let config = BrokerConfig {
+        credentials_file: Some(credentials_file),
+        quotas: QuotaConfig {
+            client: QuotaLimits {
+                produce_byte_rate: Some(1000),
+                ..QuotaLimits::default()
+            },
+            ..QuotaConfig::default()
+        },
+        ..mqtt_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let mqtt_addr = test_broker.listener_addr("mqtt");
+    let mut client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(
+        sasl::authenticate_plain(&mut client, "device", "secret").await,
+        Response::SaslAuthenticate {
+            auth_bytes: Bytes::new()
+        }
+    );
+    assert_eq!(
+        client.add_topic("sensors/temperature", None).await,
+        Response::Ack
+    );
+    // Leaves the user half a second in debt.
+    let publish = Request::Publish {
+        topic: "sensors/temperature".to_string(),
+        payload: Bytes::from(vec![0; 1500]),
+        compression: Compression::None,
+        traceparent: None,
+    };
+    assert_eq!(
+        client.send_and_receive(publish.clone()).await,
+        Response::Ack
+    );
+    assert!(matches!(
+        client.send_and_receive(publish).await,
+        Response::Throttled { .. }
+    ));
+
+    let mut options = MqttOptions::new("device", "127.0.0.1", mqtt_addr.port());
+    options.set_credentials("device", "secret");
+    options.set_keep_alive(Duration::from_secs(5));
+    let (publisher, mut events) = AsyncClient::new(options, 10);
+    assert!(matches!(
+        next_packet(&mut events).await,
+        Ok(Packet::ConnAck(_))
+    ));
+    let started = std::time::Instant::now();
+    publisher
+        .publish("sensors/temperature", QoS::AtLeastOnce, false, "21")
+        .await
+        .unwrap();
+    assert!(matches!(
+        next_packet(&mut events).await,
+        Ok(Packet::PubAck(_))
+    ));
+    assert!(
+        started.elapsed() >= Duration::from_millis(300),
+        "Publish acknowledged after {:?}",
+        started.elapsed()
+    );
+
+    test_broker.stop().await;
+}
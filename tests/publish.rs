@@ -1,8 +1,12 @@
 pub mod helpers;
 
 use crate::helpers::{test_broker, test_client};
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::config::BrokerConfig;
 use kafkalite::protocol::request::Request;
 use kafkalite::protocol::response::Response;
+use std::time::Duration;
 
 #[tokio::test]
 async fn broker_returns_error_when_client_tries_to_publish_to_unknown_topic() {
@@ -12,7 +16,9 @@ async fn broker_returns_error_when_client_tries_to_publish_to_unknown_topic() {
 
     let publish = Request::Publish {
         topic: "test-topic".to_string(),
-        payload: b"test message".to_vec(),
+        payload: Bytes::from_static(b"test message"),
+        compression: Compression::None,
+        traceparent: None,
     };
     let ack = publisher.send_and_receive(publish).await;
     assert_eq!(
@@ -22,3 +28,38 @@ async fn broker_returns_error_when_client_tries_to_publish_to_unknown_topic() {
         }
     );
 }
+
+#[tokio::test]
+async fn broker_returns_error_when_payload_exceeds_max_payload_size() {
+    let config = BrokerConfig {
+        max_payload_size: 8,
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    let add_topic = Request::AddTopic {
+        topic: "test-topic".to_string(),
+        retention: Some(1),
+        compression: None,
+    };
+    let ack = publisher.send_and_receive(add_topic).await;
+    assert_eq!(ack, Response::Ack);
+
+    let publish = Request::Publish {
+        topic: "test-topic".to_string(),
+        payload: Bytes::from_static(b"test message"),
+        compression: Compression::None,
+        traceparent: None,
+    };
+    let response = publisher.send_and_receive(publish).await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: "Payload of 12 bytes exceeds the limit of 8 bytes".to_string()
+        }
+    );
+
+    test_broker.stop().await;
+}
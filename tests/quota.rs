@@ -0,0 +1,169 @@
+pub mod helpers;
+
+use crate::helpers::{test_broker, test_client};
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::config::{BrokerConfig, QuotaConfig, QuotaLimits};
+use kafkalite::protocol::request::{IsolationLevel, Request};
+use kafkalite::protocol::response::Response;
+use std::time::Duration;
+
+async fn start_broker_with_quotas(quotas: QuotaConfig) -> test_broker::TestBroker {
+    let config = BrokerConfig {
+        quotas,
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    };
+    test_broker::TestBroker::start_with_config(config).await
+}
+
+fn publish(payload: &'static [u8]) -> Request {
+    Request::Publish {
+        topic: "test-topic".to_string(),
+        payload: Bytes::from_static(payload),
+        compression: Compression::None,
+        traceparent: None,
+    }
+}
+
+#[tokio::test]
+async fn broker_throttles_producer_over_its_byte_rate_test() {
+    let test_broker = start_broker_with_quotas(QuotaConfig {
+        client: QuotaLimits {
+            produce_byte_rate: Some(10),
+            ..QuotaLimits::default()
+        },
+        ..QuotaConfig::default()
+    })
+    .await;
+
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(producer.add_topic("test-topic", None).await, Response::Ack);
+    assert_eq!(
+        producer
+            .send_and_receive(publish(b"0123456789abcdef"))
+            .await,
+        Response::Ack
+    );
+
+    let response = producer.send_and_receive(publish(b"0123")).await;
+    let Response::Throttled { throttle_time_ms } = response else {
+        panic!("Expected producer to be throttled, got {response:?}");
+    };
+    assert!(throttle_time_ms > 0 && throttle_time_ms <= 600);
+
+    // The connection stays open, so the producer can go on after backing off.
+    tokio::time::sleep(Duration::from_millis(throttle_time_ms as u64)).await;
+    assert_eq!(
+        producer.send_and_receive(publish(b"0123")).await,
+        Response::Ack
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_keeps_unauthenticated_producer_throttled_under_a_new_client_id_test() {
+    let test_broker = start_broker_with_quotas(QuotaConfig {
+        client: QuotaLimits {
+            produce_byte_rate: Some(10),
+            ..QuotaLimits::default()
+        },
+        ..QuotaConfig::default()
+    })
+    .await;
+
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let register = Request::RegisterClient {
+        client_id: producer.client_id,
+    };
+    assert_eq!(producer.send_and_receive(register).await, Response::Ack);
+    assert_eq!(producer.add_topic("test-topic", None).await, Response::Ack);
+    assert_eq!(
+        producer
+            .send_and_receive(publish(b"0123456789abcdef"))
+            .await,
+        Response::Ack
+    );
+
+    // Quotas of unauthenticated clients follow their address, not the client id they pick.
+    let mut reconnected = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let register = Request::RegisterClient {
+        client_id: reconnected.client_id,
+    };
+    assert_eq!(reconnected.send_and_receive(register).await, Response::Ack);
+    let response = reconnected.send_and_receive(publish(b"0123")).await;
+    assert!(
+        matches!(response, Response::Throttled { .. }),
+        "Expected producer to stay throttled, got {response:?}"
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_throttles_client_over_its_request_rate_test() {
+    let test_broker = start_broker_with_quotas(QuotaConfig {
+        client: QuotaLimits {
+            request_rate: Some(2),
+            ..QuotaLimits::default()
+        },
+        ..QuotaConfig::default()
+    })
+    .await;
+
+    let mut test_client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut responses = Vec::new();
+    for _ in 0..5 {
+        responses.push(test_client.send_and_receive(Request::Ping).await);
+    }
+    assert_eq!(responses[0], Response::Pong);
+    assert!(
+        responses
+            .iter()
+            .any(|response| matches!(response, Response::Throttled { .. })),
+        "Expected client to be throttled, got {responses:?}"
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_paces_delivery_to_consumer_over_its_byte_rate_test() {
+    let test_broker = start_broker_with_quotas(QuotaConfig {
+        client: QuotaLimits {
+            consume_byte_rate: Some(20),
+            ..QuotaLimits::default()
+        },
+        ..QuotaConfig::default()
+    })
+    .await;
+
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut consumer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(producer.add_topic("test-topic", None).await, Response::Ack);
+    for _ in 0..4 {
+        assert_eq!(
+            producer.send_and_receive(publish(b"0123456789")).await,
+            Response::Ack
+        );
+    }
+
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: consumer.client_id,
+        from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    assert_eq!(consumer.send_and_receive(subscribe).await, Response::Ack);
+
+    // The first two messages use up the burst, the third one puts the consumer in debt.
+    assert_eq!(consumer.receive(3).await.len(), 3);
+    assert!(
+        consumer
+            .receive_no_messages(Duration::from_millis(200))
+            .await
+    );
+    assert_eq!(consumer.receive(1).await.len(), 1);
+
+    test_broker.stop().await;
+}
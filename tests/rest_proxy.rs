@@ -0,0 +1,316 @@
+pub mod helpers;
+
+use crate::helpers::sasl::{authenticate_plain, write_acl_file, write_credentials_file};
+use crate::helpers::{http, test_broker, test_client};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use kafkalite::config::{BrokerConfig, QuotaConfig, QuotaLimits};
+use kafkalite::protocol::request::{IsolationLevel, Request};
+use kafkalite::protocol::response::Response;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+fn rest_proxy_config() -> BrokerConfig {
+    BrokerConfig {
+        http_address: Some(([127, 0, 0, 1], 0).into()),
+        rest_proxy: true,
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    }
+}
+
+#[tokio::test]
+async fn rest_proxy_publishes_and_fetches_records_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(rest_proxy_config()).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let mut admin = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(admin.add_topic("events", None).await, Response::Ack);
+
+    let published = http::request(
+        http_addr,
+        "POST",
+        "/topics/events/records",
+        Some(r#"{"records": [{"value": {"id": 1}}, {"value": "two"}]}"#),
+    )
+    .await;
+    assert_eq!(published.status, 200);
+    assert_eq!(http::json_body(&published), json!({"published": 2}));
+    let published = http::send(
+        http_addr,
+        "POST",
+        "/topics/events/records",
+        Some("application/octet-stream"),
+        &[0xff, 0x00],
+    )
+    .await;
+    assert_eq!(published.status, 200);
+
+    let fetched = http::get(
+        http_addr,
+        "/topics/events/records?offset=0&max=2&format=json",
+    )
+    .await;
+    assert_eq!(fetched.status, 200);
+    assert_eq!(
+        http::json_body(&fetched),
+        json!({
+            "records": [{"offset": 0, "value": {"id": 1}}, {"offset": 1, "value": "two"}],
+            "next_offset": 2
+        })
+    );
+    let fetched = http::get(http_addr, "/topics/events/records?offset=2").await;
+    assert_eq!(
+        http::json_body(&fetched),
+        json!({
+            "records": [{"offset": 2, "value": BASE64.encode([0xff, 0x00])}],
+            "next_offset": 3
+        })
+    );
+    let not_json = http::get(http_addr, "/topics/events/records?offset=2&format=json").await;
+    assert_eq!(not_json.status, 422);
+
+    // Records published over HTTP reach subscribers of the binary protocol.
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let subscribe = Request::Subscribe {
+        topic: "events".to_string(),
+        client_id: subscriber.client_id,
+        from_offset: Some(1),
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    assert_eq!(subscriber.send_and_receive(subscribe).await, Response::Ack);
+    match subscriber.receive(1).await.as_slice() {
+        [Response::Message { payload, .. }] => assert_eq!(payload.as_ref(), br#""two""#),
+        other => panic!("Expected a message, got {other:?}"),
+    }
+
+    let missing = http::request(
+        http_addr,
+        "POST",
+        "/topics/missing/records",
+        Some(r#"{"records": [{"value": 1}]}"#),
+    )
+    .await;
+    assert_eq!(missing.status, 404);
+    assert_eq!(
+        http::json_body(&missing),
+        json!({"error": "Topic missing not found"})
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn rest_proxy_publishes_nothing_of_a_batch_with_a_failing_record_test() {
+    let config = BrokerConfig {
+        max_payload_size: 16,
+        ..rest_proxy_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let mut admin = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(admin.add_topic("events", None).await, Response::Ack);
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let subscribe = Request::Subscribe {
+        topic: "events".to_string(),
+        client_id: subscriber.client_id,
+        from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    assert_eq!(subscriber.send_and_receive(subscribe).await, Response::Ack);
+
+    let published = http::request(
+        http_addr,
+        "POST",
+        "/topics/events/records",
+        Some(r#"{"records": [{"value": 1}, {"value": "far too long for the limit"}]}"#),
+    )
+    .await;
+    assert_eq!(published.status, 413);
+    // Not even read-uncommitted subscribers see the records of the rejected batch.
+    assert!(
+        subscriber
+            .receive_no_messages(Duration::from_millis(200))
+            .await
+    );
+
+    // The rejected records took up no offsets either.
+    let published = http::request(
+        http_addr,
+        "POST",
+        "/topics/events/records",
+        Some(r#"{"records": [{"value": 2}]}"#),
+    )
+    .await;
+    assert_eq!(published.status, 200);
+    let fetched = http::get(http_addr, "/topics/events/records?offset=0&format=json").await;
+    assert_eq!(
+        http::json_body(&fetched),
+        json!({"records": [{"offset": 0, "value": 2}], "next_offset": 1})
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn rest_proxy_streams_records_as_server_sent_events_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(rest_proxy_config()).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let mut admin = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(admin.add_topic("events", None).await, Response::Ack);
+    let publish = |value: &'static str| {
+        http::request(http_addr, "POST", "/topics/events/records", Some(value))
+    };
+    publish(r#"{"records": [{"value": "first"}]}"#).await;
+
+    let mut stream =
+        http::EventStream::open(http_addr, "/topics/events/stream?format=json", None).await;
+    assert_eq!(stream.status, 200);
+    publish(r#"{"records": [{"value": "second"}]}"#).await;
+    let event = stream.next_event().await.expect("Expected an event");
+    assert_eq!(event.id.as_deref(), Some("1"));
+    assert_eq!(
+        serde_json::from_str::<Value>(&event.data).unwrap(),
+        json!({"offset": 1, "value": "second"})
+    );
+
+    // A reconnecting client resumes after the last event it saw.
+    let mut resumed =
+        http::EventStream::open(http_addr, "/topics/events/stream?format=json", Some(0)).await;
+    let event = resumed.next_event().await.expect("Expected an event");
+    assert_eq!(event.id.as_deref(), Some("1"));
+
+    test_broker.stop().await;
+    assert_eq!(stream.next_event().await, None);
+}
+
+#[tokio::test]
+async fn rest_proxy_keeps_stream_client_ids_from_being_taken_over_test() {
+    let config = BrokerConfig {
+        admin_api: true,
+        ..rest_proxy_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let mut admin = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(admin.add_topic("events", None).await, Response::Ack);
+    let mut stream =
+        http::EventStream::open(http_addr, "/topics/events/stream?format=json", None).await;
+    assert_eq!(stream.status, 200);
+
+    let described = http::get(http_addr, "/topics/events").await;
+    let subscriber = http::json_body(&described)["subscribers"][0]
+        .as_str()
+        .expect("Expected the stream to be subscribed")
+        .parse()
+        .unwrap();
+    let mut client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let register = Request::RegisterClient {
+        client_id: subscriber,
+    };
+    assert!(matches!(
+        client.send_and_receive(register).await,
+        Response::Error { .. }
+    ));
+
+    http::request(
+        http_addr,
+        "POST",
+        "/topics/events/records",
+        Some(r#"{"records": [{"value": "still streamed"}]}"#),
+    )
+    .await;
+    let event = stream.next_event().await.expect("Expected an event");
+    assert_eq!(event.id.as_deref(), Some("0"));
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn rest_proxy_requires_credentials_and_permissions_test() {
+    let acls = r#"
+        super_users = ["admin"]
+
+        [[acls]]
+        principal = "alice"
+        operations = ["Publish"]
+        topic = "orders"
+
+        [[acls]]
+        principal = "bob"
+        operations = ["Subscribe"]
+        topic = "orders"
+    "#;
+    let config = BrokerConfig {
+        credentials_file: Some(write_credentials_file(&[
+            ("alice", "alice-secret"),
+            ("bob", "bob-secret"),
+            ("admin", "admin-secret"),
+        ])),
+        acl_file: Some(write_acl_file(acls)),
+        ..rest_proxy_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let mut admin = test_client::TestClient::connect(test_broker.socket_addr).await;
+    authenticate_plain(&mut admin, "admin", "admin-secret").await;
+    assert_eq!(admin.add_topic("orders", None).await, Response::Ack);
+    let alice = ("alice", "alice-secret");
+    let bob = ("bob", "bob-secret");
+    let records = Some(r#"{"records": [{"value": "order"}]}"#);
+
+    let anonymous = http::request(http_addr, "POST", "/topics/orders/records", records).await;
+    assert_eq!(anonymous.status, 401);
+    let published =
+        http::request_as(http_addr, "POST", "/topics/orders/records", records, alice).await;
+    assert_eq!(published.status, 200);
+    let denied = http::request_as(http_addr, "POST", "/topics/orders/records", records, bob).await;
+    assert_eq!(denied.status, 403);
+    assert_eq!(
+        http::json_body(&denied),
+        json!({"error": "Not authorized to perform Publish on topic orders"})
+    );
+
+    let denied = http::request_as(http_addr, "GET", "/topics/orders/records", None, alice).await;
+    assert_eq!(denied.status, 403);
+    let fetched = http::request_as(http_addr, "GET", "/topics/orders/records", None, bob).await;
+    assert_eq!(fetched.status, 200);
+    assert_eq!(http::json_body(&fetched)["next_offset"], 1);
+
+    let denied = http::EventStream::open_as(http_addr, "/topics/orders/stream", alice).await;
+    assert_eq!(denied.status, 403);
+    let stream = http::EventStream::open_as(http_addr, "/topics/orders/stream", bob).await;
+    assert_eq!(stream.status, 200);
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn rest_proxy_throttles_clients_over_their_request_rate_test() {
+    let config = BrokerConfig {
+        quotas: QuotaConfig {
+            client: QuotaLimits {
+                request_rate: Some(2),
+                ..QuotaLimits::default()
+            },
+            ..QuotaConfig::default()
+        },
+        ..rest_proxy_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let mut admin = test_client::TestClient::connect(test_broker.socket_addr).await;
+    assert_eq!(admin.add_topic("events", None).await, Response::Ack);
+
+    let mut statuses = Vec::new();
+    for _ in 0..5 {
+        let published =
+            http::send(http_addr, "POST", "/topics/events/records", None, b"event").await;
+        statuses.push(published.status);
+    }
+    assert_eq!(statuses[0], 200);
+    assert!(
+        statuses.contains(&429),
+        "Expected client to be throttled, got {statuses:?}"
+    );
+
+    test_broker.stop().await;
+}
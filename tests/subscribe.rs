@@ -1,7 +1,9 @@
 pub mod helpers;
 
 use crate::helpers::{test_broker, test_client};
-use kafkalite::protocol::request::Request;
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::protocol::request::{IsolationLevel, Request};
 use kafkalite::protocol::response::Response;
 use uuid::Uuid;
 
@@ -15,6 +17,7 @@ async fn broker_returns_error_when_client_tries_to_subscribe_to_unknown_topic()
         topic: "test-topic".to_string(),
         client_id: Uuid::new_v4(),
         from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadCommitted,
     };
     let response = subscriber.send_and_receive(subscribe).await;
     assert_eq!(
@@ -34,7 +37,8 @@ async fn broker_sends_messages_from_subscribed_topic_to_client_test() {
 
     let add_topic = Request::AddTopic {
         topic: "test-topic".to_string(),
-        retention: 1,
+        retention: Some(1),
+        compression: None,
     };
     let ack = publisher.send_and_receive(add_topic).await;
     assert_eq!(ack, Response::Ack);
@@ -43,6 +47,7 @@ async fn broker_sends_messages_from_subscribed_topic_to_client_test() {
         topic: "test-topic".to_string(),
         client_id: Uuid::new_v4(),
         from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadCommitted,
     };
     let ack = subscriber.send_and_receive(subscribe).await;
     assert_eq!(ack, Response::Ack);
@@ -52,7 +57,9 @@ async fn broker_sends_messages_from_subscribed_topic_to_client_test() {
     for i in 0..5 {
         let publish = Request::Publish {
             topic: "test-topic".to_string(),
-            payload: format!("test-payload-{}", i).into_bytes(),
+            payload: Bytes::from(format!("test-payload-{}", i)),
+            compression: Compression::None,
+            traceparent: None,
         };
         let ack = publisher.send_and_receive(publish).await;
         assert_eq!(ack, Response::Ack);
@@ -73,7 +80,8 @@ async fn broker_replies_retained_messages_first_when_client_subscribes_to_topic(
 
     let add_topic = Request::AddTopic {
         topic: "test-topic".to_string(),
-        retention: 3,
+        retention: Some(3),
+        compression: None,
     };
     let ack = publisher.send_and_receive(add_topic).await;
     assert_eq!(ack, Response::Ack);
@@ -81,7 +89,9 @@ async fn broker_replies_retained_messages_first_when_client_subscribes_to_topic(
     for n in 0..5 {
         let publish = Request::Publish {
             topic: "test-topic".to_string(),
-            payload: vec![n],
+            payload: Bytes::from(vec![n]),
+            compression: Compression::None,
+            traceparent: None,
         };
         let ack = publisher.send_and_receive(publish).await;
         assert_eq!(ack, Response::Ack);
@@ -91,6 +101,7 @@ async fn broker_replies_retained_messages_first_when_client_subscribes_to_topic(
         topic: "test-topic".to_string(),
         client_id: Uuid::new_v4(),
         from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadCommitted,
     };
     let ack = subscriber.send_and_receive(subscribe).await;
     assert_eq!(ack, Response::Ack);
@@ -100,8 +111,10 @@ async fn broker_replies_retained_messages_first_when_client_subscribes_to_topic(
     let expected_messages: Vec<Response> = (2..5)
         .map(|n| Response::Message {
             topic: "test-topic".to_string(),
-            payload: vec![n],
+            payload: Bytes::from(vec![n]),
             offset: n as u64,
+            compression: Compression::None,
+            traceparent: None,
         })
         .collect();
     assert_eq!(messages, expected_messages);
@@ -119,7 +132,8 @@ async fn broker_replies_retained_messages_from_given_offset_first_when_client_su
 
     let add_topic = Request::AddTopic {
         topic: "test-topic".to_string(),
-        retention: 5,
+        retention: Some(5),
+        compression: None,
     };
     let ack = publisher.send_and_receive(add_topic).await;
     assert_eq!(ack, Response::Ack);
@@ -127,7 +141,9 @@ async fn broker_replies_retained_messages_from_given_offset_first_when_client_su
     for n in 0..5 {
         let publish = Request::Publish {
             topic: "test-topic".to_string(),
-            payload: vec![n],
+            payload: Bytes::from(vec![n]),
+            compression: Compression::None,
+            traceparent: None,
         };
         let ack = publisher.send_and_receive(publish).await;
         assert_eq!(ack, Response::Ack);
@@ -137,6 +153,7 @@ async fn broker_replies_retained_messages_from_given_offset_first_when_client_su
         topic: "test-topic".to_string(),
         client_id: Uuid::new_v4(),
         from_offset: Some(2),
+        isolation_level: IsolationLevel::ReadCommitted,
     };
     let ack = subscriber.send_and_receive(subscribe).await;
     assert_eq!(ack, Response::Ack);
@@ -146,8 +163,10 @@ async fn broker_replies_retained_messages_from_given_offset_first_when_client_su
     let expected_messages: Vec<Response> = (2..5)
         .map(|n| Response::Message {
             topic: "test-topic".to_string(),
-            payload: vec![n],
+            payload: Bytes::from(vec![n]),
             offset: n as u64,
+            compression: Compression::None,
+            traceparent: None,
         })
         .collect();
     assert_eq!(messages, expected_messages);
@@ -0,0 +1,116 @@
+pub mod helpers;
+
+use crate::helpers::tls::TestCertificates;
+use crate::helpers::{test_broker, test_client};
+use bytes::BytesMut;
+use kafkalite::config::{BrokerConfig, ListenerConfig};
+use kafkalite::protocol::request::{Request, RequestCodec};
+use kafkalite::protocol::response::{Response, ResponseCodec};
+use rustls::pki_types::ServerName;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_util::codec::{Decoder, Encoder};
+
+fn tls_broker_config(certs: &TestCertificates, require_client_cert: bool) -> BrokerConfig {
+    BrokerConfig {
+        listeners: vec![
+            ListenerConfig::new("tls", [127, 0, 0, 1].into(), 0, Duration::from_secs(1))
+                .with_tls(certs.server_tls_config(require_client_cert)),
+        ],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn broker_serves_requests_over_tls_test() {
+    let certs = TestCertificates::generate();
+    let test_broker =
+        test_broker::TestBroker::start_with_config(tls_broker_config(&certs, false)).await;
+
+    let mut test_client =
+        test_client::TestClient::connect_tls(test_broker.socket_addr, certs.client_config(false))
+            .await;
+    assert_eq!(
+        test_client.send_and_receive(Request::Ping).await,
+        Response::Pong
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_accepts_client_with_trusted_certificate_test() {
+    let certs = TestCertificates::generate();
+    let test_broker =
+        test_broker::TestBroker::start_with_config(tls_broker_config(&certs, true)).await;
+
+    let mut test_client =
+        test_client::TestClient::connect_tls(test_broker.socket_addr, certs.client_config(true))
+            .await;
+    assert_eq!(
+        test_client.send_and_receive(Request::Ping).await,
+        Response::Pong
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_rejects_client_without_certificate_when_mutual_tls_is_required_test() {
+    let certs = TestCertificates::generate();
+    let test_broker =
+        test_broker::TestBroker::start_with_config(tls_broker_config(&certs, true)).await;
+
+    let socket = TcpStream::connect(test_broker.socket_addr).await.unwrap();
+    let connector = TlsConnector::from(Arc::new(certs.client_config(false)));
+    let server_name = ServerName::try_from("localhost").unwrap();
+    // With TLS 1.3 the client finishes its side of the handshake before the server has checked
+    // the (missing) client certificate, so the rejection may only surface on the first read.
+    let rejected = match connector.connect(server_name, socket).await {
+        Err(_) => true,
+        Ok(mut stream) => {
+            let mut ping = BytesMut::new();
+            RequestCodec::default()
+                .encode(Request::Ping, &mut ping)
+                .unwrap();
+            let _ = stream.write_all(&ping).await;
+            let mut buf = [0u8; 16];
+            matches!(stream.read(&mut buf).await, Err(_) | Ok(0))
+        }
+    };
+    assert!(
+        rejected,
+        "Expected client without certificate to be rejected"
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn plaintext_client_is_not_served_by_tls_listener_test() {
+    let certs = TestCertificates::generate();
+    let test_broker =
+        test_broker::TestBroker::start_with_config(tls_broker_config(&certs, false)).await;
+
+    let mut socket = TcpStream::connect(test_broker.socket_addr).await.unwrap();
+    let mut ping = BytesMut::new();
+    RequestCodec::default()
+        .encode(Request::Ping, &mut ping)
+        .unwrap();
+    socket.write_all(&ping).await.unwrap();
+    // The broker answers with a TLS alert at most, and then drops the connection.
+    let mut received = Vec::new();
+    let _ = tokio::time::timeout(Duration::from_secs(2), socket.read_to_end(&mut received))
+        .await
+        .expect("Timed out waiting for broker to drop the connection");
+    let response = ResponseCodec.decode(&mut BytesMut::from(received.as_slice()));
+    assert!(
+        !matches!(response, Ok(Some(Response::Pong))),
+        "Expected plaintext request not to be served"
+    );
+
+    test_broker.stop().await;
+}
@@ -0,0 +1,129 @@
+pub mod helpers;
+
+use crate::helpers::{test_broker, test_client};
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::routing::post;
+use kafkalite::compression::Compression;
+use kafkalite::config::LoggingConfig;
+use kafkalite::protocol::request::{IsolationLevel, Request};
+use kafkalite::protocol::response::Response;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::tonic::trace::v1::Span;
+use prost::Message;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+const PRODUCER_SPAN_ID: &str = "b7ad6b7169203331";
+
+type ReceivedSpans = Arc<Mutex<Vec<Span>>>;
+
+/// Stands in for an OpenTelemetry collector, keeping every span exported to it.
+async fn start_collector() -> (SocketAddr, ReceivedSpans) {
+    let spans = ReceivedSpans::default();
+    let router = Router::new()
+        .route("/v1/traces", post(collect_spans))
+        .with_state(Arc::clone(&spans));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (addr, spans)
+}
+
+async fn collect_spans(State(spans): State<ReceivedSpans>, body: Bytes) {
+    let request = ExportTraceServiceRequest::decode(body).expect("Invalid OTLP request");
+    let mut spans = spans.lock().unwrap();
+    for resource_spans in request.resource_spans {
+        for scope_spans in resource_spans.scope_spans {
+            spans.extend(scope_spans.spans);
+        }
+    }
+}
+
+fn find_span(spans: &ReceivedSpans, name: &str) -> Option<Span> {
+    let spans = spans.lock().unwrap();
+    spans
+        .iter()
+        .find(|span| span.name == name && hex(&span.trace_id) == TRACE_ID)
+        .cloned()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// Installing the global subscriber can only be done once per process, so this file holds a
+// single test.
+#[tokio::test(flavor = "multi_thread")]
+async fn broker_continues_producer_trace_and_exports_spans_test() {
+    let (collector_addr, spans) = start_collector().await;
+    let logging = Arc::new(
+        kafkalite::logging::init(&LoggingConfig {
+            otlp_endpoint: Some(format!("http://{collector_addr}/v1/traces")),
+            ..LoggingConfig::default()
+        })
+        .expect("Failed to initialize logging"),
+    );
+    let test_broker = test_broker::TestBroker::start().await;
+
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut consumer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let add_topic = Request::AddTopic {
+        topic: "test-topic".to_string(),
+        retention: None,
+        compression: None,
+    };
+    assert_eq!(producer.send_and_receive(add_topic).await, Response::Ack);
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: consumer.client_id,
+        from_offset: None,
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    assert_eq!(consumer.send_and_receive(subscribe).await, Response::Ack);
+    let publish = Request::Publish {
+        topic: "test-topic".to_string(),
+        payload: Bytes::from_static(b"test message"),
+        compression: Compression::None,
+        traceparent: Some(format!("00-{TRACE_ID}-{PRODUCER_SPAN_ID}-01")),
+    };
+    assert_eq!(producer.send_and_receive(publish).await, Response::Ack);
+
+    let messages = consumer.receive(1).await;
+    let Response::Message {
+        traceparent: Some(traceparent),
+        ..
+    } = &messages[0]
+    else {
+        panic!("Expected a message with trace context, got {messages:?}");
+    };
+    let parts: Vec<&str> = traceparent.split('-').collect();
+    assert_eq!(parts[1], TRACE_ID);
+    let delivery_span_id = parts[2].to_string();
+
+    let (append, deliver) = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let logging = Arc::clone(&logging);
+            tokio::task::spawn_blocking(move || logging.flush())
+                .await
+                .unwrap();
+            if let (Some(append), Some(deliver)) =
+                (find_span(&spans, "append"), find_span(&spans, "deliver"))
+            {
+                return (append, deliver);
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("Timed out waiting for spans to be exported");
+
+    assert_eq!(hex(&append.parent_span_id), PRODUCER_SPAN_ID);
+    assert_eq!(deliver.parent_span_id, append.span_id);
+    assert_eq!(hex(&deliver.span_id), delivery_span_id);
+
+    test_broker.stop().await;
+}
@@ -0,0 +1,260 @@
+pub mod helpers;
+
+use crate::helpers::test_client::TestClient;
+use crate::helpers::{test_broker, test_client};
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::config::BrokerConfig;
+use kafkalite::protocol::request::{IsolationLevel, Request};
+use kafkalite::protocol::response::Response;
+use std::time::Duration;
+
+#[tokio::test]
+async fn broker_returns_error_when_client_commits_without_transaction() {
+    let test_broker = test_broker::TestBroker::start().await;
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    let response = producer.send_and_receive(Request::CommitTransaction).await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: "No transaction in progress".to_string()
+        }
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_delivers_transactional_messages_from_all_topics_only_after_commit() {
+    let test_broker = test_broker::TestBroker::start().await;
+
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut orders_subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut audit_subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    assert_eq!(producer.add_topic("orders", None).await, Response::Ack);
+    assert_eq!(producer.add_topic("audit", None).await, Response::Ack);
+    subscribe(&mut orders_subscriber, "orders").await;
+    subscribe(&mut audit_subscriber, "audit").await;
+
+    let ack = producer.send_and_receive(Request::BeginTransaction).await;
+    assert_eq!(ack, Response::Ack);
+    publish(&mut producer, "orders", b"order-1").await;
+    publish(&mut producer, "audit", b"audit-1").await;
+
+    assert!(
+        orders_subscriber
+            .receive_no_messages(Duration::from_millis(100))
+            .await
+    );
+    assert!(
+        audit_subscriber
+            .receive_no_messages(Duration::from_millis(100))
+            .await
+    );
+
+    let ack = producer.send_and_receive(Request::CommitTransaction).await;
+    assert_eq!(ack, Response::Ack);
+
+    let orders = orders_subscriber.receive(1).await;
+    assert_eq!(
+        orders,
+        vec![Response::Message {
+            topic: "orders".to_string(),
+            payload: Bytes::from_static(b"order-1"),
+            offset: 0,
+            compression: Compression::None,
+            traceparent: None,
+        }]
+    );
+    let audit = audit_subscriber.receive(1).await;
+    assert_eq!(
+        audit,
+        vec![Response::Message {
+            topic: "audit".to_string(),
+            payload: Bytes::from_static(b"audit-1"),
+            offset: 0,
+            compression: Compression::None,
+            traceparent: None,
+        }]
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_never_delivers_messages_from_aborted_transaction() {
+    let test_broker = test_broker::TestBroker::start().await;
+
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    assert_eq!(producer.add_topic("orders", None).await, Response::Ack);
+    subscribe(&mut subscriber, "orders").await;
+
+    let ack = producer.send_and_receive(Request::BeginTransaction).await;
+    assert_eq!(ack, Response::Ack);
+    publish(&mut producer, "orders", b"order-1").await;
+    let ack = producer.send_and_receive(Request::AbortTransaction).await;
+    assert_eq!(ack, Response::Ack);
+
+    publish(&mut producer, "orders", b"order-2").await;
+
+    let messages = subscriber.receive(1).await;
+    assert_eq!(
+        messages,
+        vec![Response::Message {
+            topic: "orders".to_string(),
+            payload: Bytes::from_static(b"order-2"),
+            offset: 1,
+            compression: Compression::None,
+            traceparent: None,
+        }]
+    );
+    assert!(
+        subscriber
+            .receive_no_messages(Duration::from_millis(100))
+            .await
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_delivers_uncommitted_messages_to_read_uncommitted_subscribers() {
+    let test_broker = test_broker::TestBroker::start().await;
+
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    assert_eq!(producer.add_topic("orders", None).await, Response::Ack);
+    let subscribe = Request::Subscribe {
+        topic: "orders".to_string(),
+        client_id: subscriber.client_id,
+        from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadUncommitted,
+    };
+    let ack = subscriber.send_and_receive(subscribe).await;
+    assert_eq!(ack, Response::Ack);
+
+    let ack = producer.send_and_receive(Request::BeginTransaction).await;
+    assert_eq!(ack, Response::Ack);
+    publish(&mut producer, "orders", b"order-1").await;
+
+    let messages = subscriber.receive(1).await;
+    assert_eq!(
+        messages,
+        vec![Response::Message {
+            topic: "orders".to_string(),
+            payload: Bytes::from_static(b"order-1"),
+            offset: 0,
+            compression: Compression::None,
+            traceparent: None,
+        }]
+    );
+
+    let ack = producer.send_and_receive(Request::AbortTransaction).await;
+    assert_eq!(ack, Response::Ack);
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_aborts_transactions_left_open_past_the_timeout() {
+    let config = BrokerConfig {
+        transaction_timeout: Duration::from_millis(200),
+        ..BrokerConfig::new(0, Duration::from_secs(5))
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    assert_eq!(producer.add_topic("orders", None).await, Response::Ack);
+    subscribe(&mut subscriber, "orders").await;
+
+    let ack = producer.send_and_receive(Request::BeginTransaction).await;
+    assert_eq!(ack, Response::Ack);
+    publish(&mut producer, "orders", b"order-1").await;
+    let mut other_producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    publish(&mut other_producer, "orders", b"order-2").await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let messages = subscriber.receive(1).await;
+    assert_eq!(
+        messages,
+        vec![Response::Message {
+            topic: "orders".to_string(),
+            payload: Bytes::from_static(b"order-2"),
+            offset: 1,
+            compression: Compression::None,
+            traceparent: None,
+        }]
+    );
+
+    let response = producer.send_and_receive(Request::CommitTransaction).await;
+    assert!(
+        matches!(&response, Response::Error { message } if message.ends_with("not found")),
+        "Expected the expired transaction to be gone, got {response:?}"
+    );
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_rejects_transactional_publishes_to_topics_recreated_mid_transaction() {
+    let test_broker = test_broker::TestBroker::start().await;
+    let mut producer = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut admin = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    assert_eq!(producer.add_topic("orders", None).await, Response::Ack);
+    let ack = producer.send_and_receive(Request::BeginTransaction).await;
+    assert_eq!(ack, Response::Ack);
+    publish(&mut producer, "orders", b"order-1").await;
+
+    let delete_topic = Request::DeleteTopic {
+        topic: "orders".to_string(),
+    };
+    let ack = admin.send_and_receive(delete_topic).await;
+    assert_eq!(ack, Response::Ack);
+    assert_eq!(admin.add_topic("orders", None).await, Response::Ack);
+
+    let publish = Request::Publish {
+        topic: "orders".to_string(),
+        payload: Bytes::from_static(b"order-2"),
+        compression: Compression::None,
+        traceparent: None,
+    };
+    let response = producer.send_and_receive(publish).await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: "Topic orders was re-created during the transaction".to_string()
+        }
+    );
+
+    test_broker.stop().await;
+}
+
+async fn subscribe(client: &mut TestClient, topic: &str) {
+    let subscribe = Request::Subscribe {
+        topic: topic.to_string(),
+        client_id: client.client_id,
+        from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadCommitted,
+    };
+    let ack = client.send_and_receive(subscribe).await;
+    assert_eq!(ack, Response::Ack);
+}
+
+async fn publish(client: &mut TestClient, topic: &str, payload: &[u8]) {
+    let publish = Request::Publish {
+        topic: topic.to_string(),
+        payload: Bytes::copy_from_slice(payload),
+        compression: Compression::None,
+        traceparent: None,
+    };
+    let ack = client.send_and_receive(publish).await;
+    assert_eq!(ack, Response::Ack);
+}
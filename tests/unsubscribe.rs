@@ -1,7 +1,9 @@
 pub mod helpers;
 
 use crate::helpers::{test_broker, test_client};
-use kafkalite::protocol::request::Request;
+use bytes::Bytes;
+use kafkalite::compression::Compression;
+use kafkalite::protocol::request::{IsolationLevel, Request};
 use kafkalite::protocol::response::Response;
 use std::time::Duration;
 use uuid::Uuid;
@@ -12,9 +14,15 @@ async fn broker_returns_error_when_client_tries_to_unsubscribe_from_not_existing
 
     let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
 
+    let register = Request::RegisterClient {
+        client_id: subscriber.client_id,
+    };
+    let response = subscriber.send_and_receive(register).await;
+    assert_eq!(response, Response::Ack);
+
     let unsubscribe = Request::Unsubscribe {
         topic: "test-topic".to_string(),
-        client_id: Uuid::new_v4(),
+        client_id: subscriber.client_id,
     };
 
     let response = subscriber.send_and_receive(unsubscribe).await;
@@ -35,7 +43,8 @@ async fn broker_unsubscribes_from_existing_topic() {
 
     let add_topic = Request::AddTopic {
         topic: "test-topic".to_string(),
-        retention: 1,
+        retention: Some(1),
+        compression: None,
     };
     let response = publisher.send_and_receive(add_topic).await;
     assert_eq!(response, Response::Ack);
@@ -44,13 +53,16 @@ async fn broker_unsubscribes_from_existing_topic() {
         topic: "test-topic".to_string(),
         client_id: subscriber.client_id,
         from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadCommitted,
     };
     let response = subscriber.send_and_receive(subscribe).await;
     assert_eq!(response, Response::Ack);
 
     let publish = Request::Publish {
         topic: "test-topic".to_string(),
-        payload: b"test-1".to_vec(),
+        payload: Bytes::from_static(b"test-1"),
+        compression: Compression::None,
+        traceparent: None,
     };
     let response = publisher.send_and_receive(publish).await;
     assert_eq!(response, Response::Ack);
@@ -67,7 +79,9 @@ async fn broker_unsubscribes_from_existing_topic() {
 
     let publish = Request::Publish {
         topic: "test-topic".to_string(),
-        payload: b"test-2".to_vec(),
+        payload: Bytes::from_static(b"test-2"),
+        compression: Compression::None,
+        traceparent: None,
     };
     let response = publisher.send_and_receive(publish).await;
     assert_eq!(response, Response::Ack);
@@ -79,3 +93,98 @@ async fn broker_unsubscribes_from_existing_topic() {
 
     test_broker.stop().await;
 }
+
+#[tokio::test]
+async fn broker_refuses_to_unsubscribe_subscription_of_another_connection() {
+    let test_broker = test_broker::TestBroker::start().await;
+
+    let mut publisher = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut subscriber = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut intruder = test_client::TestClient::connect(test_broker.socket_addr).await;
+
+    let add_topic = Request::AddTopic {
+        topic: "test-topic".to_string(),
+        retention: Some(1),
+        compression: None,
+    };
+    let response = publisher.send_and_receive(add_topic).await;
+    assert_eq!(response, Response::Ack);
+
+    let subscribe = Request::Subscribe {
+        topic: "test-topic".to_string(),
+        client_id: subscriber.client_id,
+        from_offset: Some(0),
+        isolation_level: IsolationLevel::ReadCommitted,
+    };
+    let response = subscriber.send_and_receive(subscribe).await;
+    assert_eq!(response, Response::Ack);
+
+    let unsubscribe = Request::Unsubscribe {
+        topic: "test-topic".to_string(),
+        client_id: subscriber.client_id,
+    };
+    let response = intruder.send_and_receive(unsubscribe).await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: format!(
+                "Client {} is not registered on this connection",
+                subscriber.client_id
+            )
+        }
+    );
+
+    let register = Request::RegisterClient {
+        client_id: subscriber.client_id,
+    };
+    let response = intruder.send_and_receive(register).await;
+    assert_eq!(
+        response,
+        Response::Error {
+            message: format!(
+                "Client {} is already registered on another connection",
+                subscriber.client_id
+            )
+        }
+    );
+
+    let publish = Request::Publish {
+        topic: "test-topic".to_string(),
+        payload: Bytes::from_static(b"test-1"),
+        compression: Compression::None,
+        traceparent: None,
+    };
+    let response = publisher.send_and_receive(publish).await;
+    assert_eq!(response, Response::Ack);
+
+    let message = subscriber.receive(1).await;
+    assert!(!message.is_empty());
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn broker_releases_client_id_when_connection_closes() {
+    let test_broker = test_broker::TestBroker::start().await;
+
+    let client_id = Uuid::new_v4();
+    let register = Request::RegisterClient { client_id };
+
+    let mut first = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let response = first.send_and_receive(register.clone()).await;
+    assert_eq!(response, Response::Ack);
+    drop(first);
+
+    let mut second = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let mut response = second.send_and_receive(register.clone()).await;
+    // The broker notices the closed connection asynchronously.
+    for _ in 0..20 {
+        if response == Response::Ack {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        response = second.send_and_receive(register.clone()).await;
+    }
+    assert_eq!(response, Response::Ack);
+    test_broker.stop().await;
+}
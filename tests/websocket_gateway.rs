@@ -0,0 +1,236 @@
+pub mod helpers;
+
+use crate::helpers::sasl::{write_acl_file, write_credentials_file};
+use crate::helpers::{http, test_broker, test_client};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use kafkalite::compression::Compression;
+use kafkalite::config::BrokerConfig;
+use kafkalite::protocol::request::Request;
+use kafkalite::protocol::response::Response;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::{Error, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+fn websocket_config() -> BrokerConfig {
+    BrokerConfig {
+        http_address: Some(([127, 0, 0, 1], 0).into()),
+        admin_api: true,
+        websocket_gateway: true,
+        ..BrokerConfig::new(0, Duration::from_secs(1))
+    }
+}
+
+async fn connect(http_addr: SocketAddr, path: &str) -> Socket {
+    let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{http_addr}{path}"))
+        .await
+        .expect("Failed to open WebSocket");
+    socket
+}
+
+async fn next_frame(socket: &mut Socket) -> Message {
+    tokio::time::timeout(Duration::from_secs(5), socket.next())
+        .await
+        .expect("Timed out waiting for a frame")
+        .expect("Expected a frame")
+        .expect("Failed to read frame")
+}
+
+async fn publish(client: &mut test_client::TestClient, payload: &'static [u8]) {
+    let publish = Request::Publish {
+        topic: "feed".to_string(),
+        payload: Bytes::from_static(payload),
+        compression: Compression::None,
+        traceparent: None,
+    };
+    assert_eq!(client.send_and_receive(publish).await, Response::Ack);
+}
+
+#[tokio::test]
+async fn websocket_gateway_sends_records_as_json_or_binary_frames_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(websocket_config()).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let mut client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let add_topic = Request::AddTopic {
+        topic: "feed".to_string(),
+        retention: None,
+        compression: Some(Compression::Gzip),
+    };
+    assert_eq!(client.send_and_receive(add_topic).await, Response::Ack);
+    publish(&mut client, br#"{"price": 1}"#).await;
+
+    let mut json_socket = connect(http_addr, "/topics/feed/ws?offset=0").await;
+    let mut binary_socket = connect(http_addr, "/topics/feed/ws?format=binary").await;
+    publish(&mut client, b"not json").await;
+
+    let Message::Text(text) = next_frame(&mut json_socket).await else {
+        panic!("Expected a text frame");
+    };
+    assert_eq!(
+        serde_json::from_str::<Value>(&text).unwrap(),
+        json!({"offset": 0, "value": {"price": 1}})
+    );
+    let Message::Text(text) = next_frame(&mut json_socket).await else {
+        panic!("Expected a text frame");
+    };
+    assert_eq!(
+        serde_json::from_str::<Value>(&text).unwrap(),
+        json!({"offset": 1, "error": "Record at offset 1 is not valid JSON"})
+    );
+    assert_eq!(
+        next_frame(&mut binary_socket).await,
+        Message::Binary(Bytes::from_static(b"not json"))
+    );
+
+    test_broker.stop().await;
+    assert!(matches!(
+        next_frame(&mut json_socket).await,
+        Message::Close(Some(_))
+    ));
+}
+
+#[tokio::test]
+async fn websocket_gateway_unsubscribes_when_the_socket_closes_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(websocket_config()).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let created = http::request(http_addr, "POST", "/topics", Some(r#"{"name": "feed"}"#)).await;
+    assert_eq!(created.status, 201);
+
+    let missing =
+        tokio_tungstenite::connect_async(format!("ws://{http_addr}/topics/missing/ws")).await;
+    assert!(missing.is_err());
+
+    let mut socket = connect(http_addr, "/topics/feed/ws").await;
+    let subscribers =
+        |body: &str| serde_json::from_str::<Value>(body).unwrap()["subscribers"].clone();
+    let described = http::get(http_addr, "/topics/feed").await;
+    assert_eq!(subscribers(&described.body).as_array().unwrap().len(), 1);
+
+    socket.send(Message::Close(None)).await.unwrap();
+    let mut remaining = 1;
+    for _ in 0..20 {
+        let described = http::get(http_addr, "/topics/feed").await;
+        remaining = subscribers(&described.body).as_array().unwrap().len();
+        if remaining == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    assert_eq!(remaining, 0);
+
+    test_broker.stop().await;
+}
+
+#[tokio::test]
+async fn websocket_gateway_keeps_subscriber_client_ids_from_being_taken_over_test() {
+    let test_broker = test_broker::TestBroker::start_with_config(websocket_config()).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let mut client = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let add_topic = Request::AddTopic {
+        topic: "feed".to_string(),
+        retention: None,
+        compression: None,
+    };
+    assert_eq!(client.send_and_receive(add_topic).await, Response::Ack);
+    let mut socket = connect(http_addr, "/topics/feed/ws?format=binary").await;
+
+    let described = http::get(http_addr, "/topics/feed").await;
+    let subscriber = serde_json::from_str::<Value>(&described.body).unwrap()["subscribers"][0]
+        .as_str()
+        .expect("Expected the socket to be subscribed")
+        .parse()
+        .unwrap();
+    let mut intruder = test_client::TestClient::connect(test_broker.socket_addr).await;
+    let register = Request::RegisterClient {
+        client_id: subscriber,
+    };
+    assert!(matches!(
+        intruder.send_and_receive(register).await,
+        Response::Error { .. }
+    ));
+    let unsubscribe = Request::Unsubscribe {
+        topic: "feed".to_string(),
+        client_id: subscriber,
+    };
+    assert!(matches!(
+        intruder.send_and_receive(unsubscribe).await,
+        Response::Error { .. }
+    ));
+
+    publish(&mut client, b"still delivered").await;
+    assert_eq!(
+        next_frame(&mut socket).await,
+        Message::Binary(Bytes::from_static(b"still delivered"))
+    );
+
+    test_broker.stop().await;
+}
+
+/// Opens a WebSocket authenticating as `username`, returning the HTTP status of a refused
+/// upgrade.
+async fn connect_as(
+    http_addr: SocketAddr,
+    path: &str,
+    (username, password): (&str, &str),
+) -> Result<Socket, u16> {
+    let mut request = format!("ws://{http_addr}{path}")
+        .into_client_request()
+        .unwrap();
+    let authorization = http::basic_authorization(username, password);
+    request
+        .headers_mut()
+        .insert("Authorization", authorization.parse().unwrap());
+    match tokio_tungstenite::connect_async(request).await {
+        Ok((socket, _)) => Ok(socket),
+        Err(Error::Http(response)) => Err(response.status().as_u16()),
+        Err(e) => panic!("Failed to open WebSocket: {e}"),
+    }
+}
+
+#[tokio::test]
+async fn websocket_gateway_requires_credentials_and_subscribe_permission_test() {
+    let acls = r#"
+        super_users = ["admin"]
+
+        [[acls]]
+        principal = "alice"
+        operations = ["Subscribe"]
+        topic = "feed"
+    "#;
+    let config = BrokerConfig {
+        credentials_file: Some(write_credentials_file(&[
+            ("alice", "alice-secret"),
+            ("bob", "bob-secret"),
+            ("admin", "admin-secret"),
+        ])),
+        acl_file: Some(write_acl_file(acls)),
+        ..websocket_config()
+    };
+    let test_broker = test_broker::TestBroker::start_with_config(config).await;
+    let http_addr = test_broker.http_addr.unwrap();
+    let created = http::request_as(
+        http_addr,
+        "POST",
+        "/topics",
+        Some(r#"{"name": "feed"}"#),
+        ("admin", "admin-secret"),
+    )
+    .await;
+    assert_eq!(created.status, 201);
+
+    let anonymous =
+        tokio_tungstenite::connect_async(format!("ws://{http_addr}/topics/feed/ws")).await;
+    assert!(matches!(anonymous, Err(Error::Http(response)) if response.status() == 401));
+    let bob = connect_as(http_addr, "/topics/feed/ws", ("bob", "bob-secret")).await;
+    assert_eq!(bob.err(), Some(403));
+    let alice = connect_as(http_addr, "/topics/feed/ws", ("alice", "alice-secret")).await;
+    assert!(alice.is_ok());
+
+    test_broker.stop().await;
+}